@@ -0,0 +1,154 @@
+//! Aggregation of keccak circuit proofs.
+//!
+//! A halo2-base circuit verifies the SHPLONK proofs of any number of keccak circuits with
+//! `snark-verifier`, whose halo2 loader computes the MSMs of their KZG openings with halo2-ecc's
+//! `EccChip`. The pairing checks of all the proofs are accumulated into a single KZG accumulator
+//! `(lhs, rhs)`, which is checked by the verifier of the aggregation proof:
+//! `e(lhs, [1]_2) = e(rhs, [tau]_2)`.
+//!
+//! The public inputs of the aggregation circuit are the accumulator, `4 * LIMBS` limbs of its
+//! coordinates, followed by the public inputs of the aggregated proofs.
+
+use std::env::set_var;
+
+use crate::halo2_proofs::{
+    halo2curves::{
+        bn256::{Bn256, Fq, Fr, G1Affine},
+        pairing::Engine,
+    },
+    plonk::{keygen_pk, keygen_vk, verify_proof, Error, ProvingKey, VerifyingKey},
+    poly::{
+        commitment::{Params, ParamsProver},
+        kzg::{
+            commitment::{KZGCommitmentScheme, ParamsKZG},
+            multiopen::VerifierSHPLONK,
+            strategy::SingleStrategy,
+        },
+    },
+};
+use halo2_base::gates::builder::{CircuitBuilderStage, MultiPhaseThreadBreakPoints};
+use snark_verifier_sdk::halo2::aggregation::AggregationCircuit;
+use snark_verifier_sdk::halo2::{gen_proof_shplonk, PoseidonTranscript};
+use snark_verifier_sdk::snark_verifier::util::arithmetic::fe_from_limbs;
+use snark_verifier_sdk::{NativeLoader, BITS, LIMBS, SHPLONK};
+
+pub use snark_verifier_sdk::halo2::gen_snark_shplonk;
+pub use snark_verifier_sdk::{CircuitExt, Snark};
+
+/// Minimum number of rows reserved for the blinding factors of halo2.
+const MINIMUM_ROWS: usize = 20;
+
+/// Keys of a circuit aggregating proofs of fixed keccak circuits.
+///
+/// The aggregated proofs are made with `gen_snark_shplonk`, whose transcript is the Poseidon one
+/// read by the aggregation circuit, and with the KZG parameters of the aggregation circuit
+/// downsized to their number of rows: the accumulator is checked against the same `[tau]_2`.
+pub struct KeccakAggregator {
+    lookup_bits: usize,
+    params: ParamsKZG<Bn256>,
+    pk: ProvingKey<G1Affine>,
+    break_points: MultiPhaseThreadBreakPoints,
+}
+
+impl KeccakAggregator {
+    /// Generates the keys of the circuit aggregating proofs of the circuits of `snarks`, in the
+    /// same order, with `params`. The proofs in `snarks` are only used to lay out the circuit.
+    ///
+    /// The circuit configuration goes through the `LOOKUP_BITS` and `FLEX_GATE_CONFIG_PARAMS`
+    /// environment variables of `halo2-base`, so only one aggregator can be used per process.
+    pub fn keygen(
+        params: ParamsKZG<Bn256>,
+        lookup_bits: usize,
+        snarks: Vec<Snark>,
+    ) -> Result<Self, Error> {
+        set_var("LOOKUP_BITS", lookup_bits.to_string());
+        let circuit = AggregationCircuit::public::<SHPLONK>(
+            CircuitBuilderStage::Keygen,
+            None,
+            lookup_bits,
+            &params,
+            snarks,
+            false,
+        );
+        circuit.config(params.k(), Some(MINIMUM_ROWS));
+        let vk = keygen_vk(&params, &circuit)?;
+        let pk = keygen_pk(&params, vk, &circuit)?;
+        let break_points = circuit.break_points();
+        Ok(Self { lookup_bits, params, pk, break_points })
+    }
+
+    pub fn params(&self) -> &ParamsKZG<Bn256> {
+        &self.params
+    }
+
+    pub fn vk(&self) -> &VerifyingKey<G1Affine> {
+        self.pk.get_vk()
+    }
+
+    /// Aggregates `snarks`, returning the public inputs of the aggregation circuit and its proof.
+    pub fn prove(&self, snarks: Vec<Snark>) -> (Vec<Fr>, Vec<u8>) {
+        let circuit = AggregationCircuit::public::<SHPLONK>(
+            CircuitBuilderStage::Prover,
+            Some(self.break_points.clone()),
+            self.lookup_bits,
+            &self.params,
+            snarks,
+            false,
+        );
+        let instance = circuit.instance();
+        let proof =
+            gen_proof_shplonk(&self.params, &self.pk, circuit, vec![instance.clone()], None);
+        (instance, proof)
+    }
+
+    /// Verifies the aggregation proof, then the pairing check of its accumulator, which stands for
+    /// the ones of all the aggregated proofs.
+    pub fn verify(&self, instance: &[Fr], proof: &[u8]) -> Result<(), Error> {
+        let mut transcript = PoseidonTranscript::<NativeLoader, &[u8]>::new(proof);
+        verify_proof::<KZGCommitmentScheme<Bn256>, VerifierSHPLONK<'_, Bn256>, _, _, _>(
+            self.params.verifier_params(),
+            self.vk(),
+            SingleStrategy::new(&self.params),
+            &[&[instance]],
+            &mut transcript,
+        )?;
+        let (lhs, rhs) = accumulator(instance).ok_or(Error::InvalidInstances)?;
+        if Bn256::pairing(&lhs, &self.params.g2()) == Bn256::pairing(&rhs, &self.params.s_g2()) {
+            Ok(())
+        } else {
+            Err(Error::ConstraintSystemFailure)
+        }
+    }
+}
+
+/// Verifies a proof made by `gen_snark_shplonk` against its public inputs, with the verifying key
+/// `vk` and the KZG parameters `params` it was made with.
+pub fn verify_snark(
+    params: &ParamsKZG<Bn256>,
+    vk: &VerifyingKey<G1Affine>,
+    snark: &Snark,
+) -> Result<(), Error> {
+    let instances = snark.instances.iter().map(Vec::as_slice).collect::<Vec<_>>();
+    let mut transcript = PoseidonTranscript::<NativeLoader, &[u8]>::new(snark.proof.as_slice());
+    verify_proof::<KZGCommitmentScheme<Bn256>, VerifierSHPLONK<'_, Bn256>, _, _, _>(
+        params.verifier_params(),
+        vk,
+        SingleStrategy::new(params),
+        &[&instances],
+        &mut transcript,
+    )
+}
+
+/// The KZG accumulator `(lhs, rhs)` in the public inputs of an aggregation circuit, or `None` if
+/// its limbs are not the coordinates of two points.
+pub fn accumulator(instance: &[Fr]) -> Option<(G1Affine, G1Affine)> {
+    if instance.len() < 4 * LIMBS {
+        return None;
+    }
+    let [lhs_x, lhs_y, rhs_x, rhs_y]: [Fq; 4] = std::array::from_fn(|i| {
+        fe_from_limbs::<_, _, LIMBS, BITS>(instance[i * LIMBS..(i + 1) * LIMBS].try_into().unwrap())
+    });
+    let lhs = Option::from(G1Affine::from_xy(lhs_x, lhs_y))?;
+    let rhs = Option::from(G1Affine::from_xy(rhs_x, rhs_y))?;
+    Some((lhs, rhs))
+}
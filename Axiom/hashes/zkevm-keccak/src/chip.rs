@@ -0,0 +1,314 @@
+//! Keccak of bytes assigned in a `halo2-base` circuit.
+//!
+//! `KeccakChip::keccak_fixed_len` is called like any other chip while the `GateThreadBuilder` is
+//! populated: it returns the hash of the input bytes as `SafeBytes32`, which can be used by
+//! further `GateInstructions` logic, and records the call. `KeccakCircuitBuilder` then lays out
+//! the `halo2-base` circuit next to a keccak region, hashing the inputs of all the calls in
+//! order, and constrains each call to its keccak_f's with copy constraints:
+//! - the padded input, as little-endian words composed from the input bytes and the padding
+//!   constants, to the `input_word` cells of the keccak_f's absorbing it;
+//! - the hash, as little-endian words composed from its bytes, to the `output_word` cells of the
+//!   last of these keccak_f's, which is constrained to end the hash.
+//!
+//! ```ignore
+//! let range = RangeChip::default(lookup_bits);
+//! let mut keccak = KeccakChip::default();
+//! let ctx = builder.main(0);
+//! let bytes = SafeTypeChip::new(&range).raw_bytes_to::<1, 256>(ctx, ctx.assign_witnesses(input));
+//! let hash = keccak.keccak_fixed_len(ctx, &range, bytes.value().clone());
+//! let circuit = KeccakCircuitBuilder::new(RangeWithInstanceCircuitBuilder::mock(builder, vec![]), keccak, parameters);
+//! ```
+
+use std::collections::HashMap;
+
+use crate::halo2_proofs::{
+    circuit::{Cell, Layouter, SimpleFloorPlanner},
+    plonk::{Circuit, ConstraintSystem, Error, FirstPhase},
+};
+use crate::keccak_packed_multi::{
+    get_num_keccak_f, multi_keccak_phase0, multi_keccak_phase1, KeccakAssignedRow,
+    KeccakCircuitConfig, KeccakConfigParams, KeccakError,
+};
+use crate::util::{eth_types::Field, NUM_BYTES_PER_WORD, NUM_WORDS_TO_SQUEEZE, RATE};
+use halo2_base::{
+    gates::{
+        builder::{FlexGateConfigParams, RangeWithInstanceCircuitBuilder, RangeWithInstanceConfig},
+        GateInstructions, RangeChip, RangeInstructions,
+    },
+    safe_types::{SafeBytes32, SafeTypeChip},
+    AssignedValue, Context,
+    QuantumCell::{Constant, Existing},
+};
+use itertools::Itertools;
+use keccak_hash::keccak;
+
+/// A keccak call of `KeccakChip`, with the words constrained to its keccak_f's
+#[derive(Clone, Debug)]
+pub struct KeccakFixedLenQuery<F: Field> {
+    /// The input bytes
+    pub input: Vec<u8>,
+    /// Little-endian words of the padded input
+    pub input_words: Vec<AssignedValue<F>>,
+    /// Little-endian words of the hash
+    pub output_words: Vec<AssignedValue<F>>,
+}
+
+/// Collects the keccak calls of a `halo2-base` circuit, which are proved by the keccak region of
+/// `KeccakCircuitBuilder`.
+#[derive(Clone, Debug, Default)]
+pub struct KeccakChip<F: Field> {
+    queries: Vec<KeccakFixedLenQuery<F>>,
+}
+
+impl<F: Field> KeccakChip<F> {
+    /// Returns the keccak256 hash of `input`, as big-endian bytes like `keccak`.
+    ///
+    /// The cells of `input` have to be constrained to bytes, e.g. by `SafeTypeChip::raw_bytes_to`.
+    /// The length of `input` is fixed by the circuit.
+    pub fn keccak_fixed_len(
+        &mut self,
+        ctx: &mut Context<F>,
+        range: &RangeChip<F>,
+        input: Vec<AssignedValue<F>>,
+    ) -> SafeBytes32<F> {
+        let gate = range.gate();
+        let bytes = input.iter().map(|byte| byte.value().get_lower_32() as u8).collect_vec();
+        let byte_bases =
+            (0..NUM_BYTES_PER_WORD).map(|i| Constant(gate.pow_of_two()[i * 8])).collect_vec();
+
+        let num_padded = get_num_keccak_f(bytes.len()) * RATE;
+        let mut padding = vec![F::zero(); num_padded - bytes.len()];
+        padding[0] = F::one();
+        *padding.last_mut().unwrap() += F::from(128);
+        let padded_input = input
+            .iter()
+            .map(|byte| Existing(*byte))
+            .chain(padding.into_iter().map(Constant))
+            .collect_vec();
+        let input_words = padded_input
+            .chunks(NUM_BYTES_PER_WORD)
+            .map(|word| gate.inner_product(ctx, word.to_vec(), byte_bases.clone()))
+            .collect_vec();
+
+        let hash =
+            ctx.assign_witnesses(keccak(&bytes).as_bytes().iter().map(|&b| F::from(b as u64)));
+        let hash = SafeTypeChip::new(range).raw_bytes_to::<1, 256>(ctx, hash);
+        let output_words = hash
+            .value()
+            .chunks(NUM_BYTES_PER_WORD)
+            .map(|word| gate.inner_product(ctx, word.to_vec(), byte_bases.clone()))
+            .collect_vec();
+        debug_assert_eq!(output_words.len(), NUM_WORDS_TO_SQUEEZE);
+
+        self.queries.push(KeccakFixedLenQuery { input: bytes, input_words, output_words });
+        hash
+    }
+
+    /// The keccak calls, in order
+    pub fn queries(&self) -> &[KeccakFixedLenQuery<F>] {
+        &self.queries
+    }
+
+    /// The number of keccak_f's proving the calls
+    pub fn num_keccak_f(&self) -> usize {
+        self.queries.iter().map(|query| get_num_keccak_f(query.input.len())).sum()
+    }
+}
+
+/// Configuration of `KeccakCircuitBuilder`: the `halo2-base` columns and the keccak columns
+#[derive(Clone, Debug)]
+pub struct KeccakCircuitBuilderConfig<F: Field> {
+    pub range: RangeWithInstanceConfig<F>,
+    pub keccak: KeccakCircuitConfig<F>,
+}
+
+/// A `RangeWithInstanceCircuitBuilder` whose keccak calls, collected by `keccak`, are proved by a
+/// keccak region of shape `parameters`, with `parameters.capacity()` keccak_f's, see
+/// `check_capacity`.
+///
+/// The `halo2-base` configuration is read from the `FLEX_GATE_CONFIG_PARAMS` and `LOOKUP_BITS`
+/// environment variables, with the `k` of `parameters`, and the keccak one from
+/// `KeccakConfigParams::configured`, so the circuit has to be configured within
+/// `parameters.scope`.
+#[derive(Clone, Debug)]
+pub struct KeccakCircuitBuilder<F: Field> {
+    pub circuit: RangeWithInstanceCircuitBuilder<F>,
+    pub keccak: KeccakChip<F>,
+    pub parameters: KeccakConfigParams,
+}
+
+impl<F: Field> KeccakCircuitBuilder<F> {
+    pub fn new(
+        circuit: RangeWithInstanceCircuitBuilder<F>,
+        keccak: KeccakChip<F>,
+        parameters: KeccakConfigParams,
+    ) -> Self {
+        Self { circuit, keccak, parameters }
+    }
+
+    /// Fails with `CapacityExceeded` if the keccak calls do not fit in the keccak region
+    pub fn check_capacity(&self) -> Result<(), KeccakError> {
+        let needed = self.keccak.num_keccak_f();
+        let available = self.parameters.capacity()?;
+        if needed > available {
+            return Err(KeccakError::CapacityExceeded { needed, available });
+        }
+        Ok(())
+    }
+
+    /// Calls `GateThreadBuilder::config` with the `k` of the keccak region
+    pub fn config(&self, minimum_rows: Option<usize>) -> FlexGateConfigParams {
+        self.circuit.config(self.parameters.k, minimum_rows)
+    }
+
+    /// Constrains the words of each keccak call to the keccak_f's of `assigned_rows`, which hash
+    /// the inputs of the calls in order. `assigned_advices` are the `halo2-base` cells.
+    fn constrain_queries(
+        &self,
+        layouter: &mut impl Layouter<F>,
+        assigned_advices: &HashMap<(usize, usize), (Cell, usize)>,
+        assigned_rows: &[KeccakAssignedRow<F>],
+    ) -> Result<(), Error> {
+        let cell = |value: &AssignedValue<F>| {
+            let cell = value.cell.unwrap();
+            assigned_advices[&(cell.context_id, cell.offset)].0
+        };
+        layouter.assign_region(
+            || "keccak chip",
+            |mut region| {
+                let mut assigned_rows = assigned_rows.iter();
+                for query in self.keccak.queries.iter() {
+                    let keccak_fs = assigned_rows
+                        .by_ref()
+                        .take(get_num_keccak_f(query.input.len()))
+                        .collect_vec();
+                    let last = keccak_fs.last().ok_or(Error::BoundsFailure)?;
+                    let input_cells = keccak_fs.iter().flat_map(|f| f.input_words.iter());
+                    let pairs = query
+                        .input_words
+                        .iter()
+                        .zip_eq(input_cells)
+                        .chain(query.output_words.iter().zip(last.output_words.iter()));
+                    for (value, word) in pairs {
+                        #[cfg(feature = "halo2-axiom")]
+                        region.constrain_equal(&cell(value), &word.cell());
+                        #[cfg(not(feature = "halo2-axiom"))]
+                        region.constrain_equal(cell(value), word.cell())?;
+                    }
+                    // The input is absorbed by all the keccak_f's, and only the last one ends the
+                    // hash, so absorption cannot restart at a block boundary
+                    for (idx, f) in keccak_fs.iter().enumerate() {
+                        let is_final =
+                            if idx + 1 == keccak_fs.len() { F::one() } else { F::zero() };
+                        region.constrain_constant(f.is_squeeze.cell(), F::zero())?;
+                        region.constrain_constant(f.is_final.cell(), is_final)?;
+                    }
+                }
+                Ok(())
+            },
+        )
+    }
+}
+
+impl<F: Field> Circuit<F> for KeccakCircuitBuilder<F> {
+    type Config = KeccakCircuitBuilderConfig<F>;
+    type FloorPlanner = SimpleFloorPlanner;
+
+    fn without_witnesses(&self) -> Self {
+        // The halo2-base cells are assigned unknown values, and the keccak inputs are zeros of the
+        // same lengths
+        let circuit = self.circuit.clone();
+        circuit.circuit.0.builder.replace_with(|builder| std::mem::take(builder).unknown(true));
+        let mut keccak = self.keccak.clone();
+        for query in keccak.queries.iter_mut() {
+            query.input = vec![0; query.input.len()];
+        }
+        Self { circuit, keccak, parameters: self.parameters }
+    }
+
+    fn configure(meta: &mut ConstraintSystem<F>) -> Self::Config {
+        let range = RangeWithInstanceCircuitBuilder::configure(meta);
+        let challenge = meta.challenge_usable_after(FirstPhase);
+        let keccak = KeccakCircuitConfig::new(meta, challenge, KeccakConfigParams::configured());
+        KeccakCircuitBuilderConfig { range, keccak }
+    }
+
+    fn synthesize(
+        &self,
+        config: Self::Config,
+        mut layouter: impl Layouter<F>,
+    ) -> Result<(), Error> {
+        // The keccak rows are laid out with the configured shape, but bounded by `parameters`
+        if config.keccak.parameters != self.parameters {
+            return Err(KeccakError::KeysMismatch.into());
+        }
+        let range = &config.range.range;
+        let circuit = &self.circuit.circuit.0;
+        // only load lookup table if we are actually doing lookups
+        if range.lookup_advice.iter().map(|a| a.len()).sum::<usize>() != 0
+            || !range.q_lookup.iter().all(|q| q.is_none())
+        {
+            range.load_lookup_table(&mut layouter).expect("load lookup table should not fail");
+        }
+        config.keccak.load_aux_tables(&mut layouter)?;
+        // we later `take` the builder, so we need to save this value
+        let witness_gen_only = circuit.builder.borrow().witness_gen_only();
+        // The halo2-base cells are all in `FirstPhase`, so they are assigned before the keccak
+        // region moves to the next phase
+        let assigned_advices = circuit.sub_synthesize(
+            &range.gate,
+            &range.lookup_advice,
+            &range.q_lookup,
+            &mut layouter,
+        );
+
+        let inputs = self.keccak.queries.iter().map(|query| query.input.clone()).collect_vec();
+        #[cfg_attr(not(feature = "halo2-axiom"), allow(unused_mut))]
+        let mut challenge = layouter.get_challenge(config.keccak.challenge());
+        let mut first_pass = true;
+        let assigned_rows = layouter.assign_region(
+            || "keccak chip circuit",
+            |mut region| {
+                if first_pass {
+                    first_pass = false;
+                    return Ok(vec![]);
+                }
+                let (witness, squeeze_digests) = multi_keccak_phase0(
+                    &inputs,
+                    Some(self.parameters.capacity()?),
+                    config.keccak.parameters,
+                )?;
+                let assigned_rows = config.keccak.assign(&mut region, &witness);
+
+                #[cfg(feature = "halo2-axiom")]
+                {
+                    region.next_phase();
+                    challenge = region.get_challenge(config.keccak.challenge());
+                }
+                multi_keccak_phase1(
+                    &mut region,
+                    &config.keccak.keccak_table,
+                    inputs.iter().map(|v| v.as_slice()),
+                    challenge,
+                    squeeze_digests,
+                    config.keccak.parameters,
+                );
+                Ok(assigned_rows)
+            },
+        )?;
+
+        if !witness_gen_only {
+            self.constrain_queries(&mut layouter, &assigned_advices, &assigned_rows)?;
+            // expose public instances
+            let mut layouter = layouter.namespace(|| "expose");
+            for (i, instance) in self.circuit.assigned_instances.iter().enumerate() {
+                let cell = instance.cell.unwrap();
+                let (cell, _) = assigned_advices
+                    .get(&(cell.context_id, cell.offset))
+                    .expect("instance not assigned");
+                layouter.constrain_instance(*cell, config.range.instance, i)?;
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,340 @@
+//! A circuit proving the keccak hashes of a batch of inputs, and helpers to prove it with KZG and
+//! SHPLONK over BN254.
+//!
+//! ```ignore
+//! let parameters = KeccakConfigParams { k: 14, rows_per_round: 28 };
+//! let circuit = KeccakCircuit::<Fr>::new(parameters, inputs)?;
+//! let prover = KeccakCircuitProver::keygen(ParamsKZG::setup(parameters.k, OsRng), &circuit)?;
+//! let (instance, proof) = prover.prove(circuit)?;
+//! prover.verify(&instance, &proof)?;
+//! ```
+
+use std::marker::PhantomData;
+
+use crate::halo2_proofs::{
+    circuit::{Layouter, SimpleFloorPlanner},
+    halo2curves::bn256::{Bn256, Fr, G1Affine},
+    plonk::{
+        create_proof, keygen_pk, keygen_vk, verify_proof, Circuit, Column, ConstraintSystem, Error,
+        FirstPhase, Instance, ProvingKey, VerifyingKey,
+    },
+    poly::{
+        commitment::ParamsProver,
+        kzg::{
+            commitment::{KZGCommitmentScheme, ParamsKZG},
+            multiopen::{ProverSHPLONK, VerifierSHPLONK},
+            strategy::SingleStrategy,
+        },
+    },
+    transcript::{
+        Blake2bRead, Blake2bWrite, Challenge255, TranscriptReadBuffer, TranscriptWriterBuffer,
+    },
+};
+use crate::keccak_packed_multi::{
+    constrain_keccak_instance, constrain_keccak_xof_instance, get_num_keccak_xof_f,
+    keccak_instance, keccak_xof_instance, multi_keccak_xof_phase0, multi_keccak_xof_phase1,
+    KeccakCircuitConfig, KeccakConfigParams, KeccakError,
+};
+use crate::util::{eth_types::Field, NUM_BYTES_TO_SQUEEZE};
+use rand::rngs::OsRng;
+
+/// Proves the keccak hashes of `inputs`, exposed as public inputs by default.
+///
+/// The public inputs are copied from the keccak_f's of each input, so the keys of the circuit
+/// depend on the number and lengths of its inputs, and of its outputs, but not on their bytes.
+///
+/// The circuit reads its shape from `KeccakConfigParams::configured`, so it has to be configured
+/// within the `scope` of its parameters, as `KeccakCircuitProver` does.
+#[derive(Clone, Debug)]
+pub struct KeccakCircuit<F: Field> {
+    parameters: KeccakConfigParams,
+    inputs: Vec<Vec<u8>>,
+    capacity: usize,
+    expose_instance: bool,
+    expose_inputs: bool,
+    output_lens: Option<Vec<usize>>,
+    _marker: PhantomData<F>,
+}
+
+impl<F: Field> Circuit<F> for KeccakCircuit<F> {
+    type Config = (KeccakCircuitConfig<F>, Column<Instance>);
+    type FloorPlanner = SimpleFloorPlanner;
+
+    fn without_witnesses(&self) -> Self {
+        // Keeps the input lengths, which lay out the copy constraints of the public inputs
+        let inputs = self.inputs.iter().map(|input| vec![0; input.len()]).collect();
+        Self { inputs, ..self.clone() }
+    }
+
+    fn configure(meta: &mut ConstraintSystem<F>) -> Self::Config {
+        // MockProver complains if you only have columns in SecondPhase, so let's just make an empty column in FirstPhase
+        meta.advice_column();
+
+        let challenge = meta.challenge_usable_after(FirstPhase);
+        let instance = meta.instance_column();
+        meta.enable_equality(instance);
+        (KeccakCircuitConfig::new(meta, challenge, KeccakConfigParams::configured()), instance)
+    }
+
+    fn synthesize(
+        &self,
+        (config, instance): Self::Config,
+        mut layouter: impl Layouter<F>,
+    ) -> Result<(), Error> {
+        // The rows are laid out with the configured shape, but bounded by the circuit's one
+        if config.parameters != self.parameters {
+            return Err(KeccakError::KeysMismatch.into());
+        }
+        config.load_aux_tables(&mut layouter)?;
+        #[cfg_attr(not(feature = "halo2-axiom"), allow(unused_mut))]
+        let mut challenge = layouter.get_challenge(config.challenge());
+        let mut first_pass = true;
+        let assigned_rows = layouter.assign_region(
+            || "keccak circuit",
+            |mut region| {
+                if first_pass {
+                    first_pass = false;
+                    return Ok(vec![]);
+                }
+                let (witness, squeeze_digests) = multi_keccak_xof_phase0(
+                    &self.inputs,
+                    &self.output_lens(),
+                    Some(self.capacity),
+                    config.parameters,
+                )?;
+                let assigned_rows = config.assign(&mut region, &witness);
+
+                #[cfg(feature = "halo2-axiom")]
+                {
+                    region.next_phase();
+                    challenge = region.get_challenge(config.challenge());
+                }
+                multi_keccak_xof_phase1(
+                    &mut region,
+                    &config.keccak_table,
+                    self.inputs.iter().map(|v| v.as_slice()).zip(self.output_lens()),
+                    challenge,
+                    squeeze_digests,
+                    config.parameters,
+                );
+                Ok(assigned_rows)
+            },
+        )?;
+
+        if !self.expose_instance {
+            return Ok(());
+        }
+        match &self.output_lens {
+            Some(output_lens) => constrain_keccak_xof_instance(
+                &mut layouter,
+                instance,
+                &self.inputs,
+                output_lens,
+                &assigned_rows,
+                self.expose_inputs,
+            ),
+            None => constrain_keccak_instance(
+                &mut layouter,
+                instance,
+                &self.inputs,
+                &assigned_rows,
+                self.expose_inputs,
+            ),
+        }
+    }
+}
+
+impl<F: Field> KeccakCircuit<F> {
+    /// Creates a circuit of shape `parameters` hashing `inputs`, with as many keccak_f's as fit
+    /// in its `2^k` rows. Fails with `NoCapacity` if the shape has no room for the unusable
+    /// keccak_f's; whether the inputs fit is checked by `check_capacity`.
+    pub fn new(parameters: KeccakConfigParams, inputs: Vec<Vec<u8>>) -> Result<Self, KeccakError> {
+        Ok(KeccakCircuit {
+            parameters,
+            inputs,
+            capacity: parameters.capacity()?,
+            expose_instance: true,
+            expose_inputs: false,
+            output_lens: None,
+            _marker: PhantomData,
+        })
+    }
+
+    /// Sets the number of keccak_f's of the circuit, the ones not used by the inputs hash empty
+    /// inputs. The keys of a circuit depend on its capacity.
+    pub fn with_capacity(mut self, capacity: usize) -> Self {
+        self.capacity = capacity;
+        self
+    }
+
+    /// Also exposes the padded inputs as public inputs, see `keccak_instance`
+    pub fn with_exposed_inputs(mut self) -> Self {
+        self.expose_inputs = true;
+        self
+    }
+
+    /// Exposes no public inputs, when the hashes are only needed by the prover
+    pub fn without_instance(mut self) -> Self {
+        self.expose_instance = false;
+        self
+    }
+
+    /// Squeezes `output_lens[i]` bytes for `inputs[i]` and exposes them instead of the hashes, see
+    /// `keccak_xof_instance`
+    pub fn with_output_lens(mut self, output_lens: Vec<usize>) -> Self {
+        assert_eq!(output_lens.len(), self.inputs.len());
+        self.output_lens = Some(output_lens);
+        self
+    }
+
+    pub fn parameters(&self) -> KeccakConfigParams {
+        self.parameters
+    }
+
+    pub fn inputs(&self) -> &[Vec<u8>] {
+        &self.inputs
+    }
+
+    /// The number of bytes squeezed for each input
+    fn output_lens(&self) -> Vec<usize> {
+        self.output_lens.clone().unwrap_or_else(|| vec![NUM_BYTES_TO_SQUEEZE; self.inputs.len()])
+    }
+
+    /// The public inputs of this circuit
+    pub fn instance(&self) -> Vec<F> {
+        if !self.expose_instance {
+            return vec![];
+        }
+        match &self.output_lens {
+            Some(output_lens) => keccak_xof_instance(&self.inputs, output_lens, self.expose_inputs),
+            None => keccak_instance(&self.inputs, self.expose_inputs),
+        }
+    }
+
+    /// The number of keccak_f's that can be done in this circuit
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// The number of keccak_f's hashing the inputs
+    pub fn num_keccak_f(&self) -> usize {
+        get_num_keccak_xof_f(&self.inputs, &self.output_lens())
+    }
+
+    /// Whether `self` and `other` only differ in the bytes of their inputs, so that they have the
+    /// same keys
+    pub fn has_same_keys(&self, other: &Self) -> bool {
+        self.parameters == other.parameters
+            && self.capacity == other.capacity
+            && self.expose_instance == other.expose_instance
+            && self.expose_inputs == other.expose_inputs
+            && self.output_lens == other.output_lens
+            && self.inputs.iter().map(Vec::len).eq(other.inputs.iter().map(Vec::len))
+    }
+
+    /// Fails with `CapacityExceeded` if the inputs do not fit in the capacity, so that they can
+    /// be split across several circuits before proving.
+    pub fn check_capacity(&self) -> Result<(), KeccakError> {
+        let needed = self.num_keccak_f();
+        if needed > self.capacity {
+            return Err(KeccakError::CapacityExceeded { needed, available: self.capacity });
+        }
+        Ok(())
+    }
+}
+
+#[cfg(any(feature = "aggregation", feature = "evm"))]
+impl snark_verifier_sdk::CircuitExt<Fr> for KeccakCircuit<Fr> {
+    fn num_instance(&self) -> Vec<usize> {
+        vec![self.instance().len()]
+    }
+
+    fn instances(&self) -> Vec<Vec<Fr>> {
+        vec![self.instance()]
+    }
+}
+
+/// Keys of a keccak circuit, proving with SHPLONK and a Blake2b transcript.
+pub struct KeccakCircuitProver {
+    /// The circuit of the keys, without its input bytes
+    circuit: KeccakCircuit<Fr>,
+    params: ParamsKZG<Bn256>,
+    pk: ProvingKey<G1Affine>,
+}
+
+impl KeccakCircuitProver {
+    /// Generates the keys of circuits with the shape, capacity, public inputs and input and output
+    /// lengths of `circuit`, whose input bytes are not used, with `params` of `2^k` rows. The keys
+    /// do not hold for inputs of other lengths.
+    pub fn keygen(
+        params: ParamsKZG<Bn256>,
+        circuit: &KeccakCircuit<Fr>,
+    ) -> Result<Self, KeccakError> {
+        circuit.check_capacity()?;
+        let pk = circuit.parameters.scope(|| {
+            let vk = keygen_vk(&params, circuit)?;
+            keygen_pk(&params, vk, circuit)
+        })?;
+        Ok(Self { circuit: circuit.without_witnesses(), params, pk })
+    }
+
+    pub fn params(&self) -> &ParamsKZG<Bn256> {
+        &self.params
+    }
+
+    pub fn pk(&self) -> &ProvingKey<G1Affine> {
+        &self.pk
+    }
+
+    pub fn vk(&self) -> &VerifyingKey<G1Affine> {
+        self.pk.get_vk()
+    }
+
+    /// Proves `circuit`, returning its public inputs and the proof. Fails with `KeysMismatch` if
+    /// it differs from the circuit of the keys other than in its input bytes, and with
+    /// `CapacityExceeded` if its inputs do not fit.
+    pub fn prove(&self, circuit: KeccakCircuit<Fr>) -> Result<(Vec<Fr>, Vec<u8>), KeccakError> {
+        if !circuit.has_same_keys(&self.circuit) {
+            return Err(KeccakError::KeysMismatch);
+        }
+        circuit.check_capacity()?;
+        let instance = circuit.instance();
+        let mut transcript = Blake2bWrite::<_, G1Affine, Challenge255<_>>::init(vec![]);
+        self.circuit.parameters.scope(|| {
+            create_proof::<
+                KZGCommitmentScheme<Bn256>,
+                ProverSHPLONK<'_, Bn256>,
+                Challenge255<G1Affine>,
+                _,
+                Blake2bWrite<Vec<u8>, G1Affine, Challenge255<G1Affine>>,
+                _,
+            >(
+                &self.params, &self.pk, &[circuit], &[&[&instance]], OsRng, &mut transcript
+            )
+        })?;
+        Ok((instance, transcript.finalize()))
+    }
+
+    /// Verifies a proof made by `prove`
+    pub fn verify(&self, instance: &[Fr], proof: &[u8]) -> Result<(), Error> {
+        verify(&self.params, self.vk(), instance, proof)
+    }
+}
+
+/// Verifies a proof of the keccak circuit with verifying key `vk`, made by
+/// `KeccakCircuitProver::prove`. Only the verifier part of `params` is used.
+pub fn verify(
+    params: &ParamsKZG<Bn256>,
+    vk: &VerifyingKey<G1Affine>,
+    instance: &[Fr],
+    proof: &[u8],
+) -> Result<(), Error> {
+    let mut transcript = Blake2bRead::<_, G1Affine, Challenge255<_>>::init(proof);
+    verify_proof::<
+        KZGCommitmentScheme<Bn256>,
+        VerifierSHPLONK<'_, Bn256>,
+        Challenge255<G1Affine>,
+        Blake2bRead<&[u8], G1Affine, Challenge255<G1Affine>>,
+        SingleStrategy<'_, Bn256>,
+    >(params.verifier_params(), vk, SingleStrategy::new(params), &[&[instance]], &mut transcript)
+}
@@ -0,0 +1,56 @@
+//! EVM verification of keccak circuit proofs.
+//!
+//! `snark-verifier` turns the verifying key of a circuit into a verifier contract in Yul, which is
+//! compiled with `solc`, so `solc` has to be in `PATH`. The contract reads the public inputs and
+//! the proof from the calldata, as encoded by `encode_calldata`, and reverts if the proof is
+//! invalid. The proofs it verifies are made with `gen_evm_proof_shplonk`, whose transcript is
+//! keccak256 as on the EVM, rather than Blake2b.
+//!
+//! `EvmVerifier::verify` deploys the contract and calls it in an in-process EVM, `revm`, so the
+//! gas used is measured without any network.
+
+use std::path::Path;
+
+use crate::halo2_proofs::{
+    halo2curves::bn256::{Bn256, Fr, G1Affine},
+    plonk::VerifyingKey,
+    poly::kzg::commitment::ParamsKZG,
+};
+use snark_verifier_sdk::evm::gen_evm_verifier_shplonk;
+use snark_verifier_sdk::snark_verifier::loader::evm::deploy_and_call;
+
+pub use snark_verifier_sdk::evm::{encode_calldata, gen_evm_proof_shplonk};
+pub use snark_verifier_sdk::CircuitExt;
+
+/// The verifier contract of a circuit, as deployment bytecode.
+#[derive(Clone, Debug)]
+pub struct EvmVerifier {
+    deployment_code: Vec<u8>,
+}
+
+impl EvmVerifier {
+    /// Generates the verifier contract of the circuit `C` with verifying key `vk` and
+    /// `num_instance` public inputs, in a single instance column. With `yul_path`, the Yul source
+    /// of the contract is also written there.
+    pub fn new<C: CircuitExt<Fr>>(
+        params: &ParamsKZG<Bn256>,
+        vk: &VerifyingKey<G1Affine>,
+        num_instance: usize,
+        yul_path: Option<&Path>,
+    ) -> Self {
+        let deployment_code =
+            gen_evm_verifier_shplonk::<C>(params, vk, vec![num_instance], yul_path);
+        Self { deployment_code }
+    }
+
+    pub fn deployment_code(&self) -> &[u8] {
+        &self.deployment_code
+    }
+
+    /// Deploys the contract and calls it with `instance` and `proof`, returning the gas used by
+    /// the call, or the reason it failed if the proof is invalid.
+    pub fn verify(&self, instance: &[Fr], proof: &[u8]) -> Result<u64, String> {
+        let calldata = encode_calldata(&[instance.to_vec()], proof);
+        deploy_and_call(self.deployment_code.clone(), calldata)
+    }
+}
@@ -1,27 +1,34 @@
 use super::util::{
     constraint_builder::BaseConstraintBuilder,
     eth_types::Field,
-    expression::{and, not, select, Expr},
+    expression::{and, not, select, sum, Expr},
     field_xor, get_absorb_positions, get_num_bits_per_lookup, into_bits, load_lookup_table,
     load_normalize_table, load_pack_table, pack, pack_u64, pack_with_base, rotate, scatter,
-    target_part_sizes, to_bytes, unpack, CHI_BASE_LOOKUP_TABLE, NUM_BYTES_PER_WORD, NUM_ROUNDS,
-    NUM_WORDS_TO_ABSORB, NUM_WORDS_TO_SQUEEZE, RATE, RATE_IN_BITS, RHO_MATRIX, ROUND_CST,
+    target_part_sizes, to_bytes, unpack, CHI_BASE_LOOKUP_TABLE, NUM_BYTES_PER_WORD,
+    NUM_BYTES_TO_SQUEEZE, NUM_ROUNDS, NUM_WORDS_TO_ABSORB, NUM_WORDS_TO_SQUEEZE, RATE,
+    RATE_IN_BITS, RHO_MATRIX, ROUND_CST,
 };
 use crate::halo2_proofs::{
     arithmetic::FieldExt,
-    circuit::{Layouter, Region, Value},
+    circuit::{Cell as CircuitCell, Layouter, Region, Value},
+    halo2curves::bn256::{Fr, G1Affine},
     plonk::{
-        Advice, Challenge, Column, ConstraintSystem, Error, Expression, Fixed, SecondPhase,
-        TableColumn, VirtualCells,
+        Advice, Challenge, Circuit, Column, ConstraintSystem, Error, Expression, Fixed, Instance,
+        SecondPhase, TableColumn, VerifyingKey, VirtualCells,
     },
     poly::Rotation,
+    SerdeFormat,
 };
 use halo2_base::halo2_proofs::{circuit::AssignedCell, plonk::Assigned};
 use itertools::Itertools;
+use keccak_hash::keccak;
 use log::{debug, info};
-use rayon::prelude::{IntoParallelRefIterator, ParallelIterator};
-use std::env::var;
+use rayon::prelude::{IndexedParallelIterator, IntoParallelRefIterator, ParallelIterator};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::io::{self, Read, Write};
 use std::marker::PhantomData;
+use tiny_keccak::keccakf;
 
 #[cfg(test)]
 mod tests;
@@ -32,36 +39,179 @@ const THETA_C_LOOKUP_RANGE: usize = 6;
 const RHO_PI_LOOKUP_RANGE: usize = 4;
 const CHI_BASE_LOOKUP_RANGE: usize = 5;
 
-pub fn get_num_rows_per_round() -> usize {
-    var("KECCAK_ROWS")
-        .unwrap_or_else(|_| "25".to_string())
-        .parse()
-        .expect("Cannot parse KECCAK_ROWS env var as usize")
+/// The shape of a keccak circuit
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KeccakConfigParams {
+    /// log2 of the number of rows of the circuit, which sets the size of the lookup tables
+    pub k: u32,
+    /// The number of rows of each keccak_f round: fewer rows use more advice columns
+    pub rows_per_round: usize,
 }
 
-fn get_num_bits_per_absorb_lookup() -> usize {
-    get_num_bits_per_lookup(ABSORB_LOOKUP_RANGE)
+thread_local! {
+    static CONFIGURED_PARAMS: std::cell::Cell<Option<KeccakConfigParams>> =
+        std::cell::Cell::new(None);
 }
 
-fn get_num_bits_per_theta_c_lookup() -> usize {
-    get_num_bits_per_lookup(THETA_C_LOOKUP_RANGE)
+/// Restores the shape of the enclosing `KeccakConfigParams::scope`, even on panic
+struct ScopeGuard(Option<KeccakConfigParams>);
+
+impl Drop for ScopeGuard {
+    fn drop(&mut self) {
+        CONFIGURED_PARAMS.with(|params| params.set(self.0));
+    }
 }
 
-fn get_num_bits_per_rho_pi_lookup() -> usize {
-    get_num_bits_per_lookup(CHI_BASE_LOOKUP_RANGE.max(RHO_PI_LOOKUP_RANGE))
+impl KeccakConfigParams {
+    /// The number of keccak_f's that fit in the `2^k` rows of a circuit of this shape
+    pub fn capacity(&self) -> Result<usize, KeccakError> {
+        // A shape of more rows than fit in a `usize` has no capacity
+        1usize
+            .checked_shl(self.k)
+            .and_then(|num_rows| get_keccak_capacity(num_rows, self.rows_per_round).ok())
+            .ok_or(KeccakError::NoCapacity(*self))
+    }
+
+    /// Runs `f` with `self` as the shape returned by `configured`, on this thread only.
+    ///
+    /// `Circuit::configure` has no access to the circuit, so the circuits containing a
+    /// `KeccakCircuitConfig` read its shape from `configured`. They have to be configured within
+    /// this scope: keygen, proving, `MockProver::run` and reading a verifying key.
+    pub fn scope<R>(self, f: impl FnOnce() -> R) -> R {
+        let _guard = ScopeGuard(CONFIGURED_PARAMS.with(|params| params.replace(Some(self))));
+        f()
+    }
+
+    /// The shape of the innermost `scope` of this thread.
+    pub fn configured() -> Self {
+        CONFIGURED_PARAMS
+            .with(|params| params.get())
+            .expect("Keccak circuits must be configured within KeccakConfigParams::scope")
+    }
+
+    /// Writes the shape, then `vk`.
+    pub fn write_vk<W: Write>(
+        &self,
+        writer: &mut W,
+        vk: &VerifyingKey<G1Affine>,
+        format: SerdeFormat,
+    ) -> io::Result<()> {
+        writer.write_all(&self.k.to_le_bytes())?;
+        writer.write_all(&(self.rows_per_round as u64).to_le_bytes())?;
+        vk.write(writer, format)
+    }
+
+    /// Reads a shape and the verifying key of the circuit `C` configured with it, as written by
+    /// `write_vk`.
+    pub fn read_vk<R: Read, C: Circuit<Fr>>(
+        reader: &mut R,
+        format: SerdeFormat,
+    ) -> io::Result<(Self, VerifyingKey<G1Affine>)> {
+        let mut k = [0u8; 4];
+        reader.read_exact(&mut k)?;
+        let mut rows_per_round = [0u8; 8];
+        reader.read_exact(&mut rows_per_round)?;
+        let params = Self {
+            k: u32::from_le_bytes(k),
+            rows_per_round: u64::from_le_bytes(rows_per_round) as usize,
+        };
+        let vk = params.scope(|| VerifyingKey::read::<_, C>(reader, format))?;
+        Ok((params, vk))
+    }
 }
 
-fn get_num_bits_per_base_chi_lookup() -> usize {
-    get_num_bits_per_lookup(CHI_BASE_LOOKUP_RANGE.max(RHO_PI_LOOKUP_RANGE))
+/// Errors of the keccak circuit, its witness generation and its prover
+#[derive(Debug)]
+pub enum KeccakError {
+    /// The inputs need `needed` keccak_f's, more than the `available` ones of the circuit
+    CapacityExceeded { needed: usize, available: usize },
+    /// The `2^k` rows of this shape cannot even fit the two unusable keccak_f's
+    NoCapacity(KeccakConfigParams),
+    /// `num_rows` rows of `rows_per_round` rows per round cannot even fit the two unusable
+    /// keccak_f's
+    TooFewRows { num_rows: usize, rows_per_round: usize },
+    /// The circuit differs from the one of the keys in its shape, capacity, public inputs or
+    /// input and output lengths
+    KeysMismatch,
+    Halo2(Error),
 }
 
-/// The number of keccak_f's that can be done in this circuit
-///
-/// `num_rows` should be number of usable rows without blinding factors
-pub fn get_keccak_capacity(num_rows: usize) -> usize {
-    // - 1 because we have a dummy round at the very beginning of multi_keccak
-    // - NUM_WORDS_TO_ABSORB because `absorb_data_next` and `absorb_result_next` query `NUM_WORDS_TO_ABSORB * get_num_rows_per_round()` beyond any row where `q_absorb == 1`
-    (num_rows / get_num_rows_per_round() - 1 - NUM_WORDS_TO_ABSORB) / (NUM_ROUNDS + 1)
+impl fmt::Display for KeccakError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KeccakError::CapacityExceeded { needed, available } => write!(
+                f,
+                "the inputs need {needed} keccak_f's, the circuit only fits {available}"
+            ),
+            KeccakError::NoCapacity(params) => write!(
+                f,
+                "2^{} rows cannot fit the unusable keccak_f's with {} rows per round",
+                params.k, params.rows_per_round
+            ),
+            KeccakError::TooFewRows { num_rows, rows_per_round } => write!(
+                f,
+                "{num_rows} rows cannot fit the unusable keccak_f's with {rows_per_round} rows per \
+                 round"
+            ),
+            KeccakError::KeysMismatch => {
+                write!(f, "the circuit does not have the layout its keys were generated for")
+            }
+            KeccakError::Halo2(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl std::error::Error for KeccakError {}
+
+impl From<Error> for KeccakError {
+    fn from(e: Error) -> Self {
+        KeccakError::Halo2(e)
+    }
+}
+
+/// Synthesis can only fail with a halo2 `Error`, so the capacity errors lose their details there;
+/// `KeccakCircuit::check_capacity` reports them before proving.
+impl From<KeccakError> for Error {
+    fn from(e: KeccakError) -> Self {
+        match e {
+            KeccakError::CapacityExceeded { .. } => Error::BoundsFailure,
+            KeccakError::NoCapacity(params) => Error::NotEnoughRowsAvailable { current_k: params.k },
+            // floor(log2(num_rows))
+            KeccakError::TooFewRows { num_rows, .. } => Error::NotEnoughRowsAvailable {
+                current_k: (usize::BITS - num_rows.leading_zeros()).saturating_sub(1),
+            },
+            KeccakError::KeysMismatch => Error::Synthesis,
+            KeccakError::Halo2(e) => e,
+        }
+    }
+}
+
+fn get_num_bits_per_absorb_lookup(k: u32) -> usize {
+    get_num_bits_per_lookup(ABSORB_LOOKUP_RANGE, k)
+}
+
+fn get_num_bits_per_theta_c_lookup(k: u32) -> usize {
+    get_num_bits_per_lookup(THETA_C_LOOKUP_RANGE, k)
+}
+
+fn get_num_bits_per_rho_pi_lookup(k: u32) -> usize {
+    get_num_bits_per_lookup(CHI_BASE_LOOKUP_RANGE.max(RHO_PI_LOOKUP_RANGE), k)
+}
+
+fn get_num_bits_per_base_chi_lookup(k: u32) -> usize {
+    get_num_bits_per_lookup(CHI_BASE_LOOKUP_RANGE.max(RHO_PI_LOOKUP_RANGE), k)
+}
+
+/// The number of keccak_f's that fit in a circuit of `num_rows` rows, `2^k` for a circuit of
+/// shape `KeccakConfigParams`, with `rows_per_round` rows per round. Fails with `TooFewRows` if
+/// there is no room for the unusable keccak_f's, or if `rows_per_round` is zero.
+pub fn get_keccak_capacity(num_rows: usize, rows_per_round: usize) -> Result<usize, KeccakError> {
+    // Subtract two for unusable rows
+    rows_per_round
+        .checked_mul(NUM_ROUNDS + 1)
+        .and_then(|rows_per_f| num_rows.checked_div(rows_per_f))
+        .and_then(|num_f| num_f.checked_sub(2))
+        .ok_or(KeccakError::TooFewRows { num_rows, rows_per_round })
 }
 
 pub fn get_num_keccak_f(byte_length: usize) -> usize {
@@ -69,6 +219,53 @@ pub fn get_num_keccak_f(byte_length: usize) -> usize {
     byte_length / RATE + 1
 }
 
+/// The number of keccak_f's hashing each of `bytes` and squeezing `output_lens` bytes from it
+pub fn get_num_keccak_xof_f(bytes: &[Vec<u8>], output_lens: &[usize]) -> usize {
+    bytes
+        .iter()
+        .zip(output_lens)
+        .map(|(bytes, &output_len)| get_num_keccak_f(bytes.len()) + get_num_squeeze_f(output_len))
+        .sum()
+}
+
+/// The number of keccak_f's that only squeeze output, after the input is absorbed, to squeeze
+/// `output_len` bytes
+pub fn get_num_squeeze_f(output_len: usize) -> usize {
+    // ceil( output_len / RATE ) - 1
+    output_len.saturating_sub(1) / RATE
+}
+
+/// `bytes` with keccak256 padding, a whole number of `RATE` byte blocks
+fn pad(bytes: &[u8]) -> Vec<u8> {
+    let mut padded = bytes.to_vec();
+    padded.push(1);
+    padded.resize(get_num_keccak_f(bytes.len()) * RATE, 0);
+    *padded.last_mut().unwrap() |= 128;
+    padded
+}
+
+/// The first `output_len` bytes squeezed from the keccak sponge (with keccak256 padding) after
+/// absorbing `bytes`. The first 32 bytes are `keccak(bytes)`.
+pub fn keccak_xof(bytes: &[u8], output_len: usize) -> Vec<u8> {
+    let mut state = [0u64; 25];
+    for block in pad(bytes).chunks(RATE) {
+        for (lane, word) in state.iter_mut().zip(block.chunks(NUM_BYTES_PER_WORD)) {
+            *lane ^= u64::from_le_bytes(word.try_into().unwrap());
+        }
+        keccakf(&mut state);
+    }
+    let mut output = Vec::with_capacity(output_len + RATE);
+    loop {
+        output.extend(state[..NUM_WORDS_TO_ABSORB].iter().flat_map(|lane| lane.to_le_bytes()));
+        if output.len() >= output_len {
+            break;
+        }
+        keccakf(&mut state);
+    }
+    output.truncate(output_len);
+    output
+}
+
 /// AbsorbData
 #[derive(Clone, Default, Debug, PartialEq)]
 pub(crate) struct AbsorbData<F: FieldExt> {
@@ -95,9 +292,18 @@ pub struct KeccakRow<F: FieldExt> {
     q_padding_last: bool,
     round_cst: F,
     is_final: bool,
+    // Whether this keccak_f only squeezes more output of the previous one
+    is_squeeze: bool,
     cell_values: Vec<F>,
-    // We have no need for length as RLC equality checks length implicitly
-    // length: usize,
+    // Number of input bytes absorbed so far for the current hash
+    length: usize,
+    // Padded input word absorbed on this row
+    input_word: F,
+    // Big-endian halves of the hash, only on the last row of a hash
+    output_hi: F,
+    output_lo: F,
+    // Little-endian state word squeezed on this row
+    output_word: F,
     // SecondPhase values will be assigned separately
     // data_rlc: Value<F>,
     // hash_rlc: Value<F>,
@@ -116,7 +322,13 @@ impl<F: FieldExt> KeccakRow<F> {
                 q_padding_last: false,
                 round_cst: F::zero(),
                 is_final: false,
+                is_squeeze: false,
                 cell_values: Vec::new(),
+                length: 0,
+                input_word: F::zero(),
+                output_hi: F::zero(),
+                output_lo: F::zero(),
+                output_word: F::zero(),
             })
             .collect()
     }
@@ -361,24 +573,53 @@ pub struct KeccakTable {
     pub is_enabled: Column<Advice>,
     /// Byte array input as `RLC(reversed(input))`
     pub input_rlc: Column<Advice>, // RLC of input bytes
-    // Byte array input length
-    // pub input_len: Column<Advice>,
+    /// Byte array input length
+    pub input_len: Column<Advice>,
+    /// Little-endian word of the padded input absorbed on the row
+    pub input_word: Column<Advice>,
     /// RLC of the hash result
     pub output_rlc: Column<Advice>, // RLC of hash of input bytes
+    /// High 128 bits of the hash result as a big-endian integer, zero unless `is_enabled`
+    pub output_hi: Column<Advice>,
+    /// Low 128 bits of the hash result as a big-endian integer, zero unless `is_enabled`
+    pub output_lo: Column<Advice>,
+    /// Little-endian word of the state squeezed on the row, on the rows of the last
+    /// `NUM_WORDS_TO_ABSORB` rounds of each keccak_f
+    pub output_word: Column<Advice>,
 }
 
 impl KeccakTable {
     /// Construct a new KeccakTable
     pub fn construct<F: Field>(meta: &mut ConstraintSystem<F>) -> Self {
         let input_rlc = meta.advice_column_in(SecondPhase);
+        let input_len = meta.advice_column();
+        let input_word = meta.advice_column();
         let output_rlc = meta.advice_column_in(SecondPhase);
-        meta.enable_equality(input_rlc);
-        meta.enable_equality(output_rlc);
+        let output_hi = meta.advice_column();
+        let output_lo = meta.advice_column();
+        let output_word = meta.advice_column();
+        let is_enabled = meta.advice_column();
+        for column in [
+            is_enabled,
+            input_rlc,
+            input_len,
+            input_word,
+            output_rlc,
+            output_hi,
+            output_lo,
+            output_word,
+        ] {
+            meta.enable_equality(column);
+        }
         Self {
-            is_enabled: meta.advice_column(),
+            is_enabled,
             input_rlc,
-            // input_len: meta.advice_column(),
+            input_len,
+            input_word,
             output_rlc,
+            output_hi,
+            output_lo,
+            output_word,
         }
     }
 }
@@ -406,6 +647,183 @@ pub fn assign_advice_custom<'v, F: Field>(
     }
 }
 
+/// Table cells of one keccak_f, assigned in `FirstPhase` by `KeccakCircuitConfig::assign`.
+#[derive(Clone, Debug)]
+pub struct KeccakAssignedRow<'v, F: Field> {
+    /// Number of input bytes absorbed so far, the full input length on the last keccak_f of a hash
+    pub length: KeccakAssignedValue<'v, F>,
+    /// The `NUM_WORDS_TO_ABSORB` words of padded input absorbed by this keccak_f
+    pub input_words: Vec<KeccakAssignedValue<'v, F>>,
+    /// High 128 bits of the hash, zero unless this is the last keccak_f of a hash
+    pub output_hi: KeccakAssignedValue<'v, F>,
+    /// Low 128 bits of the hash, zero unless this is the last keccak_f of a hash
+    pub output_lo: KeccakAssignedValue<'v, F>,
+    /// The `NUM_WORDS_TO_ABSORB` little-endian words of the state squeezed after this keccak_f
+    pub output_words: Vec<KeccakAssignedValue<'v, F>>,
+    /// Whether this keccak_f ends a hash
+    pub is_final: KeccakAssignedValue<'v, F>,
+    /// Whether this keccak_f only squeezes more output of the previous one
+    pub is_squeeze: KeccakAssignedValue<'v, F>,
+}
+
+/// The public inputs of `inputs` constrained by `constrain_keccak_instance`: for each input, its
+/// hash as a big-endian integer split in 128-bit halves (high first), then, if `expose_inputs`,
+/// the little-endian words of the padded input.
+pub fn keccak_instance<F: Field>(inputs: &[Vec<u8>], expose_inputs: bool) -> Vec<F> {
+    let mut instance = Vec::new();
+    for input in inputs {
+        let hash = keccak(input);
+        let (hi, lo) = hash.as_bytes().split_at(16);
+        instance.extend(
+            [hi, lo].map(|half| F::from_u128(u128::from_be_bytes(half.try_into().unwrap()))),
+        );
+        if expose_inputs {
+            instance.extend(
+                pad(input)
+                    .chunks(NUM_BYTES_PER_WORD)
+                    .map(|word| F::from(u64::from_le_bytes(word.try_into().unwrap()))),
+            );
+        }
+    }
+    instance
+}
+
+/// Constrains the hash of each of `inputs`, and if `expose_inputs` its padded input, to
+/// `instance` in the layout of `keccak_instance`. `assigned_rows` are the cells returned by
+/// `KeccakCircuitConfig::assign` for `inputs`.
+///
+/// The hash cells are zero unless the keccak_f ends a hash, and that keccak_f is constrained to
+/// absorb input, so a prover cannot expose an intermediate or squeezed state instead of the hash.
+pub fn constrain_keccak_instance<F: Field>(
+    layouter: &mut impl Layouter<F>,
+    instance: Column<Instance>,
+    inputs: &[Vec<u8>],
+    assigned_rows: &[KeccakAssignedRow<F>],
+    expose_inputs: bool,
+) -> Result<(), Error> {
+    let mut assigned_rows = assigned_rows.iter();
+    let mut row = 0;
+    let mut flags = Vec::new();
+    for input in inputs {
+        let keccak_fs = assigned_rows.by_ref().take(get_num_keccak_f(input.len())).collect_vec();
+        let last = keccak_fs.last().ok_or(Error::BoundsFailure)?;
+        flags.push((last.is_squeeze.cell(), false));
+        let mut cells = vec![last.output_hi.cell(), last.output_lo.cell()];
+        if expose_inputs {
+            cells.extend(keccak_fs.iter().flat_map(|f| f.input_words.iter().map(|w| w.cell())));
+        }
+        for cell in cells {
+            layouter.constrain_instance(cell, instance, row)?;
+            row += 1;
+        }
+    }
+    constrain_flags(layouter, flags)
+}
+
+/// The public inputs of `inputs` constrained by `constrain_keccak_xof_instance`: for each input,
+/// the first `output_len` bytes squeezed from the sponge after absorbing it (see `keccak_xof`),
+/// as little-endian words and rounded up to whole words, then, if `expose_inputs`, the
+/// little-endian words of the padded input.
+pub fn keccak_xof_instance<F: Field>(
+    inputs: &[Vec<u8>],
+    output_lens: &[usize],
+    expose_inputs: bool,
+) -> Vec<F> {
+    assert_eq!(inputs.len(), output_lens.len());
+    let mut instance = Vec::new();
+    for (input, &output_len) in inputs.iter().zip(output_lens) {
+        let num_words = (output_len + NUM_BYTES_PER_WORD - 1) / NUM_BYTES_PER_WORD;
+        let output = keccak_xof(input, num_words * NUM_BYTES_PER_WORD);
+        instance.extend(
+            output
+                .chunks(NUM_BYTES_PER_WORD)
+                .map(|word| F::from(u64::from_le_bytes(word.try_into().unwrap()))),
+        );
+        if expose_inputs {
+            instance.extend(
+                pad(input)
+                    .chunks(NUM_BYTES_PER_WORD)
+                    .map(|word| F::from(u64::from_le_bytes(word.try_into().unwrap()))),
+            );
+        }
+    }
+    instance
+}
+
+/// Constrains the first `output_len` bytes squeezed for each of `inputs`, and if `expose_inputs`
+/// its padded input, to `instance` in the layout of `keccak_xof_instance`. `assigned_rows` are
+/// the cells returned by `KeccakCircuitConfig::assign` for `inputs` and `output_lens`.
+///
+/// The output is read from the keccak_f that absorbs the last input block followed by the
+/// keccak_f's that only squeeze, and these are constrained to form a single hash, so a prover
+/// cannot splice the outputs of different hashes together.
+pub fn constrain_keccak_xof_instance<F: Field>(
+    layouter: &mut impl Layouter<F>,
+    instance: Column<Instance>,
+    inputs: &[Vec<u8>],
+    output_lens: &[usize],
+    assigned_rows: &[KeccakAssignedRow<F>],
+    expose_inputs: bool,
+) -> Result<(), Error> {
+    assert_eq!(inputs.len(), output_lens.len());
+    let mut assigned_rows = assigned_rows.iter();
+    let mut row = 0;
+    let mut flags = Vec::new();
+    for (input, &output_len) in inputs.iter().zip(output_lens) {
+        let num_absorb_f = get_num_keccak_f(input.len());
+        let keccak_fs =
+            assigned_rows.by_ref().take(num_absorb_f + get_num_squeeze_f(output_len)).collect_vec();
+        let output_fs = keccak_fs.get(num_absorb_f - 1..).ok_or(Error::BoundsFailure)?;
+        // The first output comes from the last absorbing keccak_f, the others only squeeze, and
+        // the last one ends the hash
+        for (idx, f) in output_fs.iter().enumerate() {
+            flags.push((f.is_squeeze.cell(), idx != 0));
+        }
+        flags.push((output_fs.last().ok_or(Error::BoundsFailure)?.is_final.cell(), true));
+
+        let num_words = (output_len + NUM_BYTES_PER_WORD - 1) / NUM_BYTES_PER_WORD;
+        let mut cells = output_fs
+            .iter()
+            .flat_map(|f| f.output_words.iter().map(|w| w.cell()))
+            .take(num_words)
+            .collect_vec();
+        if expose_inputs {
+            cells.extend(
+                keccak_fs[..num_absorb_f]
+                    .iter()
+                    .flat_map(|f| f.input_words.iter().map(|w| w.cell())),
+            );
+        }
+        for cell in cells {
+            layouter.constrain_instance(cell, instance, row)?;
+            row += 1;
+        }
+    }
+    constrain_flags(layouter, flags)
+}
+
+/// Constrains each of the boolean `flags` cells to its constant value.
+fn constrain_flags<F: Field>(
+    layouter: &mut impl Layouter<F>,
+    flags: Vec<(CircuitCell, bool)>,
+) -> Result<(), Error> {
+    layouter.assign_region(
+        || "keccak flags",
+        |mut region| {
+            for (cell, value) in flags.iter() {
+                region.constrain_constant(*cell, F::from(*value))?;
+            }
+            Ok(())
+        },
+    )
+}
+
+/// The hash squeezed from `hash_words` as a big-endian integer, split in 128-bit halves.
+fn digest_halves<F: Field>(hash_words: &[F; NUM_WORDS_TO_SQUEEZE]) -> [u128; 2] {
+    let bytes = hash_words.iter().flat_map(|a| to_bytes::value(&unpack(*a))).collect_vec();
+    [&bytes[..16], &bytes[16..]].map(|half| u128::from_be_bytes(half.try_into().unwrap()))
+}
+
 pub fn assign_fixed_custom<F: Field>(
     region: &mut Region<F>,
     column: Column<Fixed>,
@@ -826,6 +1244,7 @@ pub struct KeccakCircuitConfig<F> {
     q_round_last: Column<Fixed>,
     q_padding: Column<Fixed>,
     q_padding_last: Column<Fixed>,
+    is_squeeze: Column<Advice>,
 
     pub keccak_table: KeccakTable,
 
@@ -836,6 +1255,7 @@ pub struct KeccakCircuitConfig<F> {
     normalize_6: [TableColumn; 2],
     chi_base_table: [TableColumn; 2],
     pack_table: [TableColumn; 2],
+    pub parameters: KeccakConfigParams,
     _marker: PhantomData<F>,
 }
 
@@ -844,7 +1264,11 @@ impl<F: Field> KeccakCircuitConfig<F> {
         self.challenge
     }
     /// Return a new KeccakCircuitConfig
-    pub fn new(meta: &mut ConstraintSystem<F>, challenge: Challenge) -> Self {
+    pub fn new(
+        meta: &mut ConstraintSystem<F>,
+        challenge: Challenge,
+        parameters: KeccakConfigParams,
+    ) -> Self {
         let q_enable = meta.fixed_column();
         // let q_enable_row = meta.fixed_column();
         let q_first = meta.fixed_column();
@@ -855,11 +1279,20 @@ impl<F: Field> KeccakCircuitConfig<F> {
         let q_padding_last = meta.fixed_column();
         let round_cst = meta.fixed_column();
         let keccak_table = KeccakTable::construct(meta);
+        let is_squeeze = meta.advice_column();
+        meta.enable_equality(is_squeeze);
+        // Used to constrain the flags of the keccak_f's exposed as public inputs
+        let constants = meta.fixed_column();
+        meta.enable_constant(constants);
 
         let is_final = keccak_table.is_enabled;
-        // let length = keccak_table.input_len;
+        let length = keccak_table.input_len;
         let data_rlc = keccak_table.input_rlc;
+        let input_word = keccak_table.input_word;
         let hash_rlc = keccak_table.output_rlc;
+        let hash_hi = keccak_table.output_hi;
+        let hash_lo = keccak_table.output_lo;
+        let output_word = keccak_table.output_word;
 
         let normalize_3 = array_init::array_init(|_| meta.lookup_table_column());
         let normalize_4 = array_init::array_init(|_| meta.lookup_table_column());
@@ -867,8 +1300,9 @@ impl<F: Field> KeccakCircuitConfig<F> {
         let chi_base_table = array_init::array_init(|_| meta.lookup_table_column());
         let pack_table = array_init::array_init(|_| meta.lookup_table_column());
 
-        let num_rows_per_round = get_num_rows_per_round();
-        let mut cell_manager = CellManager::new(get_num_rows_per_round());
+        let k = parameters.k;
+        let num_rows_per_round = parameters.rows_per_round;
+        let mut cell_manager = CellManager::new(num_rows_per_round);
         let mut cb = BaseConstraintBuilder::new(MAX_DEGREE);
         let mut total_lookup_counter = 0;
 
@@ -919,7 +1353,7 @@ impl<F: Field> KeccakCircuitConfig<F> {
         // rlc.
         cell_manager.start_region();
         let mut lookup_counter = 0;
-        let part_size = get_num_bits_per_absorb_lookup();
+        let part_size = get_num_bits_per_absorb_lookup(k);
         let input = absorb_from.expr() + absorb_data.expr();
         let absorb_fat =
             split::expr(meta, &mut cell_manager, &mut cb, input, 0, part_size, false, None);
@@ -978,7 +1412,7 @@ impl<F: Field> KeccakCircuitConfig<F> {
         // that allows us to also calculate the rotated value "for free".
         cell_manager.start_region();
         let mut lookup_counter = 0;
-        let part_size_c = get_num_bits_per_theta_c_lookup();
+        let part_size_c = get_num_bits_per_theta_c_lookup(k);
         let mut c_parts = Vec::new();
         for s in s.iter() {
             // Calculate c and split into parts
@@ -1038,7 +1472,7 @@ impl<F: Field> KeccakCircuitConfig<F> {
         // `s[j][2 * i + 3 * j) % 5] = normalize(rot(s[i][j], RHOM[i][j]))`.
         cell_manager.start_region();
         let mut lookup_counter = 0;
-        let part_size = get_num_bits_per_base_chi_lookup();
+        let part_size = get_num_bits_per_base_chi_lookup(k);
         // To combine the rho/pi/chi steps we have to ensure a specific layout so
         // query those cells here first.
         // For chi we have to do `s[i][j] ^ ((~s[(i+1)%5][j]) & s[(i+2)%5][j])`. `j`
@@ -1123,7 +1557,7 @@ impl<F: Field> KeccakCircuitConfig<F> {
         // s[(i+2)%5][j])` five times, on each row (no selector needed).
         // This is calculated by making use of `CHI_BASE_LOOKUP_TABLE`.
         let mut lookup_counter = 0;
-        let part_size_base = get_num_bits_per_base_chi_lookup();
+        let part_size_base = get_num_bits_per_base_chi_lookup(k);
         for idx in 0..num_columns {
             // First fetch the cells we wan to use
             let mut input: [Expression<F>; 5] = array_init::array_init(|_| 0.expr());
@@ -1165,7 +1599,7 @@ impl<F: Field> KeccakCircuitConfig<F> {
         // iota
         // Simply do the single xor on state [0][0].
         cell_manager.start_region();
-        let part_size = get_num_bits_per_absorb_lookup();
+        let part_size = get_num_bits_per_absorb_lookup(k);
         let input = s[0][0].clone() + round_cst_expr.clone();
         let iota_parts =
             split::expr(meta, &mut cell_manager, &mut cb, input, 0, part_size, false, None);
@@ -1197,14 +1631,14 @@ impl<F: Field> KeccakCircuitConfig<F> {
 
         // Squeeze data
         let squeeze_from = cell_manager.query_cell(meta);
-        let mut squeeze_from_prev = vec![0u64.expr(); NUM_WORDS_TO_SQUEEZE];
+        let mut squeeze_from_prev = vec![0u64.expr(); NUM_WORDS_TO_ABSORB];
         for (idx, squeeze_from_prev) in squeeze_from_prev.iter_mut().enumerate() {
             let rot = (-(idx as i32) - 1) * num_rows_per_round as i32;
             *squeeze_from_prev = squeeze_from.at_offset(meta, rot).expr();
         }
         // Squeeze
         // The squeeze happening at the end of the 24 rounds is done spread out
-        // over those 24 rounds. In a single round (in 4 of the 24 rounds) a
+        // over those 24 rounds. In a single round (in the last 17 of the 24 rounds) a
         // single word is converted to bytes.
         // Potential optimization: could do multiple bytes per lookup
         cell_manager.start_region();
@@ -1230,6 +1664,21 @@ impl<F: Field> KeccakCircuitConfig<F> {
         // The round constraints that we've been building up till now
         meta.create_gate("round", |meta| cb.gate(meta.query_fixed(q_round, Rotation::cur())));
 
+        // The squeezed word as a little-endian integer
+        meta.create_gate("output word", |meta| {
+            let mut cb = BaseConstraintBuilder::new(MAX_DEGREE);
+            let word = squeeze_bytes
+                .iter()
+                .rev()
+                .fold(0.expr(), |acc, byte| acc * 256.expr() + byte.expr.clone());
+            cb.require_equal(
+                "output word check",
+                word,
+                meta.query_advice(output_word, Rotation::cur()),
+            );
+            cb.gate(meta.query_fixed(q_round, Rotation::cur()))
+        });
+
         // Absorb
         meta.create_gate("absorb", |meta| {
             let mut cb = BaseConstraintBuilder::new(MAX_DEGREE);
@@ -1280,19 +1729,28 @@ impl<F: Field> KeccakCircuitConfig<F> {
         // Squeeze
         meta.create_gate("squeeze", |meta| {
             let mut cb = BaseConstraintBuilder::new(MAX_DEGREE);
-            let start_new_hash = start_new_hash(meta, Rotation::cur());
-            // The words to squeeze
-            let hash_words: Vec<_> =
-                pre_s.into_iter().take(4).map(|a| a[0].clone()).take(4).collect();
+            // The words to squeeze: all the words of the rate, as any keccak_f can be followed by
+            // keccak_f's that squeeze more output
+            let squeeze_words =
+                get_absorb_positions().into_iter().map(|(i, j)| pre_s[i][j].clone());
             // Verify if we converted the correct words to bytes on previous rows
-            for (idx, word) in hash_words.iter().enumerate() {
-                cb.condition(start_new_hash.clone(), |cb| {
-                    cb.require_equal(
-                        "squeeze verify packed",
-                        word.clone(),
-                        squeeze_from_prev[idx].clone(),
-                    );
-                });
+            for (word, squeeze_from_prev) in squeeze_words.zip(squeeze_from_prev.iter()) {
+                cb.require_equal("squeeze verify packed", word, squeeze_from_prev.clone());
+            }
+
+            // The hash as a big-endian integer, split in two halves of 128 bits, is only exposed
+            // once the hash is done
+            let is_final = meta.query_advice(is_final, Rotation::cur());
+            let (hi_bytes, lo_bytes) = hash_bytes.split_at(hash_bytes.len() / 2);
+            for (name, column, bytes) in
+                [("hash hi check", hash_hi, hi_bytes), ("hash lo check", hash_lo, lo_bytes)]
+            {
+                let value = bytes.iter().fold(0.expr(), |acc, byte| acc * 256.expr() + byte.clone());
+                cb.require_equal(
+                    name,
+                    value * is_final.clone(),
+                    meta.query_advice(column, Rotation::cur()),
+                );
             }
 
             let challenge_expr = meta.query_challenge(challenge);
@@ -1306,9 +1764,33 @@ impl<F: Field> KeccakCircuitConfig<F> {
         meta.create_gate("input checks", |meta| {
             let mut cb = BaseConstraintBuilder::new(MAX_DEGREE);
             cb.require_boolean("boolean is_final", meta.query_advice(is_final, Rotation::cur()));
+            cb.require_boolean(
+                "boolean is_squeeze",
+                meta.query_advice(is_squeeze, Rotation::cur()),
+            );
             cb.gate(meta.query_fixed(q_enable, Rotation::cur()))
         });
 
+        // A keccak_f either absorbs input or only squeezes, for all its rounds
+        meta.create_gate("is squeeze", |meta| {
+            let mut cb = BaseConstraintBuilder::new(MAX_DEGREE);
+            // All rounds except the first round of a keccak_f
+            cb.condition(
+                not::expr(meta.query_fixed(q_absorb, Rotation(-(num_rows_per_round as i32)))),
+                |cb| {
+                    cb.require_equal(
+                        "is_squeeze needs to be the same for all rounds",
+                        meta.query_advice(is_squeeze, Rotation::cur()),
+                        meta.query_advice(is_squeeze, Rotation(-(num_rows_per_round as i32))),
+                    );
+                },
+            );
+            cb.gate(
+                meta.query_fixed(q_enable, Rotation::cur())
+                    - meta.query_fixed(q_first, Rotation::cur()),
+            )
+        });
+
         // Enforce fixed values on the first row
         meta.create_gate("first row", |meta| {
             let mut cb = BaseConstraintBuilder::new(MAX_DEGREE);
@@ -1331,10 +1813,18 @@ impl<F: Field> KeccakCircuitConfig<F> {
                 meta.query_fixed(q_absorb, Rotation::cur())
                     - meta.query_fixed(q_first, Rotation::cur()),
                 |cb| {
+                    // The hash is done once the padding is absorbed, unless the next keccak_f
+                    // squeezes more output
+                    let is_squeeze_next =
+                        meta.query_advice(is_squeeze, Rotation(num_rows_per_round as i32));
                     cb.require_equal(
-                        "is_final needs to be the same as the last is_padding in the block",
+                        "is_final needs to be the last is_padding in the block unless squeezing",
                         meta.query_advice(is_final, Rotation::cur()),
-                        last_is_padding_in_block.expr(),
+                        last_is_padding_in_block.expr() - is_squeeze_next.clone(),
+                    );
+                    cb.require_zero(
+                        "only squeeze after all the padding is absorbed",
+                        is_squeeze_next * not::expr(last_is_padding_in_block.expr()),
                     );
                 },
             );
@@ -1363,6 +1853,19 @@ impl<F: Field> KeccakCircuitConfig<F> {
             let q_padding = meta.query_fixed(q_padding, Rotation::cur());
             let q_padding_last = meta.query_fixed(q_padding_last, Rotation::cur());
 
+            // The absorbed word, including padding bytes, as a little-endian integer
+            cb.condition(q_padding.expr(), |cb| {
+                let word = input_bytes
+                    .iter()
+                    .rev()
+                    .fold(0.expr(), |acc, byte| acc * 256.expr() + byte.expr.clone());
+                cb.require_equal(
+                    "input word check",
+                    word,
+                    meta.query_advice(input_word, Rotation::cur()),
+                );
+            });
+
             // All padding selectors need to be boolean
             for is_padding in is_paddings.iter() {
                 cb.condition(meta.query_fixed(q_enable, Rotation::cur()), |cb| {
@@ -1370,13 +1873,21 @@ impl<F: Field> KeccakCircuitConfig<F> {
                 });
             }
             // This last padding selector will be used on the first round row so needs to be
-            // zero
+            // zero, unless the next keccak_f only squeezes and so is all padding
             cb.condition(meta.query_fixed(q_absorb, Rotation::cur()), |cb| {
-                cb.require_zero(
-                    "last is_padding should be zero on absorb rows",
+                cb.require_equal(
+                    "last is_padding should be is_squeeze of the next round on absorb rows",
                     is_paddings.last().unwrap().expr(),
+                    meta.query_advice(is_squeeze, Rotation(num_rows_per_round as i32)),
                 );
             });
+            // A keccak_f that only squeezes absorbs nothing
+            let is_squeeze = meta.query_advice(is_squeeze, Rotation::cur());
+            for is_padding in is_paddings.iter() {
+                cb.condition(and::expr([q_padding.expr(), is_squeeze.expr()]), |cb| {
+                    cb.require_equal("is_padding when squeezing", is_padding.expr(), 1.expr());
+                });
+            }
             // Now for each padding selector
             for idx in 0..is_paddings.len() {
                 // Previous padding selector can be on the previous row
@@ -1413,11 +1924,11 @@ impl<F: Field> KeccakCircuitConfig<F> {
                         and::expr([q_padding_last.expr(), is_paddings[idx].expr()]),
                         |cb| {
                             // The input byte needs to be 128, unless it's also the first padding
-                            // byte then it's 129
+                            // byte then it's 129, or this keccak_f only squeezes then it's 0
                             cb.require_equal(
                                 "padding start/end byte",
                                 input_bytes[idx].expr.clone(),
-                                is_first_padding.expr() + 128.expr(),
+                                is_first_padding.expr() + 128.expr() * not::expr(is_squeeze.expr()),
                             );
                         },
                     );
@@ -1440,15 +1951,26 @@ impl<F: Field> KeccakCircuitConfig<F> {
         // TODO: there is probably a way to only require NUM_BYTES_PER_WORD instead of
         // NUM_BYTES_PER_WORD + 1 rows per round, but for simplicity and to keep the
         // gate degree at 3, we just do the obvious thing for now Input data rlc
-        meta.create_gate("data rlc", |meta| {
+        meta.create_gate("length and data rlc", |meta| {
             let mut cb = BaseConstraintBuilder::new(MAX_DEGREE);
 
             let q_padding = meta.query_fixed(q_padding, Rotation::cur());
             let start_new_hash_prev = start_new_hash(meta, Rotation(-(num_rows_per_round as i32)));
+            let length_prev = meta.query_advice(length, Rotation(-(num_rows_per_round as i32)));
+            let length = meta.query_advice(length, Rotation::cur());
             let data_rlc_prev = meta.query_advice(data_rlc, Rotation(-(num_rows_per_round as i32)));
 
             // Update the length/data_rlc on rows where we absorb data
             cb.condition(q_padding.expr(), |cb| {
+                // Length increases by the number of bytes that aren't padding
+                cb.require_equal(
+                    "update length",
+                    length.clone(),
+                    length_prev.clone() * not::expr(start_new_hash_prev.expr())
+                        + sum::expr(
+                            is_paddings.iter().map(|is_padding| not::expr(is_padding.expr())),
+                        ),
+                );
                 let challenge_expr = meta.query_challenge(challenge);
                 // Use intermediate cells to keep the degree low
                 let mut new_data_rlc =
@@ -1487,6 +2009,7 @@ impl<F: Field> KeccakCircuitConfig<F> {
                     not::expr(q_padding),
                 ]),
                 |cb| {
+                    cb.require_equal("length equality check", length, length_prev);
                     cb.require_equal(
                         "data_rlc equality check",
                         meta.query_advice(data_rlc, Rotation::cur()),
@@ -1526,6 +2049,7 @@ impl<F: Field> KeccakCircuitConfig<F> {
             q_round_last,
             q_padding,
             q_padding_last,
+            is_squeeze,
             keccak_table,
             cell_manager,
             round_cst,
@@ -1534,19 +2058,61 @@ impl<F: Field> KeccakCircuitConfig<F> {
             normalize_6,
             chi_base_table,
             pack_table,
+            parameters,
             _marker: PhantomData,
         }
     }
 }
 
 impl<F: Field> KeccakCircuitConfig<F> {
-    pub fn assign(&self, region: &mut Region<'_, F>, witness: &[KeccakRow<F>]) {
+    /// Assigns the `FirstPhase` witness and returns the table cells of each keccak_f, in the same
+    /// order as the RLCs returned by `multi_keccak_phase1`.
+    pub fn assign<'v>(
+        &self,
+        region: &mut Region<'_, F>,
+        witness: &[KeccakRow<F>],
+    ) -> Vec<KeccakAssignedRow<'v, F>> {
+        let mut assigned_rows = Vec::new();
+        let mut input_words = Vec::with_capacity(NUM_WORDS_TO_ABSORB);
+        let mut round_words = Vec::with_capacity(NUM_ROUNDS);
         for (offset, keccak_row) in witness.iter().enumerate() {
-            self.set_row(region, offset, keccak_row);
+            let [length, input_word, output_hi, output_lo, output_word, is_final, is_squeeze] =
+                self.set_row(region, offset, keccak_row);
+            if keccak_row.q_padding {
+                input_words.push(input_word);
+            }
+            if keccak_row.q_round {
+                round_words.push(output_word);
+            }
+            if keccak_row.q_round_last {
+                // The words are squeezed on the last rounds, the first word on the last round
+                let output_words = std::mem::take(&mut round_words)
+                    .into_iter()
+                    .skip(NUM_ROUNDS - NUM_WORDS_TO_ABSORB)
+                    .rev()
+                    .collect();
+                assigned_rows.push(KeccakAssignedRow {
+                    length,
+                    input_words: std::mem::take(&mut input_words),
+                    output_hi,
+                    output_lo,
+                    output_words,
+                    is_final,
+                    is_squeeze,
+                });
+            }
         }
+        assigned_rows
     }
 
-    pub fn set_row(&self, region: &mut Region<'_, F>, offset: usize, row: &KeccakRow<F>) {
+    /// Assigns a row and returns its `input_len`, `input_word`, `output_hi`, `output_lo`,
+    /// `output_word`, `is_enabled` and `is_squeeze` cells.
+    pub fn set_row<'v>(
+        &self,
+        region: &mut Region<'_, F>,
+        offset: usize,
+        row: &KeccakRow<F>,
+    ) -> [KeccakAssignedValue<'v, F>; 7] {
         // Fixed selectors
         for (_, column, value) in &[
             ("q_enable", self.q_enable, F::from(row.q_enable)),
@@ -1560,13 +2126,6 @@ impl<F: Field> KeccakCircuitConfig<F> {
             assign_fixed_custom(region, *column, offset, *value);
         }
 
-        assign_advice_custom(
-            region,
-            self.keccak_table.is_enabled,
-            offset,
-            Value::known(F::from(row.is_final)),
-        );
-
         // Cell values
         row.cell_values.iter().zip(self.cell_manager.columns()).for_each(|(bit, column)| {
             assign_advice_custom(region, column.advice, offset, Value::known(*bit));
@@ -1574,17 +2133,29 @@ impl<F: Field> KeccakCircuitConfig<F> {
 
         // Round constant
         assign_fixed_custom(region, self.round_cst, offset, row.round_cst);
+
+        [
+            (self.keccak_table.input_len, F::from(row.length as u64)),
+            (self.keccak_table.input_word, row.input_word),
+            (self.keccak_table.output_hi, row.output_hi),
+            (self.keccak_table.output_lo, row.output_lo),
+            (self.keccak_table.output_word, row.output_word),
+            (self.keccak_table.is_enabled, F::from(row.is_final)),
+            (self.is_squeeze, F::from(row.is_squeeze)),
+        ]
+        .map(|(column, value)| assign_advice_custom(region, column, offset, Value::known(value)))
     }
 
     pub fn load_aux_tables(&self, layouter: &mut impl Layouter<F>) -> Result<(), Error> {
-        load_normalize_table(layouter, "normalize_6", &self.normalize_6, 6u64)?;
-        load_normalize_table(layouter, "normalize_4", &self.normalize_4, 4u64)?;
-        load_normalize_table(layouter, "normalize_3", &self.normalize_3, 3u64)?;
+        let k = self.parameters.k;
+        load_normalize_table(layouter, "normalize_6", &self.normalize_6, 6u64, k)?;
+        load_normalize_table(layouter, "normalize_4", &self.normalize_4, 4u64, k)?;
+        load_normalize_table(layouter, "normalize_3", &self.normalize_3, 3u64, k)?;
         load_lookup_table(
             layouter,
             "chi base",
             &self.chi_base_table,
-            get_num_bits_per_base_chi_lookup(),
+            get_num_bits_per_base_chi_lookup(k),
             &CHI_BASE_LOOKUP_TABLE,
         )?;
         load_pack_table(layouter, &self.pack_table)
@@ -1600,9 +2171,34 @@ pub fn keccak_phase1<'v, F: Field>(
     challenge: Value<F>,
     input_rlcs: &mut Vec<KeccakAssignedValue<'v, F>>,
     offset: &mut usize,
+    parameters: KeccakConfigParams,
+) {
+    keccak_xof_phase1(
+        region,
+        keccak_table,
+        bytes,
+        NUM_BYTES_TO_SQUEEZE,
+        challenge,
+        input_rlcs,
+        offset,
+        parameters,
+    );
+}
+
+/// Computes and assigns the input RLC values when squeezing `output_len` bytes, see
+/// `keccak_xof_phase0`.
+pub fn keccak_xof_phase1<'v, F: Field>(
+    region: &mut Region<F>,
+    keccak_table: &KeccakTable,
+    bytes: &[u8],
+    output_len: usize,
+    challenge: Value<F>,
+    input_rlcs: &mut Vec<KeccakAssignedValue<'v, F>>,
+    offset: &mut usize,
+    parameters: KeccakConfigParams,
 ) {
-    let num_chunks = get_num_keccak_f(bytes.len());
-    let num_rows_per_round = get_num_rows_per_round();
+    let num_chunks = get_num_keccak_f(bytes.len()) + get_num_squeeze_f(output_len);
+    let num_rows_per_round = parameters.rows_per_round;
 
     let mut byte_idx = 0;
     let mut data_rlc = Value::known(F::zero());
@@ -1640,12 +2236,27 @@ pub fn keccak_phase0<F: Field>(
     rows: &mut Vec<KeccakRow<F>>,
     squeeze_digests: &mut Vec<[F; NUM_WORDS_TO_SQUEEZE]>,
     bytes: &[u8],
+    parameters: KeccakConfigParams,
 ) {
+    keccak_xof_phase0(rows, squeeze_digests, bytes, NUM_BYTES_TO_SQUEEZE, parameters);
+}
+
+/// Witness generation in `FirstPhase` for squeezing `output_len` bytes from the keccak sponge
+/// after absorbing `bytes`, see `keccak_xof`. The keccak_f's after the input is absorbed only
+/// squeeze.
+pub fn keccak_xof_phase0<F: Field>(
+    rows: &mut Vec<KeccakRow<F>>,
+    squeeze_digests: &mut Vec<[F; NUM_WORDS_TO_SQUEEZE]>,
+    bytes: &[u8],
+    output_len: usize,
+    parameters: KeccakConfigParams,
+) {
+    let k = parameters.k;
     let mut bits = into_bits(bytes);
     let mut s = [[F::zero(); 5]; 5];
     let absorb_positions = get_absorb_positions();
     let num_bytes_in_last_block = bytes.len() % RATE;
-    let num_rows_per_round = get_num_rows_per_round();
+    let num_rows_per_round = parameters.rows_per_round;
     let two = F::from(2u64);
 
     // Padding
@@ -1655,15 +2266,22 @@ pub fn keccak_phase0<F: Field>(
     }
     bits.push(1);
 
-    let chunks = bits.chunks(RATE_IN_BITS);
-    let num_chunks = chunks.len();
+    // The keccak_f's that only squeeze absorb zeros
+    let num_chunks = bits.len() / RATE_IN_BITS;
+    let num_keccak_f = num_chunks + get_num_squeeze_f(output_len);
+    let squeeze_chunk = vec![0u8; RATE_IN_BITS];
+    let chunks = bits.chunks(RATE_IN_BITS).chain(std::iter::repeat(squeeze_chunk.as_slice()));
 
     let mut cell_managers = Vec::with_capacity(NUM_ROUNDS + 1);
     let mut regions = Vec::with_capacity(NUM_ROUNDS + 1);
-    let mut hash_words = [F::zero(); NUM_WORDS_TO_SQUEEZE];
+    let mut squeeze_words = [F::zero(); NUM_WORDS_TO_ABSORB];
+    let mut length = 0;
 
-    for (idx, chunk) in chunks.enumerate() {
-        let is_final_block = idx == num_chunks - 1;
+    for (idx, chunk) in chunks.take(num_keccak_f).enumerate() {
+        let is_last_chunk = idx == num_chunks - 1;
+        let is_squeeze = idx >= num_chunks;
+        let is_squeeze_next = idx + 1 >= num_chunks && idx + 1 < num_keccak_f;
+        let is_final_block = idx == num_keccak_f - 1;
 
         let mut absorb_rows = Vec::new();
         // Absorb
@@ -1677,6 +2295,7 @@ pub fn keccak_phase0<F: Field>(
         // better memory management to clear already allocated Vecs
         cell_managers.clear();
         regions.clear();
+        let mut round_lengths = Vec::with_capacity(NUM_ROUNDS + 1);
 
         for round in 0..NUM_ROUNDS + 1 {
             let mut cell_manager = CellManager::new(num_rows_per_round);
@@ -1705,7 +2324,7 @@ pub fn keccak_phase0<F: Field>(
 
             // Absorb
             cell_manager.start_region();
-            let part_size = get_num_bits_per_absorb_lookup();
+            let part_size = get_num_bits_per_absorb_lookup(k);
             let input = absorb_row.from + absorb_row.absorb;
             let absorb_fat =
                 split::value(&mut cell_manager, &mut region, input, 0, part_size, false, None);
@@ -1735,15 +2354,21 @@ pub fn keccak_phase0<F: Field>(
             if round < NUM_WORDS_TO_ABSORB {
                 for (padding_idx, is_padding) in is_paddings.iter().enumerate() {
                     let byte_idx = round * NUM_BYTES_PER_WORD + padding_idx;
-                    let padding = is_final_block && byte_idx >= num_bytes_in_last_block;
+                    let padding =
+                        is_squeeze || (is_last_chunk && byte_idx >= num_bytes_in_last_block);
                     is_padding.assign(&mut region, 0, F::from(padding));
+                    length += usize::from(!padding);
                 }
+            } else if round == NUM_ROUNDS {
+                // The next keccak_f starts in padding if it only squeezes
+                is_paddings.last().unwrap().assign(&mut region, 0, F::from(is_squeeze_next));
             }
+            round_lengths.push(length);
             cell_manager.start_region();
 
             if round != NUM_ROUNDS {
                 // Theta
-                let part_size = get_num_bits_per_theta_c_lookup();
+                let part_size = get_num_bits_per_theta_c_lookup(k);
                 let mut bcf = Vec::new();
                 for s in &s {
                     let c = s[0] + s[1] + s[2] + s[3] + s[4];
@@ -1777,7 +2402,7 @@ pub fn keccak_phase0<F: Field>(
                 cell_manager.start_region();
 
                 // Rho/Pi
-                let part_size = get_num_bits_per_base_chi_lookup();
+                let part_size = get_num_bits_per_base_chi_lookup(k);
                 let target_word_sizes = target_part_sizes(part_size);
                 let num_word_parts = target_word_sizes.len();
                 let mut rho_pi_chi_cells: [[[Vec<Cell<F>>; 5]; 5]; 3] =
@@ -1826,7 +2451,7 @@ pub fn keccak_phase0<F: Field>(
                 cell_manager.start_region();
 
                 // Chi
-                let part_size_base = get_num_bits_per_base_chi_lookup();
+                let part_size_base = get_num_bits_per_base_chi_lookup(k);
                 let three_packed = pack::<F>(&vec![3u8; part_size_base]);
                 let mut os = [[F::zero(); 5]; 5];
                 for j in 0..5 {
@@ -1858,7 +2483,7 @@ pub fn keccak_phase0<F: Field>(
                 cell_manager.start_region();
 
                 // iota
-                let part_size = get_num_bits_per_absorb_lookup();
+                let part_size = get_num_bits_per_absorb_lookup(k);
                 let input = s[0][0] + pack_u64::<F>(ROUND_CST[round]);
                 let iota_parts = split::value::<F>(
                     &mut cell_manager,
@@ -1880,10 +2505,10 @@ pub fn keccak_phase0<F: Field>(
                 ));
             }
 
-            // The words to squeeze out: this is the hash digest as words with
-            // NUM_BYTES_PER_WORD (=8) bytes each
-            for (hash_word, a) in hash_words.iter_mut().zip(s.iter()) {
-                *hash_word = a[0];
+            // The words to squeeze out: the first NUM_WORDS_TO_SQUEEZE are the hash digest as
+            // words with NUM_BYTES_PER_WORD (=8) bytes each
+            for (squeeze_word, &(i, j)) in squeeze_words.iter_mut().zip(absorb_positions.iter()) {
+                *squeeze_word = s[i][j];
             }
 
             cell_managers.push(cell_manager);
@@ -1892,7 +2517,10 @@ pub fn keccak_phase0<F: Field>(
 
         // Now that we know the state at the end of the rounds, set the squeeze data
         let num_rounds = cell_managers.len();
-        for (idx, word) in hash_words.iter().enumerate() {
+        let mut output_words = [F::zero(); NUM_ROUNDS + 1];
+        for (idx, word) in squeeze_words.iter().enumerate() {
+            output_words[num_rounds - 2 - idx] =
+                F::from(u64::from_le_bytes(to_bytes::value(&unpack(*word)).try_into().unwrap()));
             let cell_manager = &mut cell_managers[num_rounds - 2 - idx];
             let region = &mut regions[num_rounds - 2 - idx];
 
@@ -1905,10 +2533,23 @@ pub fn keccak_phase0<F: Field>(
             cell_manager.start_region();
             transform::value(cell_manager, region, packed, false, |v| *v, true);
         }
+        let hash_words: [F; NUM_WORDS_TO_SQUEEZE] =
+            squeeze_words[..NUM_WORDS_TO_SQUEEZE].try_into().unwrap();
         squeeze_digests.push(hash_words);
+        let (output_hi, output_lo) = if is_final_block {
+            let [hi, lo] = digest_halves(&hash_words);
+            (F::from_u128(hi), F::from_u128(lo))
+        } else {
+            (F::zero(), F::zero())
+        };
 
         for round in 0..NUM_ROUNDS + 1 {
             let round_cst = pack_u64(ROUND_CST[round]);
+            let input_word = if round < NUM_WORDS_TO_ABSORB {
+                pack_with_base(&chunk[round * 64..(round + 1) * 64], 2)
+            } else {
+                F::zero()
+            };
 
             for row_idx in 0..num_rows_per_round {
                 rows.push(KeccakRow {
@@ -1921,7 +2562,13 @@ pub fn keccak_phase0<F: Field>(
                     q_padding_last: row_idx == 0 && round == NUM_WORDS_TO_ABSORB - 1,
                     round_cst,
                     is_final: is_final_block && round == NUM_ROUNDS && row_idx == 0,
+                    is_squeeze,
                     cell_values: regions[round].rows.get(row_idx).unwrap_or(&vec![]).clone(),
+                    length: round_lengths[round],
+                    input_word: if row_idx == 0 { input_word } else { F::zero() },
+                    output_hi: if row_idx == 0 && round == NUM_ROUNDS { output_hi } else { F::zero() },
+                    output_lo: if row_idx == 0 && round == NUM_ROUNDS { output_lo } else { F::zero() },
+                    output_word: if row_idx == 0 { output_words[round] } else { F::zero() },
                 });
                 #[cfg(debug_assertions)]
                 {
@@ -1961,23 +2608,56 @@ pub fn multi_keccak_phase1<'a, 'v, F: Field>(
     bytes: impl IntoIterator<Item = &'a [u8]>,
     challenge: Value<F>,
     squeeze_digests: Vec<[F; NUM_WORDS_TO_SQUEEZE]>,
+    parameters: KeccakConfigParams,
+) -> (Vec<KeccakAssignedValue<'v, F>>, Vec<KeccakAssignedValue<'v, F>>) {
+    let bytes = bytes.into_iter().map(|bytes| (bytes, NUM_BYTES_TO_SQUEEZE));
+    multi_keccak_xof_phase1(region, keccak_table, bytes, challenge, squeeze_digests, parameters)
+}
+
+/// Computes and assigns the input and output RLC values when squeezing `output_len` bytes for
+/// each `(bytes, output_len)`, see `multi_keccak_xof_phase0`. The output RLC of each keccak_f is
+/// the RLC of the first `NUM_BYTES_TO_SQUEEZE` bytes squeezed after it.
+pub fn multi_keccak_xof_phase1<'a, 'v, F: Field>(
+    region: &mut Region<F>,
+    keccak_table: &KeccakTable,
+    inputs: impl IntoIterator<Item = (&'a [u8], usize)>,
+    challenge: Value<F>,
+    squeeze_digests: Vec<[F; NUM_WORDS_TO_SQUEEZE]>,
+    parameters: KeccakConfigParams,
 ) -> (Vec<KeccakAssignedValue<'v, F>>, Vec<KeccakAssignedValue<'v, F>>) {
     let mut input_rlcs = Vec::with_capacity(squeeze_digests.len());
     let mut output_rlcs = Vec::with_capacity(squeeze_digests.len());
 
-    let num_rows_per_round = get_num_rows_per_round();
+    let num_rows_per_round = parameters.rows_per_round;
     for idx in 0..num_rows_per_round {
         [keccak_table.input_rlc, keccak_table.output_rlc]
             .map(|column| assign_advice_custom(region, column, idx, Value::known(F::zero())));
     }
 
     let mut offset = num_rows_per_round;
-    for bytes in bytes {
-        keccak_phase1(region, keccak_table, bytes, challenge, &mut input_rlcs, &mut offset);
+    for (bytes, output_len) in inputs {
+        keccak_xof_phase1(
+            region,
+            keccak_table,
+            bytes,
+            output_len,
+            challenge,
+            &mut input_rlcs,
+            &mut offset,
+            parameters,
+        );
     }
     debug_assert!(input_rlcs.len() <= squeeze_digests.len());
     while input_rlcs.len() < squeeze_digests.len() {
-        keccak_phase1(region, keccak_table, &[], challenge, &mut input_rlcs, &mut offset);
+        keccak_phase1(
+            region,
+            keccak_table,
+            &[],
+            challenge,
+            &mut input_rlcs,
+            &mut offset,
+            parameters,
+        );
     }
 
     offset = num_rows_per_round;
@@ -1997,12 +2677,33 @@ pub fn multi_keccak_phase1<'a, 'v, F: Field>(
     (input_rlcs, output_rlcs)
 }
 
-/// Returns vector of KeccakRow and vector of hash digest outputs.
+/// Returns vector of KeccakRow and vector of hash digest outputs, or `CapacityExceeded` if the
+/// hashes do not fit in `capacity` keccak_f's.
 pub fn multi_keccak_phase0<F: Field>(
     bytes: &[Vec<u8>],
     capacity: Option<usize>,
-) -> (Vec<KeccakRow<F>>, Vec<[F; NUM_WORDS_TO_SQUEEZE]>) {
-    let num_rows_per_round = get_num_rows_per_round();
+    parameters: KeccakConfigParams,
+) -> Result<(Vec<KeccakRow<F>>, Vec<[F; NUM_WORDS_TO_SQUEEZE]>), KeccakError> {
+    multi_keccak_xof_phase0(bytes, &vec![NUM_BYTES_TO_SQUEEZE; bytes.len()], capacity, parameters)
+}
+
+/// Returns vector of KeccakRow and vector of the first `NUM_WORDS_TO_SQUEEZE` words squeezed after
+/// each keccak_f, when squeezing `output_lens[i]` bytes for `bytes[i]`, see `keccak_xof_phase0`.
+/// Returns `CapacityExceeded` if they do not fit in `capacity` keccak_f's.
+pub fn multi_keccak_xof_phase0<F: Field>(
+    bytes: &[Vec<u8>],
+    output_lens: &[usize],
+    capacity: Option<usize>,
+    parameters: KeccakConfigParams,
+) -> Result<(Vec<KeccakRow<F>>, Vec<[F; NUM_WORDS_TO_SQUEEZE]>), KeccakError> {
+    assert_eq!(bytes.len(), output_lens.len());
+    if let Some(capacity) = capacity {
+        let needed = get_num_keccak_xof_f(bytes, output_lens);
+        if needed > capacity {
+            return Err(KeccakError::CapacityExceeded { needed, available: capacity });
+        }
+    }
+    let num_rows_per_round = parameters.rows_per_round;
     let mut rows =
         Vec::with_capacity((1 + capacity.unwrap_or(0) * (NUM_ROUNDS + 1)) * num_rows_per_round);
     // Dummy first row so that the initial data is absorbed
@@ -2011,11 +2712,12 @@ pub fn multi_keccak_phase0<F: Field>(
     // Actual keccaks
     let artifacts = bytes
         .par_iter()
-        .map(|bytes| {
-            let num_keccak_f = get_num_keccak_f(bytes.len());
+        .zip(output_lens.par_iter())
+        .map(|(bytes, &output_len)| {
+            let num_keccak_f = get_num_keccak_f(bytes.len()) + get_num_squeeze_f(output_len);
             let mut squeeze_digests = Vec::with_capacity(num_keccak_f);
             let mut rows = Vec::with_capacity(num_keccak_f * (NUM_ROUNDS + 1) * num_rows_per_round);
-            keccak_phase0(&mut rows, &mut squeeze_digests, bytes);
+            keccak_xof_phase0(&mut rows, &mut squeeze_digests, bytes, output_len, parameters);
             (rows, squeeze_digests)
         })
         .collect::<Vec<_>>();
@@ -2028,13 +2730,10 @@ pub fn multi_keccak_phase0<F: Field>(
 
     if let Some(capacity) = capacity {
         // Pad with no data hashes to the expected capacity
-        while rows.len() < (1 + capacity * (NUM_ROUNDS + 1)) * get_num_rows_per_round() {
-            keccak_phase0(&mut rows, &mut squeeze_digests, &[]);
-        }
-        // Check that we are not over capacity
-        if rows.len() > (1 + capacity * (NUM_ROUNDS + 1)) * get_num_rows_per_round() {
-            panic!("{:?}", Error::BoundsFailure);
+        while rows.len() < (1 + capacity * (NUM_ROUNDS + 1)) * num_rows_per_round {
+            keccak_phase0(&mut rows, &mut squeeze_digests, &[], parameters);
         }
+        debug_assert_eq!(rows.len(), (1 + capacity * (NUM_ROUNDS + 1)) * num_rows_per_round);
     }
-    (rows, squeeze_digests)
+    Ok((rows, squeeze_digests))
 }
@@ -1,189 +1,615 @@
 use std::vec;
 
 use super::*;
+use crate::chip::{KeccakChip, KeccakCircuitBuilder};
+use crate::circuit::{KeccakCircuit, KeccakCircuitProver};
+use crate::planner::{plan, Calibration, Metric, Workload};
 use crate::halo2_proofs::{
     circuit::SimpleFloorPlanner,
     dev::MockProver,
-    halo2curves::bn256::Fr,
-    halo2curves::bn256::{Bn256, G1Affine},
-    plonk::{create_proof, keygen_pk, keygen_vk, verify_proof},
-    plonk::{Circuit, FirstPhase},
-    poly::{
-        commitment::ParamsProver,
-        kzg::{
-            commitment::{KZGCommitmentScheme, ParamsKZG, ParamsVerifierKZG},
-            multiopen::{ProverSHPLONK, VerifierSHPLONK},
-            strategy::SingleStrategy,
-        },
-    },
-    transcript::{
-        Blake2bRead, Blake2bWrite, Challenge255, TranscriptReadBuffer, TranscriptWriterBuffer,
-    },
+    halo2curves::bn256::{Bn256, Fr},
+    plonk::{keygen_pk, keygen_vk, FirstPhase},
+    poly::kzg::commitment::ParamsKZG,
+};
+use halo2_base::gates::{
+    builder::{GateThreadBuilder, RangeWithInstanceCircuitBuilder},
+    RangeChip, RangeInstructions,
 };
+use bench_memory::{PhaseMemory, PhaseTracker, TrackingAllocator};
+use jemallocator::Jemalloc;
 use rand_core::OsRng;
 use log::{debug, info};
 use std::time::{Duration, Instant};
 use serde::{Deserialize, Serialize};
 use std::{
-    env::{set_var, var},
     fs::File,
     io::{BufRead, BufReader, Write},
 };
 
+#[global_allocator]
+static GLOBAL: TrackingAllocator<Jemalloc> = TrackingAllocator::new(Jemalloc);
+
+/// The shape of the test circuits with `2^k` rows
+fn test_params(k: u32) -> KeccakConfigParams {
+    KeccakConfigParams { k, rows_per_round: 28 }
+}
+
+fn verify<F: Field>(k: u32, inputs: Vec<Vec<u8>>, _success: bool) {
+    let params = test_params(k);
+    let circuit = KeccakCircuit::new(params, inputs).unwrap();
+    let prover =
+        params.scope(|| MockProver::<F>::run(k, &circuit, vec![circuit.instance()])).unwrap();
+    assert_eq!(prover.verify(), Ok(()));
+    prover.assert_satisfied();
+}
+
+/// Cmdline: RUST_LOG=info cargo test -- --nocapture packed_multi_keccak_simple
+#[test]
+fn packed_multi_keccak_simple() {
+    let _ = env_logger::builder().is_test(true).try_init();
+    let k = 14;
+    let num_vectors = 1;
+    let vector_length = 136;
+
+    let inputs: Vec<Vec<u8>> = (0..num_vectors)
+        .map(|_| (0u8..vector_length).collect())
+        .collect();
+    verify::<Fr>(k, inputs, true);
+}
+
+/// Cmdline: RUST_LOG=info cargo test -- --nocapture packed_multi_keccak_instance
+#[test]
+fn packed_multi_keccak_instance() {
+    let _ = env_logger::builder().is_test(true).try_init();
+    let k = 14;
+    let inputs: Vec<Vec<u8>> = vec![vec![], (0u8..200).collect(), vec![7; 136]];
+    let params = test_params(k);
+    let circuit = KeccakCircuit::<Fr>::new(params, inputs).unwrap().with_exposed_inputs();
+
+    let instance = circuit.instance();
+    params.scope(|| {
+        let prover = MockProver::run(k, &circuit, vec![instance.clone()]).unwrap();
+        prover.assert_satisfied();
+
+        // A wrong digest or a wrong input is rejected
+        for idx in [0, 3] {
+            let mut wrong_instance = instance.clone();
+            wrong_instance[idx] += Fr::from(1);
+            let prover = MockProver::run(k, &circuit, vec![wrong_instance]).unwrap();
+            assert!(prover.verify().is_err());
+        }
+    });
+}
+
+/// Cmdline: RUST_LOG=info cargo test -- --nocapture packed_multi_keccak_xof
+#[test]
+fn packed_multi_keccak_xof() {
+    let _ = env_logger::builder().is_test(true).try_init();
+    let k = 14;
+    let inputs: Vec<Vec<u8>> = vec![vec![], (0u8..200).collect(), vec![7; 136]];
+    let output_lens = vec![32, 300, 137];
+
+    // The keccak sponge output starts with the keccak256 hash
+    for input in inputs.iter() {
+        assert_eq!(keccak_xof(input, 32), keccak(input).as_bytes());
+    }
+
+    let params = test_params(k);
+    let circuit = KeccakCircuit::<Fr>::new(params, inputs)
+        .unwrap()
+        .with_output_lens(output_lens)
+        .with_exposed_inputs();
+    let instance = circuit.instance();
+    params.scope(|| {
+        let prover = MockProver::run(k, &circuit, vec![instance.clone()]).unwrap();
+        prover.assert_satisfied();
+
+        // A wrong word squeezed by a keccak_f that only squeezes is rejected, the first input
+        // exposes 4 output words and 17 input words
+        let mut wrong_instance = instance;
+        wrong_instance[4 + 17 + 20] += Fr::from(1);
+        let prover = MockProver::run(k, &circuit, vec![wrong_instance]).unwrap();
+        assert!(prover.verify().is_err());
+    });
+}
+
+/// Cmdline: RUST_LOG=info cargo test -- --nocapture packed_multi_keccak_input_len
+#[test]
+fn packed_multi_keccak_input_len() {
+    let _ = env_logger::builder().is_test(true).try_init();
+    let k = 14;
+    // messages sharing an RLC prefix, only differing in leading zero bytes
+    let inputs: Vec<Vec<u8>> =
+        vec![vec![], vec![0], vec![0, 0, 1], vec![1], vec![0; 136], (0u8..200).collect()];
+
+    let (rows, _) = multi_keccak_phase0::<Fr>(&inputs, None, test_params(k)).unwrap();
+    let final_lengths =
+        rows.iter().filter(|row| row.is_final).map(|row| row.length).collect_vec();
+    assert_eq!(final_lengths, inputs.iter().map(|input| input.len()).collect_vec());
+
+    verify::<Fr>(k, inputs.clone(), true);
+
+    // An off-by-one length on the last row of a hash
+    let tampered = TamperedKeccakCircuit {
+        inputs: inputs.clone(),
+        parameters: test_params(k),
+        tamper: |rows| rows.iter_mut().find(|row| row.is_final).unwrap().length += 1,
+    };
+    let prover = test_params(k).scope(|| MockProver::run(k, &tampered, vec![])).unwrap();
+    assert!(prover.verify().is_err());
+
+    // An off-by-one length from the last absorbed word of the first hash on, which only breaks
+    // the update of the length
+    let tampered = TamperedKeccakCircuit {
+        inputs,
+        parameters: test_params(k),
+        tamper: |rows| {
+            let last = rows.iter().position(|row| row.is_final).unwrap();
+            let first = rows[..last].iter().rposition(|row| row.q_padding).unwrap();
+            for row in &mut rows[first..=last] {
+                row.length += 1;
+            }
+        },
+    };
+    let prover = test_params(k).scope(|| MockProver::run(k, &tampered, vec![])).unwrap();
+    assert!(prover.verify().is_err());
+}
 
-/// KeccakCircuit
-#[derive(Default, Clone, Debug)]
-pub struct KeccakCircuit<F: Field> {
+/// A keccak circuit without public inputs whose witness rows are changed by `tamper` before they
+/// are assigned
+#[derive(Clone)]
+struct TamperedKeccakCircuit {
     inputs: Vec<Vec<u8>>,
-    num_rows: Option<usize>,
-    _marker: PhantomData<F>,
+    parameters: KeccakConfigParams,
+    tamper: fn(&mut [KeccakRow<Fr>]),
 }
 
-#[cfg(any(feature = "test", test))]
-impl<F: Field> Circuit<F> for KeccakCircuit<F> {
-    type Config = KeccakCircuitConfig<F>;
+impl Circuit<Fr> for TamperedKeccakCircuit {
+    type Config = KeccakCircuitConfig<Fr>;
     type FloorPlanner = SimpleFloorPlanner;
 
     fn without_witnesses(&self) -> Self {
-        Self::default()
+        self.clone()
     }
 
-    fn configure(meta: &mut ConstraintSystem<F>) -> Self::Config {
-        // MockProver complains if you only have columns in SecondPhase, so let's just make an empty column in FirstPhase
+    fn configure(meta: &mut ConstraintSystem<Fr>) -> Self::Config {
         meta.advice_column();
-
         let challenge = meta.challenge_usable_after(FirstPhase);
-        KeccakCircuitConfig::new(meta, challenge)
+        KeccakCircuitConfig::new(meta, challenge, KeccakConfigParams::configured())
     }
 
     fn synthesize(
         &self,
         config: Self::Config,
-        mut layouter: impl Layouter<F>,
+        mut layouter: impl Layouter<Fr>,
     ) -> Result<(), Error> {
         config.load_aux_tables(&mut layouter)?;
-        let mut challenge = layouter.get_challenge(config.challenge);
+        #[cfg_attr(not(feature = "halo2-axiom"), allow(unused_mut))]
+        let mut challenge = layouter.get_challenge(config.challenge());
         let mut first_pass = true;
         layouter.assign_region(
-            || "keccak circuit",
+            || "tampered keccak circuit",
             |mut region| {
                 if first_pass {
                     first_pass = false;
                     return Ok(());
                 }
-                let (witness, squeeze_digests) = multi_keccak_phase0(&self.inputs, self.capacity());
-                info!("Hi 1");
+                let (mut witness, squeeze_digests) =
+                    multi_keccak_phase0(&self.inputs, None, self.parameters)?;
+                (self.tamper)(&mut witness);
                 config.assign(&mut region, &witness);
 
                 #[cfg(feature = "halo2-axiom")]
                 {
                     region.next_phase();
-                    challenge = region.get_challenge(config.challenge);
+                    challenge = region.get_challenge(config.challenge());
                 }
                 multi_keccak_phase1(
                     &mut region,
                     &config.keccak_table,
-                    self.inputs.iter().map(|v| v.as_slice()),
+                    self.inputs.iter().map(Vec::as_slice),
                     challenge,
                     squeeze_digests,
+                    self.parameters,
                 );
                 Ok(())
             },
-        )?;
+        )
+    }
+}
+
+/// Cmdline: RUST_LOG=info cargo test -- --nocapture packed_multi_keccak_configured
+#[test]
+fn packed_multi_keccak_configured() {
+    let params = test_params(12);
+    params.scope(|| {
+        assert_eq!(KeccakConfigParams::configured(), params);
+        // A scope only holds on its own thread
+        assert!(std::thread::spawn(KeccakConfigParams::configured).join().is_err());
+        // Scopes nest, and the enclosing shape is restored even on panic
+        test_params(13).scope(|| assert_eq!(KeccakConfigParams::configured(), test_params(13)));
+        let panicked = std::panic::catch_unwind(|| test_params(13).scope(|| panic!()));
+        assert!(panicked.is_err());
+        assert_eq!(KeccakConfigParams::configured(), params);
+    });
+    assert!(std::panic::catch_unwind(KeccakConfigParams::configured).is_err());
+
+    // A circuit configured with another shape than its own fails to synthesize
+    let circuit = KeccakCircuit::<Fr>::new(test_params(13), vec![vec![0; 10]]).unwrap();
+    assert!(params.scope(|| MockProver::run(13, &circuit, vec![circuit.instance()])).is_err());
+}
+
+/// Cmdline: RUST_LOG=info cargo test --release -- --nocapture packed_multi_keccak_prover
+#[test]
+fn packed_multi_keccak_prover() {
+    let _ = env_logger::builder().is_test(true).try_init();
+    let k = 12;
+    let params = test_params(k);
+    let inputs: Vec<Vec<u8>> = vec![(0u8..10).collect(), (0u8..200).collect()];
+    let circuit = KeccakCircuit::<Fr>::new(params, inputs).unwrap();
+
+    // The keys only depend on the lengths of the inputs
+    let prover =
+        KeccakCircuitProver::keygen(ParamsKZG::setup(k, OsRng), &circuit.without_witnesses())
+            .unwrap();
+    let (instance, proof) = prover.prove(circuit).unwrap();
+    prover.verify(&instance, &proof).unwrap();
+    let mut wrong_instance = instance;
+    wrong_instance[0] += Fr::from(1);
+    assert!(prover.verify(&wrong_instance, &proof).is_err());
+
+    // but do not hold for inputs of other lengths
+    let other = KeccakCircuit::<Fr>::new(params, vec![vec![0; 10], vec![0; 100]]).unwrap();
+    assert!(matches!(prover.prove(other), Err(KeccakError::KeysMismatch)));
+}
 
-        Ok(())
+/// Cmdline: RUST_LOG=info cargo test -- --nocapture packed_multi_keccak_capacity
+#[test]
+fn packed_multi_keccak_capacity() {
+    let _ = env_logger::builder().is_test(true).try_init();
+    let k = 14;
+    let params = test_params(k);
+    let capacity = params.capacity().unwrap();
+
+    // One keccak_f more than fits
+    let inputs = vec![vec![0u8; RATE * capacity]];
+    let circuit = KeccakCircuit::<Fr>::new(params, inputs.clone()).unwrap();
+    assert!(matches!(
+        circuit.check_capacity(),
+        Err(KeccakError::CapacityExceeded { needed, available })
+            if needed == capacity + 1 && available == capacity
+    ));
+    assert!(matches!(
+        multi_keccak_phase0::<Fr>(&inputs, Some(capacity), params),
+        Err(KeccakError::CapacityExceeded { .. })
+    ));
+    // Synthesis fails instead of panicking
+    assert!(params.scope(|| MockProver::run(k, &circuit, vec![circuit.instance()])).is_err());
+
+    // No room for the unusable keccak_f's
+    let tiny = KeccakConfigParams { k: 10, rows_per_round: 28 };
+    assert!(matches!(tiny.capacity(), Err(KeccakError::NoCapacity(p)) if p == tiny));
+    assert!(KeccakCircuit::<Fr>::new(tiny, vec![]).is_err());
+    // Degenerate shapes
+    for shape in [
+        KeccakConfigParams { k: 14, rows_per_round: 0 },
+        KeccakConfigParams { k: usize::BITS, rows_per_round: 28 },
+    ] {
+        assert!(matches!(shape.capacity(), Err(KeccakError::NoCapacity(p)) if p == shape));
     }
+    assert!(matches!(
+        get_keccak_capacity(1 << 14, 0),
+        Err(KeccakError::TooFewRows { num_rows: 16384, rows_per_round: 0 })
+    ));
+    assert_eq!(get_keccak_capacity(1 << 14, 28).unwrap(), test_params(14).capacity().unwrap());
+}
+
+/// Cmdline: RUST_LOG=info cargo test -- --nocapture packed_multi_keccak_shapes
+#[test]
+fn packed_multi_keccak_shapes() {
+    let _ = env_logger::builder().is_test(true).try_init();
+    let inputs: Vec<Vec<u8>> = vec![(0u8..200).collect()];
+
+    // Differently shaped circuits in parallel
+    std::thread::scope(|scope| {
+        for (k, rows_per_round) in [(14, 28), (15, 12)] {
+            let inputs = inputs.clone();
+            scope.spawn(move || {
+                let params = KeccakConfigParams { k, rows_per_round };
+                let circuit = KeccakCircuit::<Fr>::new(params, inputs).unwrap();
+                let prover = params
+                    .scope(|| MockProver::run(k, &circuit, vec![circuit.instance()]))
+                    .unwrap();
+                prover.assert_satisfied();
+            });
+        }
+    });
+
+    // The shape is read back with the verifying key
+    let k = 14;
+    let params = test_params(k);
+    let kzg_params = ParamsKZG::<Bn256>::setup(k, OsRng);
+    let circuit = KeccakCircuit::<Fr>::new(params, inputs).unwrap();
+    let vk = params.scope(|| keygen_vk(&kzg_params, &circuit)).unwrap();
+    let mut bytes = vec![];
+    params.write_vk(&mut bytes, &vk, SerdeFormat::RawBytes).unwrap();
+    let (read_params, read_vk) =
+        KeccakConfigParams::read_vk::<_, KeccakCircuit<Fr>>(&mut &bytes[..], SerdeFormat::RawBytes)
+            .unwrap();
+    assert_eq!(read_params, params);
+    assert_eq!(read_vk.transcript_repr(), vk.transcript_repr());
 }
 
-impl<F: Field> KeccakCircuit<F> {
-    /// Creates a new circuit instance
-    pub fn new(num_rows: Option<usize>, inputs: Vec<Vec<u8>>) -> Self {
-        KeccakCircuit { inputs, num_rows, _marker: PhantomData }
+/// Cmdline: RUST_LOG=info cargo test -- --nocapture packed_multi_keccak_chip
+#[test]
+fn packed_multi_keccak_chip() {
+    let _ = env_logger::builder().is_test(true).try_init();
+    let k = 14;
+    let lookup_bits = 8;
+    std::env::set_var("LOOKUP_BITS", lookup_bits.to_string());
+    let keccak_params = test_params(k);
+    let inputs: Vec<Vec<u8>> = vec![vec![], (0u8..200).collect(), vec![7; 135]];
+
+    let range = RangeChip::<Fr>::default(lookup_bits);
+    let mut keccak = KeccakChip::default();
+    let mut builder = GateThreadBuilder::mock();
+    let ctx = builder.main(0);
+    let mut hashes = vec![];
+    for input in inputs.iter() {
+        let input = ctx.assign_witnesses(input.iter().map(|&byte| Fr::from(byte as u64)));
+        for byte in input.iter() {
+            range.range_check(ctx, *byte, 8);
+        }
+        hashes.push(keccak.keccak_fixed_len(ctx, &range, input));
     }
+    assert_eq!(keccak.num_keccak_f(), 4);
+
+    // The hash bytes are ordinary cells of the halo2-base circuit, exposed here
+    let assigned_instances = hashes.iter().flat_map(|hash| hash.value().clone()).collect_vec();
+    let instance = inputs
+        .iter()
+        .flat_map(|input| keccak(input).as_bytes().to_vec())
+        .map(|byte| Fr::from(byte as u64))
+        .collect_vec();
+    let circuit = KeccakCircuitBuilder::new(
+        RangeWithInstanceCircuitBuilder::mock(builder, assigned_instances),
+        keccak,
+        keccak_params,
+    );
+    circuit.config(Some(20));
+    keccak_params.scope(|| {
+        let prover = MockProver::run(k, &circuit, vec![instance.clone()]).unwrap();
+        prover.assert_satisfied();
+
+        let mut wrong_instance = instance;
+        wrong_instance[0] += Fr::from(1);
+        let prover = MockProver::run(k, &circuit, vec![wrong_instance]).unwrap();
+        assert!(prover.verify().is_err());
+    });
+}
+
+/// Cmdline: RUST_LOG=info cargo test -- --nocapture packed_multi_keccak_chip_multi_block
+#[test]
+fn packed_multi_keccak_chip_multi_block() {
+    let _ = env_logger::builder().is_test(true).try_init();
+    let k = 14;
+    let lookup_bits = 8;
+    std::env::set_var("LOOKUP_BITS", lookup_bits.to_string());
+    let keccak_params = test_params(k);
+    // Inputs of whole blocks, whose last keccak_f only absorbs padding, and of several blocks
+    let inputs: Vec<Vec<u8>> =
+        vec![vec![1; RATE], (0..=255u8).cycle().take(3 * RATE + 7).collect(), vec![0; 2 * RATE]];
 
-    /// The number of keccak_f's that can be done in this circuit
-    pub fn capacity(&self) -> Option<usize> {
-        // Subtract two for unusable rows
-        self.num_rows.map(|num_rows| num_rows / ((NUM_ROUNDS + 1) * get_num_rows_per_round()) - 2)
+    let range = RangeChip::<Fr>::default(lookup_bits);
+    let mut keccak = KeccakChip::default();
+    let mut builder = GateThreadBuilder::mock();
+    let ctx = builder.main(0);
+    let mut hashes = vec![];
+    for input in inputs.iter() {
+        let input = ctx.assign_witnesses(input.iter().map(|&byte| Fr::from(byte as u64)));
+        for byte in input.iter() {
+            range.range_check(ctx, *byte, 8);
+        }
+        hashes.push(keccak.keccak_fixed_len(ctx, &range, input));
     }
+    assert_eq!(keccak.num_keccak_f(), 2 + 4 + 3);
+
+    let assigned_instances = hashes.iter().flat_map(|hash| hash.value().clone()).collect_vec();
+    let instance = inputs
+        .iter()
+        .flat_map(|input| keccak(input).as_bytes().to_vec())
+        .map(|byte| Fr::from(byte as u64))
+        .collect_vec();
+    let circuit = KeccakCircuitBuilder::new(
+        RangeWithInstanceCircuitBuilder::mock(builder, assigned_instances),
+        keccak,
+        keccak_params,
+    );
+    circuit.config(Some(20));
+    keccak_params.scope(|| {
+        let prover = MockProver::run(k, &circuit, vec![instance.clone()]).unwrap();
+        prover.assert_satisfied();
+
+        // A wrong byte of the hash of the input of several blocks is rejected
+        let mut wrong_instance = instance;
+        wrong_instance[32 + 31] += Fr::from(1);
+        let prover = MockProver::run(k, &circuit, vec![wrong_instance]).unwrap();
+        assert!(prover.verify().is_err());
+
+        // The keys can be generated without the witness
+        let params = ParamsKZG::<Bn256>::setup(k, OsRng);
+        keygen_vk(&params, &circuit.without_witnesses()).unwrap();
+    });
 }
 
-fn verify<F: Field>(k: u32, inputs: Vec<Vec<u8>>, _success: bool) {
-    let circuit = KeccakCircuit::new(Some(2usize.pow(k)), inputs);
-    let prover = MockProver::<F>::run(k, &circuit, vec![vec![]]).unwrap();
-    assert_eq!(prover.verify(), Ok(()));
-    prover.assert_satisfied();
+/// Cmdline: RUST_LOG=info cargo test -- --nocapture packed_multi_keccak_planner
+#[test]
+fn packed_multi_keccak_planner() {
+    let _ = env_logger::builder().is_test(true).try_init();
+    let calibration = Calibration {
+        parameters: test_params(14),
+        prove_time: 1.0,
+        proof_size: 10_000,
+        memory: Some(1 << 30),
+    };
+    let workload = Workload::Messages(vec![0, 200, 136 * 10]);
+    assert_eq!(workload.num_keccak_f(), 1 + 2 + 11);
+
+    for metric in [Metric::ProveTime, Metric::ProofSize, Metric::Memory] {
+        let plans = plan(&workload, metric, &calibration, 12..=16, 9..=30);
+        assert!(!plans.is_empty());
+        for plan in plans.iter() {
+            assert!(plan.capacity >= 14);
+            assert_eq!(plan.capacity, plan.parameters.capacity().unwrap());
+            assert!(plan.utilization <= 1.0);
+        }
+        let costs = plans.iter().map(|plan| plan.cost(metric).unwrap()).collect_vec();
+        assert!(costs.windows(2).all(|pair| pair[0] <= pair[1]));
+        info!("{metric}: {:?}", plans[0]);
+    }
+
+    // The calibration shape is predicted to cost what was measured
+    let plans = plan(&workload, Metric::ProveTime, &calibration, 14..=14, 28..=28);
+    assert_eq!(plans.len(), 1);
+    assert!((plans[0].prove_time - 1.0).abs() < 1e-9);
+    assert_eq!(plans[0].memory, Some(1 << 30));
 }
 
-/// Cmdline: KECCAK_ROWS=28 KECCAK_DEGREE=14 RUST_LOG=info cargo test -- --nocapture packed_multi_keccak_simple
+/// Cmdline: RUST_LOG=info cargo test --release --features aggregation -- --nocapture packed_multi_keccak_aggregation
+#[cfg(feature = "aggregation")]
 #[test]
-fn packed_multi_keccak_simple() {
+fn packed_multi_keccak_aggregation() {
+    use crate::aggregation::{accumulator, gen_snark_shplonk, verify_snark, KeccakAggregator, Snark};
+
     let _ = env_logger::builder().is_test(true).try_init();
     let k = 14;
-    let num_vectors = 1;
-    let vector_length = 136;
+    let aggregation_k = 21;
+    let keccak_params = test_params(k);
+    let params = ParamsKZG::<Bn256>::setup(aggregation_k, OsRng);
+    let mut inner_params = params.clone();
+    inner_params.downsize(k);
 
-    let inputs: Vec<Vec<u8>> = (0..num_vectors)
-        .map(|_| (0u8..vector_length).collect())
-        .collect();
-    verify::<Fr>(k, inputs, true);
+    // The copy constraints of the public inputs depend on the input lengths, so each input has
+    // its own keys
+    let inputs: Vec<Vec<u8>> = vec![vec![], (0u8..200).collect(), vec![7; 136]];
+    let snarks: Vec<Snark> = keccak_params.scope(|| {
+        inputs
+            .into_iter()
+            .map(|input| {
+                let circuit = KeccakCircuit::new(keccak_params, vec![input]).unwrap();
+                let vk = keygen_vk(&inner_params, &circuit).unwrap();
+                let pk = keygen_pk(&inner_params, vk, &circuit).unwrap();
+                let snark = gen_snark_shplonk(&inner_params, &pk, circuit, None::<&str>);
+                verify_snark(&inner_params, pk.get_vk(), &snark).unwrap();
+
+                // The digest is bound to the inner proof
+                let mut wrong_snark = snark.clone();
+                wrong_snark.instances[0][0] += Fr::from(1);
+                assert!(verify_snark(&inner_params, pk.get_vk(), &wrong_snark).is_err());
+                snark
+            })
+            .collect()
+    });
+
+    let aggregator =
+        KeccakAggregator::keygen(params, aggregation_k as usize - 1, snarks.clone()).unwrap();
+    let (instance, proof) = aggregator.prove(snarks.clone());
+    aggregator.verify(&instance, &proof).unwrap();
+
+    // The digests of the aggregated proofs follow the accumulator
+    let digests = snarks.iter().flat_map(|snark| snark.instances[0].clone()).collect_vec();
+    assert_eq!(instance[instance.len() - digests.len()..], digests[..]);
+    assert!(accumulator(&instance).is_some());
+
+    // A wrong accumulator or a wrong digest is rejected
+    for idx in [0, instance.len() - 1] {
+        let mut wrong_instance = instance.clone();
+        wrong_instance[idx] += Fr::from(1);
+        assert!(aggregator.verify(&wrong_instance, &proof).is_err());
+    }
 }
 
+/// Needs `solc` in `PATH`.
+/// Cmdline: RUST_LOG=info cargo test --release --features evm -- --nocapture packed_multi_keccak_evm
+#[cfg(feature = "evm")]
+#[test]
+fn packed_multi_keccak_evm() {
+    use crate::evm::{gen_evm_proof_shplonk, EvmVerifier};
 
-fn test_packed_multi(i: i32, k: u32) -> (i32, usize, f64, f64, usize, f64, f64 ){
+    let _ = env_logger::builder().is_test(true).try_init();
+    let k = 14;
+    let keccak_params = test_params(k);
+    let params = ParamsKZG::<Bn256>::setup(k, OsRng);
+    let circuit = KeccakCircuit::new(keccak_params, vec![(0u8..200).collect()]).unwrap();
+    let instance = circuit.instance();
+    let (pk, proof) = keccak_params.scope(|| {
+        let vk = keygen_vk(&params, &circuit).unwrap();
+        let pk = keygen_pk(&params, vk, &circuit).unwrap();
+        let proof = gen_evm_proof_shplonk(&params, &pk, circuit, vec![instance.clone()]);
+        (pk, proof)
+    });
+
+    let verifier =
+        EvmVerifier::new::<KeccakCircuit<Fr>>(&params, pk.get_vk(), instance.len(), None);
+    info!("verifier deployment code: {} bytes", verifier.deployment_code().len());
+    let gas = verifier.verify(&instance, &proof).unwrap();
+    info!("gas used: {gas}");
+
+    let mut wrong_instance = instance.clone();
+    wrong_instance[0] += Fr::from(1);
+    assert!(verifier.verify(&wrong_instance, &proof).is_err());
+}
+
+/// Configures the circuit with `keccak_params`, so it has to run within `keccak_params.scope`
+fn test_packed_multi(
+    i: i32,
+    keccak_params: KeccakConfigParams,
+) -> (i32, usize, f64, f64, usize, f64, f64, PhaseMemory, PhaseMemory, PhaseMemory) {
+    let k = keccak_params.k;
     let capacity = 1;
     let message_len = 136 * i;
     let inputs = (0..capacity)
         .map(|_| (0..message_len).map(|_| rand::random::<u8>()).collect_vec())
         .collect_vec();
+    let build_memory = PhaseTracker::start();
     let start_setup = Instant::now();
     let params = ParamsKZG::<Bn256>::setup(k, OsRng);
-    let circuit = KeccakCircuit::new(Some(2usize.pow(k)), inputs);
+    let circuit = KeccakCircuit::new(keccak_params, inputs).unwrap();
     let elapsed_setup = start_setup.elapsed();
     let circuit_build_time = elapsed_setup.as_secs_f64();
     let start_keygen_vk = Instant::now();
-    let vk = keygen_vk(&params, &circuit).unwrap();
-    let pk = keygen_pk(&params, vk, &circuit).unwrap();
-    let verifier_params: ParamsVerifierKZG<Bn256> = params.verifier_params().clone();
-    let mut transcript = Blake2bWrite::<_, G1Affine, Challenge255<_>>::init(vec![]);
+    let prover = KeccakCircuitProver::keygen(params, &circuit).unwrap();
     let elapsed_keygen_vk = start_keygen_vk.elapsed();
     let trusted_setup_time = elapsed_keygen_vk.as_secs_f64();
+    let build_memory = build_memory.finish();
+    // Witness generation also runs inside `create_proof`; measure it on its own here.
+    let witness_memory = PhaseTracker::start();
+    multi_keccak_phase0::<Fr>(circuit.inputs(), Some(circuit.capacity()), keccak_params)
+        .unwrap();
+    let witness_memory = witness_memory.finish();
+    let capacity = circuit.capacity();
+    let prove_memory = PhaseTracker::start();
     let start_proof_gen = Instant::now();
-    create_proof::<
-        KZGCommitmentScheme<Bn256>,
-        ProverSHPLONK<'_, Bn256>,
-        Challenge255<G1Affine>,
-        _,
-        Blake2bWrite<Vec<u8>, G1Affine, Challenge255<G1Affine>>,
-        _,
-    >(&params, &pk, &[circuit.clone()], &[&[]], OsRng, &mut transcript)
-    .expect("proof generation should not fail");
-    let proof = transcript.finalize();
+    let (instance, proof) = prover.prove(circuit).expect("proof generation should not fail");
     let elapsed_proof_gen = start_proof_gen.elapsed();
     let proof_generation_time = elapsed_proof_gen.as_secs_f64();
+    let prove_memory = prove_memory.finish();
     let start_proof_verif = Instant::now();
-    let mut verifier_transcript = Blake2bRead::<_, G1Affine, Challenge255<_>>::init(&proof[..]);
-    let strategy = SingleStrategy::new(&params);
-
-    verify_proof::<
-        KZGCommitmentScheme<Bn256>,
-        VerifierSHPLONK<'_, Bn256>,
-        Challenge255<G1Affine>,
-        Blake2bRead<&[u8], G1Affine, Challenge255<G1Affine>>,
-        SingleStrategy<'_, Bn256>,
-    >(&verifier_params, pk.get_vk(), strategy, &[&[]], &mut verifier_transcript)
-    .expect("failed to verify bench circuit");
+    prover.verify(&instance, &proof).expect("failed to verify bench circuit");
     let elapsed_proof_verif = start_proof_verif.elapsed();
     let proof_verification_time = elapsed_proof_verif.as_secs_f64();
 
     (
         message_len,
-        circuit.capacity().unwrap(),
+        capacity,
         circuit_build_time,
         proof_generation_time,
         proof.len(),
         proof_verification_time,
         trusted_setup_time,
+        build_memory,
+        witness_memory,
+        prove_memory,
     )
 
 }
@@ -208,23 +634,31 @@ fn packed_multi_keccak_prover() {
         let mut fs_results = File::create(file_path).unwrap();
         writeln!(
             fs_results,
-            "msg_len,keccak_capacity,time_build_curcuit,time_create_proof,proof_size,time_verify_proof, trusted_setup_generation"
+            "msg_len,keccak_capacity,time_build_curcuit,time_create_proof,proof_size,time_verify_proof, trusted_setup_generation,{},{},{}",
+            PhaseMemory::csv_header("build"),
+            PhaseMemory::csv_header("witness"),
+            PhaseMemory::csv_header("prove"),
         )   
         .unwrap();
-        set_var("KECCAK_ROWS", bench_params.rows_per_round.to_string());
-        set_var("KECCAK_DEGREE", bench_params.degree.to_string());
+        let keccak_params = KeccakConfigParams {
+            k: bench_params.degree,
+            rows_per_round: bench_params.rows_per_round,
+        };
         for i in 1..=1000{
-            let output = test_packed_multi(i, bench_params.degree);
+            let output = keccak_params.scope(|| test_packed_multi(i, keccak_params));
             writeln!(
                 fs_results,
-                "{},{},{},{},{},{},{}",
+                "{},{},{},{},{},{},{},{},{},{}",
             output.0,
             output.1,
             output.2,
             output.3,
             output.4,
             output.5,
-            output.6
+            output.6,
+            output.7,
+            output.8,
+            output.9
             )
             .unwrap();
         }
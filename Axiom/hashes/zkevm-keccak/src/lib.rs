@@ -3,9 +3,21 @@
 
 use halo2_base::halo2_proofs;
 
+/// Aggregation of keccak circuit proofs
+#[cfg(feature = "aggregation")]
+pub mod aggregation;
+/// Keccak of bytes assigned in halo2-base circuits
+pub mod chip;
+/// Keccak circuit and its KZG prover
+pub mod circuit;
+/// EVM verification of keccak circuit proofs
+#[cfg(feature = "evm")]
+pub mod evm;
 /// Keccak packed multi
 pub mod keccak_packed_multi;
+/// Choice of the circuit shape for a workload
+pub mod planner;
 /// Util
 pub mod util;
 
-pub use keccak_packed_multi::KeccakCircuitConfig as KeccakConfig;
+pub use keccak_packed_multi::{KeccakCircuitConfig as KeccakConfig, KeccakConfigParams};
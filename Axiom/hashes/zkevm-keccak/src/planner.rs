@@ -0,0 +1,193 @@
+//! Chooses the shape of a keccak circuit, `k` and `rows_per_round`, for a workload.
+//!
+//! The shapes whose capacity fits the keccak_f's of the workload, counted with
+//! `get_num_keccak_f`, are ranked by a cost predicted from one calibration proof. The costs are
+//! scaled by the columns of each shape, read from its `ConstraintSystem` without keygen:
+//! - the proof size by the number of columns, which are committed or opened in the proof;
+//! - the prover memory by the number of cells, `2^k` per column;
+//! - the prove time by the number of cells times `k`, for the FFTs and MSMs.
+//!
+//! The predictions are meant to rank shapes against each other; the closer a shape is to the
+//! calibration shape, the closer its prediction is to a measurement.
+
+use std::fmt;
+use std::ops::RangeInclusive;
+use std::str::FromStr;
+
+use crate::circuit::KeccakCircuit;
+use crate::halo2_proofs::{
+    halo2curves::bn256::Fr,
+    plonk::{Circuit, ConstraintSystem},
+};
+use crate::keccak_packed_multi::{get_num_keccak_f, KeccakConfigParams};
+use crate::util::NUM_BYTES_PER_WORD;
+
+/// The keccak inputs a circuit has to prove
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Workload {
+    /// Messages of these lengths in bytes
+    Messages(Vec<usize>),
+    /// A single message of this many bytes
+    Bytes(usize),
+}
+
+impl Workload {
+    /// The number of keccak_f's hashing the workload
+    pub fn num_keccak_f(&self) -> usize {
+        match self {
+            Workload::Messages(lengths) => lengths.iter().map(|&len| get_num_keccak_f(len)).sum(),
+            Workload::Bytes(len) => get_num_keccak_f(*len),
+        }
+    }
+}
+
+/// The cost to minimize
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Metric {
+    ProveTime,
+    ProofSize,
+    Memory,
+}
+
+impl FromStr for Metric {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "prove-time" => Ok(Metric::ProveTime),
+            "proof-size" => Ok(Metric::ProofSize),
+            "memory" => Ok(Metric::Memory),
+            _ => Err(format!("unknown metric {s}, expected one of prove-time, proof-size, memory")),
+        }
+    }
+}
+
+impl fmt::Display for Metric {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Metric::ProveTime => "prove-time",
+            Metric::ProofSize => "proof-size",
+            Metric::Memory => "memory",
+        };
+        write!(f, "{name}")
+    }
+}
+
+/// The number of columns of the keccak circuit of shape `parameters`, counting the three
+/// polynomials committed for each lookup
+pub fn num_columns(parameters: KeccakConfigParams) -> usize {
+    let mut meta = ConstraintSystem::<Fr>::default();
+    parameters.scope(|| KeccakCircuit::<Fr>::configure(&mut meta));
+    meta.num_advice_columns()
+        + meta.num_fixed_columns()
+        + meta.num_instance_columns()
+        + 3 * meta.lookups().len()
+}
+
+/// The costs of one proof of a circuit of shape `parameters`, measured by the caller, e.g. the
+/// bench runner, so that the library does not touch the memory statistics of the process
+#[derive(Clone, Copy, Debug)]
+pub struct Calibration {
+    pub parameters: KeccakConfigParams,
+    /// Prove time in seconds
+    pub prove_time: f64,
+    /// Proof size in bytes
+    pub proof_size: usize,
+    /// Peak resident set size while proving, in bytes, if it can be read
+    pub memory: Option<usize>,
+}
+
+/// A shape that fits a workload, with its predicted costs
+#[derive(Clone, Copy, Debug)]
+pub struct ShapePlan {
+    pub parameters: KeccakConfigParams,
+    /// The number of keccak_f's of the circuit
+    pub capacity: usize,
+    /// The fraction of the capacity used by the workload
+    pub utilization: f64,
+    /// Predicted prove time in seconds
+    pub prove_time: f64,
+    /// Predicted proof size in bytes
+    pub proof_size: usize,
+    /// Predicted peak memory in bytes, if the calibration measured it
+    pub memory: Option<usize>,
+}
+
+impl ShapePlan {
+    pub fn csv_header() -> &'static str {
+        "k,rows_per_round,capacity,utilization,prove_time,proof_size,memory"
+    }
+
+    pub fn to_csv_row(&self) -> String {
+        format!(
+            "{},{},{},{:.3},{:.3},{},{}",
+            self.parameters.k,
+            self.parameters.rows_per_round,
+            self.capacity,
+            self.utilization,
+            self.prove_time,
+            self.proof_size,
+            self.memory.map(|memory| memory.to_string()).unwrap_or_default(),
+        )
+    }
+
+    /// The predicted value of `metric`, `None` if the memory was not measured
+    pub fn cost(&self, metric: Metric) -> Option<f64> {
+        match metric {
+            Metric::ProveTime => Some(self.prove_time),
+            Metric::ProofSize => Some(self.proof_size as f64),
+            Metric::Memory => self.memory.map(|memory| memory as f64),
+        }
+    }
+}
+
+/// Shapes searched by `plan` by default: every `k` and `rows_per_round` the circuit supports up
+/// to `2^22` rows and 50 rows per round
+pub fn default_shapes() -> (RangeInclusive<u32>, RangeInclusive<usize>) {
+    (10..=22, NUM_BYTES_PER_WORD + 1..=50)
+}
+
+/// The shapes among `ks` and `rows_per_round` whose capacity fits `workload`, best first for
+/// `metric`, with costs predicted from `calibration`. Shapes of equal cost are ordered by
+/// utilization.
+pub fn plan(
+    workload: &Workload,
+    metric: Metric,
+    calibration: &Calibration,
+    ks: RangeInclusive<u32>,
+    rows_per_round: RangeInclusive<usize>,
+) -> Vec<ShapePlan> {
+    let num_keccak_f = workload.num_keccak_f();
+    let reference = calibration.parameters;
+    let reference_columns = num_columns(reference) as f64;
+    let reference_cells = reference_columns * (1u64 << reference.k) as f64;
+
+    let mut plans = Vec::new();
+    for k in ks {
+        for rows_per_round in rows_per_round.clone() {
+            let parameters = KeccakConfigParams { k, rows_per_round };
+            let capacity = match parameters.capacity() {
+                Ok(capacity) if capacity >= num_keccak_f => capacity,
+                _ => continue,
+            };
+            let columns = num_columns(parameters) as f64;
+            let cells = columns * (1u64 << k) as f64;
+            plans.push(ShapePlan {
+                parameters,
+                capacity,
+                utilization: num_keccak_f as f64 / capacity as f64,
+                prove_time: calibration.prove_time * cells * k as f64
+                    / (reference_cells * reference.k as f64),
+                proof_size: (calibration.proof_size as f64 * columns / reference_columns) as usize,
+                memory: calibration
+                    .memory
+                    .map(|memory| (memory as f64 * cells / reference_cells) as usize),
+            });
+        }
+    }
+    plans.sort_by(|a, b| {
+        let cost = |plan: &ShapePlan| plan.cost(metric).unwrap_or(f64::INFINITY);
+        cost(a).total_cmp(&cost(b)).then(b.utilization.total_cmp(&a.utilization))
+    });
+    plans
+}
@@ -0,0 +1,123 @@
+//! plonky2's Fiat-Shamir `Challenger` in a circuit, a duplex sponge over the BN254 Poseidon
+//! permutation.
+
+use halo2_base::halo2_proofs::halo2curves::bn256::Fr;
+use halo2_base::Context;
+
+use crate::field::{AssignedExtension, AssignedGoldilocks, GoldilocksChip};
+use crate::hash::{permute_bn128, AssignedHash};
+use crate::poseidon::{RATE, WIDTH};
+
+pub struct Challenger {
+    sponge_state: [AssignedGoldilocks; WIDTH],
+    input_buffer: Vec<AssignedGoldilocks>,
+    output_buffer: Vec<AssignedGoldilocks>,
+}
+
+impl Challenger {
+    pub fn new(ctx: &mut Context<Fr>, chip: &GoldilocksChip) -> Self {
+        let zero = chip.zero(ctx);
+        Self {
+            sponge_state: [zero; WIDTH],
+            input_buffer: Vec::with_capacity(RATE),
+            output_buffer: Vec::with_capacity(RATE),
+        }
+    }
+
+    pub fn observe_element(
+        &mut self,
+        ctx: &mut Context<Fr>,
+        chip: &GoldilocksChip,
+        element: AssignedGoldilocks,
+    ) {
+        // Any buffered outputs are invalidated by new inputs.
+        self.output_buffer.clear();
+        self.input_buffer.push(element);
+        if self.input_buffer.len() == RATE {
+            self.duplexing(ctx, chip);
+        }
+    }
+
+    pub fn observe_elements(
+        &mut self,
+        ctx: &mut Context<Fr>,
+        chip: &GoldilocksChip,
+        elements: &[AssignedGoldilocks],
+    ) {
+        for &element in elements {
+            self.observe_element(ctx, chip, element);
+        }
+    }
+
+    pub fn observe_extension_elements(
+        &mut self,
+        ctx: &mut Context<Fr>,
+        chip: &GoldilocksChip,
+        elements: &[AssignedExtension],
+    ) {
+        for element in elements {
+            self.observe_elements(ctx, chip, &element.0);
+        }
+    }
+
+    pub fn observe_hash(
+        &mut self,
+        ctx: &mut Context<Fr>,
+        chip: &GoldilocksChip,
+        hash: &AssignedHash,
+    ) {
+        self.observe_elements(ctx, chip, hash);
+    }
+
+    pub fn observe_cap(
+        &mut self,
+        ctx: &mut Context<Fr>,
+        chip: &GoldilocksChip,
+        cap: &[AssignedHash],
+    ) {
+        for hash in cap {
+            self.observe_hash(ctx, chip, hash);
+        }
+    }
+
+    pub fn get_challenge(
+        &mut self,
+        ctx: &mut Context<Fr>,
+        chip: &GoldilocksChip,
+    ) -> AssignedGoldilocks {
+        if !self.input_buffer.is_empty() || self.output_buffer.is_empty() {
+            self.duplexing(ctx, chip);
+        }
+        self.output_buffer.pop().expect("Output buffer should be non-empty")
+    }
+
+    pub fn get_n_challenges(
+        &mut self,
+        ctx: &mut Context<Fr>,
+        chip: &GoldilocksChip,
+        n: usize,
+    ) -> Vec<AssignedGoldilocks> {
+        (0..n).map(|_| self.get_challenge(ctx, chip)).collect()
+    }
+
+    pub fn get_extension_challenge(
+        &mut self,
+        ctx: &mut Context<Fr>,
+        chip: &GoldilocksChip,
+    ) -> AssignedExtension {
+        let c0 = self.get_challenge(ctx, chip);
+        let c1 = self.get_challenge(ctx, chip);
+        AssignedExtension([c0, c1])
+    }
+
+    /// Overwrites the start of the state with the buffered inputs, permutes it, and makes the
+    /// rate part of the state available as outputs.
+    fn duplexing(&mut self, ctx: &mut Context<Fr>, chip: &GoldilocksChip) {
+        for (i, input) in self.input_buffer.drain(..).enumerate() {
+            self.sponge_state[i] = input;
+        }
+        self.sponge_state = permute_bn128(ctx, chip, self.sponge_state);
+        self.output_buffer.clear();
+        self.output_buffer.extend_from_slice(&self.sponge_state[..RATE]);
+    }
+}
@@ -0,0 +1,126 @@
+//! The verifier circuit of a fixed plonky2 circuit, proven with SHPLONK over BN254.
+
+use std::env::set_var;
+
+use anyhow::Result;
+use halo2_base::gates::builder::{
+    GateThreadBuilder, MultiPhaseThreadBreakPoints, RangeWithInstanceCircuitBuilder,
+};
+use halo2_base::halo2_proofs::{
+    halo2curves::bn256::{Bn256, Fr, G1Affine},
+    plonk::{create_proof, keygen_pk, keygen_vk, verify_proof, ProvingKey, VerifyingKey},
+    poly::{
+        commitment::ParamsProver,
+        kzg::{
+            commitment::{KZGCommitmentScheme, ParamsKZG},
+            multiopen::{ProverSHPLONK, VerifierSHPLONK},
+            strategy::SingleStrategy,
+        },
+    },
+    transcript::{
+        Blake2bRead, Blake2bWrite, Challenge255, TranscriptReadBuffer, TranscriptWriterBuffer,
+    },
+};
+use rand::rngs::OsRng;
+
+use crate::data::{CommonData, Proof, VerifierOnlyData};
+use crate::field::GoldilocksChip;
+use crate::verifier::verify_proof as verify_plonky2_proof;
+
+/// Minimum number of rows reserved for the blinding factors of halo2.
+const MINIMUM_ROWS: usize = 20;
+
+/// Keys of the halo2 circuit verifying the proofs of the plonky2 circuit of `common` and
+/// `verifier_only`. The public inputs of the halo2 circuit are those of the plonky2 proof, one
+/// Goldilocks element per BN254 element.
+pub struct Plonky2Verifier {
+    common: CommonData,
+    verifier_only: VerifierOnlyData,
+    lookup_bits: usize,
+    params: ParamsKZG<Bn256>,
+    pk: ProvingKey<G1Affine>,
+    break_points: MultiPhaseThreadBreakPoints,
+}
+
+impl Plonky2Verifier {
+    /// Sets up the KZG parameters of `2^k` rows and generates the keys, laying out the circuit
+    /// with `sample`, any proof of the plonky2 circuit.
+    ///
+    /// The circuit configuration goes through the `LOOKUP_BITS` and `FLEX_GATE_CONFIG_PARAMS`
+    /// environment variables of `halo2-base`, so only one verifier can be used per process.
+    pub fn keygen(
+        k: u32,
+        lookup_bits: usize,
+        common: CommonData,
+        verifier_only: VerifierOnlyData,
+        sample: &Proof,
+    ) -> Result<Self> {
+        set_var("LOOKUP_BITS", lookup_bits.to_string());
+        let mut builder = GateThreadBuilder::keygen();
+        let chip = GoldilocksChip::new(lookup_bits);
+        let public_inputs =
+            verify_plonky2_proof(builder.main(0), &chip, &common, &verifier_only, sample)?;
+        builder.config(k as usize, Some(MINIMUM_ROWS));
+        let circuit = RangeWithInstanceCircuitBuilder::keygen(
+            builder,
+            public_inputs.iter().map(|x| x.cell()).collect(),
+        );
+
+        let params = ParamsKZG::<Bn256>::setup(k, OsRng);
+        let vk = keygen_vk(&params, &circuit)?;
+        let pk = keygen_pk(&params, vk, &circuit)?;
+        let break_points = circuit.break_points();
+        Ok(Self { common, verifier_only, lookup_bits, params, pk, break_points })
+    }
+
+    pub fn params(&self) -> &ParamsKZG<Bn256> {
+        &self.params
+    }
+
+    pub fn vk(&self) -> &VerifyingKey<G1Affine> {
+        self.pk.get_vk()
+    }
+
+    /// Proves that `proof` is valid, returning the public inputs and the halo2 proof.
+    pub fn prove(&self, proof: &Proof) -> Result<(Vec<Fr>, Vec<u8>)> {
+        let mut builder = GateThreadBuilder::prover();
+        let chip = GoldilocksChip::new(self.lookup_bits);
+        let public_inputs =
+            verify_plonky2_proof(builder.main(0), &chip, &self.common, &self.verifier_only, proof)?;
+        let circuit = RangeWithInstanceCircuitBuilder::prover(
+            builder,
+            public_inputs.iter().map(|x| x.cell()).collect(),
+            self.break_points.clone(),
+        );
+        let instance = circuit.instance();
+
+        let mut transcript = Blake2bWrite::<_, G1Affine, Challenge255<_>>::init(vec![]);
+        create_proof::<
+            KZGCommitmentScheme<Bn256>,
+            ProverSHPLONK<'_, Bn256>,
+            Challenge255<G1Affine>,
+            _,
+            Blake2bWrite<Vec<u8>, G1Affine, Challenge255<G1Affine>>,
+            _,
+        >(&self.params, &self.pk, &[circuit], &[&[&instance]], OsRng, &mut transcript)?;
+        Ok((instance, transcript.finalize()))
+    }
+
+    pub fn verify(&self, instance: &[Fr], proof: &[u8]) -> Result<()> {
+        let mut transcript = Blake2bRead::<_, G1Affine, Challenge255<_>>::init(proof);
+        verify_proof::<
+            KZGCommitmentScheme<Bn256>,
+            VerifierSHPLONK<'_, Bn256>,
+            Challenge255<G1Affine>,
+            Blake2bRead<&[u8], G1Affine, Challenge255<G1Affine>>,
+            SingleStrategy<'_, Bn256>,
+        >(
+            self.params.verifier_params(),
+            self.vk(),
+            SingleStrategy::new(&self.params),
+            &[&[instance]],
+            &mut transcript,
+        )?;
+        Ok(())
+    }
+}
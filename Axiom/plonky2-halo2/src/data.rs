@@ -0,0 +1,416 @@
+//! The parts of a plonky2 circuit and proof that the verifier circuit reads.
+//!
+//! Field elements are canonical `u64`s and extension elements pairs of them, so that proofs of
+//! any plonky2 version with the 0.1 proof format can be converted, as the benchmarks do for both
+//! the crates.io release and Maru's fork.
+
+use std::ops::Range;
+
+use anyhow::{bail, ensure, Context, Result};
+use serde::{Deserialize, Serialize};
+
+/// `plonky2::gates::selectors::UNUSED_SELECTOR`.
+pub const UNUSED_SELECTOR: u64 = u32::MAX as u64;
+/// Number of random elements appended to the leaves of blinded oracles of zero knowledge proofs.
+pub const SALT_SIZE: usize = 4;
+
+pub type HashOut = [u64; 4];
+pub type MerkleCap = Vec<HashOut>;
+pub type Extension = [u64; 2];
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct FriConfig {
+    pub rate_bits: usize,
+    pub cap_height: usize,
+    pub proof_of_work_bits: u32,
+    pub num_query_rounds: usize,
+    pub reduction_arity_bits: Vec<usize>,
+}
+
+/// `CommonCircuitData`, with the gates given by their `Gate::id`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CommonData {
+    pub degree_bits: usize,
+    pub num_wires: usize,
+    pub num_routed_wires: usize,
+    /// Number of constant polynomials, selectors included.
+    pub num_constants: usize,
+    pub num_public_inputs: usize,
+    pub num_challenges: usize,
+    pub num_partial_products: usize,
+    pub quotient_degree_factor: usize,
+    pub num_gate_constraints: usize,
+    /// Whether the wires, Z and quotient oracles are salted.
+    pub hiding: bool,
+    pub k_is: Vec<u64>,
+    pub fri: FriConfig,
+    pub gates: Vec<String>,
+}
+
+impl CommonData {
+    pub fn lde_bits(&self) -> usize {
+        self.degree_bits + self.fri.rate_bits
+    }
+
+    pub fn num_zs_partial_products(&self) -> usize {
+        self.num_challenges * (1 + self.num_partial_products)
+    }
+
+    /// Number of salt elements at the end of the leaves of the wires, Z and quotient oracles.
+    pub fn salt_size(&self) -> usize {
+        if self.hiding {
+            SALT_SIZE
+        } else {
+            0
+        }
+    }
+
+    pub fn parse_gates(&self) -> Result<Vec<Gate>> {
+        self.gates.iter().map(|id| Gate::from_id(id)).collect()
+    }
+
+    /// The `SelectorsInfo` of the circuit: the selector used by each gate and the range of gates
+    /// of each selector. plonky2 doesn't expose them, they are recomputed as by
+    /// `selector_polynomials` from the gates, which are sorted by degree.
+    pub fn selectors(&self, gates: &[Gate]) -> Result<(Vec<usize>, Vec<Range<usize>>)> {
+        let max_degree = self.quotient_degree_factor + 1;
+        let num_gates = gates.len();
+        let max_gate_degree = gates.last().context("no gates")?.degree();
+
+        let groups = if max_gate_degree + num_gates - 1 <= max_degree {
+            vec![0..num_gates]
+        } else {
+            ensure!(max_gate_degree < max_degree, "gates have too high degree");
+            let mut groups = vec![];
+            let mut start = 0;
+            while start < num_gates {
+                let mut size = 0;
+                while start + size < num_gates && size + gates[start + size].degree() < max_degree {
+                    size += 1;
+                }
+                groups.push(start..start + size);
+                start += size;
+            }
+            groups
+        };
+        let selector_indices = (0..num_gates)
+            .map(|i| groups.iter().position(|group| group.contains(&i)).unwrap())
+            .collect();
+        Ok((selector_indices, groups))
+    }
+}
+
+/// `VerifierOnlyCircuitData`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct VerifierOnlyData {
+    pub constants_sigmas_cap: MerkleCap,
+    pub circuit_digest: HashOut,
+}
+
+/// `OpeningSet`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct OpeningSet {
+    pub constants: Vec<Extension>,
+    pub plonk_sigmas: Vec<Extension>,
+    pub wires: Vec<Extension>,
+    pub plonk_zs: Vec<Extension>,
+    pub plonk_zs_next: Vec<Extension>,
+    pub partial_products: Vec<Extension>,
+    pub quotient_polys: Vec<Extension>,
+}
+
+/// `FriQueryStep`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct FriQueryStep {
+    pub evals: Vec<Extension>,
+    pub merkle_proof: Vec<HashOut>,
+}
+
+/// `FriQueryRound`, with the opened leaf and the Merkle proof in each initial oracle.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct FriQueryRound {
+    pub initial_trees_proof: Vec<(Vec<u64>, Vec<HashOut>)>,
+    pub steps: Vec<FriQueryStep>,
+}
+
+/// `FriProof`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct FriProof {
+    pub commit_phase_merkle_caps: Vec<MerkleCap>,
+    pub query_round_proofs: Vec<FriQueryRound>,
+    pub final_poly: Vec<Extension>,
+    pub pow_witness: u64,
+}
+
+/// `ProofWithPublicInputs`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Proof {
+    pub public_inputs: Vec<u64>,
+    pub wires_cap: MerkleCap,
+    pub plonk_zs_partial_products_cap: MerkleCap,
+    pub quotient_polys_cap: MerkleCap,
+    pub openings: OpeningSet,
+    pub opening_proof: FriProof,
+}
+
+impl Proof {
+    /// Checks that the proof has the shape the circuit of `common` expects, as plonky2's
+    /// `validate_proof_with_pis_shape` does, so that the verifier circuit only depends on
+    /// `common`.
+    pub fn validate_shape(
+        &self,
+        common: &CommonData,
+        verifier_only: &VerifierOnlyData,
+    ) -> Result<()> {
+        let cap_len = 1 << common.fri.cap_height;
+        let num_zs = common.num_challenges;
+        let OpeningSet {
+            constants,
+            plonk_sigmas,
+            wires,
+            plonk_zs,
+            plonk_zs_next,
+            partial_products,
+            quotient_polys,
+        } = &self.openings;
+        ensure!(
+            self.public_inputs.len() == common.num_public_inputs,
+            "wrong number of public inputs"
+        );
+        ensure!(
+            [
+                &verifier_only.constants_sigmas_cap,
+                &self.wires_cap,
+                &self.plonk_zs_partial_products_cap,
+                &self.quotient_polys_cap,
+            ]
+            .iter()
+            .all(|cap| cap.len() == cap_len),
+            "wrong cap length"
+        );
+        ensure!(
+            constants.len() == common.num_constants
+                && plonk_sigmas.len() == common.num_routed_wires
+                && wires.len() == common.num_wires
+                && plonk_zs.len() == num_zs
+                && plonk_zs_next.len() == num_zs
+                && partial_products.len() == num_zs * common.num_partial_products
+                && quotient_polys.len() == num_zs * common.quotient_degree_factor,
+            "wrong number of openings"
+        );
+
+        let fri = &self.opening_proof;
+        let arity_bits = &common.fri.reduction_arity_bits;
+        let final_poly_len = 1 << (common.degree_bits - arity_bits.iter().sum::<usize>());
+        ensure!(fri.commit_phase_merkle_caps.len() == arity_bits.len(), "wrong number of FRI caps");
+        ensure!(
+            fri.commit_phase_merkle_caps.iter().all(|cap| cap.len() == cap_len),
+            "wrong FRI cap length"
+        );
+        ensure!(fri.final_poly.len() == final_poly_len, "wrong final polynomial length");
+        ensure!(
+            fri.query_round_proofs.len() == common.fri.num_query_rounds,
+            "wrong number of query rounds"
+        );
+
+        let salt = common.salt_size();
+        let oracle_sizes = [
+            common.num_constants + common.num_routed_wires,
+            common.num_wires + salt,
+            common.num_zs_partial_products() + salt,
+            num_zs * common.quotient_degree_factor + salt,
+        ];
+        for round in &fri.query_round_proofs {
+            let mut height = common.lde_bits() - common.fri.cap_height;
+            ensure!(
+                round.initial_trees_proof.len() == oracle_sizes.len(),
+                "wrong number of oracles"
+            );
+            for ((leaf, proof), size) in round.initial_trees_proof.iter().zip(oracle_sizes) {
+                ensure!(leaf.len() == size && proof.len() == height, "wrong initial tree proof");
+            }
+            ensure!(round.steps.len() == arity_bits.len(), "wrong number of query steps");
+            for (step, &bits) in round.steps.iter().zip(arity_bits) {
+                height -= bits;
+                ensure!(
+                    step.evals.len() == 1 << bits && step.merkle_proof.len() == height,
+                    "wrong query step"
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The gates of plonky2 the verifier circuit can evaluate, parsed from their `Gate::id`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Gate {
+    Arithmetic { num_ops: usize },
+    ArithmeticExtension { num_ops: usize },
+    BaseSum { num_limbs: usize, base: usize },
+    Constant { num_consts: usize },
+    CosetInterpolation { subgroup_bits: usize, degree: usize },
+    Exponentiation { num_power_bits: usize },
+    MulExtension { num_ops: usize },
+    Noop,
+    Poseidon,
+    PoseidonMds,
+    PublicInput,
+    RandomAccess { bits: usize, num_copies: usize, num_extra_constants: usize },
+    Reducing { num_coeffs: usize },
+    ReducingExtension { num_coeffs: usize },
+}
+
+/// The value of `key` in the `Debug` output of a gate.
+fn param(id: &str, key: &str) -> Result<usize> {
+    let pattern = format!("{key}: ");
+    let start = id
+        .match_indices(&pattern)
+        .map(|(i, _)| i)
+        .find(|&i| !id[..i].ends_with(|c: char| c.is_alphanumeric() || c == '_'))
+        .with_context(|| format!("no {key} in gate {id}"))?
+        + pattern.len();
+    let digits = id[start..].split(|c: char| !c.is_ascii_digit()).next().unwrap();
+    digits.parse().with_context(|| format!("invalid {key} in gate {id}"))
+}
+
+impl Gate {
+    pub fn from_id(id: &str) -> Result<Self> {
+        let name = id.split([' ', '(', '<']).next().unwrap();
+        Ok(match name {
+            "ArithmeticGate" => Self::Arithmetic { num_ops: param(id, "num_ops")? },
+            "ArithmeticExtensionGate" => {
+                Self::ArithmeticExtension { num_ops: param(id, "num_ops")? }
+            }
+            "BaseSumGate" => {
+                Self::BaseSum { num_limbs: param(id, "num_limbs")?, base: param(id, "Base")? }
+            }
+            "ConstantGate" => Self::Constant { num_consts: param(id, "num_consts")? },
+            "CosetInterpolationGate" => Self::CosetInterpolation {
+                subgroup_bits: param(id, "subgroup_bits")?,
+                degree: param(id, "degree")?,
+            },
+            "ExponentiationGate" => {
+                Self::Exponentiation { num_power_bits: param(id, "num_power_bits")? }
+            }
+            "MulExtensionGate" => Self::MulExtension { num_ops: param(id, "num_ops")? },
+            "NoopGate" => Self::Noop,
+            "PoseidonGate" => Self::Poseidon,
+            "PoseidonMdsGate" => Self::PoseidonMds,
+            "PublicInputGate" => Self::PublicInput,
+            "RandomAccessGate" => Self::RandomAccess {
+                bits: param(id, "bits")?,
+                num_copies: param(id, "num_copies")?,
+                num_extra_constants: param(id, "num_extra_constants")?,
+            },
+            "ReducingGate" => Self::Reducing { num_coeffs: param(id, "num_coeffs")? },
+            "ReducingExtensionGate" => {
+                Self::ReducingExtension { num_coeffs: param(id, "num_coeffs")? }
+            }
+            _ => bail!("unsupported gate {id}"),
+        })
+    }
+
+    /// `Gate::degree`, which determines the selectors.
+    pub fn degree(&self) -> usize {
+        match *self {
+            Self::Arithmetic { .. }
+            | Self::ArithmeticExtension { .. }
+            | Self::MulExtension { .. } => 3,
+            Self::BaseSum { base, .. } => base,
+            Self::Constant { .. } | Self::PoseidonMds | Self::PublicInput => 1,
+            Self::CosetInterpolation { degree, .. } => degree,
+            Self::Exponentiation { .. } => 4,
+            Self::Noop => 0,
+            Self::Poseidon => 7,
+            Self::RandomAccess { bits, .. } => bits + 1,
+            Self::Reducing { .. } | Self::ReducingExtension { .. } => 2,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_gate_from_id() {
+        let gates = [
+            ("NoopGate", Gate::Noop),
+            ("ConstantGate { num_consts: 2 }", Gate::Constant { num_consts: 2 }),
+            ("PublicInputGate", Gate::PublicInput),
+            ("ArithmeticGate { num_ops: 20 }", Gate::Arithmetic { num_ops: 20 }),
+            ("BaseSumGate { num_limbs: 63 } + Base: 2", Gate::BaseSum { num_limbs: 63, base: 2 }),
+            (
+                "RandomAccessGate { bits: 4, num_copies: 4, num_extra_constants: 2, _phantom: \
+                 PhantomData<plonky2_field::goldilocks_field::GoldilocksField> }<D=2>",
+                Gate::RandomAccess { bits: 4, num_copies: 4, num_extra_constants: 2 },
+            ),
+            (
+                "CosetInterpolationGate { subgroup_bits: 4, degree: 6, barycentric_weights: \
+                 [17293822565076172801, 256], _phantom: PhantomData<plonky2_field::\
+                 goldilocks_field::GoldilocksField> }<D=2>",
+                Gate::CosetInterpolation { subgroup_bits: 4, degree: 6 },
+            ),
+            (
+                "PoseidonGate(PhantomData<plonky2_field::goldilocks_field::GoldilocksField>)\
+                 <WIDTH=12>",
+                Gate::Poseidon,
+            ),
+            (
+                "PoseidonMdsGate(PhantomData<plonky2_field::goldilocks_field::GoldilocksField>)\
+                 <WIDTH=12>",
+                Gate::PoseidonMds,
+            ),
+        ];
+        for (id, gate) in gates {
+            assert_eq!(Gate::from_id(id).unwrap(), gate);
+        }
+        assert!(Gate::from_id("U32AddManyGate { num_addends: 3 }").is_err());
+    }
+
+    #[test]
+    fn test_selectors() {
+        // The gates of a recursive verifier, sorted by degree
+        let gates = [
+            Gate::Noop,
+            Gate::Constant { num_consts: 2 },
+            Gate::PoseidonMds,
+            Gate::PublicInput,
+            Gate::BaseSum { num_limbs: 36, base: 2 },
+            Gate::Reducing { num_coeffs: 43 },
+            Gate::ReducingExtension { num_coeffs: 32 },
+            Gate::Arithmetic { num_ops: 20 },
+            Gate::ArithmeticExtension { num_ops: 10 },
+            Gate::MulExtension { num_ops: 13 },
+            Gate::Exponentiation { num_power_bits: 66 },
+            Gate::RandomAccess { bits: 4, num_copies: 4, num_extra_constants: 2 },
+            Gate::CosetInterpolation { subgroup_bits: 4, degree: 6 },
+            Gate::Poseidon,
+        ];
+        let common = CommonData {
+            degree_bits: 12,
+            num_wires: 135,
+            num_routed_wires: 80,
+            num_constants: 6,
+            num_public_inputs: 0,
+            num_challenges: 2,
+            num_partial_products: 9,
+            quotient_degree_factor: 8,
+            num_gate_constraints: 123,
+            hiding: false,
+            k_is: vec![],
+            fri: FriConfig {
+                rate_bits: 3,
+                cap_height: 4,
+                proof_of_work_bits: 16,
+                num_query_rounds: 28,
+                reduction_arity_bits: vec![4],
+            },
+            gates: vec![],
+        };
+        let (indices, groups) = common.selectors(&gates).unwrap();
+        assert_eq!(groups, vec![0..7, 7..11, 11..13, 13..14]);
+        assert_eq!(indices, vec![0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 3]);
+    }
+}
@@ -0,0 +1,533 @@
+//! Goldilocks arithmetic in a circuit over BN254.
+//!
+//! Each Goldilocks element is one BN254 cell holding its canonical value. Sums and products are
+//! computed without wrapping in the BN254 field, then reduced with a range checked
+//! `div_mod` by the Goldilocks order, so each operation costs one reduction.
+//! The quadratic extension `F[v] / (v^2 - 7)` and the extension algebra over it, in which
+//! plonky2 evaluates its gate constraints, are built on top.
+
+use halo2_base::gates::{GateChip, GateInstructions, RangeChip, RangeInstructions};
+use halo2_base::halo2_proofs::arithmetic::FieldExt;
+use halo2_base::halo2_proofs::halo2curves::bn256::Fr;
+use halo2_base::QuantumCell::{Constant, Existing};
+use halo2_base::{AssignedValue, Context, QuantumCell};
+use num_bigint::BigUint;
+
+use crate::goldilocks::{Goldilocks, ORDER, W};
+
+/// Number of bits of a canonical Goldilocks element.
+const BITS: usize = 64;
+
+fn fr(value: Goldilocks) -> Fr {
+    Fr::from(value.to_canonical_u64())
+}
+
+/// A Goldilocks element, constrained to be canonical.
+#[derive(Copy, Clone, Debug)]
+pub struct AssignedGoldilocks(AssignedValue<Fr>);
+
+impl AssignedGoldilocks {
+    pub fn value(&self) -> Goldilocks {
+        Goldilocks::new(self.0.value().get_lower_128() as u64)
+    }
+
+    /// The BN254 cell holding the canonical value.
+    pub fn cell(&self) -> AssignedValue<Fr> {
+        self.0
+    }
+}
+
+impl From<AssignedGoldilocks> for QuantumCell<Fr> {
+    fn from(a: AssignedGoldilocks) -> Self {
+        Existing(a.0)
+    }
+}
+
+/// An element `a[0] + a[1] v` of the quadratic extension.
+#[derive(Copy, Clone, Debug)]
+pub struct AssignedExtension(pub [AssignedGoldilocks; 2]);
+
+impl AssignedExtension {
+    pub fn value(&self) -> [Goldilocks; 2] {
+        self.0.map(|a| a.value())
+    }
+}
+
+/// An element `a[0] + a[1] v` of the extension algebra, whose coefficients are in the
+/// quadratic extension and where `v^2 = 7`.
+pub type AssignedAlgebra = [AssignedExtension; 2];
+
+/// `(a0 + a1 v)^-1 = (a0 - a1 v) / (a0^2 - 7 a1^2)`, zero for zero, which fails the constraint
+/// of `ext_inverse` instead of panicking during witness generation.
+fn ext_inverse_value([a0, a1]: [Goldilocks; 2]) -> [Goldilocks; 2] {
+    let norm = a0 * a0 - Goldilocks::new(W) * a1 * a1;
+    if norm == Goldilocks::ZERO {
+        return [Goldilocks::ZERO; 2];
+    }
+    let norm_inverse = norm.inverse();
+    [a0 * norm_inverse, -a1 * norm_inverse]
+}
+
+#[derive(Clone, Debug)]
+pub struct GoldilocksChip {
+    range: RangeChip<Fr>,
+}
+
+impl GoldilocksChip {
+    pub fn new(lookup_bits: usize) -> Self {
+        Self { range: RangeChip::default(lookup_bits) }
+    }
+
+    pub fn range(&self) -> &RangeChip<Fr> {
+        &self.range
+    }
+
+    pub fn gate(&self) -> &GateChip<Fr> {
+        self.range.gate()
+    }
+
+    pub fn load_witness(&self, ctx: &mut Context<Fr>, value: Goldilocks) -> AssignedGoldilocks {
+        let a = ctx.load_witness(fr(value));
+        self.range.check_less_than_safe(ctx, a, ORDER);
+        AssignedGoldilocks(a)
+    }
+
+    pub fn load_constant(&self, ctx: &mut Context<Fr>, value: Goldilocks) -> AssignedGoldilocks {
+        AssignedGoldilocks(ctx.load_constant(fr(value)))
+    }
+
+    pub fn zero(&self, ctx: &mut Context<Fr>) -> AssignedGoldilocks {
+        AssignedGoldilocks(ctx.load_zero())
+    }
+
+    /// Reduces `a`, which has at most `num_bits` bits, modulo the Goldilocks order.
+    pub fn reduce(
+        &self,
+        ctx: &mut Context<Fr>,
+        a: AssignedValue<Fr>,
+        num_bits: usize,
+    ) -> AssignedGoldilocks {
+        let (_, rem) = self.range.div_mod(ctx, a, BigUint::from(ORDER), num_bits.max(BITS));
+        AssignedGoldilocks(rem)
+    }
+
+    pub fn add(
+        &self,
+        ctx: &mut Context<Fr>,
+        a: AssignedGoldilocks,
+        b: AssignedGoldilocks,
+    ) -> AssignedGoldilocks {
+        let sum = self.gate().add(ctx, a, b);
+        self.reduce(ctx, sum, BITS + 1)
+    }
+
+    pub fn add_const(
+        &self,
+        ctx: &mut Context<Fr>,
+        a: AssignedGoldilocks,
+        b: Goldilocks,
+    ) -> AssignedGoldilocks {
+        let sum = self.gate().add(ctx, a, Constant(fr(b)));
+        self.reduce(ctx, sum, BITS + 1)
+    }
+
+    pub fn sub(
+        &self,
+        ctx: &mut Context<Fr>,
+        a: AssignedGoldilocks,
+        b: AssignedGoldilocks,
+    ) -> AssignedGoldilocks {
+        // a + p - b is not negative as b is canonical
+        let shifted = self.gate().add(ctx, a, Constant(Fr::from(ORDER)));
+        let diff = self.gate().sub(ctx, shifted, b);
+        self.reduce(ctx, diff, BITS + 1)
+    }
+
+    pub fn neg(&self, ctx: &mut Context<Fr>, a: AssignedGoldilocks) -> AssignedGoldilocks {
+        let diff = self.gate().sub(ctx, Constant(Fr::from(ORDER)), a);
+        self.reduce(ctx, diff, BITS)
+    }
+
+    pub fn mul(
+        &self,
+        ctx: &mut Context<Fr>,
+        a: AssignedGoldilocks,
+        b: AssignedGoldilocks,
+    ) -> AssignedGoldilocks {
+        let product = self.gate().mul(ctx, a, b);
+        self.reduce(ctx, product, 2 * BITS)
+    }
+
+    pub fn mul_add(
+        &self,
+        ctx: &mut Context<Fr>,
+        a: AssignedGoldilocks,
+        b: AssignedGoldilocks,
+        c: AssignedGoldilocks,
+    ) -> AssignedGoldilocks {
+        let result = self.gate().mul_add(ctx, a, b, c);
+        self.reduce(ctx, result, 2 * BITS + 1)
+    }
+
+    /// `sum_i c_i a_i + constant` for constant coefficients `c_i`.
+    pub fn linear_combination(
+        &self,
+        ctx: &mut Context<Fr>,
+        terms: impl IntoIterator<Item = (Goldilocks, AssignedGoldilocks)>,
+        constant: Goldilocks,
+    ) -> AssignedGoldilocks {
+        let (coeffs, values): (Vec<_>, Vec<_>) = terms
+            .into_iter()
+            .map(|(c, a)| (Constant(fr(c)), Existing(a.0)))
+            .chain([(Constant(Fr::from(1)), Constant(fr(constant)))])
+            .unzip();
+        let num_bits = 2 * BITS + bits_for(values.len());
+        let sum = self.gate().inner_product(ctx, values, coeffs);
+        self.reduce(ctx, sum, num_bits)
+    }
+
+    pub fn assert_equal(
+        &self,
+        ctx: &mut Context<Fr>,
+        a: AssignedGoldilocks,
+        b: AssignedGoldilocks,
+    ) {
+        ctx.constrain_equal(&a.0, &b.0);
+    }
+
+    pub fn assert_const(&self, ctx: &mut Context<Fr>, a: AssignedGoldilocks, b: Goldilocks) {
+        self.gate().assert_is_const(ctx, &a.0, &fr(b));
+    }
+
+    /// `a` if `sel` is one, `b` if it is zero.
+    pub fn select(
+        &self,
+        ctx: &mut Context<Fr>,
+        a: AssignedGoldilocks,
+        b: AssignedGoldilocks,
+        sel: AssignedValue<Fr>,
+    ) -> AssignedGoldilocks {
+        AssignedGoldilocks(self.gate().select(ctx, a, b, sel))
+    }
+
+    /// `values[index]`, for the index given by its indicator vector.
+    pub fn select_by_indicator(
+        &self,
+        ctx: &mut Context<Fr>,
+        values: &[AssignedGoldilocks],
+        indicator: &[AssignedValue<Fr>],
+    ) -> AssignedGoldilocks {
+        let values = values.iter().map(|a| Existing(a.0));
+        AssignedGoldilocks(self.gate().select_by_indicator(ctx, values, indicator.iter().copied()))
+    }
+
+    /// The `num_bits` low bits of `a`, little endian. All 64 bits if `num_bits` is 64.
+    pub fn to_bits(
+        &self,
+        ctx: &mut Context<Fr>,
+        a: AssignedGoldilocks,
+        num_bits: usize,
+    ) -> Vec<AssignedValue<Fr>> {
+        match num_bits {
+            0 => return vec![],
+            BITS => return self.gate().num_to_bits(ctx, a.0, BITS),
+            _ => {}
+        }
+        let (_, low) = self.range.div_mod(ctx, a.0, BigUint::from(1u64) << num_bits, BITS);
+        self.gate().num_to_bits(ctx, low, num_bits)
+    }
+
+    /// `base^e`, where `e` is given by its little endian bits.
+    pub fn exp_const_base(
+        &self,
+        ctx: &mut Context<Fr>,
+        base: Goldilocks,
+        bits: &[AssignedValue<Fr>],
+    ) -> AssignedGoldilocks {
+        let one = self.load_constant(ctx, Goldilocks::ONE);
+        let mut power = base;
+        let mut result = one;
+        for &bit in bits {
+            let factor = self.gate().select(ctx, Constant(fr(power)), one, bit);
+            result = self.mul(ctx, result, AssignedGoldilocks(factor));
+            power = power * power;
+        }
+        result
+    }
+
+    pub fn load_ext_witness(
+        &self,
+        ctx: &mut Context<Fr>,
+        value: [Goldilocks; 2],
+    ) -> AssignedExtension {
+        AssignedExtension(value.map(|a| self.load_witness(ctx, a)))
+    }
+
+    pub fn load_ext_constant(
+        &self,
+        ctx: &mut Context<Fr>,
+        value: [Goldilocks; 2],
+    ) -> AssignedExtension {
+        AssignedExtension(value.map(|a| self.load_constant(ctx, a)))
+    }
+
+    pub fn ext_zero(&self, ctx: &mut Context<Fr>) -> AssignedExtension {
+        let zero = self.zero(ctx);
+        AssignedExtension([zero; 2])
+    }
+
+    pub fn ext_one(&self, ctx: &mut Context<Fr>) -> AssignedExtension {
+        self.load_ext_constant(ctx, [Goldilocks::ONE, Goldilocks::ZERO])
+    }
+
+    pub fn ext_from_base(&self, ctx: &mut Context<Fr>, a: AssignedGoldilocks) -> AssignedExtension {
+        AssignedExtension([a, self.zero(ctx)])
+    }
+
+    pub fn ext_add(
+        &self,
+        ctx: &mut Context<Fr>,
+        a: AssignedExtension,
+        b: AssignedExtension,
+    ) -> AssignedExtension {
+        AssignedExtension([0, 1].map(|i| self.add(ctx, a.0[i], b.0[i])))
+    }
+
+    pub fn ext_sub(
+        &self,
+        ctx: &mut Context<Fr>,
+        a: AssignedExtension,
+        b: AssignedExtension,
+    ) -> AssignedExtension {
+        AssignedExtension([0, 1].map(|i| self.sub(ctx, a.0[i], b.0[i])))
+    }
+
+    /// `a + b` for a constant `b` of the base field.
+    pub fn ext_add_base_const(
+        &self,
+        ctx: &mut Context<Fr>,
+        a: AssignedExtension,
+        b: Goldilocks,
+    ) -> AssignedExtension {
+        AssignedExtension([self.add_const(ctx, a.0[0], b), a.0[1]])
+    }
+
+    pub fn ext_mul(
+        &self,
+        ctx: &mut Context<Fr>,
+        a: AssignedExtension,
+        b: AssignedExtension,
+    ) -> AssignedExtension {
+        let zero = self.ext_zero(ctx);
+        self.ext_mul_add(ctx, a, b, zero)
+    }
+
+    /// `a * b + c`, with a single reduction of each coefficient.
+    pub fn ext_mul_add(
+        &self,
+        ctx: &mut Context<Fr>,
+        a: AssignedExtension,
+        b: AssignedExtension,
+        c: AssignedExtension,
+    ) -> AssignedExtension {
+        let [a0, a1] = a.0;
+        let [b0, b1] = b.0;
+        let [c0, c1] = c.0;
+        let gate = self.gate();
+        // a0 b0 + 7 a1 b1 + c0
+        let a1b1 = gate.mul(ctx, a1, b1);
+        let a1b1_w = gate.mul_add(ctx, a1b1, Constant(Fr::from(W)), c0);
+        let d0 = gate.mul_add(ctx, a0, b0, a1b1_w);
+        // a0 b1 + a1 b0 + c1
+        let a1b0 = gate.mul_add(ctx, a1, b0, c1);
+        let d1 = gate.mul_add(ctx, a0, b1, a1b0);
+        AssignedExtension([self.reduce(ctx, d0, 2 * BITS + 4), self.reduce(ctx, d1, 2 * BITS + 2)])
+    }
+
+    pub fn ext_square(&self, ctx: &mut Context<Fr>, a: AssignedExtension) -> AssignedExtension {
+        self.ext_mul(ctx, a, a)
+    }
+
+    /// `a * b` for `a` in the base field.
+    pub fn ext_scalar_mul(
+        &self,
+        ctx: &mut Context<Fr>,
+        a: AssignedGoldilocks,
+        b: AssignedExtension,
+    ) -> AssignedExtension {
+        AssignedExtension(b.0.map(|b| self.mul(ctx, a, b)))
+    }
+
+    /// `sum_i c_i a_i + constant` for constant coefficients `c_i` of the base field.
+    pub fn ext_linear_combination(
+        &self,
+        ctx: &mut Context<Fr>,
+        terms: &[(Goldilocks, AssignedExtension)],
+        constant: [Goldilocks; 2],
+    ) -> AssignedExtension {
+        AssignedExtension([0, 1].map(|i| {
+            self.linear_combination(ctx, terms.iter().map(|&(c, a)| (c, a.0[i])), constant[i])
+        }))
+    }
+
+    pub fn ext_inverse(&self, ctx: &mut Context<Fr>, a: AssignedExtension) -> AssignedExtension {
+        let inverse = self.load_ext_witness(ctx, ext_inverse_value(a.value()));
+        let product = self.ext_mul(ctx, a, inverse);
+        self.ext_assert_const(ctx, product, [Goldilocks::ONE, Goldilocks::ZERO]);
+        inverse
+    }
+
+    /// `a / b`, `b` has to be non-zero.
+    pub fn ext_div(
+        &self,
+        ctx: &mut Context<Fr>,
+        a: AssignedExtension,
+        b: AssignedExtension,
+    ) -> AssignedExtension {
+        let inverse = self.ext_inverse(ctx, b);
+        self.ext_mul(ctx, a, inverse)
+    }
+
+    /// `a^(2^k)`.
+    pub fn ext_exp_power_of_2(
+        &self,
+        ctx: &mut Context<Fr>,
+        a: AssignedExtension,
+        k: usize,
+    ) -> AssignedExtension {
+        (0..k).fold(a, |a, _| self.ext_square(ctx, a))
+    }
+
+    pub fn ext_exp_u64(
+        &self,
+        ctx: &mut Context<Fr>,
+        a: AssignedExtension,
+        exponent: u64,
+    ) -> AssignedExtension {
+        let mut result = self.ext_one(ctx);
+        for i in (0..64 - exponent.leading_zeros()).rev() {
+            result = self.ext_square(ctx, result);
+            if (exponent >> i) & 1 == 1 {
+                result = self.ext_mul(ctx, result, a);
+            }
+        }
+        result
+    }
+
+    /// `sum_i terms[i] * alpha^i`, as plonky2's `reduce_with_powers`.
+    pub fn ext_reduce_with_powers(
+        &self,
+        ctx: &mut Context<Fr>,
+        terms: &[AssignedExtension],
+        alpha: AssignedExtension,
+    ) -> AssignedExtension {
+        let zero = self.ext_zero(ctx);
+        terms.iter().rev().fold(zero, |acc, &term| self.ext_mul_add(ctx, acc, alpha, term))
+    }
+
+    /// The product of all `terms`, one if there are none.
+    pub fn ext_product(
+        &self,
+        ctx: &mut Context<Fr>,
+        terms: &[AssignedExtension],
+    ) -> AssignedExtension {
+        match terms.split_first() {
+            Some((&first, rest)) => rest.iter().fold(first, |acc, &t| self.ext_mul(ctx, acc, t)),
+            None => self.ext_one(ctx),
+        }
+    }
+
+    pub fn ext_assert_equal(
+        &self,
+        ctx: &mut Context<Fr>,
+        a: AssignedExtension,
+        b: AssignedExtension,
+    ) {
+        for i in 0..2 {
+            self.assert_equal(ctx, a.0[i], b.0[i]);
+        }
+    }
+
+    pub fn ext_assert_const(
+        &self,
+        ctx: &mut Context<Fr>,
+        a: AssignedExtension,
+        b: [Goldilocks; 2],
+    ) {
+        for i in 0..2 {
+            self.assert_const(ctx, a.0[i], b[i]);
+        }
+    }
+
+    /// `values[index]`, for the index given by its indicator vector.
+    pub fn ext_select_by_indicator(
+        &self,
+        ctx: &mut Context<Fr>,
+        values: &[AssignedExtension],
+        indicator: &[AssignedValue<Fr>],
+    ) -> AssignedExtension {
+        AssignedExtension([0, 1].map(|i| {
+            let column = values.iter().map(|a| a.0[i]).collect::<Vec<_>>();
+            self.select_by_indicator(ctx, &column, indicator)
+        }))
+    }
+
+    pub fn algebra_add(
+        &self,
+        ctx: &mut Context<Fr>,
+        a: AssignedAlgebra,
+        b: AssignedAlgebra,
+    ) -> AssignedAlgebra {
+        [0, 1].map(|i| self.ext_add(ctx, a[i], b[i]))
+    }
+
+    pub fn algebra_sub(
+        &self,
+        ctx: &mut Context<Fr>,
+        a: AssignedAlgebra,
+        b: AssignedAlgebra,
+    ) -> AssignedAlgebra {
+        [0, 1].map(|i| self.ext_sub(ctx, a[i], b[i]))
+    }
+
+    /// `(a0 + a1 v)(b0 + b1 v) = a0 b0 + 7 a1 b1 + (a0 b1 + a1 b0) v`.
+    pub fn algebra_mul(
+        &self,
+        ctx: &mut Context<Fr>,
+        a: AssignedAlgebra,
+        b: AssignedAlgebra,
+    ) -> AssignedAlgebra {
+        let a1b1 = self.ext_mul(ctx, a[1], b[1]);
+        let a1b1_w =
+            self.ext_linear_combination(ctx, &[(Goldilocks::new(W), a1b1)], [Goldilocks::ZERO; 2]);
+        let c0 = self.ext_mul_add(ctx, a[0], b[0], a1b1_w);
+        let a1b0 = self.ext_mul(ctx, a[1], b[0]);
+        let c1 = self.ext_mul_add(ctx, a[0], b[1], a1b0);
+        [c0, c1]
+    }
+
+    /// `a * b` for `a` in the extension field.
+    pub fn algebra_scalar_mul(
+        &self,
+        ctx: &mut Context<Fr>,
+        a: AssignedExtension,
+        b: AssignedAlgebra,
+    ) -> AssignedAlgebra {
+        b.map(|b| self.ext_mul(ctx, a, b))
+    }
+
+    /// `a + b` for `b` in the base field.
+    pub fn algebra_add_base_const(
+        &self,
+        ctx: &mut Context<Fr>,
+        a: AssignedAlgebra,
+        b: Goldilocks,
+    ) -> AssignedAlgebra {
+        [self.ext_add_base_const(ctx, a[0], b), a[1]]
+    }
+}
+
+/// Number of bits of a sum of `n` terms.
+fn bits_for(n: usize) -> usize {
+    (usize::BITS - n.leading_zeros()) as usize
+}
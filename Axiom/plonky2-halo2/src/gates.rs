@@ -0,0 +1,607 @@
+//! The constraints of plonky2's gates at the opening point `zeta`, as their `eval_unfiltered` in
+//! the extension field and the extension algebra, and the selector filters combining them.
+
+use std::ops::Range;
+
+use halo2_base::halo2_proofs::halo2curves::bn256::Fr;
+use halo2_base::Context;
+
+use crate::constants::{
+    FAST_PARTIAL_FIRST_ROUND_CONSTANT, FAST_PARTIAL_ROUND_CONSTANTS,
+    FAST_PARTIAL_ROUND_INITIAL_MATRIX, FAST_PARTIAL_ROUND_VS, FAST_PARTIAL_ROUND_W_HATS,
+};
+use crate::data::{Gate, UNUSED_SELECTOR};
+use crate::field::{AssignedAlgebra, AssignedExtension, GoldilocksChip};
+use crate::goldilocks::Goldilocks;
+use crate::hash::AssignedHash;
+use crate::poseidon::{
+    mds_coefficient, round_constant, HALF_N_FULL_ROUNDS, N_PARTIAL_ROUNDS, WIDTH,
+};
+
+/// The extension degree, `D` in plonky2.
+const D: usize = 2;
+
+/// `EvaluationVars`.
+#[derive(Clone, Copy)]
+pub struct EvaluationVars<'a> {
+    pub local_constants: &'a [AssignedExtension],
+    pub local_wires: &'a [AssignedExtension],
+    pub public_inputs_hash: &'a AssignedHash,
+}
+
+impl EvaluationVars<'_> {
+    fn get_local_ext_algebra(&self, start: usize) -> AssignedAlgebra {
+        [self.local_wires[start], self.local_wires[start + 1]]
+    }
+}
+
+/// `evaluate_gate_constraints`: the sum over the gates of their filtered constraints.
+pub fn evaluate_gate_constraints(
+    ctx: &mut Context<Fr>,
+    chip: &GoldilocksChip,
+    gates: &[Gate],
+    (selector_indices, groups): (&[usize], &[Range<usize>]),
+    num_gate_constraints: usize,
+    vars: EvaluationVars,
+) -> Vec<AssignedExtension> {
+    let num_selectors = groups.len();
+    let gate_vars =
+        EvaluationVars { local_constants: &vars.local_constants[num_selectors..], ..vars };
+    let mut constraints = vec![chip.ext_zero(ctx); num_gate_constraints];
+    for (i, gate) in gates.iter().enumerate() {
+        let gate_constraints = eval_unfiltered(ctx, chip, gate, gate_vars);
+        if gate_constraints.is_empty() {
+            continue;
+        }
+        assert!(gate_constraints.len() <= num_gate_constraints, "too many constraints in {gate:?}");
+        let selector_index = selector_indices[i];
+        let filter = compute_filter(
+            ctx,
+            chip,
+            i,
+            groups[selector_index].clone(),
+            vars.local_constants[selector_index],
+            num_selectors > 1,
+        );
+        for (acc, c) in constraints.iter_mut().zip(gate_constraints) {
+            *acc = chip.ext_mul_add(ctx, filter, c, *acc);
+        }
+    }
+    constraints
+}
+
+/// `compute_filter`: zero on the rows of the other gates of the group, and on the rows of the
+/// other groups if there are several.
+fn compute_filter(
+    ctx: &mut Context<Fr>,
+    chip: &GoldilocksChip,
+    row: usize,
+    group_range: Range<usize>,
+    s: AssignedExtension,
+    many_selectors: bool,
+) -> AssignedExtension {
+    let factors = group_range
+        .filter(|&i| i != row)
+        .map(|i| i as u64)
+        .chain(many_selectors.then_some(UNUSED_SELECTOR))
+        .map(|i| {
+            chip.ext_linear_combination(
+                ctx,
+                &[(-Goldilocks::ONE, s)],
+                [Goldilocks::new(i), Goldilocks::ZERO],
+            )
+        })
+        .collect::<Vec<_>>();
+    chip.ext_product(ctx, &factors)
+}
+
+pub fn eval_unfiltered(
+    ctx: &mut Context<Fr>,
+    chip: &GoldilocksChip,
+    gate: &Gate,
+    vars: EvaluationVars,
+) -> Vec<AssignedExtension> {
+    match *gate {
+        Gate::Arithmetic { num_ops } => eval_arithmetic(ctx, chip, num_ops, vars),
+        Gate::ArithmeticExtension { num_ops } => {
+            eval_arithmetic_extension(ctx, chip, num_ops, vars)
+        }
+        Gate::BaseSum { num_limbs, base } => eval_base_sum(ctx, chip, num_limbs, base, vars),
+        Gate::Constant { num_consts } => (0..num_consts)
+            .map(|i| chip.ext_sub(ctx, vars.local_constants[i], vars.local_wires[i]))
+            .collect(),
+        Gate::CosetInterpolation { subgroup_bits, degree } => {
+            eval_coset_interpolation(ctx, chip, subgroup_bits, degree, vars)
+        }
+        Gate::Exponentiation { num_power_bits } => {
+            eval_exponentiation(ctx, chip, num_power_bits, vars)
+        }
+        Gate::MulExtension { num_ops } => eval_mul_extension(ctx, chip, num_ops, vars),
+        Gate::Noop => vec![],
+        Gate::Poseidon => eval_poseidon(ctx, chip, vars),
+        Gate::PoseidonMds => eval_poseidon_mds(ctx, chip, vars),
+        Gate::PublicInput => (0..4)
+            .map(|i| {
+                let hash = chip.ext_from_base(ctx, vars.public_inputs_hash[i]);
+                chip.ext_sub(ctx, vars.local_wires[i], hash)
+            })
+            .collect(),
+        Gate::RandomAccess { bits, num_copies, num_extra_constants } => {
+            eval_random_access(ctx, chip, bits, num_copies, num_extra_constants, vars)
+        }
+        Gate::Reducing { num_coeffs } => eval_reducing(ctx, chip, num_coeffs, false, vars),
+        Gate::ReducingExtension { num_coeffs } => eval_reducing(ctx, chip, num_coeffs, true, vars),
+    }
+}
+
+fn ext_const(ctx: &mut Context<Fr>, chip: &GoldilocksChip, value: u64) -> AssignedExtension {
+    chip.load_ext_constant(ctx, [Goldilocks::new(value), Goldilocks::ZERO])
+}
+
+/// `c * a` for a constant `c` of the base field.
+fn algebra_scale_const(
+    ctx: &mut Context<Fr>,
+    chip: &GoldilocksChip,
+    c: Goldilocks,
+    a: AssignedAlgebra,
+) -> AssignedAlgebra {
+    a.map(|a| chip.ext_linear_combination(ctx, &[(c, a)], [Goldilocks::ZERO; 2]))
+}
+
+fn eval_arithmetic(
+    ctx: &mut Context<Fr>,
+    chip: &GoldilocksChip,
+    num_ops: usize,
+    vars: EvaluationVars,
+) -> Vec<AssignedExtension> {
+    let [const_0, const_1] = [vars.local_constants[0], vars.local_constants[1]];
+    (0..num_ops)
+        .map(|i| {
+            let [multiplicand_0, multiplicand_1, addend, output] =
+                std::array::from_fn(|j| vars.local_wires[4 * i + j]);
+            let product = chip.ext_mul(ctx, multiplicand_0, multiplicand_1);
+            let product = chip.ext_mul(ctx, product, const_0);
+            let computed_output = chip.ext_mul_add(ctx, addend, const_1, product);
+            chip.ext_sub(ctx, output, computed_output)
+        })
+        .collect()
+}
+
+fn eval_arithmetic_extension(
+    ctx: &mut Context<Fr>,
+    chip: &GoldilocksChip,
+    num_ops: usize,
+    vars: EvaluationVars,
+) -> Vec<AssignedExtension> {
+    let [const_0, const_1] = [vars.local_constants[0], vars.local_constants[1]];
+    let mut constraints = Vec::with_capacity(num_ops * D);
+    for i in 0..num_ops {
+        let [multiplicand_0, multiplicand_1, addend, output] =
+            std::array::from_fn(|j| vars.get_local_ext_algebra(4 * D * i + j * D));
+        let product = chip.algebra_mul(ctx, multiplicand_0, multiplicand_1);
+        let product = chip.algebra_scalar_mul(ctx, const_0, product);
+        let addend = chip.algebra_scalar_mul(ctx, const_1, addend);
+        let computed_output = chip.algebra_add(ctx, product, addend);
+        constraints.extend(chip.algebra_sub(ctx, output, computed_output));
+    }
+    constraints
+}
+
+fn eval_mul_extension(
+    ctx: &mut Context<Fr>,
+    chip: &GoldilocksChip,
+    num_ops: usize,
+    vars: EvaluationVars,
+) -> Vec<AssignedExtension> {
+    let const_0 = vars.local_constants[0];
+    let mut constraints = Vec::with_capacity(num_ops * D);
+    for i in 0..num_ops {
+        let [multiplicand_0, multiplicand_1, output] =
+            std::array::from_fn(|j| vars.get_local_ext_algebra(3 * D * i + j * D));
+        let product = chip.algebra_mul(ctx, multiplicand_0, multiplicand_1);
+        let computed_output = chip.algebra_scalar_mul(ctx, const_0, product);
+        constraints.extend(chip.algebra_sub(ctx, output, computed_output));
+    }
+    constraints
+}
+
+fn eval_base_sum(
+    ctx: &mut Context<Fr>,
+    chip: &GoldilocksChip,
+    num_limbs: usize,
+    base: usize,
+    vars: EvaluationVars,
+) -> Vec<AssignedExtension> {
+    let sum = vars.local_wires[0];
+    let limbs = &vars.local_wires[1..1 + num_limbs];
+    let base_ext = ext_const(ctx, chip, base as u64);
+    let computed_sum = chip.ext_reduce_with_powers(ctx, limbs, base_ext);
+    let mut constraints = vec![chip.ext_sub(ctx, computed_sum, sum)];
+    for &limb in limbs {
+        let factors = (0..base as u64)
+            .map(|i| chip.ext_add_base_const(ctx, limb, -Goldilocks::new(i)))
+            .collect::<Vec<_>>();
+        constraints.push(chip.ext_product(ctx, &factors));
+    }
+    constraints
+}
+
+/// The barycentric weights of the subgroup of order `2^bits`, `1 / prod_{j != i} (x_i - x_j)`.
+fn barycentric_weights(domain: &[Goldilocks]) -> Vec<Goldilocks> {
+    (0..domain.len())
+        .map(|i| {
+            (0..domain.len())
+                .filter(|&j| j != i)
+                .fold(Goldilocks::ONE, |acc, j| acc * (domain[i] - domain[j]))
+                .inverse()
+        })
+        .collect()
+}
+
+/// `partial_interpolate_ext_algebra`.
+fn partial_interpolate(
+    ctx: &mut Context<Fr>,
+    chip: &GoldilocksChip,
+    domain: &[Goldilocks],
+    values: &[AssignedAlgebra],
+    weights: &[Goldilocks],
+    point: AssignedAlgebra,
+    initial: (AssignedAlgebra, AssignedAlgebra),
+) -> (AssignedAlgebra, AssignedAlgebra) {
+    values.iter().zip(domain).zip(weights).fold(
+        initial,
+        |(eval, partial_prod), ((&val, &x), &w)| {
+            let term = chip.algebra_add_base_const(ctx, point, -x);
+            let weighted_val = algebra_scale_const(ctx, chip, w, val);
+            let eval = chip.algebra_mul(ctx, eval, term);
+            let weighted_prod = chip.algebra_mul(ctx, weighted_val, partial_prod);
+            let eval = chip.algebra_add(ctx, weighted_prod, eval);
+            let partial_prod = chip.algebra_mul(ctx, partial_prod, term);
+            (eval, partial_prod)
+        },
+    )
+}
+
+fn eval_coset_interpolation(
+    ctx: &mut Context<Fr>,
+    chip: &GoldilocksChip,
+    subgroup_bits: usize,
+    degree: usize,
+    vars: EvaluationVars,
+) -> Vec<AssignedExtension> {
+    let num_points = 1 << subgroup_bits;
+    let num_intermediates = (num_points - 2) / (degree - 1);
+    let start_evaluation_point = 1 + num_points * D;
+    let start_evaluation_value = start_evaluation_point + D;
+    let start_intermediates = start_evaluation_value + D;
+    let mut constraints = Vec::with_capacity(2 * D + 2 * D * num_intermediates);
+
+    let shift = vars.local_wires[0];
+    let evaluation_point = vars.get_local_ext_algebra(start_evaluation_point);
+    let shifted_evaluation_point =
+        vars.get_local_ext_algebra(start_intermediates + 2 * D * num_intermediates);
+    let shifted_times_shift = chip.algebra_scalar_mul(ctx, shift, shifted_evaluation_point);
+    constraints.extend(chip.algebra_sub(ctx, evaluation_point, shifted_times_shift));
+
+    let domain = Goldilocks::two_adic_subgroup(subgroup_bits);
+    let weights = barycentric_weights(&domain);
+    let values = (0..num_points).map(|i| vars.get_local_ext_algebra(1 + i * D)).collect::<Vec<_>>();
+    let zero = chip.ext_zero(ctx);
+    let one = chip.ext_one(ctx);
+
+    let (mut computed_eval, mut computed_prod) = partial_interpolate(
+        ctx,
+        chip,
+        &domain[..degree],
+        &values[..degree],
+        &weights[..degree],
+        shifted_evaluation_point,
+        ([zero; 2], [one, zero]),
+    );
+    for i in 0..num_intermediates {
+        let intermediate_eval = vars.get_local_ext_algebra(start_intermediates + D * i);
+        let intermediate_prod =
+            vars.get_local_ext_algebra(start_intermediates + D * (num_intermediates + i));
+        constraints.extend(chip.algebra_sub(ctx, intermediate_eval, computed_eval));
+        constraints.extend(chip.algebra_sub(ctx, intermediate_prod, computed_prod));
+
+        let start = 1 + (degree - 1) * (i + 1);
+        let end = (start + degree - 1).min(num_points);
+        (computed_eval, computed_prod) = partial_interpolate(
+            ctx,
+            chip,
+            &domain[start..end],
+            &values[start..end],
+            &weights[start..end],
+            shifted_evaluation_point,
+            (intermediate_eval, intermediate_prod),
+        );
+    }
+
+    let evaluation_value = vars.get_local_ext_algebra(start_evaluation_value);
+    constraints.extend(chip.algebra_sub(ctx, evaluation_value, computed_eval));
+    constraints
+}
+
+fn eval_exponentiation(
+    ctx: &mut Context<Fr>,
+    chip: &GoldilocksChip,
+    num_power_bits: usize,
+    vars: EvaluationVars,
+) -> Vec<AssignedExtension> {
+    let base = vars.local_wires[0];
+    let power_bits = &vars.local_wires[1..1 + num_power_bits];
+    let output = vars.local_wires[1 + num_power_bits];
+    let intermediate_values = &vars.local_wires[2 + num_power_bits..2 + 2 * num_power_bits];
+    // bit * (base - 1) + 1
+    let base_minus_one = chip.ext_add_base_const(ctx, base, -Goldilocks::ONE);
+
+    let mut constraints = Vec::with_capacity(num_power_bits + 1);
+    for i in 0..num_power_bits {
+        // power_bits is in LE order, but we accumulate in BE order.
+        let cur_bit = power_bits[num_power_bits - i - 1];
+        let factor = chip.ext_mul(ctx, cur_bit, base_minus_one);
+        let factor = chip.ext_add_base_const(ctx, factor, Goldilocks::ONE);
+        let computed_intermediate_value = if i == 0 {
+            factor
+        } else {
+            let prev = chip.ext_square(ctx, intermediate_values[i - 1]);
+            chip.ext_mul(ctx, prev, factor)
+        };
+        constraints.push(chip.ext_sub(ctx, computed_intermediate_value, intermediate_values[i]));
+    }
+    constraints.push(chip.ext_sub(ctx, output, intermediate_values[num_power_bits - 1]));
+    constraints
+}
+
+fn eval_random_access(
+    ctx: &mut Context<Fr>,
+    chip: &GoldilocksChip,
+    bits: usize,
+    num_copies: usize,
+    num_extra_constants: usize,
+    vars: EvaluationVars,
+) -> Vec<AssignedExtension> {
+    let vec_size = 1 << bits;
+    let num_routed_wires = (2 + vec_size) * num_copies + num_extra_constants;
+    let mut constraints = Vec::with_capacity(num_copies * (bits + 2) + num_extra_constants);
+
+    for copy in 0..num_copies {
+        let start = (2 + vec_size) * copy;
+        let access_index = vars.local_wires[start];
+        let claimed_element = vars.local_wires[start + 1];
+        let mut list_items = vars.local_wires[start + 2..start + 2 + vec_size].to_vec();
+        let start_bits = num_routed_wires + copy * bits;
+        let bit_wires = &vars.local_wires[start_bits..start_bits + bits];
+
+        for &b in bit_wires {
+            let b_minus_one = chip.ext_add_base_const(ctx, b, -Goldilocks::ONE);
+            constraints.push(chip.ext_mul(ctx, b, b_minus_one));
+        }
+
+        let terms = bit_wires
+            .iter()
+            .enumerate()
+            .map(|(i, &b)| (Goldilocks::new(1 << i), b))
+            .chain([(-Goldilocks::ONE, access_index)])
+            .collect::<Vec<_>>();
+        constraints.push(chip.ext_linear_combination(ctx, &terms, [Goldilocks::ZERO; 2]));
+
+        for &b in bit_wires {
+            list_items = list_items
+                .chunks(2)
+                .map(|pair| {
+                    let diff = chip.ext_sub(ctx, pair[1], pair[0]);
+                    chip.ext_mul_add(ctx, b, diff, pair[0])
+                })
+                .collect();
+        }
+        constraints.push(chip.ext_sub(ctx, list_items[0], claimed_element));
+    }
+
+    let start_extra_constants = (2 + vec_size) * num_copies;
+    constraints.extend((0..num_extra_constants).map(|i| {
+        chip.ext_sub(ctx, vars.local_constants[i], vars.local_wires[start_extra_constants + i])
+    }));
+    constraints
+}
+
+/// `ReducingGate` or, with `extension_coeffs`, `ReducingExtensionGate`.
+fn eval_reducing(
+    ctx: &mut Context<Fr>,
+    chip: &GoldilocksChip,
+    num_coeffs: usize,
+    extension_coeffs: bool,
+    vars: EvaluationVars,
+) -> Vec<AssignedExtension> {
+    let alpha = vars.get_local_ext_algebra(D);
+    let old_acc = vars.get_local_ext_algebra(2 * D);
+    let zero = chip.ext_zero(ctx);
+    let coeff_size = if extension_coeffs { D } else { 1 };
+    let coeff = |i: usize| {
+        let start = 3 * D + i * coeff_size;
+        if extension_coeffs {
+            vars.get_local_ext_algebra(start)
+        } else {
+            [vars.local_wires[start], zero]
+        }
+    };
+    let start_accs = 3 * D + num_coeffs * coeff_size;
+    let accs = (0..num_coeffs)
+        .map(|i| {
+            // The last accumulator is the output.
+            let start = if i == num_coeffs - 1 { 0 } else { start_accs + D * i };
+            vars.get_local_ext_algebra(start)
+        })
+        .collect::<Vec<_>>();
+
+    let mut constraints = Vec::with_capacity(num_coeffs * D);
+    let mut acc = old_acc;
+    for (i, &next_acc) in accs.iter().enumerate() {
+        let product = chip.algebra_mul(ctx, acc, alpha);
+        let computed = chip.algebra_add(ctx, product, coeff(i));
+        constraints.extend(chip.algebra_sub(ctx, computed, next_acc));
+        acc = next_acc;
+    }
+    constraints
+}
+
+fn eval_poseidon_mds(
+    ctx: &mut Context<Fr>,
+    chip: &GoldilocksChip,
+    vars: EvaluationVars,
+) -> Vec<AssignedExtension> {
+    let inputs: [AssignedAlgebra; WIDTH] =
+        std::array::from_fn(|i| vars.get_local_ext_algebra(i * D));
+    let mut constraints = Vec::with_capacity(WIDTH * D);
+    for r in 0..WIDTH {
+        let computed: AssignedAlgebra = std::array::from_fn(|k| {
+            let terms =
+                (0..WIDTH).map(|j| (mds_coefficient(r, j), inputs[j][k])).collect::<Vec<_>>();
+            chip.ext_linear_combination(ctx, &terms, [Goldilocks::ZERO; 2])
+        });
+        let output = vars.get_local_ext_algebra((WIDTH + r) * D);
+        constraints.extend(chip.algebra_sub(ctx, output, computed));
+    }
+    constraints
+}
+
+fn sbox_monomial(
+    ctx: &mut Context<Fr>,
+    chip: &GoldilocksChip,
+    x: AssignedExtension,
+) -> AssignedExtension {
+    let x2 = chip.ext_square(ctx, x);
+    let x3 = chip.ext_mul(ctx, x2, x);
+    let x4 = chip.ext_square(ctx, x2);
+    chip.ext_mul(ctx, x3, x4)
+}
+
+fn mds_layer(
+    ctx: &mut Context<Fr>,
+    chip: &GoldilocksChip,
+    state: &[AssignedExtension; WIDTH],
+) -> [AssignedExtension; WIDTH] {
+    std::array::from_fn(|r| {
+        let terms = (0..WIDTH).map(|j| (mds_coefficient(r, j), state[j])).collect::<Vec<_>>();
+        chip.ext_linear_combination(ctx, &terms, [Goldilocks::ZERO; 2])
+    })
+}
+
+/// A full round of the permutation, whose S-box inputs are constrained to `sbox_wires` if the
+/// gate stores them.
+fn full_round(
+    ctx: &mut Context<Fr>,
+    chip: &GoldilocksChip,
+    mut state: [AssignedExtension; WIDTH],
+    round: usize,
+    sbox_wires: Option<&[AssignedExtension]>,
+    constraints: &mut Vec<AssignedExtension>,
+) -> [AssignedExtension; WIDTH] {
+    for (i, s) in state.iter_mut().enumerate() {
+        *s = chip.ext_add_base_const(ctx, *s, round_constant(round, i));
+        if let Some(sbox_wires) = sbox_wires {
+            constraints.push(chip.ext_sub(ctx, *s, sbox_wires[i]));
+            *s = sbox_wires[i];
+        }
+        *s = sbox_monomial(ctx, chip, *s);
+    }
+    mds_layer(ctx, chip, &state)
+}
+
+fn eval_poseidon(
+    ctx: &mut Context<Fr>,
+    chip: &GoldilocksChip,
+    vars: EvaluationVars,
+) -> Vec<AssignedExtension> {
+    const WIRE_SWAP: usize = 2 * WIDTH;
+    const START_DELTA: usize = WIRE_SWAP + 1;
+    const START_FULL_0: usize = START_DELTA + 4;
+    const START_PARTIAL: usize = START_FULL_0 + WIDTH * (HALF_N_FULL_ROUNDS - 1);
+    const START_FULL_1: usize = START_PARTIAL + N_PARTIAL_ROUNDS;
+    let wires = vars.local_wires;
+    let mut constraints = vec![];
+
+    // Assert that `swap` is binary.
+    let swap = wires[WIRE_SWAP];
+    let swap_minus_one = chip.ext_add_base_const(ctx, swap, -Goldilocks::ONE);
+    constraints.push(chip.ext_mul(ctx, swap, swap_minus_one));
+
+    // Assert that each delta wire is set properly: `delta_i = swap * (rhs - lhs)`.
+    for i in 0..4 {
+        let diff = chip.ext_sub(ctx, wires[i + 4], wires[i]);
+        let delta = chip.ext_mul(ctx, swap, diff);
+        constraints.push(chip.ext_sub(ctx, delta, wires[START_DELTA + i]));
+    }
+
+    // Compute the possibly-swapped input layer.
+    let mut state: [AssignedExtension; WIDTH] = std::array::from_fn(|i| wires[i]);
+    for i in 0..4 {
+        let delta = wires[START_DELTA + i];
+        state[i] = chip.ext_add(ctx, wires[i], delta);
+        state[i + 4] = chip.ext_sub(ctx, wires[i + 4], delta);
+    }
+
+    // First set of full rounds.
+    for r in 0..HALF_N_FULL_ROUNDS {
+        let sbox_wires = (r != 0).then(|| START_FULL_0 + WIDTH * (r - 1));
+        state = full_round(
+            ctx,
+            chip,
+            state,
+            r,
+            sbox_wires.map(|start| &wires[start..]),
+            &mut constraints,
+        );
+    }
+
+    // Partial rounds.
+    for (s, &c) in state.iter_mut().zip(&FAST_PARTIAL_FIRST_ROUND_CONSTANT) {
+        *s = chip.ext_add_base_const(ctx, *s, Goldilocks::new(c));
+    }
+    let init = state;
+    for (c, s) in state.iter_mut().enumerate().skip(1) {
+        let terms = (1..WIDTH)
+            .map(|r| (Goldilocks::new(FAST_PARTIAL_ROUND_INITIAL_MATRIX[r - 1][c - 1]), init[r]))
+            .collect::<Vec<_>>();
+        *s = chip.ext_linear_combination(ctx, &terms, [Goldilocks::ZERO; 2]);
+    }
+    for r in 0..N_PARTIAL_ROUNDS {
+        let sbox_in = wires[START_PARTIAL + r];
+        constraints.push(chip.ext_sub(ctx, state[0], sbox_in));
+        state[0] = sbox_monomial(ctx, chip, sbox_in);
+        if r < N_PARTIAL_ROUNDS - 1 {
+            let constant = Goldilocks::new(FAST_PARTIAL_ROUND_CONSTANTS[r]);
+            state[0] = chip.ext_add_base_const(ctx, state[0], constant);
+        }
+        // mds_partial_layer_fast
+        let s0 = state[0];
+        let terms = [(mds_coefficient(0, 0), s0)]
+            .into_iter()
+            .chain(
+                (1..WIDTH)
+                    .map(|i| (Goldilocks::new(FAST_PARTIAL_ROUND_W_HATS[r][i - 1]), state[i])),
+            )
+            .collect::<Vec<_>>();
+        state[0] = chip.ext_linear_combination(ctx, &terms, [Goldilocks::ZERO; 2]);
+        for i in 1..WIDTH {
+            let terms = [
+                (Goldilocks::new(FAST_PARTIAL_ROUND_VS[r][i - 1]), s0),
+                (Goldilocks::ONE, state[i]),
+            ];
+            state[i] = chip.ext_linear_combination(ctx, &terms, [Goldilocks::ZERO; 2]);
+        }
+    }
+
+    // Second set of full rounds.
+    for r in 0..HALF_N_FULL_ROUNDS {
+        let round = HALF_N_FULL_ROUNDS + N_PARTIAL_ROUNDS + r;
+        let sbox_wires = &wires[START_FULL_1 + WIDTH * r..];
+        state = full_round(ctx, chip, state, round, Some(sbox_wires), &mut constraints);
+    }
+
+    for (i, s) in state.into_iter().enumerate() {
+        constraints.push(chip.ext_sub(ctx, s, wires[WIDTH + i]));
+    }
+    constraints
+}
@@ -0,0 +1,122 @@
+//! Native arithmetic in the Goldilocks field, for the constants of the verifier circuit.
+
+use std::ops::{Add, Mul, Neg, Sub};
+
+/// The Goldilocks prime `2^64 - 2^32 + 1`.
+pub const ORDER: u64 = 0xFFFF_FFFF_0000_0001;
+/// Generator of the multiplicative group, the coset shift of plonky2's LDE domain.
+pub const MULTIPLICATIVE_GENERATOR: u64 = 7;
+/// `x^2 - W` is the irreducible polynomial of the quadratic extension.
+pub const W: u64 = 7;
+pub const TWO_ADICITY: usize = 32;
+/// A `2^TWO_ADICITY`-th root of unity.
+const POWER_OF_TWO_GENERATOR: u64 = 7277203076849721926;
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Goldilocks(u64);
+
+impl Goldilocks {
+    pub const ZERO: Self = Self(0);
+    pub const ONE: Self = Self(1);
+
+    pub fn new(value: u64) -> Self {
+        Self(value % ORDER)
+    }
+
+    pub fn to_canonical_u64(self) -> u64 {
+        self.0
+    }
+
+    pub fn exp_u64(self, mut exponent: u64) -> Self {
+        let mut base = self;
+        let mut result = Self::ONE;
+        while exponent > 0 {
+            if exponent & 1 == 1 {
+                result = result * base;
+            }
+            base = base * base;
+            exponent >>= 1;
+        }
+        result
+    }
+
+    pub fn inverse(self) -> Self {
+        assert_ne!(self, Self::ZERO, "zero has no inverse");
+        self.exp_u64(ORDER - 2)
+    }
+
+    /// The generator of the subgroup of order `2^bits` that plonky2 uses.
+    pub fn primitive_root_of_unity(bits: usize) -> Self {
+        assert!(bits <= TWO_ADICITY);
+        Self(POWER_OF_TWO_GENERATOR).exp_u64(1 << (TWO_ADICITY - bits))
+    }
+
+    /// The elements of the subgroup of order `2^bits`, in the order of plonky2's
+    /// `two_adic_subgroup`.
+    pub fn two_adic_subgroup(bits: usize) -> Vec<Self> {
+        let generator = Self::primitive_root_of_unity(bits);
+        std::iter::successors(Some(Self::ONE), |&x| Some(x * generator)).take(1 << bits).collect()
+    }
+}
+
+impl Add for Goldilocks {
+    type Output = Self;
+
+    fn add(self, rhs: Self) -> Self {
+        Self(((self.0 as u128 + rhs.0 as u128) % ORDER as u128) as u64)
+    }
+}
+
+impl Sub for Goldilocks {
+    type Output = Self;
+
+    fn sub(self, rhs: Self) -> Self {
+        self + -rhs
+    }
+}
+
+impl Neg for Goldilocks {
+    type Output = Self;
+
+    fn neg(self) -> Self {
+        if self.0 == 0 {
+            self
+        } else {
+            Self(ORDER - self.0)
+        }
+    }
+}
+
+impl Mul for Goldilocks {
+    type Output = Self;
+
+    fn mul(self, rhs: Self) -> Self {
+        Self(((self.0 as u128 * rhs.0 as u128) % ORDER as u128) as u64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_roots_of_unity() {
+        for bits in [0, 1, 4, 13, TWO_ADICITY] {
+            let root = Goldilocks::primitive_root_of_unity(bits);
+            let mut x = root;
+            for _ in 0..bits {
+                assert_ne!(x, Goldilocks::ONE);
+                x = x * x;
+            }
+            assert_eq!(x, Goldilocks::ONE);
+        }
+    }
+
+    #[test]
+    fn test_inverse() {
+        for x in [1, 2, 7, ORDER - 1, 0x1234_5678_9abc_def0] {
+            let x = Goldilocks::new(x);
+            assert_eq!(x * x.inverse(), Goldilocks::ONE);
+        }
+    }
+}
@@ -0,0 +1,195 @@
+//! The hashes of a `PoseidonBN128GoldilocksConfig` proof in a circuit: the BN254 Poseidon
+//! sponge of its Merkle trees and challenger, and the Goldilocks Poseidon hash of its public
+//! inputs.
+
+use halo2_base::gates::{GateInstructions, RangeInstructions};
+use halo2_base::halo2_proofs::arithmetic::FieldExt;
+use halo2_base::halo2_proofs::halo2curves::bn256::Fr;
+use halo2_base::utils::{fe_to_biguint, modulus};
+use halo2_base::QuantumCell::Constant;
+use halo2_base::{AssignedValue, Context};
+use num_bigint::BigUint;
+use poseidon_bn128::{is_full_round, mds, round_constants, GOLDILOCKS_PER_ELEMENT};
+
+use crate::field::{AssignedGoldilocks, GoldilocksChip};
+use crate::poseidon::{self, RATE, WIDTH};
+
+/// A plonky2 `HashOut`.
+pub type AssignedHash = [AssignedGoldilocks; 4];
+
+/// Number of bits of the top limb of a BN254 element.
+const TOP_LIMB_BITS: usize = 254 - 3 * 64;
+
+fn to_fr(x: &poseidon_bn128::Fr) -> Fr {
+    Fr::from_raw(x.to_canonical())
+}
+
+/// The permutation of `poseidon_bn128::permute_goldilocks`.
+pub fn permute_bn128(
+    ctx: &mut Context<Fr>,
+    chip: &GoldilocksChip,
+    input: [AssignedGoldilocks; WIDTH],
+) -> [AssignedGoldilocks; WIDTH] {
+    let gate = chip.gate();
+    let two_64 = Fr::from_u128(1 << 64);
+    let powers = [Fr::from(1), two_64, two_64 * two_64];
+
+    let mut state: [AssignedValue<Fr>; poseidon_bn128::WIDTH] = std::array::from_fn(|i| {
+        let lanes = &input[i * GOLDILOCKS_PER_ELEMENT..(i + 1) * GOLDILOCKS_PER_ELEMENT];
+        gate.inner_product(ctx, lanes.iter().map(|a| a.cell()), powers.map(Constant))
+    });
+
+    for (round, constants) in round_constants().iter().enumerate() {
+        let sbox_lanes = if is_full_round(round) { poseidon_bn128::WIDTH } else { 1 };
+        for (i, (s, c)) in state.iter_mut().zip(constants).enumerate() {
+            *s = gate.add(ctx, *s, Constant(to_fr(c)));
+            if i < sbox_lanes {
+                let x2 = gate.mul(ctx, *s, *s);
+                let x4 = gate.mul(ctx, x2, x2);
+                *s = gate.mul(ctx, x4, *s);
+            }
+        }
+        state = std::array::from_fn(|i| {
+            gate.inner_product(ctx, state, mds()[i].iter().map(|m| Constant(to_fr(m))))
+        });
+    }
+
+    let mut output = Vec::with_capacity(WIDTH);
+    for s in state {
+        output.extend(unpack(ctx, chip, s));
+    }
+    output.try_into().unwrap()
+}
+
+/// Splits a BN254 element into its low 3 limbs of 64 bits, each reduced modulo the Goldilocks
+/// order. The decomposition is constrained to be the canonical one.
+fn unpack(
+    ctx: &mut Context<Fr>,
+    chip: &GoldilocksChip,
+    x: AssignedValue<Fr>,
+) -> [AssignedGoldilocks; GOLDILOCKS_PER_ELEMENT] {
+    let gate = chip.gate();
+    let range = chip.range();
+    let mut digits = fe_to_biguint(x.value()).to_u64_digits();
+    digits.resize(4, 0);
+    let limbs = digits.iter().map(|&d| ctx.load_witness(Fr::from(d))).collect::<Vec<_>>();
+    for (i, &limb) in limbs.iter().enumerate() {
+        range.range_check(ctx, limb, if i == 3 { TOP_LIMB_BITS } else { 64 });
+    }
+
+    let two_64 = Fr::from_u128(1 << 64);
+    let lo = gate.mul_add(ctx, limbs[1], Constant(two_64), limbs[0]);
+    let hi = gate.mul_add(ctx, limbs[3], Constant(two_64), limbs[2]);
+    let recomposed = gate.mul_add(ctx, hi, Constant(two_64 * two_64), lo);
+    ctx.constrain_equal(&recomposed, &x);
+
+    // (hi, lo) < (modulus_hi, modulus_lo)
+    let modulus = modulus::<Fr>();
+    let mask = (BigUint::from(1u64) << 128) - 1u64;
+    let [modulus_hi, modulus_lo] =
+        [&modulus >> 128, modulus & mask].map(|m| Fr::from_u128(m.try_into().unwrap()));
+    let hi_lt = range.is_less_than(ctx, hi, Constant(modulus_hi), 128);
+    let hi_eq = gate.is_equal(ctx, hi, Constant(modulus_hi));
+    let lo_lt = range.is_less_than(ctx, lo, Constant(modulus_lo), 128);
+    let canonical = gate.or_and(ctx, hi_lt, hi_eq, lo_lt);
+    gate.assert_is_const(ctx, &canonical, &Fr::from(1));
+
+    std::array::from_fn(|i| chip.reduce(ctx, limbs[i], 64))
+}
+
+/// plonky2's `hash_n_to_hash_no_pad`, an overwrite mode sponge.
+fn hash_no_pad(
+    ctx: &mut Context<Fr>,
+    chip: &GoldilocksChip,
+    inputs: &[AssignedGoldilocks],
+    permute: impl Fn(
+        &mut Context<Fr>,
+        &GoldilocksChip,
+        [AssignedGoldilocks; WIDTH],
+    ) -> [AssignedGoldilocks; WIDTH],
+) -> AssignedHash {
+    let zero = chip.zero(ctx);
+    let mut state = [zero; WIDTH];
+    for chunk in inputs.chunks(RATE) {
+        state[..chunk.len()].copy_from_slice(chunk);
+        state = permute(ctx, chip, state);
+    }
+    state[..4].try_into().unwrap()
+}
+
+/// The hash of the public inputs, with Goldilocks Poseidon.
+pub fn hash_public_inputs(
+    ctx: &mut Context<Fr>,
+    chip: &GoldilocksChip,
+    public_inputs: &[AssignedGoldilocks],
+) -> AssignedHash {
+    hash_no_pad(ctx, chip, public_inputs, poseidon::permute_circuit)
+}
+
+/// `Hasher::hash_or_noop`: inputs that fit in a hash are zero padded rather than hashed.
+pub fn hash_or_noop(
+    ctx: &mut Context<Fr>,
+    chip: &GoldilocksChip,
+    inputs: &[AssignedGoldilocks],
+) -> AssignedHash {
+    if inputs.len() <= 4 {
+        let zero = chip.zero(ctx);
+        std::array::from_fn(|i| inputs.get(i).copied().unwrap_or(zero))
+    } else {
+        hash_no_pad(ctx, chip, inputs, permute_bn128)
+    }
+}
+
+/// `Hasher::two_to_one`.
+pub fn two_to_one(
+    ctx: &mut Context<Fr>,
+    chip: &GoldilocksChip,
+    left: AssignedHash,
+    right: AssignedHash,
+) -> AssignedHash {
+    let zero = chip.zero(ctx);
+    let mut state = [zero; WIDTH];
+    state[..4].copy_from_slice(&left);
+    state[4..8].copy_from_slice(&right);
+    permute_bn128(ctx, chip, state)[..4].try_into().unwrap()
+}
+
+/// `cap[index]`, for the index given by its little endian bits.
+pub fn select_cap(
+    ctx: &mut Context<Fr>,
+    chip: &GoldilocksChip,
+    cap: &[AssignedHash],
+    index_bits: &[AssignedValue<Fr>],
+) -> AssignedHash {
+    assert_eq!(cap.len(), 1 << index_bits.len());
+    if index_bits.is_empty() {
+        return cap[0];
+    }
+    let indicator = chip.gate().bits_to_indicator(ctx, index_bits);
+    std::array::from_fn(|i| {
+        let column = cap.iter().map(|h| h[i]).collect::<Vec<_>>();
+        chip.select_by_indicator(ctx, &column, &indicator)
+    })
+}
+
+/// `verify_merkle_proof_to_cap`: the leaf at the index given by its little endian bits is in
+/// the tree of `cap`. The bits after the `siblings` are the index in the cap.
+pub fn verify_merkle_proof_to_cap(
+    ctx: &mut Context<Fr>,
+    chip: &GoldilocksChip,
+    leaf: &[AssignedGoldilocks],
+    index_bits: &[AssignedValue<Fr>],
+    cap: &[AssignedHash],
+    siblings: &[AssignedHash],
+) {
+    let mut digest = hash_or_noop(ctx, chip, leaf);
+    for (&bit, sibling) in index_bits.iter().zip(siblings) {
+        let left = std::array::from_fn(|i| chip.select(ctx, sibling[i], digest[i], bit));
+        let right = std::array::from_fn(|i| chip.select(ctx, digest[i], sibling[i], bit));
+        digest = two_to_one(ctx, chip, left, right);
+    }
+    let root = select_cap(ctx, chip, cap, &index_bits[siblings.len()..]);
+    for (a, b) in digest.into_iter().zip(root) {
+        chip.assert_equal(ctx, a, b);
+    }
+}
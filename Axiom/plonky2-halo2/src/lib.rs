@@ -0,0 +1,23 @@
+//! A halo2 circuit verifying plonky2 proofs, so that they can be verified on the EVM through a
+//! KZG proof over BN254.
+//!
+//! The verified proofs use a `GenericConfig` whose Merkle trees and Fiat-Shamir transcript are
+//! hashed with [`poseidon_bn128::permute_goldilocks`] rather than Goldilocks Poseidon, which
+//! would be far too costly to emulate in the circuit. Such proofs are produced by a last layer of
+//! recursion, e.g. `PoseidonBN128GoldilocksConfig` of `keccak256` and `keccak1`.
+//! Goldilocks elements are emulated with range checks in [`field`].
+
+pub mod challenger;
+pub mod circuit;
+#[rustfmt::skip]
+mod constants;
+pub mod data;
+pub mod field;
+pub mod gates;
+pub mod goldilocks;
+pub mod hash;
+pub mod poseidon;
+pub mod verifier;
+
+pub use circuit::Plonky2Verifier;
+pub use data::{CommonData, Proof, VerifierOnlyData};
@@ -0,0 +1,139 @@
+//! plonky2's Poseidon permutation over Goldilocks, which hashes the public inputs of a proof
+//! whatever its `GenericConfig`, and whose constants are used by `PoseidonGate`.
+
+use halo2_base::halo2_proofs::halo2curves::bn256::Fr;
+use halo2_base::Context;
+
+use crate::constants::{ALL_ROUND_CONSTANTS, MDS_MATRIX_CIRC, MDS_MATRIX_DIAG};
+use crate::field::{AssignedGoldilocks, GoldilocksChip};
+use crate::goldilocks::Goldilocks;
+
+pub const WIDTH: usize = 12;
+pub const RATE: usize = 8;
+pub const HALF_N_FULL_ROUNDS: usize = 4;
+pub const N_PARTIAL_ROUNDS: usize = 22;
+pub const N_ROUNDS: usize = 2 * HALF_N_FULL_ROUNDS + N_PARTIAL_ROUNDS;
+
+fn is_full_round(round: usize) -> bool {
+    !(HALF_N_FULL_ROUNDS..HALF_N_FULL_ROUNDS + N_PARTIAL_ROUNDS).contains(&round)
+}
+
+/// The coefficient of `state[j]` in the `i`-th output of the MDS layer.
+pub(crate) fn mds_coefficient(i: usize, j: usize) -> Goldilocks {
+    let diag = if i == j { MDS_MATRIX_DIAG[i] } else { 0 };
+    Goldilocks::new(MDS_MATRIX_CIRC[(j + WIDTH - i) % WIDTH] + diag)
+}
+
+pub(crate) fn round_constant(round: usize, i: usize) -> Goldilocks {
+    Goldilocks::new(ALL_ROUND_CONSTANTS[i + WIDTH * round])
+}
+
+/// The permutation, without the partial round optimizations of plonky2.
+pub fn permute(state: [u64; WIDTH]) -> [u64; WIDTH] {
+    let mut state = state.map(Goldilocks::new);
+    for round in 0..N_ROUNDS {
+        for (i, s) in state.iter_mut().enumerate() {
+            *s = *s + round_constant(round, i);
+        }
+        let sbox_lanes = if is_full_round(round) { WIDTH } else { 1 };
+        for s in &mut state[..sbox_lanes] {
+            *s = s.exp_u64(7);
+        }
+        state = std::array::from_fn(|i| {
+            (0..WIDTH).fold(Goldilocks::ZERO, |acc, j| acc + mds_coefficient(i, j) * state[j])
+        });
+    }
+    state.map(Goldilocks::to_canonical_u64)
+}
+
+/// `permute` in a circuit.
+pub fn permute_circuit(
+    ctx: &mut Context<Fr>,
+    chip: &GoldilocksChip,
+    mut state: [AssignedGoldilocks; WIDTH],
+) -> [AssignedGoldilocks; WIDTH] {
+    for round in 0..N_ROUNDS {
+        let sbox_lanes = if is_full_round(round) { WIDTH } else { 1 };
+        for (i, s) in state.iter_mut().enumerate() {
+            *s = chip.add_const(ctx, *s, round_constant(round, i));
+            if i < sbox_lanes {
+                let x2 = chip.mul(ctx, *s, *s);
+                let x3 = chip.mul(ctx, x2, *s);
+                let x4 = chip.mul(ctx, x2, x2);
+                *s = chip.mul(ctx, x3, x4);
+            }
+        }
+        state = std::array::from_fn(|i| {
+            let terms = (0..WIDTH).map(|j| (mds_coefficient(i, j), state[j]));
+            chip.linear_combination(ctx, terms, Goldilocks::ZERO)
+        });
+    }
+    state
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::goldilocks::ORDER;
+
+    #[test]
+    fn test_permute_matches_plonky2() {
+        // From plonky2's `poseidon_goldilocks::tests::test_vectors`
+        let test_vectors: [([u64; WIDTH], [u64; WIDTH]); 3] = [
+            (
+                [0; WIDTH],
+                [
+                    0x3c18a9786cb0b359,
+                    0xc4055e3364a246c3,
+                    0x7953db0ab48808f4,
+                    0xc71603f33a1144ca,
+                    0xd7709673896996dc,
+                    0x46a84e87642f44ed,
+                    0xd032648251ee0b3c,
+                    0x1c687363b207df62,
+                    0xdf8565563e8045fe,
+                    0x40f5b37ff4254dae,
+                    0xd070f637b431067c,
+                    0x1792b1c4342109d7,
+                ],
+            ),
+            (
+                [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11],
+                [
+                    0xd64e1e3efc5b8e9e,
+                    0x53666633020aaa47,
+                    0xd40285597c6a8825,
+                    0x613a4f81e81231d2,
+                    0x414754bfebd051f0,
+                    0xcb1f8980294a023f,
+                    0x6eb2a9e4d54a9d0f,
+                    0x1902bc3af467e056,
+                    0xf045d5eafdc6021f,
+                    0xe4150f77caaa3be5,
+                    0xc9bfd01d39b50cce,
+                    0x5c0a27fcb0e1459b,
+                ],
+            ),
+            (
+                [ORDER - 1; WIDTH],
+                [
+                    0xbe0085cfc57a8357,
+                    0xd95af71847d05c09,
+                    0xcf55a13d33c1c953,
+                    0x95803a74f4530e82,
+                    0xfcd99eb30a135df1,
+                    0xe095905e913a3029,
+                    0xde0392461b42919b,
+                    0x7d3260e24e81d031,
+                    0x10d3d0465d9deaa0,
+                    0xa87571083dfc2a47,
+                    0xe18263681e9958f8,
+                    0xe28e96f1ae5e60d3,
+                ],
+            ),
+        ];
+        for (input, output) in test_vectors {
+            assert_eq!(permute(input), output);
+        }
+    }
+}
@@ -0,0 +1,572 @@
+//! plonky2's `verify` in a circuit: the Fiat-Shamir challenges, the check of the vanishing
+//! polynomial against the quotient at `zeta`, and the FRI opening proof.
+
+use anyhow::Result;
+use halo2_base::gates::{GateInstructions, RangeInstructions};
+use halo2_base::halo2_proofs::halo2curves::bn256::Fr;
+use halo2_base::{AssignedValue, Context};
+
+use crate::challenger::Challenger;
+use crate::data::{CommonData, Extension, HashOut, Proof, VerifierOnlyData};
+use crate::field::{AssignedExtension, AssignedGoldilocks, GoldilocksChip};
+use crate::gates::{evaluate_gate_constraints, EvaluationVars};
+use crate::goldilocks::{Goldilocks, MULTIPLICATIVE_GENERATOR};
+use crate::hash::{hash_public_inputs, verify_merkle_proof_to_cap, AssignedHash};
+
+type AssignedCap = Vec<AssignedHash>;
+
+struct AssignedOpeningSet {
+    constants: Vec<AssignedExtension>,
+    plonk_sigmas: Vec<AssignedExtension>,
+    wires: Vec<AssignedExtension>,
+    plonk_zs: Vec<AssignedExtension>,
+    plonk_zs_next: Vec<AssignedExtension>,
+    partial_products: Vec<AssignedExtension>,
+    quotient_polys: Vec<AssignedExtension>,
+}
+
+impl AssignedOpeningSet {
+    /// The batches of `FriOpenings`: the values at `zeta`, then those at `g * zeta`.
+    fn batches(&self) -> [Vec<AssignedExtension>; 2] {
+        [
+            [
+                &self.constants,
+                &self.plonk_sigmas,
+                &self.wires,
+                &self.plonk_zs,
+                &self.partial_products,
+                &self.quotient_polys,
+            ]
+            .concat(),
+            self.plonk_zs_next.clone(),
+        ]
+    }
+}
+
+struct AssignedQueryStep {
+    evals: Vec<AssignedExtension>,
+    merkle_proof: Vec<AssignedHash>,
+}
+
+struct AssignedQueryRound {
+    initial_trees_proof: Vec<(Vec<AssignedGoldilocks>, Vec<AssignedHash>)>,
+    steps: Vec<AssignedQueryStep>,
+}
+
+struct AssignedProof {
+    public_inputs: Vec<AssignedGoldilocks>,
+    wires_cap: AssignedCap,
+    plonk_zs_partial_products_cap: AssignedCap,
+    quotient_polys_cap: AssignedCap,
+    openings: AssignedOpeningSet,
+    commit_phase_merkle_caps: Vec<AssignedCap>,
+    query_round_proofs: Vec<AssignedQueryRound>,
+    final_poly: Vec<AssignedExtension>,
+    pow_witness: AssignedGoldilocks,
+}
+
+/// `ProofChallenges`.
+struct Challenges {
+    plonk_betas: Vec<AssignedGoldilocks>,
+    plonk_gammas: Vec<AssignedGoldilocks>,
+    plonk_alphas: Vec<AssignedGoldilocks>,
+    plonk_zeta: AssignedExtension,
+    fri_alpha: AssignedExtension,
+    fri_betas: Vec<AssignedExtension>,
+    fri_pow_response: AssignedGoldilocks,
+    fri_query_indices: Vec<AssignedGoldilocks>,
+}
+
+fn load_hash(ctx: &mut Context<Fr>, chip: &GoldilocksChip, hash: &HashOut) -> AssignedHash {
+    hash.map(|x| chip.load_witness(ctx, Goldilocks::new(x)))
+}
+
+fn load_cap(ctx: &mut Context<Fr>, chip: &GoldilocksChip, cap: &[HashOut]) -> AssignedCap {
+    cap.iter().map(|hash| load_hash(ctx, chip, hash)).collect()
+}
+
+fn load_extensions(
+    ctx: &mut Context<Fr>,
+    chip: &GoldilocksChip,
+    values: &[Extension],
+) -> Vec<AssignedExtension> {
+    values.iter().map(|x| chip.load_ext_witness(ctx, x.map(Goldilocks::new))).collect()
+}
+
+fn load_proof(ctx: &mut Context<Fr>, chip: &GoldilocksChip, proof: &Proof) -> AssignedProof {
+    let openings = &proof.openings;
+    let fri = &proof.opening_proof;
+    AssignedProof {
+        public_inputs: proof
+            .public_inputs
+            .iter()
+            .map(|&x| chip.load_witness(ctx, Goldilocks::new(x)))
+            .collect(),
+        wires_cap: load_cap(ctx, chip, &proof.wires_cap),
+        plonk_zs_partial_products_cap: load_cap(ctx, chip, &proof.plonk_zs_partial_products_cap),
+        quotient_polys_cap: load_cap(ctx, chip, &proof.quotient_polys_cap),
+        openings: AssignedOpeningSet {
+            constants: load_extensions(ctx, chip, &openings.constants),
+            plonk_sigmas: load_extensions(ctx, chip, &openings.plonk_sigmas),
+            wires: load_extensions(ctx, chip, &openings.wires),
+            plonk_zs: load_extensions(ctx, chip, &openings.plonk_zs),
+            plonk_zs_next: load_extensions(ctx, chip, &openings.plonk_zs_next),
+            partial_products: load_extensions(ctx, chip, &openings.partial_products),
+            quotient_polys: load_extensions(ctx, chip, &openings.quotient_polys),
+        },
+        commit_phase_merkle_caps: fri
+            .commit_phase_merkle_caps
+            .iter()
+            .map(|cap| load_cap(ctx, chip, cap))
+            .collect(),
+        query_round_proofs: fri
+            .query_round_proofs
+            .iter()
+            .map(|round| AssignedQueryRound {
+                initial_trees_proof: round
+                    .initial_trees_proof
+                    .iter()
+                    .map(|(leaf, proof)| {
+                        let leaf = leaf
+                            .iter()
+                            .map(|&x| chip.load_witness(ctx, Goldilocks::new(x)))
+                            .collect();
+                        (leaf, load_cap(ctx, chip, proof))
+                    })
+                    .collect(),
+                steps: round
+                    .steps
+                    .iter()
+                    .map(|step| AssignedQueryStep {
+                        evals: load_extensions(ctx, chip, &step.evals),
+                        merkle_proof: load_cap(ctx, chip, &step.merkle_proof),
+                    })
+                    .collect(),
+            })
+            .collect(),
+        final_poly: load_extensions(ctx, chip, &fri.final_poly),
+        pow_witness: chip.load_witness(ctx, Goldilocks::new(fri.pow_witness)),
+    }
+}
+
+/// `get_challenges`, with the circuit digest of the verified circuit.
+fn get_challenges(
+    ctx: &mut Context<Fr>,
+    chip: &GoldilocksChip,
+    common: &CommonData,
+    circuit_digest: &AssignedHash,
+    public_inputs_hash: &AssignedHash,
+    proof: &AssignedProof,
+) -> Challenges {
+    let num_challenges = common.num_challenges;
+    let mut challenger = Challenger::new(ctx, chip);
+
+    challenger.observe_hash(ctx, chip, circuit_digest);
+    challenger.observe_hash(ctx, chip, public_inputs_hash);
+
+    challenger.observe_cap(ctx, chip, &proof.wires_cap);
+    let plonk_betas = challenger.get_n_challenges(ctx, chip, num_challenges);
+    let plonk_gammas = challenger.get_n_challenges(ctx, chip, num_challenges);
+
+    challenger.observe_cap(ctx, chip, &proof.plonk_zs_partial_products_cap);
+    let plonk_alphas = challenger.get_n_challenges(ctx, chip, num_challenges);
+
+    challenger.observe_cap(ctx, chip, &proof.quotient_polys_cap);
+    let plonk_zeta = challenger.get_extension_challenge(ctx, chip);
+
+    for batch in proof.openings.batches() {
+        challenger.observe_extension_elements(ctx, chip, &batch);
+    }
+
+    // fri_challenges
+    let fri_alpha = challenger.get_extension_challenge(ctx, chip);
+    let fri_betas = proof
+        .commit_phase_merkle_caps
+        .iter()
+        .map(|cap| {
+            challenger.observe_cap(ctx, chip, cap);
+            challenger.get_extension_challenge(ctx, chip)
+        })
+        .collect();
+    challenger.observe_extension_elements(ctx, chip, &proof.final_poly);
+    challenger.observe_element(ctx, chip, proof.pow_witness);
+    let fri_pow_response = challenger.get_challenge(ctx, chip);
+    let fri_query_indices = challenger.get_n_challenges(ctx, chip, common.fri.num_query_rounds);
+
+    Challenges {
+        plonk_betas,
+        plonk_gammas,
+        plonk_alphas,
+        plonk_zeta,
+        fri_alpha,
+        fri_betas,
+        fri_pow_response,
+        fri_query_indices,
+    }
+}
+
+/// `check_partial_products`: each partial product is the previous one times the quotient of the
+/// next chunk of numerators and denominators, starting from `z_x` and ending at `z_gx`.
+#[allow(clippy::too_many_arguments)]
+fn check_partial_products(
+    ctx: &mut Context<Fr>,
+    chip: &GoldilocksChip,
+    numerators: &[AssignedExtension],
+    denominators: &[AssignedExtension],
+    partials: &[AssignedExtension],
+    z_x: AssignedExtension,
+    z_gx: AssignedExtension,
+    max_degree: usize,
+) -> Vec<AssignedExtension> {
+    let accs = [&[z_x], partials, &[z_gx]].concat();
+    numerators
+        .chunks(max_degree)
+        .zip(denominators.chunks(max_degree))
+        .zip(accs.windows(2))
+        .map(|((numerators, denominators), accs)| {
+            let numerator_product = chip.ext_product(ctx, numerators);
+            let denominator_product = chip.ext_product(ctx, denominators);
+            let prev = chip.ext_mul(ctx, accs[0], numerator_product);
+            let next = chip.ext_mul(ctx, accs[1], denominator_product);
+            chip.ext_sub(ctx, prev, next)
+        })
+        .collect()
+}
+
+/// `eval_vanishing_poly` at `zeta`, combined with each of the alphas.
+fn eval_vanishing_poly(
+    ctx: &mut Context<Fr>,
+    chip: &GoldilocksChip,
+    common: &CommonData,
+    challenges: &Challenges,
+    zeta_pow_deg: AssignedExtension,
+    public_inputs_hash: &AssignedHash,
+    openings: &AssignedOpeningSet,
+) -> Result<Vec<AssignedExtension>> {
+    let gates = common.parse_gates()?;
+    let (selector_indices, groups) = common.selectors(&gates)?;
+    let vars = EvaluationVars {
+        local_constants: &openings.constants,
+        local_wires: &openings.wires,
+        public_inputs_hash,
+    };
+    let constraint_terms = evaluate_gate_constraints(
+        ctx,
+        chip,
+        &gates,
+        (&selector_indices, &groups),
+        common.num_gate_constraints,
+        vars,
+    );
+
+    // L_0(zeta) = (zeta^n - 1) / (n (zeta - 1))
+    let zeta = challenges.plonk_zeta;
+    let n = Goldilocks::new(1 << common.degree_bits);
+    let z_h_zeta = chip.ext_add_base_const(ctx, zeta_pow_deg, -Goldilocks::ONE);
+    let denominator = chip.ext_linear_combination(ctx, &[(n, zeta)], [-n, Goldilocks::ZERO]);
+    let l_0_zeta = chip.ext_div(ctx, z_h_zeta, denominator);
+
+    let num_prods = common.num_partial_products;
+    let mut vanishing_z_1_terms = vec![];
+    let mut vanishing_partial_products_terms = vec![];
+    for i in 0..common.num_challenges {
+        let z_x = openings.plonk_zs[i];
+        let z_gx = openings.plonk_zs_next[i];
+        let z_x_minus_one = chip.ext_add_base_const(ctx, z_x, -Goldilocks::ONE);
+        vanishing_z_1_terms.push(chip.ext_mul(ctx, l_0_zeta, z_x_minus_one));
+
+        let beta = challenges.plonk_betas[i];
+        let gamma = challenges.plonk_gammas[i];
+        let beta_ext = chip.ext_from_base(ctx, beta);
+        let mut numerators = Vec::with_capacity(common.num_routed_wires);
+        let mut denominators = Vec::with_capacity(common.num_routed_wires);
+        for j in 0..common.num_routed_wires {
+            let wire = openings.wires[j];
+            let gamma_plus_wire = AssignedExtension([chip.add(ctx, wire.0[0], gamma), wire.0[1]]);
+            // wire + beta * k_j * zeta + gamma
+            let k_i = Goldilocks::new(common.k_is[j]);
+            let beta_k_i = chip.linear_combination(ctx, [(k_i, beta)], Goldilocks::ZERO);
+            let beta_k_i = chip.ext_from_base(ctx, beta_k_i);
+            numerators.push(chip.ext_mul_add(ctx, beta_k_i, zeta, gamma_plus_wire));
+            // wire + beta * sigma_j + gamma
+            let s_sigma = openings.plonk_sigmas[j];
+            denominators.push(chip.ext_mul_add(ctx, beta_ext, s_sigma, gamma_plus_wire));
+        }
+        vanishing_partial_products_terms.extend(check_partial_products(
+            ctx,
+            chip,
+            &numerators,
+            &denominators,
+            &openings.partial_products[i * num_prods..(i + 1) * num_prods],
+            z_x,
+            z_gx,
+            common.quotient_degree_factor,
+        ));
+    }
+
+    let vanishing_terms =
+        [vanishing_z_1_terms, vanishing_partial_products_terms, constraint_terms].concat();
+    Ok(challenges
+        .plonk_alphas
+        .iter()
+        .map(|&alpha| {
+            let alpha = chip.ext_from_base(ctx, alpha);
+            chip.ext_reduce_with_powers(ctx, &vanishing_terms, alpha)
+        })
+        .collect())
+}
+
+/// The `num_bits` low bits of `n` in reverse order.
+fn reverse_bits(n: usize, num_bits: usize) -> usize {
+    (0..num_bits).fold(0, |acc, i| (acc << 1) | ((n >> i) & 1))
+}
+
+/// `compute_evaluation`: the value at `beta` of the interpolant of `evals` on the coset of `x`
+/// of order `2^arity_bits`, where `within_coset_bits` is the index of `x` in the coset, i.e.
+/// P'(x^arity) for the FRI reduced polynomial P'.
+fn compute_evaluation(
+    ctx: &mut Context<Fr>,
+    chip: &GoldilocksChip,
+    x: AssignedGoldilocks,
+    within_coset_bits: &[AssignedValue<Fr>],
+    evals: &[AssignedExtension],
+    beta: AssignedExtension,
+) -> AssignedExtension {
+    let arity_bits = within_coset_bits.len();
+    let arity = 1 << arity_bits;
+    let g = Goldilocks::primitive_root_of_unity(arity_bits);
+
+    // The coset starts at x * g^-rev(index), which are the reversed bits of the index.
+    let reversed_bits = within_coset_bits.iter().rev().copied().collect::<Vec<_>>();
+    let shift = chip.exp_const_base(ctx, g.inverse(), &reversed_bits);
+    let coset_start = chip.mul(ctx, x, shift);
+
+    // The points are c g^i with c = coset_start, whose barycentric weights are
+    // g^i / (n c^(n - 1)), so the interpolant at beta is
+    // (beta^n - c^n) / (n c^(n - 1)) * sum_i g^i y_i / (beta - c g^i).
+    let mut c_pow_n = coset_start;
+    let mut c_pow_n_minus_one = chip.load_constant(ctx, Goldilocks::ONE);
+    for _ in 0..arity_bits {
+        c_pow_n_minus_one = chip.mul(ctx, c_pow_n_minus_one, c_pow_n);
+        c_pow_n = chip.mul(ctx, c_pow_n, c_pow_n);
+    }
+
+    let mut sum = chip.ext_zero(ctx);
+    let mut g_i = Goldilocks::ONE;
+    for i in 0..arity {
+        // The evaluations are in bit reversed order.
+        let y_i = evals[reverse_bits(i, arity_bits)];
+        let point = chip.linear_combination(ctx, [(g_i, coset_start)], Goldilocks::ZERO);
+        let point = chip.ext_from_base(ctx, point);
+        let difference = chip.ext_sub(ctx, beta, point);
+        let numerator = chip.ext_linear_combination(ctx, &[(g_i, y_i)], [Goldilocks::ZERO; 2]);
+        let term = chip.ext_div(ctx, numerator, difference);
+        sum = chip.ext_add(ctx, sum, term);
+        g_i = g_i * g;
+    }
+
+    let beta_pow_n = chip.ext_exp_power_of_2(ctx, beta, arity_bits);
+    let c_pow_n = chip.ext_from_base(ctx, c_pow_n);
+    let l_beta = chip.ext_sub(ctx, beta_pow_n, c_pow_n);
+    let n_c_pow_n_minus_one = chip.linear_combination(
+        ctx,
+        [(Goldilocks::new(arity), c_pow_n_minus_one)],
+        Goldilocks::ZERO,
+    );
+    let n_c_pow_n_minus_one = chip.ext_from_base(ctx, n_c_pow_n_minus_one);
+    let factor = chip.ext_div(ctx, l_beta, n_c_pow_n_minus_one);
+    chip.ext_mul(ctx, sum, factor)
+}
+
+/// `verify_fri_proof` for the instance of a plonky2 proof: the polynomials of the four oracles
+/// opened at `zeta`, and the Z polynomials opened at `g * zeta`.
+#[allow(clippy::too_many_arguments)]
+fn verify_fri_proof(
+    ctx: &mut Context<Fr>,
+    chip: &GoldilocksChip,
+    common: &CommonData,
+    challenges: &Challenges,
+    initial_merkle_caps: &[&AssignedCap],
+    proof: &AssignedProof,
+) {
+    let lde_bits = common.lde_bits();
+    let fri = &common.fri;
+
+    // Check PoW: the response has `proof_of_work_bits` leading zeros.
+    if fri.proof_of_work_bits > 0 {
+        let num_bits = 64 - fri.proof_of_work_bits as usize;
+        chip.range().range_check(ctx, challenges.fri_pow_response.cell(), num_bits);
+    }
+
+    let zeta = challenges.plonk_zeta;
+    let g = Goldilocks::primitive_root_of_unity(common.degree_bits);
+    let zeta_next = chip.ext_linear_combination(ctx, &[(g, zeta)], [Goldilocks::ZERO; 2]);
+    let points = [zeta, zeta_next];
+    let alpha = challenges.fri_alpha;
+
+    // PrecomputedReducedOpenings
+    let batches = proof.openings.batches();
+    let reduced_openings = batches
+        .iter()
+        .map(|batch| chip.ext_reduce_with_powers(ctx, batch, alpha))
+        .collect::<Vec<_>>();
+    let alpha_shifts = batches
+        .iter()
+        .map(|batch| chip.ext_exp_u64(ctx, alpha, batch.len() as u64))
+        .collect::<Vec<_>>();
+
+    // The polynomials of each batch, as (oracle, polynomials) pairs.
+    let num_zs = common.num_challenges;
+    let num_preprocessed = common.num_constants + common.num_routed_wires;
+    let zeta_batch: [(usize, usize); 4] = [
+        (0, num_preprocessed),
+        (1, common.num_wires),
+        (2, common.num_zs_partial_products()),
+        (3, num_zs * common.quotient_degree_factor),
+    ];
+    let batch_polynomials: [&[(usize, usize)]; 2] = [&zeta_batch, &[(2, num_zs)]];
+
+    for (x_index, round_proof) in challenges.fri_query_indices.iter().zip(&proof.query_round_proofs)
+    {
+        // x_index % lde_size
+        let mut x_index_bits = chip.to_bits(ctx, *x_index, lde_bits);
+
+        // fri_verify_initial_proof
+        for ((leaf, merkle_proof), cap) in
+            round_proof.initial_trees_proof.iter().zip(initial_merkle_caps)
+        {
+            verify_merkle_proof_to_cap(ctx, chip, leaf, &x_index_bits, cap, merkle_proof);
+        }
+
+        // subgroup_x = g * phi^rev(x_index) for the generator g and phi of order lde_size.
+        let phi = Goldilocks::primitive_root_of_unity(lde_bits);
+        let reversed_bits = x_index_bits.iter().rev().copied().collect::<Vec<_>>();
+        let phi_pow = chip.exp_const_base(ctx, phi, &reversed_bits);
+        let mut subgroup_x = chip.linear_combination(
+            ctx,
+            [(Goldilocks::new(MULTIPLICATIVE_GENERATOR), phi_pow)],
+            Goldilocks::ZERO,
+        );
+
+        // fri_combine_initial
+        let mut old_eval = chip.ext_zero(ctx);
+        let x_ext = chip.ext_from_base(ctx, subgroup_x);
+        for (i, polynomials) in batch_polynomials.iter().enumerate() {
+            let evals = polynomials
+                .iter()
+                .flat_map(|&(oracle, num_polys)| {
+                    &round_proof.initial_trees_proof[oracle].0[..num_polys]
+                })
+                .map(|&eval| chip.ext_from_base(ctx, eval))
+                .collect::<Vec<_>>();
+            let reduced_evals = chip.ext_reduce_with_powers(ctx, &evals, alpha);
+            let numerator = chip.ext_sub(ctx, reduced_evals, reduced_openings[i]);
+            let denominator = chip.ext_sub(ctx, x_ext, points[i]);
+            let quotient = chip.ext_div(ctx, numerator, denominator);
+            old_eval = chip.ext_mul_add(ctx, old_eval, alpha_shifts[i], quotient);
+        }
+
+        for (i, &arity_bits) in fri.reduction_arity_bits.iter().enumerate() {
+            let evals = &round_proof.steps[i].evals;
+            let (within_coset_bits, coset_index_bits) = x_index_bits.split_at(arity_bits);
+
+            // Check consistency with our old evaluation from the previous round.
+            let indicator = chip.gate().bits_to_indicator(ctx, within_coset_bits);
+            let eval = chip.ext_select_by_indicator(ctx, evals, &indicator);
+            chip.ext_assert_equal(ctx, eval, old_eval);
+
+            // Infer P(y) from {P(x)}_{x^arity=y}.
+            old_eval = compute_evaluation(
+                ctx,
+                chip,
+                subgroup_x,
+                within_coset_bits,
+                evals,
+                challenges.fri_betas[i],
+            );
+
+            let leaf = evals.iter().flat_map(|eval| eval.0).collect::<Vec<_>>();
+            verify_merkle_proof_to_cap(
+                ctx,
+                chip,
+                &leaf,
+                coset_index_bits,
+                &proof.commit_phase_merkle_caps[i],
+                &round_proof.steps[i].merkle_proof,
+            );
+
+            // Update the point x to x^arity.
+            for _ in 0..arity_bits {
+                subgroup_x = chip.mul(ctx, subgroup_x, subgroup_x);
+            }
+            x_index_bits = coset_index_bits.to_vec();
+        }
+
+        // Final check of FRI. After all the reductions, we check that the final polynomial is
+        // equal to the one sent by the prover.
+        let x_ext = chip.ext_from_base(ctx, subgroup_x);
+        let final_eval = chip.ext_reduce_with_powers(ctx, &proof.final_poly, x_ext);
+        chip.ext_assert_equal(ctx, final_eval, old_eval);
+    }
+}
+
+/// Constrains `proof` to be a valid proof of the circuit given by `common` and `verifier_only`,
+/// and returns its public inputs.
+///
+/// The verified circuit is fixed: its digest and constants are constants of the verifier
+/// circuit. Its proofs must use `PoseidonBN128GoldilocksConfig`, hashing their Merkle trees and
+/// transcript with `hash::permute_bn128`.
+pub fn verify_proof(
+    ctx: &mut Context<Fr>,
+    chip: &GoldilocksChip,
+    common: &CommonData,
+    verifier_only: &VerifierOnlyData,
+    proof: &Proof,
+) -> Result<Vec<AssignedGoldilocks>> {
+    proof.validate_shape(common, verifier_only)?;
+    let load_constant_hash = |ctx: &mut Context<Fr>, hash: &HashOut| {
+        hash.map(|x| chip.load_constant(ctx, Goldilocks::new(x)))
+    };
+    let circuit_digest = load_constant_hash(ctx, &verifier_only.circuit_digest);
+    let constants_sigmas_cap = verifier_only
+        .constants_sigmas_cap
+        .iter()
+        .map(|hash| load_constant_hash(ctx, hash))
+        .collect::<Vec<_>>();
+    let proof = load_proof(ctx, chip, proof);
+
+    let public_inputs_hash = hash_public_inputs(ctx, chip, &proof.public_inputs);
+    let challenges =
+        get_challenges(ctx, chip, common, &circuit_digest, &public_inputs_hash, &proof);
+
+    let zeta_pow_deg = chip.ext_exp_power_of_2(ctx, challenges.plonk_zeta, common.degree_bits);
+    let vanishing_polys_zeta = eval_vanishing_poly(
+        ctx,
+        chip,
+        common,
+        &challenges,
+        zeta_pow_deg,
+        &public_inputs_hash,
+        &proof.openings,
+    )?;
+
+    // Check each polynomial identity, of the form `vanishing(x) = Z_H(x) quotient(x)`, at zeta.
+    let z_h_zeta = chip.ext_add_base_const(ctx, zeta_pow_deg, -Goldilocks::ONE);
+    for (i, chunk) in
+        proof.openings.quotient_polys.chunks(common.quotient_degree_factor).enumerate()
+    {
+        let quotient = chip.ext_reduce_with_powers(ctx, chunk, zeta_pow_deg);
+        let z_h_quotient = chip.ext_mul(ctx, z_h_zeta, quotient);
+        chip.ext_assert_equal(ctx, vanishing_polys_zeta[i], z_h_quotient);
+    }
+
+    let initial_merkle_caps = [
+        &constants_sigmas_cap,
+        &proof.wires_cap,
+        &proof.plonk_zs_partial_products_cap,
+        &proof.quotient_polys_cap,
+    ];
+    verify_fri_proof(ctx, chip, common, &challenges, &initial_merkle_caps, &proof);
+
+    Ok(proof.public_inputs)
+}
@@ -6,27 +6,49 @@ use plonky2::iop::witness::Witness;
 use plonky2::plonk::circuit_builder::CircuitBuilder;
 
 use crate::nonnative::biguint::CircuitBuilderBiguint;
-use crate::types::{HashInputTarget, HashOutputTarget, WitnessHash};
+use crate::types::{
+    CircuitBuilderHash, HashInputTarget, HashOutputTarget, VarHashInputTarget, WitnessHash,
+};
 use crate::u32::arithmetic_u32::{CircuitBuilderU32, U32Target};
 use crate::u32::interleaved_u32::CircuitBuilderB32;
 
 const KECCAK256_C: usize = 1600;
 pub const KECCAK256_R: usize = 1088;
+pub(crate) const KECCAK256_R_BYTES: usize = KECCAK256_R / 8;
+pub(crate) const KECCAK256_DOMAIN: u8 = 0x01;
+// SHAKE256 has the same rate as keccak256, only its domain separation differs
+const SHAKE256_DOMAIN: u8 = 0x1f;
 
 pub trait WitnessHashKeccak<F: PrimeField64>: Witness<F> {
     fn set_keccak256_input_target(&mut self, target: &HashInputTarget, value: &[u8]);
+    fn set_keccak256_var_input_target(&mut self, target: &VarHashInputTarget, value: &[u8]);
     fn set_keccak256_output_target(&mut self, target: &HashOutputTarget, value: &[u8]);
+    // input padded for SHAKE256, to be squeezed with hash_keccak256_xof
+    fn set_shake256_input_target(&mut self, target: &HashInputTarget, value: &[u8]);
+    fn _set_padded_input_target(&mut self, target: &HashInputTarget, value: &[u8], domain: u8);
 }
 
 impl<T: Witness<F>, F: PrimeField64> WitnessHashKeccak<F> for T {
     fn set_keccak256_input_target(&mut self, target: &HashInputTarget, value: &[u8]) {
+        self._set_padded_input_target(target, value, KECCAK256_DOMAIN);
+    }
+
+    fn set_shake256_input_target(&mut self, target: &HashInputTarget, value: &[u8]) {
+        self._set_padded_input_target(target, value, SHAKE256_DOMAIN);
+    }
+
+    fn _set_padded_input_target(&mut self, target: &HashInputTarget, value: &[u8], domain: u8) {
         let mut input_biguint = BigUint::from_bytes_le(value);
         let input_len_bits = value.len() * 8;
         let num_actual_blocks = 1 + input_len_bits / KECCAK256_R;
         let padded_len_bits = num_actual_blocks * KECCAK256_R;
 
-        // bit right after the end of the message
-        input_biguint.set_bit(input_len_bits as u64, true);
+        // domain separation bits right after the end of the message
+        for bit in 0..8 {
+            if domain >> bit & 1 == 1 {
+                input_biguint.set_bit((input_len_bits + bit) as u64, true);
+            }
+        }
 
         // last bit of the last block
         input_biguint.set_bit(padded_len_bits as u64 - 1, true);
@@ -35,6 +57,20 @@ impl<T: Witness<F>, F: PrimeField64> WitnessHashKeccak<F> for T {
         self.set_hash_blocks_target(target, num_actual_blocks);
     }
 
+    fn set_keccak256_var_input_target(&mut self, target: &VarHashInputTarget, value: &[u8]) {
+        assert!(
+            value.len() < target.len_flags.len(),
+            "message of {} bytes does not fit in {} blocks",
+            value.len(),
+            target.len_flags.len() / KECCAK256_R_BYTES
+        );
+        self.set_keccak256_input_target(&target.hash, value);
+        self.set_target(target.input_len, F::from_canonical_usize(value.len()));
+        for (i, flag) in target.len_flags.iter().enumerate() {
+            self.set_bool_target(*flag, i == value.len());
+        }
+    }
+
     fn set_keccak256_output_target(&mut self, target: &HashOutputTarget, value: &[u8]) {
         self.set_hash_output_le_target(target, value);
     }
@@ -42,7 +78,17 @@ impl<T: Witness<F>, F: PrimeField64> WitnessHashKeccak<F> for T {
 
 pub trait CircuitBuilderHashKeccak<F: RichField + Extendable<D>, const D: usize> {
     fn hash_keccak256(&mut self, hash: &HashInputTarget) -> HashOutputTarget;
+    // first output_bytes bytes of the sponge output, squeezing more blocks past the rate
+    fn hash_keccak256_xof(
+        &mut self,
+        hash: &HashInputTarget,
+        output_bytes: usize,
+    ) -> HashOutputTarget;
+    // input of up to max_blocks blocks, padded in-circuit according to its length
+    fn add_virtual_keccak256_var_input_target(&mut self, max_blocks: usize) -> VarHashInputTarget;
     fn _hash_keccak256_f1600(&mut self, state: &mut [[U32Target; 2]; 25]);
+    // state after absorbing all the processed blocks of the input
+    fn _absorb_keccak256(&mut self, hash: &HashInputTarget) -> [[U32Target; 2]; 25];
 }
 
 #[rustfmt::skip]
@@ -127,9 +173,109 @@ impl<F: RichField + Extendable<D>, const D: usize> CircuitBuilderHashKeccak<F, D
         }
     }
 
+    fn add_virtual_keccak256_var_input_target(&mut self, max_blocks: usize) -> VarHashInputTarget {
+        let hash = self.add_virtual_hash_input_target(max_blocks, KECCAK256_R);
+        let input_len = self.add_virtual_target();
+        let max_bytes = max_blocks * KECCAK256_R_BYTES;
+
+        // len_flags[j] is set iff j == input_len
+        let len_flags = (0..max_bytes)
+            .map(|_| self.add_virtual_bool_target_safe())
+            .collect::<Vec<_>>();
+        let one = self.one();
+        let zero = self.zero();
+        let num_flags = self.add_many(len_flags.iter().map(|flag| flag.target));
+        self.connect(num_flags, one);
+        let flagged_len = len_flags.iter().enumerate().fold(zero, |acc, (j, flag)| {
+            self.mul_const_add(F::from_canonical_usize(j), flag.target, acc)
+        });
+        self.connect(flagged_len, input_len);
+
+        // past[j] is set iff j > input_len, so past[j + 1] marks the padding bytes
+        let mut past = vec![zero];
+        for flag in &len_flags {
+            let last = *past.last().unwrap();
+            past.push(self.add(last, flag.target));
+        }
+
+        // a block is processed iff the message does not end before it
+        for (k, blk) in hash.blocks.iter().enumerate() {
+            let processed = self.sub(one, past[(k + 1) * KECCAK256_R_BYTES]);
+            self.connect(blk.target, processed);
+        }
+
+        // padding bytes: 0x01 right after the message, 0x80 at the end of its final block and
+        // zero elsewhere, including all the blocks which are not processed
+        let bytes = hash
+            .input
+            .limbs
+            .iter()
+            .flat_map(|limb| {
+                let bits = self.split_le(limb.0, 32);
+                bits.chunks(8)
+                    .map(|byte_bits| self.le_sum(byte_bits.iter()))
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        let pad_end = F::from_canonical_u8(0x80);
+        for k in 0..max_blocks {
+            let block_start = k * KECCAK256_R_BYTES;
+            let block_end = block_start + KECCAK256_R_BYTES;
+            let is_final_block = self.sub(past[block_end], past[block_start]);
+            for j in block_start..block_end {
+                let mut expected = len_flags[j].target;
+                if j == block_end - 1 {
+                    expected = self.mul_const_add(pad_end, is_final_block, expected);
+                }
+                let diff = self.sub(bytes[j], expected);
+                let padding_diff = self.mul(past[j + 1], diff);
+                self.assert_zero(padding_diff);
+            }
+        }
+
+        VarHashInputTarget {
+            hash,
+            input_len,
+            len_flags,
+        }
+    }
+
     fn hash_keccak256(&mut self, hash: &HashInputTarget) -> HashOutputTarget {
-        let output = self.add_virtual_biguint_target(8);
+        self.hash_keccak256_xof(hash, 32)
+    }
+
+    fn hash_keccak256_xof(
+        &mut self,
+        hash: &HashInputTarget,
+        output_bytes: usize,
+    ) -> HashOutputTarget {
+        let output = self.add_virtual_biguint_target((output_bytes + 3) / 4);
+        let mut state = self._absorb_keccak256(hash);
+
+        // squeeze, permuting again once all the limbs of the rate are read
+        let rate_limbs = KECCAK256_R / 32;
+        for (i, limb) in output.limbs.iter().enumerate() {
+            if i > 0 && i % rate_limbs == 0 {
+                self._hash_keccak256_f1600(&mut state);
+            }
+            let j = i % rate_limbs;
+            let s = state[j / 2][j % 2];
+
+            // the last limb only holds the bytes left
+            let num_bytes = (output_bytes - 4 * i).min(4);
+            if num_bytes == 4 {
+                self.connect_u32(s, *limb);
+            } else {
+                let bits = self.split_le(s.0, 32);
+                let low = self.le_sum(bits[..8 * num_bytes].iter());
+                self.connect(low, limb.0);
+            }
+        }
 
+        output
+    }
+
+    fn _absorb_keccak256(&mut self, hash: &HashInputTarget) -> [[U32Target; 2]; 25] {
         let chunks_len = KECCAK256_R / 64;
         let zero = self.zero_u32();
         let mut state = [[zero; 2]; KECCAK256_C / 64];
@@ -166,14 +312,7 @@ impl<F: RichField + Extendable<D>, const D: usize> CircuitBuilderHashKeccak<F, D
             }
         }
 
-        // squeeze
-        let output_len = output.num_limbs();
-        for (i, s) in state.iter().enumerate().take(output_len / 2) {
-            self.connect_u32(s[0], output.limbs[2 * i]);
-            self.connect_u32(s[1], output.limbs[2 * i + 1]);
-        }
-
-        output
+        state
     }
 }
 
@@ -186,10 +325,14 @@ mod tests {
     use plonky2::plonk::circuit_data::CircuitConfig;
     use plonky2::plonk::config::{GenericConfig, KeccakGoldilocksConfig, PoseidonGoldilocksConfig};
     use plonky2::util::timing::TimingTree;
-    use sha3::{Digest, Keccak256};
+    use sha3::digest::ExtendableOutput;
+    use sha3::{Digest, Keccak256, Shake256};
 
     use crate::keccak256::{CircuitBuilderHashKeccak, WitnessHashKeccak, KECCAK256_R};
-    use crate::types::CircuitBuilderHash;
+    use crate::types::{CircuitBuilderHash, WitnessHash};
+    use num::BigUint;
+    use plonky2::field::types::Field;
+    use plonky2::iop::witness::WitnessWrite;
     use env_logger::{try_init_from_env, Env, DEFAULT_FILTER_ENV};
     use keccak_hash::keccak;
 
@@ -253,4 +396,97 @@ mod tests {
             assert!(data.verify(proof).is_ok());
         }
     }
+
+    #[test]
+    #[ignore]
+    fn test_keccak256_var_len() {
+        const D: usize = 2;
+        type C = PoseidonGoldilocksConfig;
+        type F = <C as GenericConfig<D>>::F;
+
+        // build circuit once for all lengths
+        let config = CircuitConfig::standard_recursion_config();
+        let mut builder = CircuitBuilder::<F, D>::new(config);
+        let hash_target = builder.add_virtual_keccak256_var_input_target(3);
+        let hash_output = builder.hash_keccak256(&hash_target.hash);
+        let data = builder.build::<C>();
+
+        for len in [0, 1, 135, 136, 200, 271, 407] {
+            let input: Vec<u8> = (0..len).map(|i| i as u8).collect();
+            let output = keccak(&input);
+
+            let mut pw = PartialWitness::new();
+            pw.set_keccak256_var_input_target(&hash_target, &input);
+            pw.set_keccak256_output_target(&hash_output, output.as_bytes());
+
+            let proof = data.prove(pw).unwrap();
+            assert!(data.verify(proof).is_ok());
+        }
+    }
+
+    #[test]
+    #[ignore]
+    fn test_shake256_xof() {
+        const D: usize = 2;
+        type C = PoseidonGoldilocksConfig;
+        type F = <C as GenericConfig<D>>::F;
+
+        // squeezing past the rate, and a last partial limb
+        let output_lens = [300, 7];
+        let config = CircuitConfig::standard_recursion_config();
+        let mut builder = CircuitBuilder::<F, D>::new(config);
+        let hash_target = builder.add_virtual_hash_input_target(2, KECCAK256_R);
+        let hash_outputs = output_lens
+            .iter()
+            .map(|&len| builder.hash_keccak256_xof(&hash_target, len))
+            .collect::<Vec<_>>();
+        let data = builder.build::<C>();
+
+        for len in [0, 135, 200] {
+            let input: Vec<u8> = (0..len).map(|i| i as u8).collect();
+
+            let mut pw = PartialWitness::new();
+            pw.set_shake256_input_target(&hash_target, &input);
+            for (hash_output, &output_len) in hash_outputs.iter().zip(output_lens.iter()) {
+                let mut output = vec![0u8; output_len];
+                Shake256::digest_xof(&input, &mut output);
+                pw.set_keccak256_output_target(hash_output, &output);
+            }
+
+            let proof = data.prove(pw).unwrap();
+            assert!(data.verify(proof).is_ok());
+        }
+    }
+
+    #[test]
+    #[ignore]
+    #[should_panic(expected = "set twice with different values")]
+    fn test_keccak256_var_len_bad_padding() {
+        const D: usize = 2;
+        type C = PoseidonGoldilocksConfig;
+        type F = <C as GenericConfig<D>>::F;
+
+        let config = CircuitConfig::standard_recursion_config();
+        let mut builder = CircuitBuilder::<F, D>::new(config);
+        let hash_target = builder.add_virtual_keccak256_var_input_target(2);
+        builder.hash_keccak256(&hash_target.hash);
+        let data = builder.build::<C>();
+
+        // SHA3 domain padding instead of keccak's
+        let input = b"abc";
+        let mut padded = input.to_vec();
+        padded.push(0x06);
+        padded.resize(KECCAK256_R / 8, 0);
+        padded[KECCAK256_R / 8 - 1] |= 0x80;
+
+        let mut pw = PartialWitness::new();
+        pw.set_hash_input_le_target(&hash_target.hash, &BigUint::from_bytes_le(&padded));
+        pw.set_hash_blocks_target(&hash_target.hash, 1);
+        pw.set_target(hash_target.input_len, F::from_canonical_usize(input.len()));
+        for (i, flag) in hash_target.len_flags.iter().enumerate() {
+            pw.set_bool_target(*flag, i == input.len());
+        }
+
+        data.prove(pw).unwrap();
+    }
 }
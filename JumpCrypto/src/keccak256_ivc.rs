@@ -0,0 +1,400 @@
+use anyhow::{bail, ensure, Result};
+use plonky2::field::extension::Extendable;
+use plonky2::hash::hash_types::{HashOut, HashOutTarget, RichField};
+use plonky2::hash::poseidon::PoseidonHash;
+use plonky2::iop::target::{BoolTarget, Target};
+use plonky2::iop::witness::{PartialWitness, WitnessWrite};
+use plonky2::plonk::circuit_builder::CircuitBuilder;
+use plonky2::plonk::circuit_data::{
+    CircuitConfig, CircuitData, CommonCircuitData, VerifierCircuitTarget,
+};
+use plonky2::plonk::config::{AlgebraicHasher, GenericConfig, Hasher};
+use plonky2::plonk::proof::{ProofWithPublicInputs, ProofWithPublicInputsTarget};
+use plonky2::recursion::cyclic_recursion::check_cyclic_proof_verifier_data;
+use plonky2::recursion::dummy_circuit::cyclic_base_proof;
+
+use crate::keccak256::{CircuitBuilderHashKeccak, KECCAK256_DOMAIN, KECCAK256_R_BYTES};
+use crate::u32::arithmetic_u32::{CircuitBuilderU32, U32Target};
+use crate::u32::interleaved_u32::CircuitBuilderB32;
+use crate::u32::witness::WitnessU32;
+
+pub const KECCAK256_IVC_BLOCK_BYTES: usize = KECCAK256_R_BYTES;
+const BLOCK_LIMBS: usize = KECCAK256_R_BYTES / 4;
+
+// public inputs: the running state as 50 u32 limbs, the commitment to the absorbed blocks and
+// their number, followed by the verifier data of the cycle
+const STATE_LIMBS: usize = 50;
+const COMMITMENT_OFFSET: usize = STATE_LIMBS;
+const NUM_BLOCKS_OFFSET: usize = COMMITMENT_OFFSET + 4;
+const NUM_IVC_PUBLIC_INPUTS: usize = NUM_BLOCKS_OFFSET + 1;
+
+// the step circuit verifies proofs of itself, its shape is found by rebuilding it until the
+// verified common data matches the built one
+const MAX_COMMON_DATA_ITERATIONS: usize = 8;
+
+struct Keccak256IvcTargets<const D: usize> {
+    blocks: Vec<Vec<U32Target>>,
+    block_flags: Vec<BoolTarget>,
+    condition: BoolTarget,
+    inner_proof: ProofWithPublicInputsTarget<D>,
+    verifier_data: VerifierCircuitTarget,
+}
+
+/// Incrementally verifiable keccak256 absorption: every step absorbs up to `blocks_per_step`
+/// 136-byte blocks into a running keccak state and verifies the proof of the previous step, so
+/// the proof of an unbounded stream stays the size of a single step.
+pub struct Keccak256IvcCircuit<F, C, const D: usize>
+where
+    F: RichField + Extendable<D>,
+    C: GenericConfig<D, F = F>,
+{
+    pub data: CircuitData<F, C, D>,
+    pub blocks_per_step: usize,
+    targets: Keccak256IvcTargets<D>,
+}
+
+impl<F, C, const D: usize> Keccak256IvcCircuit<F, C, D>
+where
+    F: RichField + Extendable<D>,
+    C: GenericConfig<D, F = F> + 'static,
+    C::Hasher: AlgebraicHasher<F>,
+{
+    pub fn new(blocks_per_step: usize) -> Result<Self> {
+        ensure!(blocks_per_step > 0, "a step must absorb at least one block");
+
+        let common_data = ivc_common_data::<F, C, D>(blocks_per_step)?;
+        let (data, targets) = build_step::<F, C, D>(blocks_per_step, &common_data, true)?;
+
+        Ok(Self {
+            data,
+            blocks_per_step,
+            targets,
+        })
+    }
+
+    /// Absorbs `blocks` on top of `previous`, or on top of the zero state if there is no
+    /// previous step. Blocks are absorbed as given, the last one has to carry the padding.
+    pub fn prove_step(
+        &self,
+        previous: Option<&ProofWithPublicInputs<F, C, D>>,
+        blocks: &[[u8; KECCAK256_IVC_BLOCK_BYTES]],
+    ) -> Result<ProofWithPublicInputs<F, C, D>> {
+        ensure!(
+            blocks.len() <= self.blocks_per_step,
+            "{} blocks do not fit in a step of {} blocks",
+            blocks.len(),
+            self.blocks_per_step
+        );
+
+        let mut pw = PartialWitness::new();
+        let block_targets = self.targets.blocks.iter().zip(&self.targets.block_flags);
+        for (i, (block_target, flag)) in block_targets.enumerate() {
+            let limbs = match blocks.get(i) {
+                Some(block) => block_limbs(block),
+                None => vec![0; BLOCK_LIMBS],
+            };
+            for (&target, limb) in block_target.iter().zip(limbs) {
+                pw.set_u32_target(target, limb);
+            }
+            pw.set_bool_target(*flag, i < blocks.len());
+        }
+
+        match previous {
+            Some(proof) => {
+                pw.set_bool_target(self.targets.condition, true);
+                pw.set_proof_with_pis_target(&self.targets.inner_proof, proof);
+            }
+            None => {
+                let base_proof =
+                    cyclic_base_proof(&self.data.common, &self.data.verifier_only, Default::default());
+                pw.set_bool_target(self.targets.condition, false);
+                pw.set_proof_with_pis_target(&self.targets.inner_proof, &base_proof);
+            }
+        }
+        pw.set_verifier_data_target(&self.targets.verifier_data, &self.data.verifier_only);
+
+        self.data.prove(pw)
+    }
+
+    /// Pads `input` for keccak256 and absorbs it step by step.
+    pub fn prove(&self, input: &[u8]) -> Result<ProofWithPublicInputs<F, C, D>> {
+        let mut proof = None;
+        for blocks in keccak256_padded_blocks(input).chunks(self.blocks_per_step) {
+            proof = Some(self.prove_step(proof.as_ref(), blocks)?);
+        }
+
+        Ok(proof.expect("padded input has at least one block"))
+    }
+
+    pub fn verify(&self, proof: ProofWithPublicInputs<F, C, D>) -> Result<()> {
+        check_cyclic_proof_verifier_data(&proof, &self.data.verifier_only, &self.data.common)?;
+        self.data.verify(proof)
+    }
+}
+
+/// Running values of an IVC proof, read from its public inputs.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Keccak256IvcOutput<F: RichField> {
+    pub state: [u64; 25],
+    pub commitment: HashOut<F>,
+    pub num_blocks: u64,
+}
+
+impl<F: RichField> Keccak256IvcOutput<F> {
+    pub fn from_public_inputs(public_inputs: &[F]) -> Self {
+        let mut state = [0u64; 25];
+        for (i, lane) in state.iter_mut().enumerate() {
+            let lo = public_inputs[2 * i].to_canonical_u64();
+            let hi = public_inputs[2 * i + 1].to_canonical_u64();
+            *lane = lo | (hi << 32);
+        }
+
+        Self {
+            state,
+            commitment: HashOut::from_partial(&public_inputs[COMMITMENT_OFFSET..NUM_BLOCKS_OFFSET]),
+            num_blocks: public_inputs[NUM_BLOCKS_OFFSET].to_canonical_u64(),
+        }
+    }
+
+    /// keccak256 digest, once the last absorbed block carried the padding.
+    pub fn digest(&self) -> [u8; 32] {
+        let mut digest = [0u8; 32];
+        for (bytes, lane) in digest.chunks_mut(8).zip(self.state) {
+            bytes.copy_from_slice(&lane.to_le_bytes());
+        }
+        digest
+    }
+}
+
+pub fn keccak256_padded_blocks(input: &[u8]) -> Vec<[u8; KECCAK256_IVC_BLOCK_BYTES]> {
+    let num_blocks = input.len() / KECCAK256_R_BYTES + 1;
+    let mut padded = input.to_vec();
+    padded.resize(num_blocks * KECCAK256_R_BYTES, 0);
+    padded[input.len()] |= KECCAK256_DOMAIN;
+    padded[num_blocks * KECCAK256_R_BYTES - 1] |= 0x80;
+
+    padded
+        .chunks(KECCAK256_R_BYTES)
+        .map(|block| block.try_into().unwrap())
+        .collect()
+}
+
+/// Commitment the IVC circuit folds the absorbed blocks into, for checking which input a
+/// proof absorbed.
+pub fn keccak256_ivc_commitment<F: RichField>(
+    blocks: &[[u8; KECCAK256_IVC_BLOCK_BYTES]],
+) -> HashOut<F> {
+    blocks.iter().fold(HashOut::ZERO, |commitment, block| {
+        let inputs: Vec<F> = commitment
+            .elements
+            .into_iter()
+            .chain(block_limbs(block).into_iter().map(F::from_canonical_u32))
+            .collect();
+        PoseidonHash::hash_no_pad(&inputs)
+    })
+}
+
+fn block_limbs(block: &[u8; KECCAK256_IVC_BLOCK_BYTES]) -> Vec<u32> {
+    block
+        .chunks(4)
+        .map(|limb| u32::from_le_bytes(limb.try_into().unwrap()))
+        .collect()
+}
+
+fn ivc_common_data<F, C, const D: usize>(blocks_per_step: usize) -> Result<CommonCircuitData<F, D>>
+where
+    F: RichField + Extendable<D>,
+    C: GenericConfig<D, F = F> + 'static,
+    C::Hasher: AlgebraicHasher<F>,
+{
+    // start from an empty circuit, each rebuild verifies proofs of the previous shape
+    let builder = CircuitBuilder::<F, D>::new(CircuitConfig::standard_recursion_config());
+    let mut common_data = builder.build::<C>().common;
+
+    for _ in 0..MAX_COMMON_DATA_ITERATIONS {
+        let (data, _) = build_step::<F, C, D>(blocks_per_step, &common_data, false)?;
+        if data.common == common_data {
+            return Ok(common_data);
+        }
+        common_data = data.common;
+    }
+
+    bail!(
+        "common data of the IVC step did not converge after {} builds",
+        MAX_COMMON_DATA_ITERATIONS
+    )
+}
+
+// with `cyclic` unset, the inner proof is verified against the verifier data public inputs
+// instead, which yields the same shape without requiring the shape to be known already
+fn build_step<F, C, const D: usize>(
+    blocks_per_step: usize,
+    common_data: &CommonCircuitData<F, D>,
+    cyclic: bool,
+) -> Result<(CircuitData<F, C, D>, Keccak256IvcTargets<D>)>
+where
+    F: RichField + Extendable<D>,
+    C: GenericConfig<D, F = F> + 'static,
+    C::Hasher: AlgebraicHasher<F>,
+{
+    let mut builder = CircuitBuilder::<F, D>::new(CircuitConfig::standard_recursion_config());
+
+    // the verifier data has to be the last public input
+    let state_pis = builder.add_virtual_targets(STATE_LIMBS);
+    let commitment_pis = builder.add_virtual_hash();
+    let num_blocks_pi = builder.add_virtual_target();
+    builder.register_public_inputs(&state_pis);
+    builder.register_public_inputs(&commitment_pis.elements);
+    builder.register_public_input(num_blocks_pi);
+    let verifier_data = builder.add_verifier_data_public_inputs();
+
+    let mut common_data = common_data.clone();
+    common_data.num_public_inputs = builder.num_public_inputs();
+
+    let condition = builder.add_virtual_bool_target_safe();
+    let inner_proof = builder.add_virtual_proof_with_pis(&common_data);
+
+    // the first step starts from the zero state, commitment and block count
+    let zero = builder.zero();
+    let previous: Vec<Target> = inner_proof.public_inputs[..NUM_IVC_PUBLIC_INPUTS]
+        .iter()
+        .map(|&t| builder.select(condition, t, zero))
+        .collect();
+
+    let mut state = [[U32Target(zero); 2]; 25];
+    for (i, s) in state.iter_mut().enumerate() {
+        s[0] = U32Target(previous[2 * i]);
+        s[1] = U32Target(previous[2 * i + 1]);
+    }
+    let mut commitment =
+        HashOutTarget::from_vec(previous[COMMITMENT_OFFSET..NUM_BLOCKS_OFFSET].to_vec());
+    let mut num_blocks = previous[NUM_BLOCKS_OFFSET];
+
+    let mut blocks = Vec::with_capacity(blocks_per_step);
+    let mut block_flags = Vec::with_capacity(blocks_per_step);
+    for _ in 0..blocks_per_step {
+        let block = builder.add_virtual_u32_targets(BLOCK_LIMBS);
+        let flag = builder.add_virtual_bool_target_safe();
+
+        // xor, the interleave gates range check the block limbs
+        let mut next_state = state;
+        for (i, s) in next_state.iter_mut().enumerate().take(BLOCK_LIMBS / 2) {
+            s[0] = builder.xor_u32(state[i][0], block[2 * i]);
+            s[1] = builder.xor_u32(state[i][1], block[2 * i + 1]);
+        }
+
+        builder._hash_keccak256_f1600(&mut next_state);
+
+        // conditionally set old or new state, depending if block needs to be processed
+        for (i, s) in next_state.iter().enumerate() {
+            state[i] = builder.conditional_u64(s, &state[i], flag);
+        }
+
+        let inputs = commitment
+            .elements
+            .iter()
+            .copied()
+            .chain(block.iter().map(|limb| limb.0))
+            .collect();
+        let next_commitment = builder.hash_n_to_hash_no_pad::<PoseidonHash>(inputs);
+        for (c, next) in commitment.elements.iter_mut().zip(next_commitment.elements) {
+            *c = builder.select(flag, next, *c);
+        }
+        num_blocks = builder.add(num_blocks, flag.target);
+
+        blocks.push(block);
+        block_flags.push(flag);
+    }
+
+    for (i, s) in state.iter().enumerate() {
+        builder.connect(s[0].0, state_pis[2 * i]);
+        builder.connect(s[1].0, state_pis[2 * i + 1]);
+    }
+    builder.connect_hashes(commitment, commitment_pis);
+    builder.connect(num_blocks, num_blocks_pi);
+
+    if cyclic {
+        builder.conditionally_verify_cyclic_proof_or_dummy::<C>(
+            condition,
+            &inner_proof,
+            &common_data,
+        )?;
+    } else {
+        builder.conditionally_verify_proof_or_dummy::<C>(
+            condition,
+            &inner_proof,
+            &verifier_data,
+            &common_data,
+        )?;
+    }
+
+    let data = builder.build::<C>();
+    let targets = Keccak256IvcTargets {
+        blocks,
+        block_flags,
+        condition,
+        inner_proof,
+        verifier_data,
+    };
+
+    Ok((data, targets))
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use plonky2::plonk::config::{GenericConfig, PoseidonGoldilocksConfig};
+    use sha3::{Digest, Keccak256};
+
+    use crate::keccak256_ivc::{
+        keccak256_ivc_commitment, keccak256_padded_blocks, Keccak256IvcCircuit, Keccak256IvcOutput,
+    };
+
+    #[test]
+    fn test_keccak256_padded_blocks() {
+        for (len, num_blocks) in [(0, 1), (135, 1), (136, 2), (300, 3)] {
+            let input: Vec<u8> = (0..len).map(|i| i as u8 + 1).collect();
+            let blocks = keccak256_padded_blocks(&input);
+            assert_eq!(blocks.len(), num_blocks);
+
+            let padded = blocks.concat();
+            assert_eq!(padded[..len], input[..]);
+            if len % 136 == 135 {
+                assert_eq!(padded[len], 0x81);
+            } else {
+                assert_eq!(padded[len], 0x01);
+                assert_eq!(padded[padded.len() - 1], 0x80);
+            }
+        }
+    }
+
+    #[test]
+    #[ignore]
+    fn test_keccak256_ivc() -> Result<()> {
+        const D: usize = 2;
+        type C = PoseidonGoldilocksConfig;
+        type F = <C as GenericConfig<D>>::F;
+
+        // build circuit once for all lengths
+        let circuit = Keccak256IvcCircuit::<F, C, D>::new(2)?;
+        println!(
+            "keccak256 ivc degree_bits={}, num_public_inputs={}",
+            circuit.data.common.degree_bits(),
+            circuit.data.common.num_public_inputs
+        );
+
+        for len in [0, 135, 136, 300, 700] {
+            let input: Vec<u8> = (0..len).map(|i| i as u8).collect();
+            let proof = circuit.prove(&input)?;
+            let output = Keccak256IvcOutput::<F>::from_public_inputs(&proof.public_inputs);
+            circuit.verify(proof)?;
+
+            let blocks = keccak256_padded_blocks(&input);
+            assert_eq!(output.digest()[..], Keccak256::digest(&input)[..]);
+            assert_eq!(output.commitment, keccak256_ivc_commitment(&blocks));
+            assert_eq!(output.num_blocks, blocks.len() as u64);
+        }
+
+        Ok(())
+    }
+}
@@ -6,6 +6,8 @@
 extern crate alloc;
 
 pub mod keccak256;
+pub mod keccak256_ivc;
 pub mod nonnative;
+pub mod poseidon_bn128;
 pub mod types;
 pub mod u32;
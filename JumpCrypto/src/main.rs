@@ -1,14 +1,16 @@
+use bench_memory::{PhaseMemory, PhaseTracker, TrackingAllocator};
 use env_logger::{try_init_from_env, Env, DEFAULT_FILTER_ENV};
+use jemallocator::Jemalloc;
 use keccak256::{
-    keccak256::{CircuitBuilderHashKeccak, WitnessHashKeccak, KECCAK256_R},
-    types::CircuitBuilderHash,
+    keccak256::{CircuitBuilderHashKeccak, WitnessHashKeccak},
+    types::{HashOutputTarget, VarHashInputTarget},
 };
 use keccak_hash::keccak;
 use plonky2::{
     iop::witness::PartialWitness,
     plonk::{
         circuit_builder::CircuitBuilder,
-        circuit_data::CircuitConfig,
+        circuit_data::{CircuitConfig, CircuitData},
         config::{GenericConfig, KeccakGoldilocksConfig},
     },
     util::timing::TimingTree,
@@ -21,38 +23,79 @@ use std::{
     io::{BufRead, BufReader, Write},
 };
 
+#[global_allocator]
+static GLOBAL: TrackingAllocator<Jemalloc> = TrackingAllocator::new(Jemalloc);
 
+const D: usize = 2;
+type C = KeccakGoldilocksConfig;
+type F = <C as GenericConfig<D>>::F;
 
-fn benchmark(i: usize, block_num: usize) -> (usize, f32, f32, usize, f32){
-    const D: usize = 2;
-    type C = KeccakGoldilocksConfig;
-    type F = <C as GenericConfig<D>>::F;
-    let message_length = i * 136;
-    let input_data: Vec<u8> = vec![1; message_length];
-    let output_hash = keccak(&input_data);
+// circuit hashing any message of up to max_blocks blocks
+struct KeccakCircuit {
+    max_blocks: usize,
+    data: CircuitData<F, C, D>,
+    hash_target: VarHashInputTarget,
+    hash_output: HashOutputTarget,
+    build_time: f32,
+    build_memory: PhaseMemory,
+}
+
+fn build_circuit(max_blocks: usize) -> KeccakCircuit {
     let config = CircuitConfig::standard_recursion_config();
+    let build_memory = PhaseTracker::start();
     let mut circuit_builder = CircuitBuilder::<F, D>::new(config);
     let circuit_build_start_time = Instant::now();
-    let hash_target = circuit_builder.add_virtual_hash_input_target(block_num, KECCAK256_R);
-    let hash_output = circuit_builder.hash_keccak256(&hash_target);
-    let circuit_data = circuit_builder.build::<C>();
-    let circuit_building_time = circuit_build_start_time.elapsed().as_secs_f32();
+    let hash_target = circuit_builder.add_virtual_keccak256_var_input_target(max_blocks);
+    let hash_output = circuit_builder.hash_keccak256(&hash_target.hash);
+    let data = circuit_builder.build::<C>();
+    let build_time = circuit_build_start_time.elapsed().as_secs_f32();
+    let build_memory = build_memory.finish();
+    KeccakCircuit {
+        max_blocks,
+        data,
+        hash_target,
+        hash_output,
+        build_time,
+        build_memory,
+    }
+}
+
+fn benchmark(
+    i: usize,
+    circuit: &KeccakCircuit,
+) -> (usize, f32, f32, usize, f32, PhaseMemory, PhaseMemory, PhaseMemory) {
+    let message_length = i * 136;
+    let input_data: Vec<u8> = vec![1; message_length];
+    let output_hash = keccak(&input_data);
     let mut hasher = Keccak256::new();
     hasher.update(input_data.as_slice());
     let hash_result = hasher.finalize();
     assert_eq!(hash_result[..], output_hash[..]);
     let proof_gen_start_time = Instant::now();
+    let witness_memory = PhaseTracker::start();
     let mut partial_witness = PartialWitness::new();
-    partial_witness.set_keccak256_input_target(&hash_target, &input_data);
-    partial_witness.set_keccak256_output_target(&hash_output, &output_hash.as_bytes());
-    let proof = circuit_data.prove(partial_witness).unwrap();
+    partial_witness.set_keccak256_var_input_target(&circuit.hash_target, &input_data);
+    partial_witness.set_keccak256_output_target(&circuit.hash_output, &output_hash.as_bytes());
+    let witness_memory = witness_memory.finish();
+    let prove_memory = PhaseTracker::start();
+    let proof = circuit.data.prove(partial_witness).unwrap();
+    let prove_memory = prove_memory.finish();
     let proof_gen_time = proof_gen_start_time.elapsed().as_secs_f32();
     let proof_size = proof.to_bytes().len();
     let proof_verif_start_time = Instant::now();
-    let verification_result = circuit_data.verify(proof);
+    let verification_result = circuit.data.verify(proof);
     let proof_verif_time = proof_verif_start_time.elapsed().as_secs_f32();
     assert!(verification_result.is_ok());
-    (input_data.len(), circuit_building_time, proof_gen_time, proof_size, proof_verif_time)
+    (
+        input_data.len(),
+        circuit.build_time,
+        proof_gen_time,
+        proof_size,
+        proof_verif_time,
+        circuit.build_memory,
+        witness_memory,
+        prove_memory,
+    )
 }
 
 // cargo run --release
@@ -63,23 +106,34 @@ fn main() {
     let mut fs_results = File::create(file_path).unwrap();
     writeln!(
         fs_results,
-        "msg_len,block_nums,time_build_curcuit,time_create_proof,proof_size,time_verify_proof"
+        "msg_len,block_nums,time_build_curcuit,time_create_proof,proof_size,time_verify_proof,{},{},{}",
+        PhaseMemory::csv_header("build"),
+        PhaseMemory::csv_header("witness"),
+        PhaseMemory::csv_header("prove"),
     )   
     .unwrap();
+    let mut circuit = build_circuit(block_num);
     for i in 1..=1000{
         if i + 1 >= block_num{
             block_num += 4;
         }
-        let output = benchmark(i, block_num);
+        // the circuit is only rebuilt once messages outgrow it
+        if circuit.max_blocks != block_num {
+            circuit = build_circuit(block_num);
+        }
+        let output = benchmark(i, &circuit);
         writeln!(
             fs_results,
-            "{},{},{},{},{},{}",
+            "{},{},{},{},{},{},{},{},{}",
         output.0,
         block_num,
         output.1,
         output.2,
         output.3,
         output.4,
+        output.5,
+        output.6,
+        output.7,
         )
         .unwrap();
     }
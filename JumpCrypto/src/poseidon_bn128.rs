@@ -0,0 +1,99 @@
+use anyhow::Result;
+use plonky2::field::extension::quadratic::QuadraticExtension;
+use plonky2::field::goldilocks_field::GoldilocksField;
+use plonky2::field::types::{Field, PrimeField64};
+use plonky2::hash::hash_types::HashOut;
+use plonky2::hash::hashing::{compress, hash_n_to_hash_no_pad, PlonkyPermutation, SPONGE_WIDTH};
+use plonky2::hash::poseidon::PoseidonHash;
+use plonky2::iop::witness::{PartialWitness, WitnessWrite};
+use plonky2::plonk::circuit_builder::CircuitBuilder;
+use plonky2::plonk::circuit_data::{CircuitConfig, CircuitData};
+use plonky2::plonk::config::{AlgebraicHasher, GenericConfig, Hasher};
+use plonky2::plonk::proof::ProofWithPublicInputs;
+use poseidon_bn128::permute_goldilocks;
+
+type F = GoldilocksField;
+
+/// The permutation of `poseidon_bn128`, which packs the sponge state into BN254 elements.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct PoseidonBN128Permutation;
+
+impl PlonkyPermutation<F> for PoseidonBN128Permutation {
+    fn permute(input: [F; SPONGE_WIDTH]) -> [F; SPONGE_WIDTH] {
+        permute_goldilocks(input.map(|x| x.to_canonical_u64())).map(F::from_canonical_u64)
+    }
+}
+
+/// Poseidon over BN254, for the Merkle trees and the challenger of proofs that are verified in
+/// a circuit over BN254 rather than recursively. It is not an `AlgebraicHasher`.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct PoseidonBN128Hash;
+
+impl Hasher<F> for PoseidonBN128Hash {
+    const HASH_SIZE: usize = 4 * 8;
+    type Hash = HashOut<F>;
+    type Permutation = PoseidonBN128Permutation;
+
+    fn hash_no_pad(input: &[F]) -> Self::Hash {
+        hash_n_to_hash_no_pad::<F, Self::Permutation>(input)
+    }
+
+    fn two_to_one(left: Self::Hash, right: Self::Hash) -> Self::Hash {
+        compress::<F, Self::Permutation>(left, right)
+    }
+}
+
+/// Configuration of the last recursion layer, whose proof is verified by `plonky2-halo2`.
+/// Same as the one of Maru, which depends on another plonky2 version.
+/// Public inputs are still hashed with Goldilocks Poseidon.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct PoseidonBN128GoldilocksConfig;
+
+impl GenericConfig<2> for PoseidonBN128GoldilocksConfig {
+    type F = F;
+    type FE = QuadraticExtension<Self::F>;
+    type Hasher = PoseidonBN128Hash;
+    type InnerHasher = PoseidonHash;
+}
+
+/// Verifies `proof` in a circuit proved with `PoseidonBN128GoldilocksConfig`, with the same public
+/// inputs. The inner proof has to be recursively verifiable, e.g. a `PoseidonGoldilocksConfig`
+/// proof rather than a `KeccakGoldilocksConfig` one.
+pub fn wrap_bn128<C: GenericConfig<2, F = F>>(
+    data: &CircuitData<F, C, 2>,
+    proof: &ProofWithPublicInputs<F, C, 2>,
+) -> Result<(
+    CircuitData<F, PoseidonBN128GoldilocksConfig, 2>,
+    ProofWithPublicInputs<F, PoseidonBN128GoldilocksConfig, 2>,
+)>
+where
+    C::Hasher: AlgebraicHasher<F>,
+{
+    let mut builder = CircuitBuilder::<F, 2>::new(CircuitConfig::standard_recursion_config());
+    let proof_target = builder.add_virtual_proof_with_pis(&data.common);
+    let verifier_data = builder.constant_verifier_data(&data.verifier_only);
+    builder.verify_proof::<C>(&proof_target, &verifier_data, &data.common);
+    builder.register_public_inputs(&proof_target.public_inputs);
+    let wrapper = builder.build::<PoseidonBN128GoldilocksConfig>();
+
+    let mut pw = PartialWitness::new();
+    pw.set_proof_with_pis_target(&proof_target, proof);
+    let wrapped_proof = wrapper.prove(pw)?;
+    Ok((wrapper, wrapped_proof))
+}
+
+#[cfg(test)]
+mod tests {
+    use plonky2::field::types::Field;
+    use plonky2::hash::hashing::PlonkyPermutation;
+
+    use crate::poseidon_bn128::{PoseidonBN128Permutation, F};
+
+    #[test]
+    fn test_permutation_matches_poseidon_bn128() {
+        let input = core::array::from_fn(|i| F::from_canonical_usize(i));
+        let output = PoseidonBN128Permutation::permute(input);
+        assert_eq!(output[0], F::from_canonical_u64(0xa63e62030c9dc32d));
+        assert_eq!(output[11], F::from_canonical_u64(0x53bdea1971e02d77));
+    }
+}
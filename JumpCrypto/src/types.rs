@@ -2,7 +2,7 @@ use num::BigUint;
 use plonky2::field::extension::Extendable;
 use plonky2::field::types::PrimeField64;
 use plonky2::hash::hash_types::RichField;
-use plonky2::iop::target::BoolTarget;
+use plonky2::iop::target::{BoolTarget, Target};
 use plonky2::iop::witness::Witness;
 use plonky2::plonk::circuit_builder::CircuitBuilder;
 
@@ -26,6 +26,17 @@ pub struct HashInputTarget {
     pub blocks: Vec<BoolTarget>,
 }
 
+/// A hash input of any byte length up to the capacity of `hash`, whose padding is enforced
+/// in-circuit from `input_len`.
+#[derive(Clone, Debug)]
+pub struct VarHashInputTarget {
+    pub hash: HashInputTarget,
+    /// Length of the message in bytes.
+    pub input_len: Target,
+    /// One-hot flags marking the byte right after the end of the message.
+    pub len_flags: Vec<BoolTarget>,
+}
+
 pub type HashOutputTarget = BigUintTarget;
 
 fn read_u32_be_at(array: &[u8], index: usize) -> u32 {
@@ -0,0 +1,160 @@
+use core::fmt;
+use core::str::FromStr;
+
+use anyhow::{bail, Error};
+use plonky2::plonk::circuit_data::CircuitConfig;
+use starky::config::{num_query_rounds, StarkConfig};
+
+/// Proof system parameters of the `keccak::keccak256` pipeline: the sponge and permutation STARKs
+/// linked by the CTL, and the plonky2 circuit aggregating them.
+#[derive(Clone, Debug)]
+pub struct KeccakConfig {
+    pub stark_config: StarkConfig,
+    pub aggregation_config: CircuitConfig,
+}
+
+impl KeccakConfig {
+    /// Rate 2 STARKs aggregated with the standard plonky2 recursion config (rate 8).
+    /// Targets ~100 bit conjectured security.
+    pub fn standard_fast_config() -> Self {
+        Self {
+            stark_config: StarkConfig::standard_fast_config(),
+            aggregation_config: CircuitConfig::standard_recursion_config(),
+        }
+    }
+
+    /// Rate 16 STARKs aggregated with a rate 128 circuit. Proving takes longer, but the STARK and
+    /// aggregated proofs need 4x and 2x fewer FRI queries than `standard_fast_config`.
+    /// Targets ~100 bit conjectured security.
+    pub fn standard_small_proof_config() -> Self {
+        Self {
+            stark_config: StarkConfig::standard_small_proof_config(),
+            aggregation_config: aggregation_config(100, 7, 16),
+        }
+    }
+
+    /// `standard_fast_config` with ~128 bit conjectured security, for both the STARKs and the
+    /// aggregation.
+    pub fn standard_high_security_config() -> Self {
+        Self {
+            stark_config: StarkConfig::standard_high_security_config(),
+            aggregation_config: aggregation_config(128, 3, 20),
+        }
+    }
+}
+
+impl Default for KeccakConfig {
+    fn default() -> Self {
+        Self::standard_fast_config()
+    }
+}
+
+/// The standard plonky2 recursion config, with its FRI parameters adjusted to the given rate,
+/// grinding and security level.
+fn aggregation_config(
+    security_bits: usize,
+    rate_bits: usize,
+    proof_of_work_bits: u32,
+) -> CircuitConfig {
+    let mut config = CircuitConfig::standard_recursion_config();
+    config.security_bits = security_bits;
+    config.fri_config.rate_bits = rate_bits;
+    config.fri_config.proof_of_work_bits = proof_of_work_bits;
+    config.fri_config.num_query_rounds =
+        num_query_rounds(security_bits, rate_bits, proof_of_work_bits);
+    config
+}
+
+/// Named `KeccakConfig`s, so benchmarks can select them from the command line.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum KeccakConfigPreset {
+    Fast,
+    SmallProof,
+    HighSecurity,
+}
+
+impl KeccakConfigPreset {
+    pub fn all() -> [Self; 3] {
+        [Self::Fast, Self::SmallProof, Self::HighSecurity]
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Fast => "fast",
+            Self::SmallProof => "small-proof",
+            Self::HighSecurity => "high-security",
+        }
+    }
+
+    pub fn config(&self) -> KeccakConfig {
+        match self {
+            Self::Fast => KeccakConfig::standard_fast_config(),
+            Self::SmallProof => KeccakConfig::standard_small_proof_config(),
+            Self::HighSecurity => KeccakConfig::standard_high_security_config(),
+        }
+    }
+}
+
+impl fmt::Display for KeccakConfigPreset {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for KeccakConfigPreset {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match Self::all().into_iter().find(|preset| preset.name() == s) {
+            Some(preset) => Ok(preset),
+            None => bail!(
+                "Unknown config preset {s:?}, expected one of: {}",
+                Self::all().map(|preset| preset.name()).join(", ")
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use plonky2::plonk::circuit_data::CircuitConfig;
+    use starky::config::StarkConfig;
+
+    use crate::config::{KeccakConfig, KeccakConfigPreset};
+
+    #[test]
+    fn test_presets_match_standard_configs() {
+        let fast = KeccakConfig::standard_fast_config();
+        assert_eq!(fast.stark_config.fri_config.num_query_rounds, 84);
+        assert_eq!(
+            fast.aggregation_config,
+            CircuitConfig::standard_recursion_config()
+        );
+        assert_eq!(
+            StarkConfig::standard_small_proof_config().fri_config.num_query_rounds,
+            21
+        );
+    }
+
+    #[test]
+    fn test_presets_reach_security_level() {
+        for preset in KeccakConfigPreset::all() {
+            let config = preset.config();
+            assert_eq!(preset.name().parse::<KeccakConfigPreset>().unwrap(), preset);
+
+            let stark_fri = &config.stark_config.fri_config;
+            assert!(
+                stark_fri.rate_bits * stark_fri.num_query_rounds
+                    + stark_fri.proof_of_work_bits as usize
+                    >= config.stark_config.security_bits
+            );
+            let aggregation_fri = &config.aggregation_config.fri_config;
+            assert!(
+                aggregation_fri.rate_bits * aggregation_fri.num_query_rounds
+                    + aggregation_fri.proof_of_work_bits as usize
+                    >= config.aggregation_config.security_bits
+            );
+        }
+        assert!("medium".parse::<KeccakConfigPreset>().is_err());
+    }
+}
@@ -1,5 +1,5 @@
 use std::borrow::Borrow;
-use std::iter::repeat;
+use std::iter::{once, repeat};
 
 use anyhow::{ensure, Result};
 use itertools::Itertools;
@@ -23,9 +23,12 @@ use starky::proof::{StarkProofChallenges, StarkProofTarget, StarkProofWithPublic
 use starky::stark::Stark;
 use starky::vars::{StarkEvaluationTargets, StarkEvaluationVars};
 
+#[cfg(feature = "bytes-table")]
+use crate::keccak_bytes::ctl::*;
 use crate::keccak_permutation::ctl::*;
 use crate::keccak_permutation::keccak_permutation_stark;
 use crate::keccak_sponge::ctl::*;
+use crate::keccak_sponge::variant::KeccakSpongeVariant;
 //use crate::keccak_sponge::ctl;
 
 /// Represent a linear combination of columns.
@@ -149,13 +152,23 @@ impl<F: Field> Column<F> {
 pub enum Table {
     KeccakPermutation = 0,
     KeccakSponge = 1,
+    #[cfg(feature = "bytes-table")]
+    KeccakBytes = 2,
 }
 
+#[cfg(not(feature = "bytes-table"))]
 pub const NUM_TABLES: usize = Table::KeccakSponge as usize + 1;
+#[cfg(feature = "bytes-table")]
+pub const NUM_TABLES: usize = Table::KeccakBytes as usize + 1;
 
 impl Table {
     pub fn all() -> [Self; NUM_TABLES] {
-        [Self::KeccakPermutation, Self::KeccakSponge]
+        [
+            Self::KeccakPermutation,
+            Self::KeccakSponge,
+            #[cfg(feature = "bytes-table")]
+            Self::KeccakBytes,
+        ]
     }
 }
 
@@ -187,12 +200,6 @@ impl<F: Field> CrossTableLookup<F> {
         looking_tables: Vec<TableWithColumns<F>>,
         looked_table: TableWithColumns<F>,
     ) -> Self {
-        println!("\n\nCol len {}", looking_tables.len());
-        for i in looking_tables.iter() {
-            print!("{} ", i.columns.len());
-        }
-        println!("\n\nCol len: {}", looked_table.columns.len());
-
         assert!(looking_tables
             .iter()
             .all(|twc| twc.columns.len() == looked_table.columns.len()));
@@ -212,8 +219,17 @@ impl<F: Field> CrossTableLookup<F> {
     }
 }
 
-pub fn all_cross_table_lookups<F: Field>() -> Vec<CrossTableLookup<F>> {
-    let ctls = vec![ctl_keccak_permutation()];
+/// The CTLs of a proof of the sponge of `variant`.
+pub fn all_cross_table_lookups<F: Field>(variant: KeccakSpongeVariant) -> Vec<CrossTableLookup<F>> {
+    #[allow(unused_mut)]
+    let mut ctls = vec![
+        ctl_keccak_permutation(),
+        ctl_keccak_sponge_digests(variant),
+        ctl_keccak_sponge_initial_states(),
+        ctl_keccak_sponge_final_states(variant),
+    ];
+    #[cfg(feature = "bytes-table")]
+    ctls.extend([ctl_keccak_sponge_bytes(variant), ctl_keccak_message_bytes()]);
     ctls
 }
 
@@ -235,6 +251,220 @@ pub fn ctl_keccak_permutation<F: Field>() -> CrossTableLookup<F> {
     CrossTableLookup::new(vec![keccak_sponge_looking], keccak_looked)
 }
 
+/// The digest of every sponge, looked up by the public values rather than by another table: the
+/// verifier supplies the looking product itself, see `ctl_extra_looking_products`.
+pub fn ctl_keccak_sponge_digests<F: Field>(variant: KeccakSpongeVariant) -> CrossTableLookup<F> {
+    let keccak_sponge_looked = TableWithColumns::new(
+        Table::KeccakSponge,
+        ctl_looked_digest(variant),
+        Some(ctl_looked_digest_filter()),
+    );
+    CrossTableLookup::new(vec![], keccak_sponge_looked)
+}
+
+/// The state each resumed sponge starts from, looked up by the public initial states, like the
+/// digests.
+pub fn ctl_keccak_sponge_initial_states<F: Field>() -> CrossTableLookup<F> {
+    let keccak_sponge_looked = TableWithColumns::new(
+        Table::KeccakSponge,
+        ctl_looked_initial_state(),
+        Some(ctl_looked_initial_state_filter()),
+    );
+    CrossTableLookup::new(vec![], keccak_sponge_looked)
+}
+
+/// The state each suspended sponge stops in, looked up by the public final states, like the
+/// digests.
+pub fn ctl_keccak_sponge_final_states<F: Field>(
+    variant: KeccakSpongeVariant,
+) -> CrossTableLookup<F> {
+    let keccak_sponge_looked = TableWithColumns::new(
+        Table::KeccakSponge,
+        ctl_looked_final_state(variant),
+        Some(ctl_looked_final_state_filter()),
+    );
+    CrossTableLookup::new(vec![], keccak_sponge_looked)
+}
+
+/// Each input byte absorbed by the sponge, looked up in the bytes table rather than trusted.
+#[cfg(feature = "bytes-table")]
+pub fn ctl_keccak_sponge_bytes<F: Field>(variant: KeccakSpongeVariant) -> CrossTableLookup<F> {
+    let keccak_sponge_looking = (0..variant.rate_bytes)
+        .map(|i| {
+            TableWithColumns::new(
+                Table::KeccakSponge,
+                ctl_looking_bytes(i),
+                Some(ctl_looking_bytes_filter(i)),
+            )
+        })
+        .collect();
+    let keccak_bytes_looked = TableWithColumns::new(
+        Table::KeccakBytes,
+        ctl_looked_bytes(),
+        Some(ctl_looked_bytes_filter()),
+    );
+    CrossTableLookup::new(keccak_sponge_looking, keccak_bytes_looked)
+}
+
+/// The bytes of every message, looked up by the public values, so that the bytes table holds
+/// exactly the hashed messages. Like the digests, the looking product comes from the verifier.
+#[cfg(feature = "bytes-table")]
+pub fn ctl_keccak_message_bytes<F: Field>() -> CrossTableLookup<F> {
+    let keccak_bytes_looked = TableWithColumns::new(
+        Table::KeccakBytes,
+        ctl_looked_bytes(),
+        Some(ctl_looked_bytes_filter()),
+    );
+    CrossTableLookup::new(vec![], keccak_bytes_looked)
+}
+
+/// Splits the public values into the rows looked up in the sponge by `ctl_keccak_sponge_digests`,
+/// one per output block. The `i`-th message is hashed by the sponge operation with timestamp `i`.
+pub fn sponge_digest_rows<T: Copy>(
+    variant: KeccakSpongeVariant,
+    public_values: &[T],
+    index: impl Fn(usize) -> T,
+) -> Vec<Vec<T>> {
+    public_values
+        .chunks_exact(public_values_per_hash(variant))
+        .enumerate()
+        .flat_map(|(i, len_and_output)| {
+            let (len, output) = len_and_output.split_first().unwrap();
+            output
+                .chunks_exact(variant.output_u32s_per_block())
+                .enumerate()
+                .map(|(block, limbs)| {
+                    [index(i), *len, index(block)]
+                        .into_iter()
+                        .chain(limbs.iter().copied())
+                        .collect()
+                })
+                .collect_vec()
+        })
+        .collect()
+}
+
+/// Splits the public initial or final states into the rows looked up in the sponge by
+/// `ctl_keccak_sponge_initial_states` or `ctl_keccak_sponge_final_states`.
+pub fn sponge_state_rows<T: Copy>(states: &[T]) -> Vec<Vec<T>> {
+    states
+        .chunks_exact(SPONGE_STATE_VALUES)
+        .map(<[T]>::to_vec)
+        .collect()
+}
+
+/// Splits the public messages into the rows looked up in the bytes table by
+/// `ctl_keccak_message_bytes`: the index of the message, the position of the byte and the byte.
+pub fn message_byte_rows<T: Copy>(messages: &[Vec<T>], index: impl Fn(usize) -> T) -> Vec<Vec<T>> {
+    messages
+        .iter()
+        .enumerate()
+        .flat_map(|(i, msg)| msg.iter().enumerate().map(move |(j, &byte)| (i, j, byte)))
+        .map(|(i, j, byte)| vec![index(i), index(j), byte])
+        .collect()
+}
+
+/// For each CTL in `all_cross_table_lookups`, the looking rows which do not come from a table but
+/// from the public values: the sponge digests, the initial and final states of the resumed and
+/// suspended sponges and, with the bytes table, the hashed messages.
+pub fn ctl_extra_looking_rows<T: Copy>(
+    variant: KeccakSpongeVariant,
+    public_values: &[T],
+    initial_states: &[T],
+    final_states: &[T],
+    messages: &[Vec<T>],
+    index: impl Fn(usize) -> T,
+) -> Vec<Vec<Vec<T>>> {
+    let mut rows = vec![
+        vec![],
+        sponge_digest_rows(variant, public_values, &index),
+        sponge_state_rows(initial_states),
+        sponge_state_rows(final_states),
+    ];
+    if cfg!(feature = "bytes-table") {
+        rows.extend([vec![], message_byte_rows(messages, &index)]);
+    }
+    rows
+}
+
+/// For each CTL in `all_cross_table_lookups` and each challenge, the product of the looking values
+/// that do not come from a table, see `ctl_extra_looking_rows`.
+pub fn ctl_extra_looking_products<F: Field>(
+    variant: KeccakSpongeVariant,
+    public_values: &[F],
+    initial_states: &[F],
+    final_states: &[F],
+    messages: &[Vec<F>],
+    ctl_challenges: &PermutationChallengeSet<F>,
+) -> Vec<Vec<F>> {
+    let rows = ctl_extra_looking_rows(
+        variant,
+        public_values,
+        initial_states,
+        final_states,
+        messages,
+        F::from_canonical_usize,
+    );
+    rows.iter()
+        .map(|table_rows| {
+            ctl_challenges
+                .challenges
+                .iter()
+                .map(|challenge| {
+                    table_rows
+                        .iter()
+                        .map(|row| challenge.combine(row.iter()))
+                        .product()
+                })
+                .collect()
+        })
+        .collect()
+}
+
+pub fn ctl_extra_looking_products_circuit<F: RichField + Extendable<D>, const D: usize>(
+    builder: &mut CircuitBuilder<F, D>,
+    variant: KeccakSpongeVariant,
+    public_values: &[Target],
+    initial_states: &[Target],
+    final_states: &[Target],
+    messages: &[Vec<Target>],
+    ctl_challenges: &PermutationChallengeSet<Target>,
+) -> Vec<Vec<Target>> {
+    let num_indices = messages
+        .iter()
+        .map(Vec::len)
+        .chain(once(public_values.len() / public_values_per_hash(variant)))
+        .chain(once(variant.output_blocks()))
+        .max()
+        .unwrap();
+    let indices = (0..num_indices)
+        .map(|i| builder.constant(F::from_canonical_usize(i)))
+        .collect_vec();
+    let rows = ctl_extra_looking_rows(
+        variant,
+        public_values,
+        initial_states,
+        final_states,
+        messages,
+        |i| indices[i],
+    );
+    rows.iter()
+        .map(|table_rows| {
+            ctl_challenges
+                .challenges
+                .iter()
+                .map(|challenge| {
+                    let combined = table_rows
+                        .iter()
+                        .map(|row| challenge.combine_base_circuit(builder, row))
+                        .collect_vec();
+                    builder.mul_many(combined)
+                })
+                .collect()
+        })
+        .collect()
+}
+
 /// Cross-table lookup data for one table.
 #[derive(Clone, Default)]
 pub struct CtlData<F: Field> {
@@ -295,14 +525,6 @@ pub fn cross_table_lookup_data<F: RichField, const D: usize>(
                 challenge,
             );
 
-            debug_assert_eq!(
-                zs_looking
-                    .clone()
-                    .map(|z| *z.values.last().unwrap())
-                    .product::<F>(),
-                *z_looked.values.last().unwrap()
-            );
-
             for (table, z) in looking_tables.iter().zip(zs_looking) {
                 ctl_data_per_table[table.table as usize]
                     .zs_columns
@@ -372,16 +594,12 @@ where
 impl<'a, F: RichField + Extendable<D>, const D: usize>
     CtlCheckVars<'a, F, F::Extension, F::Extension, D>
 {
-    pub fn from_proofs<C: GenericConfig<D, F = F>, S: Stark<F, D>>(
+    pub fn from_proofs<C: GenericConfig<D, F = F>>(
         proofs: &[StarkProofWithPublicInputs<F, C, D>; NUM_TABLES],
         cross_table_lookups: &'a [CrossTableLookup<F>],
         ctl_challenges: &'a PermutationChallengeSet<F>,
         num_permutation_zs: &[usize; NUM_TABLES],
-    ) -> [Vec<Self>; NUM_TABLES]
-    where
-        [(); S::COLUMNS]:,
-        [(); S::PUBLIC_INPUTS]:,
-    {
+    ) -> [Vec<Self>; NUM_TABLES] {
         let mut ctl_zs = proofs
             .iter()
             .zip(num_permutation_zs)
@@ -479,6 +697,22 @@ pub fn eval_cross_table_lookup_checks<F, FE, P, S, const D: usize, const D2: usi
     }
 }
 
+/// Checks that each CTL `Z` polynomial ends at the value claimed by the prover, so that the
+/// claimed values can be used in `verify_cross_table_lookups`.
+pub fn eval_cross_table_lookup_zs_last<F, FE, P, const D2: usize>(
+    ctl_vars: &[CtlCheckVars<F, FE, P, D2>],
+    ctl_zs_last: &[F],
+    consumer: &mut ConstraintConsumer<P>,
+) where
+    F: Field,
+    FE: FieldExtension<D2, BaseField = F>,
+    P: PackedField<Scalar = FE>,
+{
+    for (lookup_vars, &z_last) in ctl_vars.iter().zip_eq(ctl_zs_last) {
+        consumer.constraint_last_row(lookup_vars.local_z - FE::from_basefield(z_last));
+    }
+}
+
 #[derive(Clone)]
 pub struct CtlCheckVarsTarget<'a, F: Field, const D: usize> {
     pub(crate) local_z: ExtensionTarget<D>,
@@ -612,22 +846,44 @@ pub fn eval_cross_table_lookup_checks_circuit<
     }
 }
 
+pub fn eval_cross_table_lookup_zs_last_circuit<F: RichField + Extendable<D>, const D: usize>(
+    builder: &mut CircuitBuilder<F, D>,
+    ctl_vars: &[CtlCheckVarsTarget<F, D>],
+    ctl_zs_last: &[Target],
+    consumer: &mut RecursiveConstraintConsumer<F, D>,
+) {
+    for (lookup_vars, &z_last) in ctl_vars.iter().zip_eq(ctl_zs_last) {
+        let z_last = builder.convert_to_ext(z_last);
+        let constraint = builder.sub_extension(lookup_vars.local_z, z_last);
+        consumer.constraint_last_row(builder, constraint);
+    }
+}
+
 pub fn verify_cross_table_lookups<F: RichField + Extendable<D>, const D: usize>(
     cross_table_lookups: &[CrossTableLookup<F>],
     ctl_zs_lasts: [Vec<F>; NUM_TABLES],
+    ctl_extra_looking_products: &[Vec<F>],
     config: &StarkConfig,
 ) -> Result<()> {
     let mut ctl_zs_openings = ctl_zs_lasts.iter().map(|v| v.iter()).collect::<Vec<_>>();
-    for CrossTableLookup {
-        looking_tables,
-        looked_table,
-    } in cross_table_lookups.iter()
+    for (
+        CrossTableLookup {
+            looking_tables,
+            looked_table,
+        },
+        extra_products,
+    ) in cross_table_lookups.iter().zip_eq(ctl_extra_looking_products)
     {
-        for _ in 0..config.num_challenges {
+        ensure!(
+            extra_products.len() == config.num_challenges,
+            "Wrong number of extra looking products."
+        );
+        for &extra_product in extra_products {
             let looking_zs_prod = looking_tables
                 .iter()
                 .map(|table| *ctl_zs_openings[table.table as usize].next().unwrap())
-                .product::<F>();
+                .product::<F>()
+                * extra_product;
             let looked_z = *ctl_zs_openings[looked_table.table as usize].next().unwrap();
 
             ensure!(
@@ -645,19 +901,25 @@ pub fn verify_cross_table_lookups_circuit<F: RichField + Extendable<D>, const D:
     builder: &mut CircuitBuilder<F, D>,
     cross_table_lookups: Vec<CrossTableLookup<F>>,
     ctl_zs_lasts: [Vec<Target>; NUM_TABLES],
+    ctl_extra_looking_products: &[Vec<Target>],
     inner_config: &StarkConfig,
 ) {
     let mut ctl_zs_openings = ctl_zs_lasts.iter().map(|v| v.iter()).collect::<Vec<_>>();
-    for CrossTableLookup {
-        looking_tables,
-        looked_table,
-    } in cross_table_lookups.into_iter()
+    for (
+        CrossTableLookup {
+            looking_tables,
+            looked_table,
+        },
+        extra_products,
+    ) in cross_table_lookups.into_iter().zip_eq(ctl_extra_looking_products)
     {
-        for _ in 0..inner_config.num_challenges {
+        assert_eq!(extra_products.len(), inner_config.num_challenges);
+        for &extra_product in extra_products {
             let looking_zs_prod = builder.mul_many(
                 looking_tables
                     .iter()
-                    .map(|table| *ctl_zs_openings[table.table as usize].next().unwrap()),
+                    .map(|table| *ctl_zs_openings[table.table as usize].next().unwrap())
+                    .chain(once(extra_product)),
             );
             let looked_z = *ctl_zs_openings[looked_table.table as usize].next().unwrap();
             builder.connect(looking_zs_prod, looked_z);
@@ -675,22 +937,33 @@ pub(crate) mod testutils {
 
     use crate::cross_table_lookup::{CrossTableLookup, Table, TableWithColumns};
 
-    type MultiSet<F> = HashMap<Vec<F>, Vec<(Table, usize)>>;
+    /// Where a looked up row comes from: a table row, or the `i`-th extra looking row.
+    #[derive(Debug)]
+    enum Location {
+        Row(Table, usize),
+        Extra(usize),
+    }
+
+    type MultiSet<F> = HashMap<Vec<F>, Vec<Location>>;
 
-    /// Check that the provided traces and cross-table lookups are consistent.
+    /// Check that the provided traces and cross-table lookups are consistent. `extra_looking_rows`
+    /// holds, for each CTL, the looking rows which do not come from a table, e.g. public values.
     #[allow(unused)]
     pub(crate) fn check_ctls<F: Field>(
         trace_poly_values: &[Vec<PolynomialValues<F>>],
         cross_table_lookups: &[CrossTableLookup<F>],
+        extra_looking_rows: &[Vec<Vec<F>>],
     ) {
-        for (i, ctl) in cross_table_lookups.iter().enumerate() {
-            check_ctl(trace_poly_values, ctl, i);
+        for (i, (ctl, extra_rows)) in cross_table_lookups.iter().zip(extra_looking_rows).enumerate()
+        {
+            check_ctl(trace_poly_values, ctl, extra_rows, i);
         }
     }
 
     fn check_ctl<F: Field>(
         trace_poly_values: &[Vec<PolynomialValues<F>>],
         ctl: &CrossTableLookup<F>,
+        extra_rows: &[Vec<F>],
         ctl_index: usize,
     ) {
         let CrossTableLookup {
@@ -706,6 +979,9 @@ pub(crate) mod testutils {
         for table in looking_tables {
             process_table(trace_poly_values, table, &mut looking_multiset);
         }
+        for (i, row) in extra_rows.iter().enumerate() {
+            looking_multiset.entry(row.clone()).or_default().push(Location::Extra(i));
+        }
         process_table(trace_poly_values, looked_table, &mut looked_multiset);
 
         let empty = &vec![];
@@ -739,7 +1015,7 @@ pub(crate) mod testutils {
                     .iter()
                     .map(|c| c.eval_table(trace, i))
                     .collect::<Vec<_>>();
-                multiset.entry(row).or_default().push((table.table, i));
+                multiset.entry(row).or_default().push(Location::Row(table.table, i));
             } else {
                 assert_eq!(filter, F::ZERO, "Non-binary filter?")
             }
@@ -747,8 +1023,8 @@ pub(crate) mod testutils {
     }
 
     fn check_locations<F: Field>(
-        looking_locations: &[(Table, usize)],
-        looked_locations: &[(Table, usize)],
+        looking_locations: &[Location],
+        looked_locations: &[Location],
         ctl_index: usize,
         row: &[F],
     ) {
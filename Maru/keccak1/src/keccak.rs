@@ -1,49 +1,142 @@
 use anyhow::Result;
+use bench_memory::PhaseMemory;
 use itertools::Itertools;
+use keccak_hash::keccak;
 use log::Level;
 use plonky2::hash::hash_types::RichField;
+use plonky2::iop::target::Target;
+use plonky2::plonk::circuit_builder::CircuitBuilder;
 use plonky2::plonk::circuit_data::CircuitData;
-use plonky2::plonk::config::{AlgebraicHasher, GenericConfig, PoseidonGoldilocksConfig};
+use plonky2::plonk::config::{AlgebraicHasher, GenericConfig};
 use plonky2::plonk::proof::ProofWithPublicInputs;
 use plonky2::util::timing::TimingTree;
 
 use plonky2_field::extension::Extendable;
-use plonky2_field::goldilocks_field::GoldilocksField;
 use starky::stark::Stark;
 
-use crate::keccak_permutation::keccak_permutation_proof::{
-    keccak256_permutation_proof,
-    keccak256_permutation_verify,
+use crate::config::KeccakConfig;
+use crate::cross_table_lookup::Table;
+use crate::keccak_bytes::keccak_bytes_stark::KeccakBytesStark;
+use crate::keccak_ctl_proof::{
+    keccak_sponge_batch_ctl_proof, keccak_sponge_ctl_verify, keccak_sponge_segment_ctl_proof,
+    KeccakCtlProof,
 };
-use crate::keccak_permutation::keccak_permutation_stark::{KeccakPermutationStark, NUM_INPUTS};
-use crate::keccak_sponge::keccak_sponge_proof::{keccak256_sponge_proof, keccak256_sponge_verify};
-use crate::keccak_sponge::keccak_sponge_stark::KeccakSpongeStark;
-use crate::stark_aggregation::{aggregation_sponge_permutation, u32_to_u64};
+use crate::keccak_permutation::keccak_permutation_stark::KeccakPermutationStark;
+use crate::keccak_sponge::ctl::SPONGE_STATE_VALUES;
+use crate::keccak_sponge::keccak_sponge_stark::{
+    KeccakSpongeOp, KeccakSpongeSegmentOp, KeccakSpongeStark,
+};
+use crate::keccak_sponge::variant::KeccakSpongeVariant;
+use crate::snark_aggregation::aggregation_two_with_public_inputs;
+use crate::stark_aggregation::aggregation_sponge_permutation;
 use plonky2::plonk::config::Hasher;
 use std::time::{Instant};
 
+/// Circuit, aggregated proof, the CTL proof it aggregates, timings (seconds), proof sizes (bytes)
+/// and memory usage of each phase.
+pub type KeccakProofOutput<F, C, const D: usize> = (
+    CircuitData<F, C, D>,
+    ProofWithPublicInputs<F, C, D>,
+    KeccakCtlProof<F, C, D>,
+    f32,
+    f32,
+    f32,
+    f32,
+    f32,
+    f32,
+    usize,
+    usize,
+    usize,
+    f32,
+    PhaseMemory,
+    PhaseMemory,
+    PhaseMemory
+);
+
+/// Proves that `hash` is the keccak256 digest of `msg`, with the proof system parameters of `config`.
 pub fn keccak256<F, C, const D: usize>(
     msg: &[u8],
     hash: &[u8],
+    config: &KeccakConfig,
+)
+    -> Result<KeccakProofOutput<F, C, D>>
+    where
+        F: RichField + Extendable<D>,
+        C: GenericConfig<D, F=F>,
+        C::Hasher: AlgebraicHasher<F>,
+        [(); C::Hasher::HASH_SIZE]:,
+        [(); KeccakSpongeStark::<F, D>::COLUMNS]:,
+        [(); KeccakSpongeStark::<F, D>::PUBLIC_INPUTS]:,
+        [(); KeccakPermutationStark::<F, D>::COLUMNS]:,
+        [(); KeccakPermutationStark::<F, D>::PUBLIC_INPUTS]:,
+        [(); KeccakBytesStark::<F, D>::COLUMNS]:,
+        [(); KeccakBytesStark::<F, D>::PUBLIC_INPUTS]:
+{
+    prove_and_aggregate(KeccakSpongeVariant::KECCAK256, &[msg.to_vec()], &[hash], config)
+}
+
+/// Proves the keccak256 hashes of all `msgs` in one sponge trace and one permutation trace. The
+/// aggregated proof exposes a `(length, digest)` pair per message as public inputs, in order,
+/// followed by the messages themselves with the `bytes-table` feature.
+pub fn keccak256_batch<F, C, const D: usize>(
+    msgs: &[Vec<u8>],
+    config: &KeccakConfig,
+)
+    -> Result<KeccakProofOutput<F, C, D>>
+    where
+        F: RichField + Extendable<D>,
+        C: GenericConfig<D, F=F>,
+        C::Hasher: AlgebraicHasher<F>,
+        [(); C::Hasher::HASH_SIZE]:,
+        [(); KeccakSpongeStark::<F, D>::COLUMNS]:,
+        [(); KeccakSpongeStark::<F, D>::PUBLIC_INPUTS]:,
+        [(); KeccakPermutationStark::<F, D>::COLUMNS]:,
+        [(); KeccakPermutationStark::<F, D>::PUBLIC_INPUTS]:,
+        [(); KeccakBytesStark::<F, D>::COLUMNS]:,
+        [(); KeccakBytesStark::<F, D>::PUBLIC_INPUTS]:
+{
+    let hashes = msgs.iter().map(keccak).collect::<Vec<_>>();
+    let hashes = hashes.iter().map(|hash| hash.as_bytes()).collect::<Vec<_>>();
+    prove_and_aggregate(KeccakSpongeVariant::KECCAK256, msgs, &hashes, config)
+}
+
+/// Like `keccak256_batch`, for the hash function of any sponge `variant`, e.g. SHA3-256 with
+/// `KeccakSpongeVariant::SHA3_256`.
+pub fn keccak_sponge_batch<F, C, const D: usize>(
+    variant: KeccakSpongeVariant,
+    msgs: &[Vec<u8>],
+    config: &KeccakConfig,
+)
+    -> Result<KeccakProofOutput<F, C, D>>
+    where
+        F: RichField + Extendable<D>,
+        C: GenericConfig<D, F=F>,
+        C::Hasher: AlgebraicHasher<F>,
+        [(); C::Hasher::HASH_SIZE]:,
+        [(); KeccakSpongeStark::<F, D>::COLUMNS]:,
+        [(); KeccakSpongeStark::<F, D>::PUBLIC_INPUTS]:,
+        [(); KeccakPermutationStark::<F, D>::COLUMNS]:,
+        [(); KeccakPermutationStark::<F, D>::PUBLIC_INPUTS]:,
+        [(); KeccakBytesStark::<F, D>::COLUMNS]:,
+        [(); KeccakBytesStark::<F, D>::PUBLIC_INPUTS]:
+{
+    let hashes = msgs.iter().map(|msg| variant.hash(msg)).collect::<Vec<_>>();
+    let hashes = hashes.iter().map(Vec::as_slice).collect::<Vec<_>>();
+    prove_and_aggregate(variant, msgs, &hashes, config)
+}
+
+/// Proves the hash of `msg` with the sponge of `variant` in segments of `segment_blocks` input
+/// blocks, one at a time, so that the traces of a long message are never held at once. The
+/// aggregated segment proofs are linked by their public sponge states: the first segment resumes
+/// from the all-0s state, and each other one from the state the previous one is suspended in. The
+/// final proof exposes the `(length, digest)` pair of `msg` as public inputs, like a batch of one.
+pub fn keccak_sponge_segmented<F, C, const D: usize>(
+    variant: KeccakSpongeVariant,
+    msg: &[u8],
+    segment_blocks: usize,
+    config: &KeccakConfig,
 )
-    -> Result<
-        (
-            CircuitData<F, C, D>,
-            ProofWithPublicInputs<F, C, D>,
-            f32,
-            f32,
-            f32,
-            f32,
-            f32,
-            f32,
-            usize,
-            usize,
-            usize,
-            f32,
-            f32,
-            f32
-        )
-    >
+    -> Result<(CircuitData<F, C, D>, ProofWithPublicInputs<F, C, D>)>
     where
         F: RichField + Extendable<D>,
         C: GenericConfig<D, F=F>,
@@ -52,24 +145,99 @@ pub fn keccak256<F, C, const D: usize>(
         [(); KeccakSpongeStark::<F, D>::COLUMNS]:,
         [(); KeccakSpongeStark::<F, D>::PUBLIC_INPUTS]:,
         [(); KeccakPermutationStark::<F, D>::COLUMNS]:,
-        [(); KeccakPermutationStark::<F, D>::PUBLIC_INPUTS]:
+        [(); KeccakPermutationStark::<F, D>::PUBLIC_INPUTS]:,
+        [(); KeccakBytesStark::<F, D>::COLUMNS]:,
+        [(); KeccakBytesStark::<F, D>::PUBLIC_INPUTS]:
 {
-    let (sponge_proof, sponge_states, pol_values, sponge_circuit_time, proof_sponge_time) = keccak256_sponge_proof::<F, C, D>(&msg, hash)?;
-    let sponge_proof_size = sponge_proof.to_bytes().len();
-    let verify_sponge_proof = Instant::now();
-    keccak256_sponge_verify::<F, C, D>(sponge_proof.clone())?;
-    let input_permutation: Vec<[u64; NUM_INPUTS]> = sponge_states
-        .iter()
-        .map(|x| u32_to_u64(x).try_into().unwrap())
-        .collect_vec();
-    let verify_sponge_proof_time = verify_sponge_proof.elapsed().as_secs_f32();
-    let (permutation_proof, _, circuit_perm_time, proof_permutations_time) = keccak256_permutation_proof::<F, C, D>(input_permutation)?;
-    let permutations_proof_size = permutation_proof.to_bytes().len();
-    let verify_permutations = Instant::now();
-    keccak256_permutation_verify::<F, C, D>(permutation_proof.clone())?;
-    let verify_permutation_proof_time = verify_permutations.elapsed().as_secs_f32();
-    let (data, proof, aggregation_circuit_build_time, aggregation_sponge_permutations_proof_time) =
-        aggregation_sponge_permutation(sponge_proof, permutation_proof)?;
+    let hash = variant.hash(msg);
+    let op = KeccakSpongeOp { timestamp: 0, input: msg.to_vec() };
+    let mut aggregated: Option<(CircuitData<F, C, D>, ProofWithPublicInputs<F, C, D>)> = None;
+    for segment in KeccakSpongeSegmentOp::split(variant, &op, segment_blocks) {
+        let segment_hash = segment.is_last().then_some(hash.as_slice());
+        let (ctl_proof, ..) = keccak_sponge_segment_ctl_proof::<F, C, D>(
+            variant,
+            &segment,
+            segment_hash,
+            &config.stark_config,
+        )?;
+        let (data, proof, ..) = aggregation_sponge_permutation(variant, &ctl_proof, config)?;
+        // The public inputs of a segment are its public values, its initial state, then its final
+        // state. Only the (length, digest) pair or the final state are exposed further.
+        let num_public_values = ctl_proof.public_values.len();
+        let initial_state = num_public_values..num_public_values + SPONGE_STATE_VALUES;
+        let register_segment_outputs =
+            |builder: &mut CircuitBuilder<F, D>, segment_pis: &[Target]| {
+                builder.register_public_inputs(&segment_pis[..initial_state.start]);
+                builder.register_public_inputs(&segment_pis[initial_state.end..]);
+            };
+        aggregated = Some(match aggregated {
+            None => aggregation_two_with_public_inputs(
+                (&data, &proof),
+                None,
+                |builder, segment_pis, _| {
+                    // The timestamp and the length are free, but nothing is absorbed yet.
+                    for &value in &segment_pis[initial_state.clone()][2..] {
+                        builder.assert_zero(value);
+                    }
+                    register_segment_outputs(builder, segment_pis);
+                },
+            )?,
+            Some((previous_data, previous_proof)) => aggregation_two_with_public_inputs(
+                (&previous_data, &previous_proof),
+                Some((&data, &proof)),
+                |builder, previous_pis, segment_pis| {
+                    let resumed_state = &segment_pis[initial_state.clone()];
+                    for (&suspended, &resumed) in previous_pis.iter().zip_eq(resumed_state) {
+                        builder.connect(suspended, resumed);
+                    }
+                    register_segment_outputs(builder, segment_pis);
+                },
+            )?,
+        });
+    }
+    Ok(aggregated.expect("A message has at least one segment"))
+}
+
+fn prove_and_aggregate<F, C, const D: usize>(
+    variant: KeccakSpongeVariant,
+    msgs: &[Vec<u8>],
+    hashes: &[&[u8]],
+    config: &KeccakConfig,
+)
+    -> Result<KeccakProofOutput<F, C, D>>
+    where
+        F: RichField + Extendable<D>,
+        C: GenericConfig<D, F=F>,
+        C::Hasher: AlgebraicHasher<F>,
+        [(); C::Hasher::HASH_SIZE]:,
+        [(); KeccakSpongeStark::<F, D>::COLUMNS]:,
+        [(); KeccakSpongeStark::<F, D>::PUBLIC_INPUTS]:,
+        [(); KeccakPermutationStark::<F, D>::COLUMNS]:,
+        [(); KeccakPermutationStark::<F, D>::PUBLIC_INPUTS]:,
+        [(); KeccakBytesStark::<F, D>::COLUMNS]:,
+        [(); KeccakBytesStark::<F, D>::PUBLIC_INPUTS]:
+{
+    let (
+        ctl_proof,
+        sponge_trace_time,
+        permutation_trace_time,
+        proof_ctl_time,
+        witness_memory,
+        proof_ctl_memory,
+    ) = keccak_sponge_batch_ctl_proof::<F, C, D>(variant, msgs, hashes, &config.stark_config)?;
+    let sponge_proof_size = ctl_proof.stark_proofs[Table::KeccakSponge as usize].to_bytes().len();
+    let permutations_proof_size = ctl_proof.stark_proofs[Table::KeccakPermutation as usize].to_bytes().len();
+    let verify_ctl_proof = Instant::now();
+    keccak_sponge_ctl_verify::<F, C, D>(variant, &ctl_proof, &config.stark_config)?;
+    let verify_ctl_proof_time = verify_ctl_proof.elapsed().as_secs_f32();
+    let (
+        data,
+        proof,
+        aggregation_circuit_build_time,
+        aggregation_sponge_permutations_proof_time,
+        build_memory,
+        aggregation_proof_memory,
+    ) = aggregation_sponge_permutation(variant, &ctl_proof, config)?;
     let aggregated_proof_size = proof.to_bytes().len();
     let timing = TimingTree::new("verify aggregation: sponge & permutation", Level::Debug);
     let verify_aggregation = Instant::now();
@@ -79,17 +247,19 @@ pub fn keccak256<F, C, const D: usize>(
     Ok((
         data,
         proof,
-        proof_sponge_time,
-        verify_sponge_proof_time,
-        proof_permutations_time,
-        verify_permutation_proof_time,
+        ctl_proof,
+        sponge_trace_time,
+        permutation_trace_time,
+        proof_ctl_time,
+        verify_ctl_proof_time,
         aggregation_sponge_permutations_proof_time,
         verify_aggregation_time,
         sponge_proof_size,
         permutations_proof_size,
         aggregated_proof_size,
-        sponge_circuit_time,
-        circuit_perm_time,
-        aggregation_circuit_build_time
+        aggregation_circuit_build_time,
+        build_memory,
+        witness_memory,
+        proof_ctl_memory.merge(aggregation_proof_memory)
     ))
 }
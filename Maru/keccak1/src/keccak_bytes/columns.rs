@@ -0,0 +1,77 @@
+use std::borrow::{Borrow, BorrowMut};
+use std::mem::{size_of, transmute};
+
+use starky::util::{indices_arr, transmute_no_compile_time_size_checks};
+
+#[repr(C)]
+#[derive(Eq, PartialEq, Debug)]
+pub struct KeccakBytesColumnsView<T: Copy> {
+    /// 1 if this row holds a message byte, 0 if it is a padding row. Padding rows come last.
+    pub is_real: T,
+
+    /// 1 if this row holds the last byte of its message, 0 otherwise.
+    pub is_last_byte: T,
+
+    /// The index of the message, which is also the timestamp of the sponge hashing it.
+    pub timestamp: T,
+
+    /// The position of the byte in its message.
+    pub index: T,
+
+    /// The byte itself.
+    pub value: T,
+}
+
+// `u8` is guaranteed to have a `size_of` of 1.
+pub const NUM_KECCAK_BYTES_COLUMNS: usize = size_of::<KeccakBytesColumnsView<u8>>();
+
+impl<T: Copy> From<[T; NUM_KECCAK_BYTES_COLUMNS]> for KeccakBytesColumnsView<T> {
+    fn from(value: [T; NUM_KECCAK_BYTES_COLUMNS]) -> Self {
+        unsafe { transmute_no_compile_time_size_checks(value) }
+    }
+}
+
+impl<T: Copy> From<KeccakBytesColumnsView<T>> for [T; NUM_KECCAK_BYTES_COLUMNS] {
+    fn from(value: KeccakBytesColumnsView<T>) -> Self {
+        unsafe { transmute_no_compile_time_size_checks(value) }
+    }
+}
+
+impl<T: Copy> Borrow<KeccakBytesColumnsView<T>> for [T; NUM_KECCAK_BYTES_COLUMNS] {
+    fn borrow(&self) -> &KeccakBytesColumnsView<T> {
+        unsafe { transmute(self) }
+    }
+}
+
+impl<T: Copy> BorrowMut<KeccakBytesColumnsView<T>> for [T; NUM_KECCAK_BYTES_COLUMNS] {
+    fn borrow_mut(&mut self) -> &mut KeccakBytesColumnsView<T> {
+        unsafe { transmute(self) }
+    }
+}
+
+impl<T: Copy> Borrow<[T; NUM_KECCAK_BYTES_COLUMNS]> for KeccakBytesColumnsView<T> {
+    fn borrow(&self) -> &[T; NUM_KECCAK_BYTES_COLUMNS] {
+        unsafe { transmute(self) }
+    }
+}
+
+impl<T: Copy> BorrowMut<[T; NUM_KECCAK_BYTES_COLUMNS]> for KeccakBytesColumnsView<T> {
+    fn borrow_mut(&mut self) -> &mut [T; NUM_KECCAK_BYTES_COLUMNS] {
+        unsafe { transmute(self) }
+    }
+}
+
+impl<T: Copy + Default> Default for KeccakBytesColumnsView<T> {
+    fn default() -> Self {
+        [T::default(); NUM_KECCAK_BYTES_COLUMNS].into()
+    }
+}
+
+const fn make_col_map() -> KeccakBytesColumnsView<usize> {
+    let indices_arr = indices_arr::<NUM_KECCAK_BYTES_COLUMNS>();
+    unsafe {
+        transmute::<[usize; NUM_KECCAK_BYTES_COLUMNS], KeccakBytesColumnsView<usize>>(indices_arr)
+    }
+}
+
+pub const KECCAK_BYTES_COL_MAP: KeccakBytesColumnsView<usize> = make_col_map();
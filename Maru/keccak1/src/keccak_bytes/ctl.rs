@@ -0,0 +1,15 @@
+use plonky2::field::types::Field;
+
+use crate::cross_table_lookup::Column;
+use crate::keccak_bytes::columns::*;
+
+/// Each message byte: the index of its message, its position in the message and its value. These
+/// are looked up by the sponge, for each input byte it absorbs, and by the public values.
+pub fn ctl_looked_bytes<F: Field>() -> Vec<Column<F>> {
+    let cols = KECCAK_BYTES_COL_MAP;
+    Column::singles([cols.timestamp, cols.index, cols.value]).collect()
+}
+
+pub fn ctl_looked_bytes_filter<F: Field>() -> Column<F> {
+    Column::single(KECCAK_BYTES_COL_MAP.is_real)
+}
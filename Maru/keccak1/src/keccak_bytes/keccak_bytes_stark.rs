@@ -0,0 +1,170 @@
+use std::borrow::Borrow;
+use std::marker::PhantomData;
+
+use plonky2::field::extension::{Extendable, FieldExtension};
+use plonky2::field::packed::PackedField;
+use plonky2::field::polynomial::PolynomialValues;
+use plonky2::hash::hash_types::RichField;
+use plonky2::iop::ext_target::ExtensionTarget;
+use plonky2::plonk::circuit_builder::CircuitBuilder;
+
+use crate::keccak_bytes::columns::*;
+use starky::constraint_consumer::{ConstraintConsumer, RecursiveConstraintConsumer};
+use starky::stark::Stark;
+use starky::util::trace_rows_to_poly_values;
+use starky::vars::{StarkEvaluationTargets, StarkEvaluationVars};
+
+#[derive(Copy, Clone, Default)]
+pub struct KeccakBytesStark<F, const D: usize> {
+    f: PhantomData<F>,
+}
+
+impl<F: RichField + Extendable<D>, const D: usize> KeccakBytesStark<F, D> {
+    /// Lays out the bytes of `msgs` one per row, the `i`-th message having timestamp `i`, followed
+    /// by padding rows up to a power of two.
+    pub fn generate_trace(&self, msgs: &[Vec<u8>], min_rows: usize) -> Vec<PolynomialValues<F>> {
+        trace_rows_to_poly_values(self.generate_trace_rows(msgs, min_rows))
+    }
+
+    pub fn generate_trace_rows(
+        &self,
+        msgs: &[Vec<u8>],
+        min_rows: usize,
+    ) -> Vec<[F; NUM_KECCAK_BYTES_COLUMNS]> {
+        let mut rows = vec![];
+        for (timestamp, msg) in msgs.iter().enumerate() {
+            for (index, &byte) in msg.iter().enumerate() {
+                let row = KeccakBytesColumnsView {
+                    is_real: F::ONE,
+                    is_last_byte: F::from_bool(index == msg.len() - 1),
+                    timestamp: F::from_canonical_usize(timestamp),
+                    index: F::from_canonical_usize(index),
+                    value: F::from_canonical_u8(byte),
+                };
+                rows.push(row.into());
+            }
+        }
+        let padded_rows = rows.len().max(min_rows).next_power_of_two();
+        rows.resize(padded_rows, KeccakBytesColumnsView::default().into());
+        rows
+    }
+}
+
+impl<F: RichField + Extendable<D>, const D: usize> Stark<F, D> for KeccakBytesStark<F, D> {
+    const COLUMNS: usize = NUM_KECCAK_BYTES_COLUMNS;
+    const PUBLIC_INPUTS: usize = 0;
+
+    fn eval_packed_generic<FE, P, const D2: usize>(
+        &self,
+        vars: StarkEvaluationVars<FE, P, { Self::COLUMNS }, { Self::PUBLIC_INPUTS }>,
+        yield_constr: &mut ConstraintConsumer<P>,
+    ) where
+        FE: FieldExtension<D2, BaseField = F>,
+        P: PackedField<Scalar = FE>,
+    {
+        let local_values: &KeccakBytesColumnsView<P> = vars.local_values.borrow();
+        let next_values: &KeccakBytesColumnsView<P> = vars.next_values.borrow();
+
+        // Both flags must be boolean, and only a real row can end a message.
+        let is_real = local_values.is_real;
+        yield_constr.constraint(is_real * (is_real - P::ONES));
+        let is_last_byte = local_values.is_last_byte;
+        yield_constr.constraint(is_last_byte * (is_last_byte - P::ONES));
+        yield_constr.constraint(is_last_byte * (P::ONES - is_real));
+
+        // A padding row is always followed by another padding row.
+        yield_constr.constraint_transition((P::ONES - is_real) * next_values.is_real);
+
+        // The first message starts at position 0, and so does each message following another one.
+        yield_constr.constraint_first_row(local_values.index);
+        yield_constr.constraint_transition(is_last_byte * next_values.index);
+
+        // Within a message, the next row holds the next byte of the same message.
+        let is_inner_byte = is_real - is_last_byte;
+        yield_constr.constraint_transition(is_inner_byte * (next_values.is_real - P::ONES));
+        yield_constr.constraint_transition(
+            is_inner_byte * (next_values.timestamp - local_values.timestamp),
+        );
+        yield_constr.constraint_transition(
+            is_inner_byte * (next_values.index - local_values.index - P::ONES),
+        );
+
+        // The trace cannot end in the middle of a message.
+        yield_constr.constraint_last_row(is_inner_byte);
+    }
+
+    fn eval_ext_circuit(
+        &self,
+        builder: &mut CircuitBuilder<F, D>,
+        vars: StarkEvaluationTargets<D, { Self::COLUMNS }, { Self::PUBLIC_INPUTS }>,
+        yield_constr: &mut RecursiveConstraintConsumer<F, D>,
+    ) {
+        let local_values: &KeccakBytesColumnsView<ExtensionTarget<D>> = vars.local_values.borrow();
+        let next_values: &KeccakBytesColumnsView<ExtensionTarget<D>> = vars.next_values.borrow();
+
+        let one = builder.one_extension();
+
+        // Both flags must be boolean, and only a real row can end a message.
+        let is_real = local_values.is_real;
+        let constraint = builder.mul_sub_extension(is_real, is_real, is_real);
+        yield_constr.constraint(builder, constraint);
+        let is_last_byte = local_values.is_last_byte;
+        let constraint = builder.mul_sub_extension(is_last_byte, is_last_byte, is_last_byte);
+        yield_constr.constraint(builder, constraint);
+        let is_padding = builder.sub_extension(one, is_real);
+        let constraint = builder.mul_extension(is_last_byte, is_padding);
+        yield_constr.constraint(builder, constraint);
+
+        // A padding row is always followed by another padding row.
+        let constraint = builder.mul_extension(is_padding, next_values.is_real);
+        yield_constr.constraint_transition(builder, constraint);
+
+        // The first message starts at position 0, and so does each message following another one.
+        yield_constr.constraint_first_row(builder, local_values.index);
+        let constraint = builder.mul_extension(is_last_byte, next_values.index);
+        yield_constr.constraint_transition(builder, constraint);
+
+        // Within a message, the next row holds the next byte of the same message.
+        let is_inner_byte = builder.sub_extension(is_real, is_last_byte);
+        let constraint =
+            builder.mul_sub_extension(is_inner_byte, next_values.is_real, is_inner_byte);
+        yield_constr.constraint_transition(builder, constraint);
+        let timestamp_diff = builder.sub_extension(next_values.timestamp, local_values.timestamp);
+        let constraint = builder.mul_extension(is_inner_byte, timestamp_diff);
+        yield_constr.constraint_transition(builder, constraint);
+        let index_diff = builder.sub_extension(next_values.index, local_values.index);
+        let constraint = builder.mul_sub_extension(is_inner_byte, index_diff, is_inner_byte);
+        yield_constr.constraint_transition(builder, constraint);
+
+        // The trace cannot end in the middle of a message.
+        yield_constr.constraint_last_row(builder, is_inner_byte);
+    }
+
+    fn constraint_degree(&self) -> usize {
+        3
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use plonky2::plonk::config::{GenericConfig, PoseidonGoldilocksConfig};
+    use starky::stark_testing::{test_stark_circuit_constraints, test_stark_low_degree};
+
+    use crate::keccak_bytes::keccak_bytes_stark::KeccakBytesStark;
+
+    const D: usize = 2;
+    type C = PoseidonGoldilocksConfig;
+    type F = <C as GenericConfig<D>>::F;
+    type S = KeccakBytesStark<F, D>;
+
+    #[test]
+    fn test_stark_degree() -> Result<()> {
+        test_stark_low_degree(S::default())
+    }
+
+    #[test]
+    fn test_stark_circuit() -> Result<()> {
+        test_stark_circuit_constraints::<F, C, S, D>(S::default())
+    }
+}
@@ -0,0 +1,7 @@
+//! The Keccak bytes STARK holds the bytes of the hashed messages, one per row. The sponge looks up
+//! each input byte it absorbs in it, and the public values look up each message byte, so the
+//! proof commits to the messages themselves rather than to their lengths only.
+
+pub mod columns;
+pub mod ctl;
+pub mod keccak_bytes_stark;
@@ -0,0 +1,2064 @@
+use std::iter::once;
+use std::path::Path;
+use std::time::Instant;
+
+use anyhow::{anyhow, ensure, Result};
+use bench_memory::{PhaseMemory, PhaseTracker};
+use env_logger::{try_init_from_env, Env, DEFAULT_FILTER_ENV};
+use itertools::Itertools;
+use plonky2::field::extension::{Extendable, FieldExtension};
+use plonky2::field::packable::Packable;
+use plonky2::field::packed::PackedField;
+use plonky2::field::polynomial::{PolynomialCoeffs, PolynomialValues};
+use plonky2::field::types::{Field, PrimeField64};
+use plonky2::field::zero_poly_coset::ZeroPolyOnCoset;
+use plonky2::fri::oracle::PolynomialBatch;
+use plonky2::fri::proof::FriProof;
+use plonky2::fri::structure::{
+    FriBatchInfo, FriBatchInfoTarget, FriInstanceInfo, FriInstanceInfoTarget, FriOracleInfo,
+    FriPolynomialInfo,
+};
+use plonky2::fri::verifier::verify_fri_proof;
+use plonky2::hash::hash_types::RichField;
+use plonky2::iop::challenger::Challenger;
+use plonky2::iop::ext_target::ExtensionTarget;
+use plonky2::iop::target::Target;
+use plonky2::plonk::circuit_builder::CircuitBuilder;
+use plonky2::plonk::config::{GenericConfig, Hasher};
+use plonky2::plonk::plonk_common::reduce_with_powers;
+use plonky2::timed;
+use plonky2::util::timing::TimingTree;
+use plonky2::util::transpose;
+use plonky2_maybe_rayon::*;
+use plonky2_util::{log2_ceil, log2_strict};
+use serde::{Deserialize, Serialize};
+
+use starky::config::StarkConfig;
+use starky::constraint_consumer::ConstraintConsumer;
+use starky::permutation::{
+    compute_permutation_z_polys, get_n_permutation_challenge_sets, get_permutation_challenge_set,
+    PermutationChallengeSet, PermutationCheckVars,
+};
+use starky::proof::{
+    StarkOpeningSet, StarkProof, StarkProofChallenges, StarkProofWithPublicInputs,
+    StarkProofWithPublicInputsTarget,
+};
+use starky::serialization::{load_proof, save_proof, Read, Write};
+use starky::stark::Stark;
+use starky::util::trace_rows_to_poly_values;
+use starky::vars::StarkEvaluationVars;
+
+use crate::cross_table_lookup::{
+    cross_table_lookup_data, ctl_extra_looking_products, eval_cross_table_lookup_checks,
+    eval_cross_table_lookup_zs_last, verify_cross_table_lookups, CtlCheckVars, CtlData, Table,
+    NUM_TABLES,
+};
+use crate::keccak_bytes::keccak_bytes_stark::KeccakBytesStark;
+use crate::keccak_ctl_stark::KeccakCtl;
+use crate::keccak_permutation::keccak_permutation_stark::{KeccakPermutationStark, NUM_INPUTS};
+use crate::keccak_sponge::columns::KECCAK_WIDTH_U32S;
+use crate::keccak_sponge::ctl::{public_values_per_hash, SPONGE_STATE_VALUES};
+use crate::keccak_sponge::keccak_sponge_stark::{
+    KeccakSpongeOp, KeccakSpongeSegmentOp, KeccakSpongeStark,
+};
+use crate::keccak_sponge::keccak_util::{keccakf_u32s, u8_to_u32_reverse};
+use crate::keccak_sponge::variant::KeccakSpongeVariant;
+use crate::stark_aggregation::u32_to_u64;
+
+/// A proof of the sponge and permutation STARKs, bound together by the cross-table lookup.
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq)]
+#[serde(bound = "")]
+pub struct KeccakCtlProof<F: RichField + Extendable<D>, C: GenericConfig<D, F = F>, const D: usize>
+{
+    /// One proof per table, indexed by `Table`. The CTL `Z` polynomials are committed after the
+    /// permutation `Z`s, in the permutation oracle.
+    pub stark_proofs: [StarkProofWithPublicInputs<F, C, D>; NUM_TABLES],
+    /// Value of each CTL `Z` polynomial on the last row, per table.
+    pub ctl_zs_last: [Vec<F>; NUM_TABLES],
+    /// For each hashed message, in order, its length followed by its digest, see
+    /// `hash_public_values`.
+    pub public_values: Vec<F>,
+    /// For each resumed sponge, the state it resumes from, `SPONGE_STATE_VALUES` field elements.
+    /// Empty unless the proof is of a segment, see `keccak_sponge_segment_ctl_proof`.
+    pub initial_states: Vec<F>,
+    /// For each suspended sponge, the state it stops in, like `initial_states`.
+    pub final_states: Vec<F>,
+    /// The hashed messages, one value per byte, see `message_public_values`. Empty unless the
+    /// proof has a bytes table.
+    pub messages: Vec<Vec<F>>,
+}
+
+pub struct KeccakCtlProofTarget<const D: usize> {
+    pub stark_proofs: [StarkProofWithPublicInputsTarget<D>; NUM_TABLES],
+    pub ctl_zs_last: [Vec<Target>; NUM_TABLES],
+    pub public_values: Vec<Target>,
+    pub initial_states: Vec<Target>,
+    pub final_states: Vec<Target>,
+    pub messages: Vec<Vec<Target>>,
+}
+
+/// Lays out the `(length, digest)` pair of each message as `public_values_per_hash` field
+/// elements. When the digest ends partway through the last output block of the `variant`, the rest
+/// of that block is squeezed natively.
+pub fn hash_public_values<F: Field>(
+    variant: KeccakSpongeVariant,
+    msgs: &[Vec<u8>],
+    hashes: &[&[u8]],
+) -> Vec<F> {
+    assert_eq!(msgs.len(), hashes.len());
+    msgs.iter()
+        .zip(hashes)
+        .flat_map(|(msg, hash)| {
+            let mut output = hash.to_vec();
+            if variant.squeezed_u32s() > variant.output_u32s() {
+                output.extend_from_slice(&variant.squeeze(msg)[variant.output_bytes..]);
+            }
+            once(F::from_canonical_usize(msg.len()))
+                .chain(u8_to_u32_reverse(&output).into_iter().map(F::from_canonical_u32))
+        })
+        .collect()
+}
+
+/// With the `bytes-table` feature, the messages themselves are public, as one field element per
+/// byte. Without it, only their lengths are.
+pub fn message_public_values<F: Field>(msgs: &[Vec<u8>]) -> Vec<Vec<F>> {
+    if !cfg!(feature = "bytes-table") {
+        return vec![];
+    }
+    msgs.iter()
+        .map(|msg| msg.iter().map(|&byte| F::from_canonical_u8(byte)).collect())
+        .collect()
+}
+
+// make a proof that the hash matches the message, with the sponge and permutation linked by the CTL
+pub fn keccak256_ctl_proof<F, C, const D: usize>(
+    msg: &[u8],
+    hash: &[u8],
+    config: &StarkConfig,
+) -> Result<(KeccakCtlProof<F, C, D>, f32, f32, f32, PhaseMemory, PhaseMemory)>
+where
+    F: RichField + Extendable<D>,
+    C: GenericConfig<D, F = F>,
+    [(); C::Hasher::HASH_SIZE]:,
+    [(); KeccakSpongeStark::<F, D>::COLUMNS]:,
+    [(); KeccakSpongeStark::<F, D>::PUBLIC_INPUTS]:,
+    [(); KeccakPermutationStark::<F, D>::COLUMNS]:,
+    [(); KeccakPermutationStark::<F, D>::PUBLIC_INPUTS]:,
+    [(); KeccakBytesStark::<F, D>::COLUMNS]:,
+    [(); KeccakBytesStark::<F, D>::PUBLIC_INPUTS]:,
+{
+    keccak256_batch_ctl_proof(&[msg.to_vec()], &[hash], config)
+}
+
+/// Proves the hashes of all `msgs` at once, in a single sponge trace and a single permutation
+/// trace. `hashes[i]` is the claimed digest of `msgs[i]`.
+pub fn keccak256_batch_ctl_proof<F, C, const D: usize>(
+    msgs: &[Vec<u8>],
+    hashes: &[&[u8]],
+    config: &StarkConfig,
+) -> Result<(KeccakCtlProof<F, C, D>, f32, f32, f32, PhaseMemory, PhaseMemory)>
+where
+    F: RichField + Extendable<D>,
+    C: GenericConfig<D, F = F>,
+    [(); C::Hasher::HASH_SIZE]:,
+    [(); KeccakSpongeStark::<F, D>::COLUMNS]:,
+    [(); KeccakSpongeStark::<F, D>::PUBLIC_INPUTS]:,
+    [(); KeccakPermutationStark::<F, D>::COLUMNS]:,
+    [(); KeccakPermutationStark::<F, D>::PUBLIC_INPUTS]:,
+    [(); KeccakBytesStark::<F, D>::COLUMNS]:,
+    [(); KeccakBytesStark::<F, D>::PUBLIC_INPUTS]:,
+{
+    keccak_sponge_batch_ctl_proof(KeccakSpongeVariant::KECCAK256, msgs, hashes, config)
+}
+
+/// Like `keccak256_batch_ctl_proof`, for the hash function of any sponge `variant`.
+pub fn keccak_sponge_batch_ctl_proof<F, C, const D: usize>(
+    variant: KeccakSpongeVariant,
+    msgs: &[Vec<u8>],
+    hashes: &[&[u8]],
+    config: &StarkConfig,
+) -> Result<(KeccakCtlProof<F, C, D>, f32, f32, f32, PhaseMemory, PhaseMemory)>
+where
+    F: RichField + Extendable<D>,
+    C: GenericConfig<D, F = F>,
+    [(); C::Hasher::HASH_SIZE]:,
+    [(); KeccakSpongeStark::<F, D>::COLUMNS]:,
+    [(); KeccakSpongeStark::<F, D>::PUBLIC_INPUTS]:,
+    [(); KeccakPermutationStark::<F, D>::COLUMNS]:,
+    [(); KeccakPermutationStark::<F, D>::PUBLIC_INPUTS]:,
+    [(); KeccakBytesStark::<F, D>::COLUMNS]:,
+    [(); KeccakBytesStark::<F, D>::PUBLIC_INPUTS]:,
+{
+    let _ = try_init_from_env(Env::default().filter_or(DEFAULT_FILTER_ENV, "debug"));
+    ensure!(!msgs.is_empty(), "Nothing to hash");
+    ensure!(
+        msgs.len() == hashes.len(),
+        "Got {} messages but {} hashes",
+        msgs.len(),
+        hashes.len()
+    );
+    ensure!(
+        hashes.iter().all(|hash| hash.len() == variant.output_bytes),
+        "Hashes must be {} bytes long",
+        variant.output_bytes
+    );
+    let public_values = hash_public_values(variant, msgs, hashes);
+    let messages = message_public_values(msgs);
+    let keccak_ctl = KeccakCtl::<F, D>::new(variant);
+
+    let mut timing = TimingTree::new("prove", log::Level::Debug);
+
+    let witness_memory = PhaseTracker::start();
+    let generate_sponge_trace = Instant::now();
+    let sponge_operations = msgs
+        .iter()
+        .enumerate()
+        .map(|(timestamp, msg)| KeccakSpongeOp {
+            timestamp,
+            input: msg.clone(),
+        })
+        .collect_vec();
+    let (sponge_trace_rows, sponge_states) = timed!(
+        timing,
+        "generate sponge trace",
+        keccak_ctl
+            .keccak_sponge_stark
+            .generate_trace_rows(sponge_operations, 8)
+    );
+    let sponge_poly_values = timed!(
+        timing,
+        "convert sponge trace to PolynomialValues",
+        trace_rows_to_poly_values(sponge_trace_rows)
+    );
+    let generate_sponge_trace_time = generate_sponge_trace.elapsed().as_secs_f32();
+
+    let generate_permutation_trace = Instant::now();
+    let permutation_inputs: Vec<[u64; NUM_INPUTS]> = sponge_states
+        .iter()
+        .map(|state| u32_to_u64(state).try_into().unwrap())
+        .collect_vec();
+    let permutation_poly_values = timed!(
+        timing,
+        "generate permutation trace",
+        keccak_ctl
+            .keccak_permutation_stark
+            .generate_trace(permutation_inputs, 8, &mut timing)
+    );
+    let generate_permutation_trace_time = generate_permutation_trace.elapsed().as_secs_f32();
+    #[cfg(feature = "bytes-table")]
+    let bytes_poly_values = timed!(
+        timing,
+        "generate bytes trace",
+        keccak_ctl.keccak_bytes_stark.generate_trace(msgs, 8)
+    );
+    let witness_memory = witness_memory.finish();
+
+    let prove_memory = PhaseTracker::start();
+    let proof_generation = Instant::now();
+    let proof = prove_keccak_ctl::<F, C, D>(
+        &keccak_ctl,
+        config,
+        [
+            permutation_poly_values,
+            sponge_poly_values,
+            #[cfg(feature = "bytes-table")]
+            bytes_poly_values,
+        ],
+        &public_values,
+        &[],
+        &[],
+        &messages,
+        &mut timing,
+    )?;
+    let proof_generation_time = proof_generation.elapsed().as_secs_f32();
+    let prove_memory = prove_memory.finish();
+
+    timing.print();
+
+    Ok((
+        proof,
+        generate_sponge_trace_time,
+        generate_permutation_trace_time,
+        proof_generation_time,
+        witness_memory,
+        prove_memory,
+    ))
+}
+
+/// Lays out the state of the sponge with the given `timestamp` over `len` bytes, after it absorbed
+/// `absorbed_bytes` of them, as `SPONGE_STATE_VALUES` field elements.
+pub fn sponge_state_values<F: Field>(
+    timestamp: usize,
+    len: usize,
+    absorbed_bytes: usize,
+    state: &[u32; KECCAK_WIDTH_U32S],
+) -> Vec<F> {
+    [timestamp, len, absorbed_bytes]
+        .into_iter()
+        .map(F::from_canonical_usize)
+        .chain(state.iter().map(|&limb| F::from_canonical_u32(limb)))
+        .collect()
+}
+
+/// Proves a single segment of a sponge over a long message, see `KeccakSpongeSegmentOp::split`.
+/// The state the segment resumes from is public. So is the state it is suspended in, except for the
+/// last segment, which proves the `(length, digest)` pair of the message instead: `hash` is the
+/// claimed digest, given for the last segment only.
+pub fn keccak_sponge_segment_ctl_proof<F, C, const D: usize>(
+    variant: KeccakSpongeVariant,
+    segment: &KeccakSpongeSegmentOp,
+    hash: Option<&[u8]>,
+    config: &StarkConfig,
+) -> Result<(KeccakCtlProof<F, C, D>, f32, f32, f32, PhaseMemory, PhaseMemory)>
+where
+    F: RichField + Extendable<D>,
+    C: GenericConfig<D, F = F>,
+    [(); C::Hasher::HASH_SIZE]:,
+    [(); KeccakSpongeStark::<F, D>::COLUMNS]:,
+    [(); KeccakSpongeStark::<F, D>::PUBLIC_INPUTS]:,
+    [(); KeccakPermutationStark::<F, D>::COLUMNS]:,
+    [(); KeccakPermutationStark::<F, D>::PUBLIC_INPUTS]:,
+    [(); KeccakBytesStark::<F, D>::COLUMNS]:,
+    [(); KeccakBytesStark::<F, D>::PUBLIC_INPUTS]:,
+{
+    let _ = try_init_from_env(Env::default().filter_or(DEFAULT_FILTER_ENV, "debug"));
+    ensure!(
+        !cfg!(feature = "bytes-table"),
+        "Segments cannot be proved with the bytes table, which makes whole messages public"
+    );
+    ensure!(
+        segment.is_last() == hash.is_some(),
+        "A hash must be given for the last segment only"
+    );
+    let public_values = match hash {
+        Some(hash) => {
+            ensure!(
+                hash.len() == variant.output_bytes,
+                "Hashes must be {} bytes long",
+                variant.output_bytes
+            );
+            let state = segment
+                .initial_state
+                .iter()
+                .flat_map(|limb| limb.to_le_bytes())
+                .collect_vec();
+            let output = variant.squeeze_from(state.try_into().unwrap(), &segment.input);
+            ensure!(
+                &output[..variant.output_bytes] == hash,
+                "The hash does not match the message"
+            );
+            once(F::from_canonical_usize(segment.len))
+                .chain(u8_to_u32_reverse(&output).into_iter().map(F::from_canonical_u32))
+                .collect()
+        }
+        None => vec![],
+    };
+    let initial_states = sponge_state_values(
+        segment.timestamp,
+        segment.len,
+        segment.already_absorbed_bytes,
+        &segment.initial_state,
+    );
+    let keccak_ctl = KeccakCtl::<F, D>::new(variant);
+
+    let mut timing = TimingTree::new("prove", log::Level::Debug);
+
+    let witness_memory = PhaseTracker::start();
+    let generate_sponge_trace = Instant::now();
+    let (sponge_trace_rows, sponge_states) = timed!(
+        timing,
+        "generate sponge trace",
+        keccak_ctl
+            .keccak_sponge_stark
+            .generate_segment_trace_rows(segment, 8)
+    );
+    let final_states = if segment.is_last() {
+        vec![]
+    } else {
+        let mut state = *sponge_states.last().unwrap();
+        keccakf_u32s(&mut state);
+        sponge_state_values(
+            segment.timestamp,
+            segment.len,
+            segment.absorbed_bytes(),
+            &state,
+        )
+    };
+    let sponge_poly_values = timed!(
+        timing,
+        "convert sponge trace to PolynomialValues",
+        trace_rows_to_poly_values(sponge_trace_rows)
+    );
+    let generate_sponge_trace_time = generate_sponge_trace.elapsed().as_secs_f32();
+
+    let generate_permutation_trace = Instant::now();
+    let permutation_inputs: Vec<[u64; NUM_INPUTS]> = sponge_states
+        .iter()
+        .map(|state| u32_to_u64(state).try_into().unwrap())
+        .collect_vec();
+    let permutation_poly_values = timed!(
+        timing,
+        "generate permutation trace",
+        keccak_ctl
+            .keccak_permutation_stark
+            .generate_trace(permutation_inputs, 8, &mut timing)
+    );
+    let generate_permutation_trace_time = generate_permutation_trace.elapsed().as_secs_f32();
+    let witness_memory = witness_memory.finish();
+
+    let prove_memory = PhaseTracker::start();
+    let proof_generation = Instant::now();
+    let proof = prove_keccak_ctl::<F, C, D>(
+        &keccak_ctl,
+        config,
+        [
+            permutation_poly_values,
+            sponge_poly_values,
+            #[cfg(feature = "bytes-table")]
+            keccak_ctl.keccak_bytes_stark.generate_trace(&[], 8),
+        ],
+        &public_values,
+        &initial_states,
+        &final_states,
+        &[],
+        &mut timing,
+    )?;
+    let proof_generation_time = proof_generation.elapsed().as_secs_f32();
+    let prove_memory = prove_memory.finish();
+
+    timing.print();
+
+    Ok((
+        proof,
+        generate_sponge_trace_time,
+        generate_permutation_trace_time,
+        proof_generation_time,
+        witness_memory,
+        prove_memory,
+    ))
+}
+
+pub fn keccak256_ctl_verify<F, C, const D: usize>(
+    proof: &KeccakCtlProof<F, C, D>,
+    config: &StarkConfig,
+) -> Result<()>
+where
+    F: RichField + Extendable<D>,
+    C: GenericConfig<D, F = F>,
+    [(); C::Hasher::HASH_SIZE]:,
+    [(); KeccakSpongeStark::<F, D>::COLUMNS]:,
+    [(); KeccakSpongeStark::<F, D>::PUBLIC_INPUTS]:,
+    [(); KeccakPermutationStark::<F, D>::COLUMNS]:,
+    [(); KeccakPermutationStark::<F, D>::PUBLIC_INPUTS]:,
+    [(); KeccakBytesStark::<F, D>::COLUMNS]:,
+    [(); KeccakBytesStark::<F, D>::PUBLIC_INPUTS]:,
+{
+    keccak_sponge_ctl_verify(KeccakSpongeVariant::KECCAK256, proof, config)
+}
+
+/// Verifies a proof of `keccak_sponge_batch_ctl_proof` for the same `variant`.
+pub fn keccak_sponge_ctl_verify<F, C, const D: usize>(
+    variant: KeccakSpongeVariant,
+    proof: &KeccakCtlProof<F, C, D>,
+    config: &StarkConfig,
+) -> Result<()>
+where
+    F: RichField + Extendable<D>,
+    C: GenericConfig<D, F = F>,
+    [(); C::Hasher::HASH_SIZE]:,
+    [(); KeccakSpongeStark::<F, D>::COLUMNS]:,
+    [(); KeccakSpongeStark::<F, D>::PUBLIC_INPUTS]:,
+    [(); KeccakPermutationStark::<F, D>::COLUMNS]:,
+    [(); KeccakPermutationStark::<F, D>::PUBLIC_INPUTS]:,
+    [(); KeccakBytesStark::<F, D>::COLUMNS]:,
+    [(); KeccakBytesStark::<F, D>::PUBLIC_INPUTS]:,
+{
+    let keccak_ctl = KeccakCtl::<F, D>::new(variant);
+
+    let _ = try_init_from_env(Env::default().filter_or(DEFAULT_FILTER_ENV, "debug"));
+
+    let timing = TimingTree::new("verify", log::Level::Debug);
+
+    let res = verify_keccak_ctl_proof(&keccak_ctl, proof, config);
+
+    timing.print();
+
+    res
+}
+
+/// Writes `proof` to `dir`: the proof of each table with `save_proof`, and the CTL `Z`s on the last
+/// rows, the public values, the public states and the public messages in `ctl.bin`.
+pub fn save_keccak_ctl_proof<F, C, const D: usize>(
+    dir: impl AsRef<Path>,
+    proof: &KeccakCtlProof<F, C, D>,
+    config: &StarkConfig,
+) -> Result<()>
+where
+    F: RichField + Extendable<D>,
+    C: GenericConfig<D, F = F>,
+{
+    let dir = dir.as_ref();
+    std::fs::create_dir_all(dir)?;
+    for table in Table::all() {
+        save_proof(
+            dir.join(format!("{table:?}.proof")),
+            &proof.stark_proofs[table as usize],
+            config,
+        )?;
+    }
+    let mut bytes = Vec::new();
+    let num_messages = proof.messages.len().try_into()?;
+    bytes
+        .write_u32(num_messages)
+        .expect("Writing to a byte-vector cannot fail.");
+    for values in proof
+        .ctl_zs_last
+        .iter()
+        .chain([
+            &proof.public_values,
+            &proof.initial_states,
+            &proof.final_states,
+        ])
+        .chain(&proof.messages)
+    {
+        let len = values.len().try_into()?;
+        bytes
+            .write_u32(len)
+            .expect("Writing to a byte-vector cannot fail.");
+        bytes
+            .write_field_vec(values)
+            .expect("Writing to a byte-vector cannot fail.");
+    }
+    std::fs::write(dir.join("ctl.bin"), bytes)?;
+    Ok(())
+}
+
+/// Reads a proof written by `save_keccak_ctl_proof` from `dir`.
+pub fn load_keccak_ctl_proof<F, C, const D: usize>(
+    dir: impl AsRef<Path>,
+    config: &StarkConfig,
+) -> Result<KeccakCtlProof<F, C, D>>
+where
+    F: RichField + Extendable<D>,
+    C: GenericConfig<D, F = F>,
+{
+    let dir = dir.as_ref();
+    let stark_proofs = Table::all()
+        .map(|table| load_proof(dir.join(format!("{table:?}.proof")), config));
+    let stark_proofs = stark_proofs.into_iter().collect::<Result<Vec<_>>>()?;
+
+    let bytes = std::fs::read(dir.join("ctl.bin"))?;
+    let mut reader = bytes.as_slice();
+    let num_messages = reader
+        .read_u32()
+        .map_err(|_| anyhow!("Invalid CTL values in {}", dir.display()))?;
+    let mut values = (0..NUM_TABLES + 3 + num_messages as usize)
+        .map(|_| {
+            let len = reader.read_u32()?;
+            reader.read_field_vec(len as usize)
+        })
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|_| anyhow!("Invalid CTL values in {}", dir.display()))?;
+    ensure!(reader.is_empty(), "Trailing bytes in {}", dir.display());
+    let messages = values.split_off(NUM_TABLES + 3);
+    let final_states = values.pop().unwrap();
+    let initial_states = values.pop().unwrap();
+    let public_values = values.pop().unwrap();
+
+    Ok(KeccakCtlProof {
+        stark_proofs: stark_proofs.try_into().unwrap(),
+        ctl_zs_last: values.try_into().unwrap(),
+        public_values,
+        initial_states,
+        final_states,
+        messages,
+    })
+}
+
+/// Proves all tables with a shared challenger. The trace caps, the public values, the public states
+/// and the public messages are observed before the CTL challenges are drawn, so the CTL `Z`s are
+/// bound to all traces and to the hashes.
+pub fn prove_keccak_ctl<F, C, const D: usize>(
+    keccak_ctl: &KeccakCtl<F, D>,
+    config: &StarkConfig,
+    trace_poly_values: [Vec<PolynomialValues<F>>; NUM_TABLES],
+    public_values: &[F],
+    initial_states: &[F],
+    final_states: &[F],
+    messages: &[Vec<F>],
+    timing: &mut TimingTree,
+) -> Result<KeccakCtlProof<F, C, D>>
+where
+    F: RichField + Extendable<D>,
+    C: GenericConfig<D, F = F>,
+    [(); C::Hasher::HASH_SIZE]:,
+    [(); KeccakSpongeStark::<F, D>::COLUMNS]:,
+    [(); KeccakSpongeStark::<F, D>::PUBLIC_INPUTS]:,
+    [(); KeccakPermutationStark::<F, D>::COLUMNS]:,
+    [(); KeccakPermutationStark::<F, D>::PUBLIC_INPUTS]:,
+    [(); KeccakBytesStark::<F, D>::COLUMNS]:,
+    [(); KeccakBytesStark::<F, D>::PUBLIC_INPUTS]:,
+{
+    let rate_bits = config.fri_config.rate_bits;
+    let cap_height = config.fri_config.cap_height;
+
+    let trace_commitments = timed!(
+        timing,
+        "compute trace commitments",
+        trace_poly_values
+            .iter()
+            .map(|trace| {
+                PolynomialBatch::<F, C, D>::from_values(
+                    // TODO: Cloning this isn't great; consider having `from_values` accept a reference.
+                    trace.clone(),
+                    rate_bits,
+                    false,
+                    cap_height,
+                    timing,
+                    None,
+                )
+            })
+            .collect::<Vec<_>>()
+    );
+
+    let mut challenger = Challenger::<F, C::Hasher>::new();
+    for commitment in &trace_commitments {
+        challenger.observe_cap(&commitment.merkle_tree.cap);
+    }
+    challenger.observe_elements(public_values);
+    challenger.observe_elements(initial_states);
+    challenger.observe_elements(final_states);
+    for message in messages {
+        challenger.observe_elements(message);
+    }
+
+    let ctl_challenges = get_permutation_challenge_set(&mut challenger, config.num_challenges);
+    let ctl_data_per_table = timed!(
+        timing,
+        "compute CTL data",
+        cross_table_lookup_data::<F, D>(
+            &trace_poly_values,
+            &keccak_ctl.cross_table_lookups,
+            &ctl_challenges,
+        )
+    );
+
+    let (permutation_proof, permutation_ctl_zs_last) = timed!(
+        timing,
+        "prove keccak permutation STARK",
+        prove_single_table::<F, C, KeccakPermutationStark<F, D>, D>(
+            &keccak_ctl.keccak_permutation_stark,
+            config,
+            &trace_poly_values[Table::KeccakPermutation as usize],
+            &trace_commitments[Table::KeccakPermutation as usize],
+            &ctl_data_per_table[Table::KeccakPermutation as usize],
+            &[],
+            &mut challenger,
+            timing,
+        )?
+    );
+    let (sponge_proof, sponge_ctl_zs_last) = timed!(
+        timing,
+        "prove keccak sponge STARK",
+        prove_single_table::<F, C, KeccakSpongeStark<F, D>, D>(
+            &keccak_ctl.keccak_sponge_stark,
+            config,
+            &trace_poly_values[Table::KeccakSponge as usize],
+            &trace_commitments[Table::KeccakSponge as usize],
+            &ctl_data_per_table[Table::KeccakSponge as usize],
+            &[],
+            &mut challenger,
+            timing,
+        )?
+    );
+    #[cfg(feature = "bytes-table")]
+    let (bytes_proof, bytes_ctl_zs_last) = timed!(
+        timing,
+        "prove keccak bytes STARK",
+        prove_single_table::<F, C, KeccakBytesStark<F, D>, D>(
+            &keccak_ctl.keccak_bytes_stark,
+            config,
+            &trace_poly_values[Table::KeccakBytes as usize],
+            &trace_commitments[Table::KeccakBytes as usize],
+            &ctl_data_per_table[Table::KeccakBytes as usize],
+            &[],
+            &mut challenger,
+            timing,
+        )?
+    );
+
+    Ok(KeccakCtlProof {
+        stark_proofs: [
+            permutation_proof,
+            sponge_proof,
+            #[cfg(feature = "bytes-table")]
+            bytes_proof,
+        ],
+        ctl_zs_last: [
+            permutation_ctl_zs_last,
+            sponge_ctl_zs_last,
+            #[cfg(feature = "bytes-table")]
+            bytes_ctl_zs_last,
+        ],
+        public_values: public_values.to_vec(),
+        initial_states: initial_states.to_vec(),
+        final_states: final_states.to_vec(),
+        messages: messages.to_vec(),
+    })
+}
+
+/// Proves a single table, whose trace has already been committed and observed. The CTL `Z`s are
+/// committed together with the permutation `Z`s, and their last values are observed before
+/// drawing the constraint alphas.
+fn prove_single_table<F, C, S, const D: usize>(
+    stark: &S,
+    config: &StarkConfig,
+    trace_poly_values: &[PolynomialValues<F>],
+    trace_commitment: &PolynomialBatch<F, C, D>,
+    ctl_data: &CtlData<F>,
+    public_inputs: &[F],
+    challenger: &mut Challenger<F, C::Hasher>,
+    timing: &mut TimingTree,
+) -> Result<(StarkProofWithPublicInputs<F, C, D>, Vec<F>)>
+where
+    F: RichField + Extendable<D>,
+    C: GenericConfig<D, F = F>,
+    S: Stark<F, D>,
+    [(); S::COLUMNS]:,
+    [(); S::PUBLIC_INPUTS]:,
+    [(); C::Hasher::HASH_SIZE]:,
+{
+    let degree = trace_poly_values[0].len();
+    let degree_bits = log2_strict(degree);
+    let fri_params = config.fri_params(degree_bits);
+    let rate_bits = config.fri_config.rate_bits;
+    let cap_height = config.fri_config.cap_height;
+    assert!(
+        fri_params.total_arities() <= degree_bits + rate_bits - cap_height,
+        "FRI total reduction arity is too large.",
+    );
+    let public_inputs: [F; S::PUBLIC_INPUTS] = public_inputs.to_vec().try_into().unwrap();
+
+    // Permutation arguments.
+    let permutation_challenges = stark.uses_permutation_args().then(|| {
+        get_n_permutation_challenge_sets(
+            challenger,
+            config.num_challenges,
+            stark.permutation_batch_size(),
+        )
+    });
+    let num_permutation_zs = stark.num_permutation_batches(config);
+    let mut z_polys = match &permutation_challenges {
+        Some(permutation_challenge_sets) => compute_permutation_z_polys::<F, C, S, D>(
+            stark,
+            config,
+            trace_poly_values,
+            permutation_challenge_sets,
+        ),
+        None => vec![],
+    };
+    z_polys.extend(ctl_data.z_polys());
+
+    let permutation_ctl_zs_commitment = (!z_polys.is_empty()).then(|| {
+        timed!(
+            timing,
+            "compute permutation and CTL Z commitments",
+            PolynomialBatch::from_values(z_polys, rate_bits, false, cap_height, timing, None)
+        )
+    });
+    let permutation_ctl_zs_cap = permutation_ctl_zs_commitment
+        .as_ref()
+        .map(|commit| commit.merkle_tree.cap.clone());
+    if let Some(cap) = &permutation_ctl_zs_cap {
+        challenger.observe_cap(cap);
+    }
+
+    let ctl_zs_last = ctl_data
+        .zs_columns
+        .iter()
+        .map(|zs_columns| *zs_columns.z.values.last().unwrap())
+        .collect::<Vec<_>>();
+    challenger.observe_elements(&ctl_zs_last);
+
+    let alphas = challenger.get_n_challenges(config.num_challenges);
+    let quotient_polys = timed!(
+        timing,
+        "compute quotient polys",
+        compute_quotient_polys::<F, <F as Packable>::Packing, C, S, D>(
+            stark,
+            trace_commitment,
+            &permutation_ctl_zs_commitment,
+            &permutation_challenges,
+            num_permutation_zs,
+            ctl_data,
+            &ctl_zs_last,
+            public_inputs,
+            alphas,
+            degree_bits,
+            config,
+        )
+    );
+    let all_quotient_chunks = quotient_polys
+        .into_par_iter()
+        .flat_map(|mut quotient_poly| {
+            quotient_poly
+                .trim_to_len(degree * stark.quotient_degree_factor())
+                .expect("Quotient has failed, the vanishing polynomial is not divisible by Z_H");
+            // Split quotient into degree-n chunks.
+            quotient_poly.chunks(degree)
+        })
+        .collect();
+    let quotient_commitment = timed!(
+        timing,
+        "compute quotient commitment",
+        PolynomialBatch::from_coeffs(
+            all_quotient_chunks,
+            rate_bits,
+            false,
+            cap_height,
+            timing,
+            None,
+        )
+    );
+    let quotient_polys_cap = quotient_commitment.merkle_tree.cap.clone();
+    challenger.observe_cap(&quotient_polys_cap);
+
+    let zeta = challenger.get_extension_challenge::<D>();
+    // To avoid leaking witness data, we want to ensure that our opening locations, `zeta` and
+    // `g * zeta`, are not in our subgroup `H`. It suffices to check `zeta` only, since
+    // `(g * zeta)^n = zeta^n`, where `n` is the order of `g`.
+    let g = F::primitive_root_of_unity(degree_bits);
+    ensure!(
+        zeta.exp_power_of_2(degree_bits) != F::Extension::ONE,
+        "Opening point is in the subgroup."
+    );
+    let openings = StarkOpeningSet::new(
+        zeta,
+        g,
+        trace_commitment,
+        permutation_ctl_zs_commitment.as_ref(),
+        &quotient_commitment,
+    );
+    challenger.observe_openings(&openings.to_fri_openings());
+    let initial_merkle_trees = once(trace_commitment)
+        .chain(permutation_ctl_zs_commitment.as_ref())
+        .chain(once(&quotient_commitment))
+        .collect_vec();
+
+    let opening_proof = timed!(
+        timing,
+        "compute openings proof",
+        PolynomialBatch::prove_openings(
+            &fri_instance(stark, zeta, g, ctl_data.len(), config),
+            &initial_merkle_trees,
+            challenger,
+            &fri_params,
+            timing,
+        )
+    );
+    let proof = StarkProof {
+        trace_cap: trace_commitment.merkle_tree.cap.clone(),
+        permutation_zs_cap: permutation_ctl_zs_cap,
+        quotient_polys_cap,
+        openings,
+        opening_proof,
+    };
+
+    Ok((
+        StarkProofWithPublicInputs {
+            proof,
+            public_inputs: public_inputs.to_vec(),
+        },
+        ctl_zs_last,
+    ))
+}
+
+/// Computes the quotient polynomials `(sum alpha^i C_i(x)) / Z_H(x)` for `alpha` in `alphas`,
+/// where the `C_i`s are the Stark constraints together with the CTL constraints.
+fn compute_quotient_polys<'a, F, P, C, S, const D: usize>(
+    stark: &S,
+    trace_commitment: &'a PolynomialBatch<F, C, D>,
+    permutation_ctl_zs_commitment: &'a Option<PolynomialBatch<F, C, D>>,
+    permutation_challenges: &'a Option<Vec<PermutationChallengeSet<F>>>,
+    num_permutation_zs: usize,
+    ctl_data: &CtlData<F>,
+    ctl_zs_last: &[F],
+    public_inputs: [F; S::PUBLIC_INPUTS],
+    alphas: Vec<F>,
+    degree_bits: usize,
+    config: &StarkConfig,
+) -> Vec<PolynomialCoeffs<F>>
+where
+    F: RichField + Extendable<D>,
+    P: PackedField<Scalar = F>,
+    C: GenericConfig<D, F = F>,
+    S: Stark<F, D>,
+    [(); S::COLUMNS]:,
+    [(); S::PUBLIC_INPUTS]:,
+{
+    let degree = 1 << degree_bits;
+    let rate_bits = config.fri_config.rate_bits;
+
+    let quotient_degree_bits = log2_ceil(stark.quotient_degree_factor());
+    assert!(
+        quotient_degree_bits <= rate_bits,
+        "Having constraints of degree higher than the rate is not supported yet."
+    );
+    let step = 1 << (rate_bits - quotient_degree_bits);
+    // When opening the `Z`s polys at the "next" point, need to look at the point `next_step` steps away.
+    let next_step = 1 << quotient_degree_bits;
+
+    // Evaluation of the first Lagrange polynomial on the LDE domain.
+    let lagrange_first = PolynomialValues::selector(degree, 0).lde_onto_coset(quotient_degree_bits);
+    // Evaluation of the last Lagrange polynomial on the LDE domain.
+    let lagrange_last =
+        PolynomialValues::selector(degree, degree - 1).lde_onto_coset(quotient_degree_bits);
+
+    let z_h_on_coset = ZeroPolyOnCoset::<F>::new(degree_bits, quotient_degree_bits);
+
+    // Retrieve the LDE values at index `i`.
+    let get_trace_values_packed = |i_start| -> [P; S::COLUMNS] {
+        trace_commitment
+            .get_lde_values_packed(i_start, step)
+            .try_into()
+            .unwrap()
+    };
+
+    // Last element of the subgroup.
+    let last = F::primitive_root_of_unity(degree_bits).inverse();
+    let size = degree << quotient_degree_bits;
+    let coset = F::cyclic_subgroup_coset_known_order(
+        F::primitive_root_of_unity(degree_bits + quotient_degree_bits),
+        F::coset_shift(),
+        size,
+    );
+
+    // We will step by `P::WIDTH`, and in each iteration, evaluate the quotient polynomial at
+    // a batch of `P::WIDTH` points.
+    let quotient_values = (0..size)
+        .into_par_iter()
+        .step_by(P::WIDTH)
+        .flat_map_iter(|i_start| {
+            let i_next_start = (i_start + next_step) % size;
+            let i_range = i_start..i_start + P::WIDTH;
+
+            let x = *P::from_slice(&coset[i_range.clone()]);
+            let z_last = x - last;
+            let lagrange_basis_first = *P::from_slice(&lagrange_first.values[i_range.clone()]);
+            let lagrange_basis_last = *P::from_slice(&lagrange_last.values[i_range]);
+
+            let mut consumer = ConstraintConsumer::new(
+                alphas.clone(),
+                z_last,
+                lagrange_basis_first,
+                lagrange_basis_last,
+            );
+            let vars = StarkEvaluationVars {
+                local_values: &get_trace_values_packed(i_start),
+                next_values: &get_trace_values_packed(i_next_start),
+                public_inputs: &public_inputs,
+            };
+            let (local_zs, next_zs) = permutation_ctl_zs_commitment
+                .as_ref()
+                .map(|commitment| {
+                    (
+                        commitment.get_lde_values_packed(i_start, step),
+                        commitment.get_lde_values_packed(i_next_start, step),
+                    )
+                })
+                .unwrap_or_default();
+            let permutation_check_data =
+                permutation_challenges
+                    .as_ref()
+                    .map(|permutation_challenge_sets| PermutationCheckVars {
+                        local_zs: local_zs[..num_permutation_zs].to_vec(),
+                        next_zs: next_zs[..num_permutation_zs].to_vec(),
+                        permutation_challenge_sets: permutation_challenge_sets.to_vec(),
+                    });
+            let ctl_vars = ctl_data
+                .zs_columns
+                .iter()
+                .enumerate()
+                .map(|(i, zs_columns)| CtlCheckVars::<F, F, P, 1> {
+                    local_z: local_zs[num_permutation_zs + i],
+                    next_z: next_zs[num_permutation_zs + i],
+                    challenges: zs_columns.challenge,
+                    columns: &zs_columns.columns,
+                    filter_column: &zs_columns.filter_column,
+                })
+                .collect::<Vec<_>>();
+            eval_vanishing_poly::<F, F, P, C, S, D, 1>(
+                stark,
+                config,
+                vars,
+                permutation_check_data,
+                &ctl_vars,
+                ctl_zs_last,
+                &mut consumer,
+            );
+
+            let mut constraints_evals = consumer.accumulators();
+            // We divide the constraints evaluations by `Z_H(x)`.
+            let denominator_inv: P = z_h_on_coset.eval_inverse_packed(i_start);
+
+            for eval in &mut constraints_evals {
+                *eval *= denominator_inv;
+            }
+
+            let num_challenges = alphas.len();
+
+            (0..P::WIDTH).map(move |i| {
+                (0..num_challenges)
+                    .map(|j| constraints_evals[j].as_slice()[i])
+                    .collect()
+            })
+        })
+        .collect::<Vec<_>>();
+
+    transpose(&quotient_values)
+        .into_par_iter()
+        .map(PolynomialValues::new)
+        .map(|values| values.coset_ifft(F::coset_shift()))
+        .collect()
+}
+
+/// Evaluates the Stark constraints, the permutation checks and the CTL checks of a table.
+fn eval_vanishing_poly<F, FE, P, C, S, const D: usize, const D2: usize>(
+    stark: &S,
+    config: &StarkConfig,
+    vars: StarkEvaluationVars<FE, P, { S::COLUMNS }, { S::PUBLIC_INPUTS }>,
+    permutation_data: Option<PermutationCheckVars<F, FE, P, D2>>,
+    ctl_vars: &[CtlCheckVars<F, FE, P, D2>],
+    ctl_zs_last: &[F],
+    consumer: &mut ConstraintConsumer<P>,
+) where
+    F: RichField + Extendable<D>,
+    FE: FieldExtension<D2, BaseField = F>,
+    P: PackedField<Scalar = FE>,
+    C: GenericConfig<D, F = F>,
+    S: Stark<F, D>,
+    [(); S::COLUMNS]:,
+    [(); S::PUBLIC_INPUTS]:,
+{
+    starky::vanishing_poly::eval_vanishing_poly::<F, FE, P, C, S, D, D2>(
+        stark,
+        config,
+        vars,
+        permutation_data,
+        consumer,
+    );
+    eval_cross_table_lookup_checks::<F, FE, P, S, D, D2>(vars, ctl_vars, consumer);
+    eval_cross_table_lookup_zs_last(ctl_vars, ctl_zs_last, consumer);
+}
+
+pub fn verify_keccak_ctl_proof<F, C, const D: usize>(
+    keccak_ctl: &KeccakCtl<F, D>,
+    proof: &KeccakCtlProof<F, C, D>,
+    config: &StarkConfig,
+) -> Result<()>
+where
+    F: RichField + Extendable<D>,
+    C: GenericConfig<D, F = F>,
+    [(); C::Hasher::HASH_SIZE]:,
+    [(); KeccakSpongeStark::<F, D>::COLUMNS]:,
+    [(); KeccakSpongeStark::<F, D>::PUBLIC_INPUTS]:,
+    [(); KeccakPermutationStark::<F, D>::COLUMNS]:,
+    [(); KeccakPermutationStark::<F, D>::PUBLIC_INPUTS]:,
+    [(); KeccakBytesStark::<F, D>::COLUMNS]:,
+    [(); KeccakBytesStark::<F, D>::PUBLIC_INPUTS]:,
+{
+    let KeccakCtlProof {
+        stark_proofs,
+        ctl_zs_last,
+        public_values,
+        initial_states,
+        final_states,
+        messages,
+    } = proof;
+    let nums_ctl_zs = keccak_ctl.nums_ctl_zs(config);
+    let variant = keccak_ctl.variant();
+    let public_values_per_hash = public_values_per_hash(variant);
+    let permutation_proof = &stark_proofs[Table::KeccakPermutation as usize];
+    let sponge_proof = &stark_proofs[Table::KeccakSponge as usize];
+
+    validate_proof_shape(
+        &keccak_ctl.keccak_permutation_stark,
+        permutation_proof,
+        nums_ctl_zs[Table::KeccakPermutation as usize],
+        config,
+    )?;
+    validate_proof_shape(
+        &keccak_ctl.keccak_sponge_stark,
+        sponge_proof,
+        nums_ctl_zs[Table::KeccakSponge as usize],
+        config,
+    )?;
+    #[cfg(feature = "bytes-table")]
+    validate_proof_shape(
+        &keccak_ctl.keccak_bytes_stark,
+        &stark_proofs[Table::KeccakBytes as usize],
+        nums_ctl_zs[Table::KeccakBytes as usize],
+        config,
+    )?;
+    for table in Table::all() {
+        ensure!(
+            ctl_zs_last[table as usize].len() == nums_ctl_zs[table as usize],
+            "Wrong number of CTL Z values for {:?}",
+            table
+        );
+    }
+    ensure!(
+        public_values.len() % public_values_per_hash == 0,
+        "Public values must hold a (length, digest) pair per message"
+    );
+    ensure!(
+        initial_states.len() % SPONGE_STATE_VALUES == 0
+            && final_states.len() % SPONGE_STATE_VALUES == 0,
+        "Public states must hold {} values per sponge",
+        SPONGE_STATE_VALUES
+    );
+    ensure!(
+        !public_values.is_empty() || !final_states.is_empty(),
+        "Nothing is proved"
+    );
+    if cfg!(feature = "bytes-table") {
+        ensure!(
+            initial_states.is_empty() && final_states.is_empty(),
+            "Segments cannot be proved with the bytes table"
+        );
+        ensure!(
+            messages.len() * public_values_per_hash == public_values.len(),
+            "Expected one public message per digest"
+        );
+        for (message, len_and_digest) in messages
+            .iter()
+            .zip(public_values.chunks_exact(public_values_per_hash))
+        {
+            ensure!(
+                F::from_canonical_usize(message.len()) == len_and_digest[0],
+                "Public message does not match its length"
+            );
+            ensure!(
+                message
+                    .iter()
+                    .all(|byte| byte.to_canonical_u64() <= u8::MAX as u64),
+                "Public messages must be bytes"
+            );
+        }
+    } else {
+        ensure!(
+            messages.is_empty(),
+            "Messages are only public with the bytes table"
+        );
+    }
+
+    let mut challenger = Challenger::<F, C::Hasher>::new();
+    for stark_proof in stark_proofs {
+        challenger.observe_cap(&stark_proof.proof.trace_cap);
+    }
+    challenger.observe_elements(public_values);
+    challenger.observe_elements(initial_states);
+    challenger.observe_elements(final_states);
+    for message in messages {
+        challenger.observe_elements(message);
+    }
+
+    let ctl_challenges = get_permutation_challenge_set(&mut challenger, config.num_challenges);
+    let ctl_vars_per_table = CtlCheckVars::from_proofs(
+        stark_proofs,
+        &keccak_ctl.cross_table_lookups,
+        &ctl_challenges,
+        &keccak_ctl.nums_permutation_zs(config),
+    );
+
+    // The challenges of each table depend on the transcript of the previous ones, so they are all
+    // computed before any table is checked.
+    let permutation_degree_bits = permutation_proof.proof.recover_degree_bits(config);
+    let permutation_challenges = get_stark_challenges(
+        &keccak_ctl.keccak_permutation_stark,
+        &permutation_proof.proof,
+        &ctl_zs_last[Table::KeccakPermutation as usize],
+        &mut challenger,
+        permutation_degree_bits,
+        config,
+    );
+    let sponge_degree_bits = sponge_proof.proof.recover_degree_bits(config);
+    let sponge_challenges = get_stark_challenges(
+        &keccak_ctl.keccak_sponge_stark,
+        &sponge_proof.proof,
+        &ctl_zs_last[Table::KeccakSponge as usize],
+        &mut challenger,
+        sponge_degree_bits,
+        config,
+    );
+    #[cfg(feature = "bytes-table")]
+    let bytes_proof = &stark_proofs[Table::KeccakBytes as usize];
+    #[cfg(feature = "bytes-table")]
+    let bytes_degree_bits = bytes_proof.proof.recover_degree_bits(config);
+    #[cfg(feature = "bytes-table")]
+    let bytes_challenges = get_stark_challenges(
+        &keccak_ctl.keccak_bytes_stark,
+        &bytes_proof.proof,
+        &ctl_zs_last[Table::KeccakBytes as usize],
+        &mut challenger,
+        bytes_degree_bits,
+        config,
+    );
+
+    verify_stark_proof_with_challenges(
+        &keccak_ctl.keccak_permutation_stark,
+        permutation_proof,
+        permutation_challenges,
+        &ctl_vars_per_table[Table::KeccakPermutation as usize],
+        &ctl_zs_last[Table::KeccakPermutation as usize],
+        permutation_degree_bits,
+        config,
+    )?;
+    verify_stark_proof_with_challenges(
+        &keccak_ctl.keccak_sponge_stark,
+        sponge_proof,
+        sponge_challenges,
+        &ctl_vars_per_table[Table::KeccakSponge as usize],
+        &ctl_zs_last[Table::KeccakSponge as usize],
+        sponge_degree_bits,
+        config,
+    )?;
+    #[cfg(feature = "bytes-table")]
+    verify_stark_proof_with_challenges(
+        &keccak_ctl.keccak_bytes_stark,
+        bytes_proof,
+        bytes_challenges,
+        &ctl_vars_per_table[Table::KeccakBytes as usize],
+        &ctl_zs_last[Table::KeccakBytes as usize],
+        bytes_degree_bits,
+        config,
+    )?;
+
+    verify_cross_table_lookups::<F, D>(
+        &keccak_ctl.cross_table_lookups,
+        ctl_zs_last.clone(),
+        &ctl_extra_looking_products(
+            variant,
+            public_values,
+            initial_states,
+            final_states,
+            messages,
+            &ctl_challenges,
+        ),
+        config,
+    )
+}
+
+/// Computes the Fiat-Shamir challenges of one table, in the order used by `prove_single_table`.
+fn get_stark_challenges<F, C, S, const D: usize>(
+    stark: &S,
+    proof: &StarkProof<F, C, D>,
+    ctl_zs_last: &[F],
+    challenger: &mut Challenger<F, C::Hasher>,
+    degree_bits: usize,
+    config: &StarkConfig,
+) -> StarkProofChallenges<F, D>
+where
+    F: RichField + Extendable<D>,
+    C: GenericConfig<D, F = F>,
+    S: Stark<F, D>,
+{
+    let StarkProof {
+        permutation_zs_cap,
+        quotient_polys_cap,
+        openings,
+        opening_proof:
+            FriProof {
+                commit_phase_merkle_caps,
+                final_poly,
+                pow_witness,
+                ..
+            },
+        ..
+    } = proof;
+    let num_challenges = config.num_challenges;
+
+    let permutation_challenge_sets = stark.uses_permutation_args().then(|| {
+        get_n_permutation_challenge_sets(
+            challenger,
+            num_challenges,
+            stark.permutation_batch_size(),
+        )
+    });
+    if let Some(cap) = permutation_zs_cap {
+        challenger.observe_cap(cap);
+    }
+    challenger.observe_elements(ctl_zs_last);
+
+    let stark_alphas = challenger.get_n_challenges(num_challenges);
+
+    challenger.observe_cap(quotient_polys_cap);
+    let stark_zeta = challenger.get_extension_challenge::<D>();
+
+    challenger.observe_openings(&openings.to_fri_openings());
+
+    StarkProofChallenges {
+        permutation_challenge_sets,
+        stark_alphas,
+        stark_zeta,
+        fri_challenges: challenger.fri_challenges::<C, D>(
+            commit_phase_merkle_caps,
+            final_poly,
+            *pow_witness,
+            degree_bits,
+            &config.fri_config,
+        ),
+    }
+}
+
+fn verify_stark_proof_with_challenges<F, C, S, const D: usize>(
+    stark: &S,
+    proof_with_pis: &StarkProofWithPublicInputs<F, C, D>,
+    challenges: StarkProofChallenges<F, D>,
+    ctl_vars: &[CtlCheckVars<F, F::Extension, F::Extension, D>],
+    ctl_zs_last: &[F],
+    degree_bits: usize,
+    config: &StarkConfig,
+) -> Result<()>
+where
+    F: RichField + Extendable<D>,
+    C: GenericConfig<D, F = F>,
+    S: Stark<F, D>,
+    [(); S::COLUMNS]:,
+    [(); S::PUBLIC_INPUTS]:,
+    [(); C::Hasher::HASH_SIZE]:,
+{
+    let StarkProofWithPublicInputs {
+        proof,
+        public_inputs,
+    } = proof_with_pis;
+    let StarkOpeningSet {
+        local_values,
+        next_values,
+        permutation_zs,
+        permutation_zs_next,
+        quotient_polys,
+    } = &proof.openings;
+    let vars = StarkEvaluationVars {
+        local_values: &local_values.to_vec().try_into().unwrap(),
+        next_values: &next_values.to_vec().try_into().unwrap(),
+        public_inputs: &public_inputs
+            .iter()
+            .map(|&pi| F::Extension::from_basefield(pi))
+            .collect::<Vec<_>>()
+            .try_into()
+            .unwrap(),
+    };
+
+    let (l_0, l_last) = eval_l_0_and_l_last(degree_bits, challenges.stark_zeta);
+    let last = F::primitive_root_of_unity(degree_bits).inverse();
+    let z_last = challenges.stark_zeta - last.into();
+    let mut consumer = ConstraintConsumer::<F::Extension>::new(
+        challenges
+            .stark_alphas
+            .iter()
+            .map(|&alpha| F::Extension::from_basefield(alpha))
+            .collect::<Vec<_>>(),
+        z_last,
+        l_0,
+        l_last,
+    );
+    let num_permutation_zs = stark.num_permutation_batches(config);
+    let permutation_data = stark.uses_permutation_args().then(|| PermutationCheckVars {
+        local_zs: permutation_zs.as_ref().unwrap()[..num_permutation_zs].to_vec(),
+        next_zs: permutation_zs_next.as_ref().unwrap()[..num_permutation_zs].to_vec(),
+        permutation_challenge_sets: challenges.permutation_challenge_sets.unwrap(),
+    });
+    eval_vanishing_poly::<F, F::Extension, F::Extension, C, S, D, D>(
+        stark,
+        config,
+        vars,
+        permutation_data,
+        ctl_vars,
+        ctl_zs_last,
+        &mut consumer,
+    );
+    let vanishing_polys_zeta = consumer.accumulators();
+
+    // Check each polynomial identity, of the form `vanishing(x) = Z_H(x) quotient(x)`, at zeta.
+    let zeta_pow_deg = challenges.stark_zeta.exp_power_of_2(degree_bits);
+    let z_h_zeta = zeta_pow_deg - F::Extension::ONE;
+    // `quotient_polys_zeta` holds `num_challenges * quotient_degree_factor` evaluations.
+    // Each chunk of `quotient_degree_factor` holds the evaluations of `t_0(zeta),...,t_{quotient_degree_factor-1}(zeta)`
+    // where the "real" quotient polynomial is `t(X) = t_0(X) + t_1(X)*X^n + t_2(X)*X^{2n} + ...`.
+    // So to reconstruct `t(zeta)` we can compute `reduce_with_powers(chunk, zeta^n)` for each
+    // `quotient_degree_factor`-sized chunk of the original evaluations.
+    for (i, chunk) in quotient_polys
+        .chunks(stark.quotient_degree_factor())
+        .enumerate()
+    {
+        ensure!(
+            vanishing_polys_zeta[i] == z_h_zeta * reduce_with_powers(chunk, zeta_pow_deg),
+            "Mismatch between evaluation and opening of quotient polynomial"
+        );
+    }
+
+    let merkle_caps = once(proof.trace_cap.clone())
+        .chain(proof.permutation_zs_cap.clone())
+        .chain(once(proof.quotient_polys_cap.clone()))
+        .collect_vec();
+
+    verify_fri_proof::<F, C, D>(
+        &fri_instance(
+            stark,
+            challenges.stark_zeta,
+            F::primitive_root_of_unity(degree_bits),
+            ctl_vars.len(),
+            config,
+        ),
+        &proof.openings.to_fri_openings(),
+        &challenges.fri_challenges,
+        &merkle_caps,
+        &proof.opening_proof,
+        &config.fri_params(degree_bits),
+    )?;
+
+    Ok(())
+}
+
+fn validate_proof_shape<F, C, S, const D: usize>(
+    stark: &S,
+    proof_with_pis: &StarkProofWithPublicInputs<F, C, D>,
+    num_ctl_zs: usize,
+    config: &StarkConfig,
+) -> Result<()>
+where
+    F: RichField + Extendable<D>,
+    C: GenericConfig<D, F = F>,
+    S: Stark<F, D>,
+    [(); S::COLUMNS]:,
+    [(); C::Hasher::HASH_SIZE]:,
+{
+    let StarkProofWithPublicInputs {
+        proof,
+        public_inputs,
+    } = proof_with_pis;
+    let degree_bits = proof.recover_degree_bits(config);
+
+    let StarkProof {
+        trace_cap,
+        permutation_zs_cap,
+        quotient_polys_cap,
+        openings,
+        // The shape of the opening proof will be checked in the FRI verifier (see
+        // validate_fri_proof_shape), so we ignore it here.
+        opening_proof: _,
+    } = proof;
+
+    let StarkOpeningSet {
+        local_values,
+        next_values,
+        permutation_zs,
+        permutation_zs_next,
+        quotient_polys,
+    } = openings;
+
+    ensure!(public_inputs.len() == S::PUBLIC_INPUTS);
+
+    let fri_params = config.fri_params(degree_bits);
+    let cap_height = fri_params.config.cap_height;
+    let num_zs = stark.num_permutation_batches(config) + num_ctl_zs;
+
+    ensure!(trace_cap.height() == cap_height);
+    ensure!(quotient_polys_cap.height() == cap_height);
+
+    ensure!(local_values.len() == S::COLUMNS);
+    ensure!(next_values.len() == S::COLUMNS);
+    ensure!(quotient_polys.len() == stark.num_quotient_polys(config));
+
+    if num_zs > 0 {
+        let permutation_zs_cap = permutation_zs_cap
+            .as_ref()
+            .ok_or_else(|| anyhow!("Missing Zs cap"))?;
+        let permutation_zs = permutation_zs
+            .as_ref()
+            .ok_or_else(|| anyhow!("Missing permutation_zs"))?;
+        let permutation_zs_next = permutation_zs_next
+            .as_ref()
+            .ok_or_else(|| anyhow!("Missing permutation_zs_next"))?;
+
+        ensure!(permutation_zs_cap.height() == cap_height);
+        ensure!(permutation_zs.len() == num_zs);
+        ensure!(permutation_zs_next.len() == num_zs);
+    } else {
+        ensure!(permutation_zs_cap.is_none());
+        ensure!(permutation_zs.is_none());
+        ensure!(permutation_zs_next.is_none());
+    }
+
+    Ok(())
+}
+
+/// Evaluate the Lagrange polynomials `L_0` and `L_(n-1)` at a point `x`.
+/// `L_0(x) = (x^n - 1)/(n * (x - 1))`
+/// `L_(n-1)(x) = (x^n - 1)/(n * (g * x - 1))`, with `g` the first element of the subgroup.
+fn eval_l_0_and_l_last<F: Field>(log_n: usize, x: F) -> (F, F) {
+    let n = F::from_canonical_usize(1 << log_n);
+    let g = F::primitive_root_of_unity(log_n);
+    let z_x = x.exp_power_of_2(log_n) - F::ONE;
+    let invs = F::batch_multiplicative_inverse(&[n * (x - F::ONE), n * (g * x - F::ONE)]);
+
+    (z_x * invs[0], z_x * invs[1])
+}
+
+/// Like `Stark::fri_instance`, but the permutation oracle also holds `num_ctl_zs` CTL `Z`s.
+pub(crate) fn fri_instance<F, S, const D: usize>(
+    stark: &S,
+    zeta: F::Extension,
+    g: F,
+    num_ctl_zs: usize,
+    config: &StarkConfig,
+) -> FriInstanceInfo<F, D>
+where
+    F: RichField + Extendable<D>,
+    S: Stark<F, D>,
+{
+    let mut oracle_indices = 0..;
+
+    let trace_info = FriPolynomialInfo::from_range(oracle_indices.next().unwrap(), 0..S::COLUMNS);
+    let trace_oracle = FriOracleInfo {
+        num_polys: S::COLUMNS,
+        blinding: false,
+    };
+
+    let num_zs = stark.num_permutation_batches(config) + num_ctl_zs;
+    let permutation_ctl_oracle_info = (num_zs > 0).then(|| {
+        let info = FriPolynomialInfo::from_range(oracle_indices.next().unwrap(), 0..num_zs);
+        let oracle = FriOracleInfo {
+            num_polys: num_zs,
+            blinding: false,
+        };
+        (oracle, info)
+    });
+
+    let num_quotient_polys = stark.num_quotient_polys(config);
+    let quotient_info =
+        FriPolynomialInfo::from_range(oracle_indices.next().unwrap(), 0..num_quotient_polys);
+    let quotient_oracle = FriOracleInfo {
+        num_polys: num_quotient_polys,
+        blinding: false,
+    };
+
+    let zeta_batch = FriBatchInfo {
+        point: zeta,
+        polynomials: once(&trace_info)
+            .chain(permutation_ctl_oracle_info.as_ref().map(|(_, info)| info))
+            .chain(once(&quotient_info))
+            .flat_map(|info| info.iter().cloned())
+            .collect_vec(),
+    };
+    let zeta_next_batch = FriBatchInfo {
+        point: zeta.scalar_mul(g),
+        polynomials: once(&trace_info)
+            .chain(permutation_ctl_oracle_info.as_ref().map(|(_, info)| info))
+            .flat_map(|info| info.iter().cloned())
+            .collect(),
+    };
+
+    let oracles = once(trace_oracle)
+        .chain(permutation_ctl_oracle_info.map(|(oracle, _)| oracle))
+        .chain(once(quotient_oracle))
+        .collect_vec();
+
+    FriInstanceInfo {
+        oracles,
+        batches: vec![zeta_batch, zeta_next_batch],
+    }
+}
+
+/// Circuit version of `fri_instance`.
+pub(crate) fn fri_instance_target<F, S, const D: usize>(
+    builder: &mut CircuitBuilder<F, D>,
+    stark: &S,
+    zeta: ExtensionTarget<D>,
+    g: F,
+    num_ctl_zs: usize,
+    config: &StarkConfig,
+) -> FriInstanceInfoTarget<D>
+where
+    F: RichField + Extendable<D>,
+    S: Stark<F, D>,
+{
+    let mut oracle_indices = 0..;
+
+    let trace_info = FriPolynomialInfo::from_range(oracle_indices.next().unwrap(), 0..S::COLUMNS);
+    let trace_oracle = FriOracleInfo {
+        num_polys: S::COLUMNS,
+        blinding: false,
+    };
+
+    let num_zs = stark.num_permutation_batches(config) + num_ctl_zs;
+    let permutation_ctl_oracle_info = (num_zs > 0).then(|| {
+        let info = FriPolynomialInfo::from_range(oracle_indices.next().unwrap(), 0..num_zs);
+        let oracle = FriOracleInfo {
+            num_polys: num_zs,
+            blinding: false,
+        };
+        (oracle, info)
+    });
+
+    let num_quotient_polys = stark.num_quotient_polys(config);
+    let quotient_info =
+        FriPolynomialInfo::from_range(oracle_indices.next().unwrap(), 0..num_quotient_polys);
+    let quotient_oracle = FriOracleInfo {
+        num_polys: num_quotient_polys,
+        blinding: false,
+    };
+
+    let zeta_batch = FriBatchInfoTarget {
+        point: zeta,
+        polynomials: once(&trace_info)
+            .chain(permutation_ctl_oracle_info.as_ref().map(|(_, info)| info))
+            .chain(once(&quotient_info))
+            .flat_map(|info| info.iter().cloned())
+            .collect_vec(),
+    };
+    let zeta_next = builder.mul_const_extension(g, zeta);
+    let zeta_next_batch = FriBatchInfoTarget {
+        point: zeta_next,
+        polynomials: once(&trace_info)
+            .chain(permutation_ctl_oracle_info.as_ref().map(|(_, info)| info))
+            .flat_map(|info| info.iter().cloned())
+            .collect(),
+    };
+
+    let oracles = once(trace_oracle)
+        .chain(permutation_ctl_oracle_info.map(|(oracle, _)| oracle))
+        .chain(once(quotient_oracle))
+        .collect_vec();
+
+    FriInstanceInfoTarget {
+        oracles,
+        batches: vec![zeta_batch, zeta_next_batch],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use itertools::Itertools;
+    use keccak_hash::keccak;
+    use plonky2::field::polynomial::PolynomialValues;
+    use plonky2::field::types::Field;
+    use plonky2::plonk::config::{GenericConfig, PoseidonGoldilocksConfig};
+    use plonky2::util::timing::TimingTree;
+    use starky::config::StarkConfig;
+    use starky::proof::StarkProofWithPublicInputs;
+    use starky::serialization::{load_proof, save_proof, StarkProofShape};
+    use starky::util::trace_rows_to_poly_values;
+
+    use crate::config::KeccakConfig;
+    use crate::cross_table_lookup::ctl_extra_looking_rows;
+    use crate::cross_table_lookup::testutils::check_ctls;
+    use crate::cross_table_lookup::NUM_TABLES;
+    #[cfg(not(feature = "bytes-table"))]
+    use crate::keccak::keccak_sponge_segmented;
+    #[cfg(not(feature = "bytes-table"))]
+    use crate::keccak_ctl_proof::sponge_state_values;
+    use crate::keccak_ctl_proof::{
+        hash_public_values, keccak256_batch_ctl_proof, keccak256_ctl_proof, keccak256_ctl_verify,
+        keccak_sponge_batch_ctl_proof, keccak_sponge_ctl_verify, message_public_values,
+        prove_keccak_ctl, verify_keccak_ctl_proof,
+    };
+    use crate::keccak_ctl_stark::KeccakCtl;
+    use crate::keccak_permutation::keccak_permutation_stark::NUM_INPUTS;
+    use crate::keccak_sponge::ctl::PUBLIC_VALUES_PER_HASH;
+    use crate::keccak_sponge::keccak_sponge_stark::KeccakSpongeOp;
+    #[cfg(not(feature = "bytes-table"))]
+    use crate::keccak_sponge::keccak_sponge_stark::KeccakSpongeSegmentOp;
+    use crate::keccak_sponge::variant::KeccakSpongeVariant;
+    use crate::stark_aggregation::u32_to_u64;
+
+    const D: usize = 2;
+    type C = PoseidonGoldilocksConfig;
+    type F = <C as GenericConfig<D>>::F;
+
+    const MSG_1_BYTES: usize = 1;
+    const MSG_136_BYTES: usize = 136;
+    const MSG_1_000_BYTES: usize = 1_000;
+
+    fn check_traces_consistent(variant: KeccakSpongeVariant) {
+        let keccak_ctl = KeccakCtl::<F, D>::new(variant);
+        let inputs: Vec<Vec<u8>> = [MSG_1_000_BYTES, MSG_1_BYTES, MSG_136_BYTES]
+            .iter()
+            .map(|&len| (0..len).map(|_| rand::random()).collect())
+            .collect();
+        let hashes = inputs.iter().map(|input| variant.hash(input)).collect_vec();
+        let hashes = hashes.iter().map(Vec::as_slice).collect_vec();
+        let public_values = hash_public_values::<F>(variant, &inputs, &hashes);
+        let messages = message_public_values::<F>(&inputs);
+        #[cfg(feature = "bytes-table")]
+        let bytes_trace = keccak_ctl.keccak_bytes_stark.generate_trace(&inputs, 8);
+
+        let sponge_operations = inputs
+            .into_iter()
+            .enumerate()
+            .map(|(timestamp, input)| KeccakSpongeOp { timestamp, input })
+            .collect();
+        let (sponge_rows, sponge_states) = keccak_ctl
+            .keccak_sponge_stark
+            .generate_trace_rows(sponge_operations, 8);
+        let permutation_inputs: Vec<[u64; NUM_INPUTS]> = sponge_states
+            .iter()
+            .map(|state| u32_to_u64(state).try_into().unwrap())
+            .collect_vec();
+        let permutation_trace = keccak_ctl.keccak_permutation_stark.generate_trace(
+            permutation_inputs,
+            8,
+            &mut TimingTree::new("prove", log::Level::Debug),
+        );
+
+        check_ctls(
+            &[
+                permutation_trace,
+                trace_rows_to_poly_values(sponge_rows),
+                #[cfg(feature = "bytes-table")]
+                bytes_trace,
+            ],
+            &keccak_ctl.cross_table_lookups,
+            &ctl_extra_looking_rows(
+                keccak_ctl.variant(),
+                &public_values,
+                &[],
+                &[],
+                &messages,
+                F::from_canonical_usize,
+            ),
+        );
+    }
+
+    #[test]
+    fn test_keccak_ctl_traces_consistent() {
+        check_traces_consistent(KeccakSpongeVariant::KECCAK256);
+    }
+
+    #[test]
+    fn test_sponge_variants_ctl_traces_consistent() {
+        check_traces_consistent(KeccakSpongeVariant::SHA3_256);
+        check_traces_consistent(KeccakSpongeVariant::SHA3_512);
+        check_traces_consistent(KeccakSpongeVariant::shake128(32));
+    }
+
+    #[test]
+    fn test_xof_ctl_traces_consistent() {
+        // Squeezes 3 blocks, the last of them partially.
+        check_traces_consistent(KeccakSpongeVariant::shake256(300));
+        check_traces_consistent(KeccakSpongeVariant::shake128(336));
+    }
+
+    #[test]
+    #[ignore]
+    fn test_shake256_xof_ctl_proof() -> Result<()> {
+        let variant = KeccakSpongeVariant::shake256(300);
+        let input: Vec<u8> = (0..MSG_136_BYTES).map(|_| rand::random()).collect();
+        let output = variant.hash(&input);
+
+        let config = StarkConfig::standard_fast_config();
+        let (proof, _, _, _, _, _) =
+            keccak_sponge_batch_ctl_proof::<F, C, D>(variant, &[input], &[&output], &config)?;
+        keccak_sponge_ctl_verify(variant, &proof, &config)?;
+
+        // The limbs of a squeeze block are bound to the sponge as well.
+        let mut tampered = proof;
+        tampered.public_values[1 + variant.rate_u32s()] += F::ONE;
+        assert!(keccak_sponge_ctl_verify(variant, &tampered, &config).is_err());
+        Ok(())
+    }
+
+    #[cfg(not(feature = "bytes-table"))]
+    fn check_segment_traces_consistent(
+        variant: KeccakSpongeVariant,
+        len: usize,
+        segment_blocks: usize,
+    ) {
+        let keccak_ctl = KeccakCtl::<F, D>::new(variant);
+        let input: Vec<u8> = (0..len).map(|_| rand::random()).collect();
+        let hash = variant.hash(&input);
+        let public_values = hash_public_values::<F>(variant, &[input.clone()], &[&hash]);
+        let op = KeccakSpongeOp {
+            timestamp: 0,
+            input,
+        };
+
+        let segments = KeccakSpongeSegmentOp::split(variant, &op, segment_blocks);
+        let segment_bytes = variant.rate_bytes * segment_blocks;
+        assert_eq!(
+            segments.len(),
+            ((len + segment_bytes - 1) / segment_bytes).max(1)
+        );
+        for (i, segment) in segments.iter().enumerate() {
+            assert_eq!(segment.is_last(), i == segments.len() - 1);
+            let (sponge_rows, sponge_states) = keccak_ctl
+                .keccak_sponge_stark
+                .generate_segment_trace_rows(segment, 8);
+            let initial_states = sponge_state_values(
+                segment.timestamp,
+                segment.len,
+                segment.already_absorbed_bytes,
+                &segment.initial_state,
+            );
+            // Each segment resumes from the state the previous one is suspended in.
+            let final_states = match segments.get(i + 1) {
+                Some(next) => sponge_state_values(
+                    next.timestamp,
+                    next.len,
+                    next.already_absorbed_bytes,
+                    &next.initial_state,
+                ),
+                None => vec![],
+            };
+            let public_values = if segment.is_last() {
+                public_values.clone()
+            } else {
+                vec![]
+            };
+            let permutation_inputs: Vec<[u64; NUM_INPUTS]> = sponge_states
+                .iter()
+                .map(|state| u32_to_u64(state).try_into().unwrap())
+                .collect_vec();
+            let permutation_trace = keccak_ctl.keccak_permutation_stark.generate_trace(
+                permutation_inputs,
+                8,
+                &mut TimingTree::new("prove", log::Level::Debug),
+            );
+
+            check_ctls(
+                &[permutation_trace, trace_rows_to_poly_values(sponge_rows)],
+                &keccak_ctl.cross_table_lookups,
+                &ctl_extra_looking_rows(
+                    variant,
+                    &public_values,
+                    &initial_states,
+                    &final_states,
+                    &[],
+                    F::from_canonical_usize,
+                ),
+            );
+        }
+    }
+
+    #[test]
+    #[cfg(not(feature = "bytes-table"))]
+    fn test_segment_ctl_traces_consistent() {
+        check_segment_traces_consistent(KeccakSpongeVariant::KECCAK256, MSG_1_000_BYTES, 2);
+        // The last segment only holds the final block.
+        check_segment_traces_consistent(KeccakSpongeVariant::KECCAK256, MSG_136_BYTES * 4, 2);
+        check_segment_traces_consistent(KeccakSpongeVariant::KECCAK256, 0, 1);
+        check_segment_traces_consistent(KeccakSpongeVariant::shake256(300), MSG_1_000_BYTES, 3);
+    }
+
+    #[test]
+    #[ignore]
+    #[cfg(not(feature = "bytes-table"))]
+    fn test_keccak_segmented_proof() -> Result<()> {
+        let variant = KeccakSpongeVariant::KECCAK256;
+        let input: Vec<u8> = (0..MSG_1_000_BYTES).map(|_| rand::random()).collect();
+        let hash = variant.hash(&input);
+
+        let (data, proof) = keccak_sponge_segmented::<F, C, D>(
+            variant,
+            &input,
+            3,
+            &KeccakConfig::standard_fast_config(),
+        )?;
+        data.verify(proof.clone())?;
+        assert_eq!(
+            proof.public_inputs,
+            hash_public_values::<F>(variant, &[input], &[&hash])
+        );
+        Ok(())
+    }
+
+    #[test]
+    #[ignore]
+    fn test_sha3_256_ctl_proof() -> Result<()> {
+        let variant = KeccakSpongeVariant::SHA3_256;
+        let input: Vec<u8> = (0..MSG_1_000_BYTES).map(|_| rand::random()).collect();
+        let hash = variant.hash(&input);
+
+        let config = StarkConfig::standard_fast_config();
+        let (proof, _, _, _, _, _) =
+            keccak_sponge_batch_ctl_proof::<F, C, D>(variant, &[input], &[&hash], &config)?;
+        keccak_sponge_ctl_verify(variant, &proof, &config)?;
+
+        // The same proof does not hold for the keccak256 sponge.
+        assert!(keccak256_ctl_verify(&proof, &config).is_err());
+        Ok(())
+    }
+
+    fn prove_verify(msg_len: usize) -> Result<()> {
+        let input: Vec<u8> = (0..msg_len).map(|_| rand::random()).collect();
+        let expected = keccak(&input);
+
+        let config = StarkConfig::standard_fast_config();
+        let (proof, _, _, _, _, _) =
+            keccak256_ctl_proof::<F, C, D>(&input, expected.as_bytes(), &config)?;
+
+        keccak256_ctl_verify(&proof, &config)
+    }
+
+    #[test]
+    #[ignore]
+    fn test_keccak_ctl_proof() -> Result<()> {
+        prove_verify(MSG_1_BYTES)?;
+        prove_verify(MSG_136_BYTES)?;
+        prove_verify(MSG_1_000_BYTES)
+    }
+
+    #[test]
+    #[ignore]
+    fn test_keccak_batch_ctl_proof() -> Result<()> {
+        let inputs: Vec<Vec<u8>> = [MSG_1_BYTES, MSG_1_000_BYTES, 0, MSG_136_BYTES]
+            .iter()
+            .map(|&len| (0..len).map(|_| rand::random()).collect())
+            .collect();
+        let hashes = inputs.iter().map(|input| keccak(input)).collect_vec();
+        let hashes = hashes.iter().map(|hash| hash.as_bytes()).collect_vec();
+
+        let config = StarkConfig::standard_fast_config();
+        let (proof, _, _, _, _, _) =
+            keccak256_batch_ctl_proof::<F, C, D>(&inputs, &hashes, &config)?;
+        keccak256_ctl_verify(&proof, &config)?;
+
+        // Swapping two digests breaks the lookup, as each digest is bound to its message index.
+        let mut swapped = proof;
+        let (first, second) = swapped.public_values.split_at_mut(PUBLIC_VALUES_PER_HASH);
+        first.swap_with_slice(&mut second[..PUBLIC_VALUES_PER_HASH]);
+        assert!(keccak256_ctl_verify(&swapped, &config).is_err());
+        Ok(())
+    }
+
+    #[test]
+    #[ignore]
+    #[cfg(feature = "bytes-table")]
+    fn test_keccak_ctl_proof_public_messages() -> Result<()> {
+        let config = StarkConfig::standard_fast_config();
+        let input: Vec<u8> = (0..MSG_1_000_BYTES).map(|_| rand::random()).collect();
+        let (proof, _, _, _, _, _) =
+            keccak256_ctl_proof::<F, C, D>(&input, keccak(&input).as_bytes(), &config)?;
+        assert_eq!(proof.messages, message_public_values::<F>(&[input.clone()]));
+        keccak256_ctl_verify(&proof, &config)?;
+
+        // The sponge absorbed the bytes of the bytes table, which must be the public message.
+        let mut tampered = proof;
+        tampered.messages[0][0] = F::from_canonical_u8(input[0] ^ 1);
+        assert!(keccak256_ctl_verify(&tampered, &config).is_err());
+        Ok(())
+    }
+
+    #[test]
+    #[ignore]
+    fn test_keccak_ctl_proof_serialization() -> Result<()> {
+        let config = StarkConfig::standard_fast_config();
+        let input: Vec<u8> = (0..MSG_1_000_BYTES).map(|_| rand::random()).collect();
+        let (mut proof, _, _, _, _, _) =
+            keccak256_ctl_proof::<F, C, D>(&input, keccak(&input).as_bytes(), &config)?;
+
+        for (i, stark_proof) in proof.stark_proofs.iter_mut().enumerate() {
+            let shape = StarkProofShape::from_proof(&stark_proof.proof, &config);
+            let bytes = stark_proof.to_bytes();
+            assert_eq!(
+                &StarkProofWithPublicInputs::from_bytes(&bytes, &shape, &config)?,
+                stark_proof
+            );
+            assert!(StarkProofWithPublicInputs::<F, C, D>::from_bytes(
+                &bytes[..bytes.len() - 1],
+                &shape,
+                &config
+            )
+            .is_err());
+
+            let path = std::env::temp_dir().join(format!("keccak_ctl_stark_proof_{i}.bin"));
+            save_proof(&path, stark_proof, &config)?;
+            let loaded = load_proof::<F, C, D>(&path, &config)?;
+            std::fs::remove_file(&path)?;
+            assert_eq!(&loaded, stark_proof);
+            *stark_proof = loaded;
+        }
+        keccak256_ctl_verify(&proof, &config)
+    }
+
+    /// The traces of a one-block message, with a valid permutation trace but for as many
+    /// permutations of unrelated states, and its public values and messages.
+    fn unrelated_permutation_traces(
+        keccak_ctl: &KeccakCtl<F, D>,
+    ) -> ([Vec<PolynomialValues<F>>; NUM_TABLES], Vec<F>, Vec<Vec<F>>) {
+        let input: Vec<u8> = (0..MSG_1_BYTES).map(|_| rand::random()).collect();
+        let public_values = hash_public_values::<F>(
+            KeccakSpongeVariant::KECCAK256,
+            &[input.clone()],
+            &[keccak(&input).as_bytes()],
+        );
+        let messages = message_public_values::<F>(&[input.clone()]);
+        #[cfg(feature = "bytes-table")]
+        let bytes_trace = keccak_ctl
+            .keccak_bytes_stark
+            .generate_trace(&[input.clone()], 8);
+
+        let (sponge_rows, sponge_states) = keccak_ctl
+            .keccak_sponge_stark
+            .generate_trace_rows(vec![KeccakSpongeOp { timestamp: 0, input }], 8);
+        let permutation_inputs: Vec<[u64; NUM_INPUTS]> =
+            sponge_states.iter().map(|_| rand::random()).collect();
+        let permutation_trace = keccak_ctl.keccak_permutation_stark.generate_trace(
+            permutation_inputs,
+            8,
+            &mut TimingTree::new("prove", log::Level::Debug),
+        );
+
+        let traces = [
+            permutation_trace,
+            trace_rows_to_poly_values(sponge_rows),
+            #[cfg(feature = "bytes-table")]
+            bytes_trace,
+        ];
+        (traces, public_values, messages)
+    }
+
+    #[test]
+    #[should_panic(expected = "in the looked table")]
+    fn test_keccak_ctl_traces_unrelated_permutation() {
+        let keccak_ctl = KeccakCtl::<F, D>::default();
+        let (traces, public_values, messages) = unrelated_permutation_traces(&keccak_ctl);
+        check_ctls(
+            &traces,
+            &keccak_ctl.cross_table_lookups,
+            &ctl_extra_looking_rows(
+                keccak_ctl.variant(),
+                &public_values,
+                &[],
+                &[],
+                &messages,
+                F::from_canonical_usize,
+            ),
+        );
+    }
+
+    #[test]
+    fn test_keccak_ctl_proof_unrelated_permutation_trace() -> Result<()> {
+        // A single FRI query and no grinding keep the proof quick enough to run by default: the
+        // CTL check fails whatever the soundness of the proof.
+        let config = StarkConfig::with_rate(1, 1, 0);
+        let keccak_ctl = KeccakCtl::<F, D>::default();
+        let (traces, public_values, messages) = unrelated_permutation_traces(&keccak_ctl);
+
+        let proof = prove_keccak_ctl::<F, C, D>(
+            &keccak_ctl,
+            &config,
+            traces,
+            &public_values,
+            &[],
+            &[],
+            &messages,
+            &mut TimingTree::new("prove", log::Level::Debug),
+        )?;
+
+        assert!(verify_keccak_ctl_proof(&keccak_ctl, &proof, &config).is_err());
+        Ok(())
+    }
+}
@@ -3,12 +3,14 @@ use plonky2::field::types::Field;
 use plonky2::hash::hash_types::RichField;
 
 use crate::cross_table_lookup::{
-    all_cross_table_lookups, Column, CrossTableLookup, TableWithColumns, NUM_TABLES,
+    all_cross_table_lookups, Column, CrossTableLookup, Table, TableWithColumns, NUM_TABLES,
 };
+use crate::keccak_bytes::keccak_bytes_stark::KeccakBytesStark;
 use crate::keccak_permutation::keccak_permutation_stark;
 use crate::keccak_permutation::keccak_permutation_stark::KeccakPermutationStark;
 use crate::keccak_sponge::keccak_sponge_stark;
 use crate::keccak_sponge::keccak_sponge_stark::KeccakSpongeStark;
+use crate::keccak_sponge::variant::KeccakSpongeVariant;
 use starky::config::StarkConfig;
 use starky::stark::Stark;
 
@@ -16,32 +18,54 @@ use starky::stark::Stark;
 pub struct KeccakCtl<F: RichField + Extendable<D>, const D: usize> {
     pub keccak_permutation_stark: KeccakPermutationStark<F, D>,
     pub keccak_sponge_stark: KeccakSpongeStark<F, D>,
+    /// Only part of the proof with the `bytes-table` feature.
+    pub keccak_bytes_stark: KeccakBytesStark<F, D>,
     pub cross_table_lookups: Vec<CrossTableLookup<F>>,
 }
 
 impl<F: RichField + Extendable<D>, const D: usize> Default for KeccakCtl<F, D> {
     fn default() -> Self {
+        Self::new(KeccakSpongeVariant::KECCAK256)
+    }
+}
+
+impl<F: RichField + Extendable<D>, const D: usize> KeccakCtl<F, D> {
+    /// The tables and CTLs proving hashes with the sponge of `variant`.
+    pub fn new(variant: KeccakSpongeVariant) -> Self {
         Self {
             keccak_permutation_stark: KeccakPermutationStark::default(),
-            keccak_sponge_stark: KeccakSpongeStark::default(),
-            cross_table_lookups: all_cross_table_lookups(),
+            keccak_sponge_stark: KeccakSpongeStark::new(variant),
+            keccak_bytes_stark: KeccakBytesStark::default(),
+            cross_table_lookups: all_cross_table_lookups(variant),
         }
     }
-}
 
-impl<F: RichField + Extendable<D>, const D: usize> KeccakCtl<F, D> {
+    pub fn variant(&self) -> KeccakSpongeVariant {
+        self.keccak_sponge_stark.variant
+    }
+
     pub fn nums_permutation_zs(&self, config: &StarkConfig) -> [usize; NUM_TABLES] {
         [
             self.keccak_permutation_stark
                 .num_permutation_batches(config),
             self.keccak_sponge_stark.num_permutation_batches(config),
+            #[cfg(feature = "bytes-table")]
+            self.keccak_bytes_stark.num_permutation_batches(config),
         ]
     }
 
+    pub fn nums_ctl_zs(&self, config: &StarkConfig) -> [usize; NUM_TABLES] {
+        Table::all().map(|table| {
+            CrossTableLookup::num_ctl_zs(&self.cross_table_lookups, table, config.num_challenges)
+        })
+    }
+
     pub fn permutation_batch_sizes(&self) -> [usize; NUM_TABLES] {
         [
             self.keccak_permutation_stark.permutation_batch_size(),
             self.keccak_sponge_stark.permutation_batch_size(),
+            #[cfg(feature = "bytes-table")]
+            self.keccak_bytes_stark.permutation_batch_size(),
         ]
     }
 }
@@ -17,6 +17,7 @@ use starky::prover::prove;
 use std::time::{ Instant };
 pub fn keccak256_permutation_proof<F, C, const D: usize>(
     states: Vec<[u64; NUM_INPUTS]>,
+    config: &StarkConfig,
 ) -> Result<(
     StarkProofWithPublicInputs<F, C, D>,
     Vec<PolynomialValues<F>>,
@@ -32,7 +33,6 @@ where
     [(); KeccakPermutationStark::<F, D>::PUBLIC_INPUTS]:,
 {
     let generate_sponge_proof = Instant::now();
-    let config = StarkConfig::standard_fast_config();
     let stark = KeccakPermutationStark::<F, D>::default();
 
     let _ = try_init_from_env(Env::default().filter_or(DEFAULT_FILTER_ENV, "debug"));
@@ -47,7 +47,7 @@ where
     let proof_generation = Instant::now();
     let permutation_proof = prove::<F, C, KeccakPermutationStark<F, D>, D>(
         stark,
-        &config,
+        config,
         permutation_poly_values.clone(),
         None,
         &mut timing,
@@ -59,6 +59,7 @@ where
 
 pub fn keccak256_permutation_verify<F, C, const D: usize>(
     proof: StarkProofWithPublicInputs<F, C, D>,
+    config: &StarkConfig,
 ) -> Result<()>
 where
     F: RichField + Extendable<D>,
@@ -68,14 +69,13 @@ where
     [(); KeccakPermutationStark::<F, D>::COLUMNS]:,
     [(); KeccakPermutationStark::<F, D>::PUBLIC_INPUTS]:,
 {
-    let config = StarkConfig::standard_fast_config();
     let stark = KeccakPermutationStark::<F, D>::default();
 
     let _ = try_init_from_env(Env::default().filter_or(DEFAULT_FILTER_ENV, "debug"));
 
     let timing = TimingTree::new("verify", log::Level::Debug);
 
-    let res = verify_stark_proof(stark, proof, &config);
+    let res = verify_stark_proof(stark, proof, config);
 
     timing.print();
 
@@ -180,14 +180,14 @@ mod tests {
 
         let input: Vec<[u64; NUM_INPUTS]> = (0..num_perm).map(|_| rand::random()).collect();
 
-        let (permutation_proof, _) = keccak256_permutation_proof::<F, C, D>(input)?;
+        let (permutation_proof, _) = keccak256_permutation_proof::<F, C, D>(input, &config)?;
 
         println!(
             "Proof size permutation (bytes): {}",
             permutation_proof.to_bytes().len()
         );
 
-        keccak256_permutation_verify(permutation_proof)
+        keccak256_permutation_verify(permutation_proof, &config)
     }
 
     #[test]
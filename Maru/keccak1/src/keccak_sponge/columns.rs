@@ -5,10 +5,14 @@ use starky::util::{indices_arr, transmute_no_compile_time_size_checks};
 
 pub const KECCAK_WIDTH_BYTES: usize = 200;
 pub const KECCAK_WIDTH_U32S: usize = KECCAK_WIDTH_BYTES / 4;
+/// The rate of Keccak-256 and SHA3-256.
 pub const KECCAK_RATE_BYTES: usize = 136;
 pub const KECCAK_RATE_U32S: usize = KECCAK_RATE_BYTES / 4;
 pub const KECCAK_CAPACITY_BYTES: usize = 64;
 pub const KECCAK_CAPACITY_U32S: usize = KECCAK_CAPACITY_BYTES / 4;
+/// The largest rate of the supported sponges, that of SHAKE128. The columns holding a block are
+/// this wide, whatever the rate of the sponge, see `KeccakSpongeVariant`.
+pub const KECCAK_MAX_RATE_BYTES: usize = 168;
 
 #[repr(C)]
 #[derive(Eq, PartialEq, Debug)]
@@ -21,6 +25,23 @@ pub struct KeccakSpongeColumnsView<T: Copy> {
     /// in the block will be padding bytes; 0 otherwise.
     pub is_final_block: T,
 
+    /// 1 if this row squeezes a further output block, by permuting the state of the previous row
+    /// without absorbing anything; 0 otherwise.
+    pub is_squeeze_block: T,
+
+    /// 1 if this row resumes a sponge from a carried state rather than from the all-0 state: its
+    /// `original_state_u32s` and `already_absorbed_bytes` are those reached by the previous
+    /// segments of the input, and are public. 0 otherwise.
+    pub is_resumed_block: T,
+
+    /// 1 if this row is a full input block after which the sponge is suspended, with its
+    /// `updated_state_u32s` public, so that a later segment can resume from it; 0 otherwise.
+    pub is_suspended_block: T,
+
+    /// 1 if this row is the last of its sponge: a suspended block, or a final or squeeze block not
+    /// followed by a squeeze block; 0 otherwise.
+    pub is_sponge_end: T,
+
     // The base address at which we will read the input block.
     pub context: T,
     pub segment: T,
@@ -35,24 +56,24 @@ pub struct KeccakSpongeColumnsView<T: Copy> {
     /// The number of input bytes that have already been absorbed prior to this block.
     pub already_absorbed_bytes: T,
 
+    /// The index of the output block squeezed from `updated_state_u32s`: 0 on the final block, and
+    /// counting up on the squeeze blocks which follow it.
+    pub squeeze_index: T,
+
     /// If this row represents a final block row, the `i`th entry should be 1 if the final chunk of
     /// input has length `i` (in other words if `len - already_absorbed == i`), otherwise 0.
     ///
-    /// If this row represents a full input block, this should contain all 0s.
-    pub is_final_input_len: [T; KECCAK_RATE_BYTES],
-
-    /// The initial rate part of the sponge, at the start of this step.
-    pub original_rate_u32s: [T; KECCAK_RATE_U32S],
+    /// If this row represents a full input block, this should contain all 0s. Entries past the
+    /// rate of the sponge are always 0.
+    pub is_final_input_len: [T; KECCAK_MAX_RATE_BYTES],
 
-    /// The capacity part of the sponge, encoded as 32-bit chunks, at the start of this step.
-    pub original_capacity_u32s: [T; KECCAK_CAPACITY_U32S],
+    /// The entire state (rate + capacity) of the sponge, encoded as 32-bit chunks, at the start of
+    /// this step.
+    pub original_state_u32s: [T; KECCAK_WIDTH_U32S],
 
-    /// The block being absorbed, which may contain input bytes and/or padding bytes.
-    pub block_bytes: [T; KECCAK_RATE_BYTES],
-
-    /// The rate part of the sponge, encoded as 32-bit chunks, after the current block is xor'd in,
-    /// but before the permutation is applied.
-    pub xored_rate_u32s: [T; KECCAK_RATE_BYTES],
+    /// The block being absorbed, which may contain input bytes and/or padding bytes. Bytes past
+    /// the rate of the sponge are always 0.
+    pub block_bytes: [T; KECCAK_MAX_RATE_BYTES],
 
     /// The entire state , encoded as 32-bit chunks, after the current block is xor'd in,
     /// but before the permutation is applied.
@@ -1,14 +1,18 @@
+use std::iter::once;
+
 use plonky2::field::types::Field;
 
 use crate::cross_table_lookup::Column;
 use crate::keccak_sponge::columns::*;
+use crate::keccak_sponge::variant::KeccakSpongeVariant;
 
+/// The sponge looks up each permutation it applies: the state after the block is xor'd in, followed
+/// by the state after the permutation, matching `keccak_permutation::ctl::ctl_data`.
 pub fn ctl_looking_keccak<F: Field>() -> Vec<Column<F>> {
     let cols = KECCAK_SPONGE_COL_MAP;
     Column::singles(
         [
-            cols.xored_rate_u32s.as_slice(),
-            &cols.original_capacity_u32s,
+            cols.xored_state_u32s.as_slice(),
             &cols.updated_state_u32s,
         ]
         .concat(),
@@ -18,5 +22,105 @@ pub fn ctl_looking_keccak<F: Field>() -> Vec<Column<F>> {
 
 pub(crate) fn ctl_looking_keccak_filter<F: Field>() -> Column<F> {
     let cols = KECCAK_SPONGE_COL_MAP;
-    Column::sum([cols.is_full_input_block, cols.is_final_block])
+    Column::sum([
+        cols.is_full_input_block,
+        cols.is_final_block,
+        cols.is_squeeze_block,
+    ])
+}
+
+/// Number of `u32` limbs of a keccak256 digest.
+pub const DIGEST_U32S: usize = KeccakSpongeVariant::KECCAK256.output_u32s();
+
+/// Public values of each hashed message: its length in bytes, then the limbs squeezed from each of
+/// its output blocks, of which the digest is a prefix, see `KeccakSpongeVariant::squeeze`.
+pub const fn public_values_per_hash(variant: KeccakSpongeVariant) -> usize {
+    1 + variant.squeezed_u32s()
+}
+
+/// Public values of each keccak256 hash, see `public_values_per_hash`.
+pub const PUBLIC_VALUES_PER_HASH: usize = public_values_per_hash(KeccakSpongeVariant::KECCAK256);
+
+/// On the final block and the squeeze blocks of each sponge, its timestamp, input length, the index
+/// of the output block and the limbs of the `variant`'s output squeezed from it. These are looked
+/// up by the public values of the proof, one row per output block of each hashed message.
+pub fn ctl_looked_digest<F: Field>(variant: KeccakSpongeVariant) -> Vec<Column<F>> {
+    let cols = KECCAK_SPONGE_COL_MAP;
+    Column::singles(
+        [
+            [cols.timestamp, cols.len, cols.squeeze_index].as_slice(),
+            &cols.updated_state_u32s[..variant.output_u32s_per_block()],
+        ]
+        .concat(),
+    )
+    .collect()
+}
+
+pub(crate) fn ctl_looked_digest_filter<F: Field>() -> Column<F> {
+    let cols = KECCAK_SPONGE_COL_MAP;
+    Column::sum([cols.is_final_block, cols.is_squeeze_block])
+}
+
+/// Public values of each resumed or suspended sponge: its timestamp, the length of its whole input,
+/// the number of input bytes absorbed before the state, then the state itself.
+pub const SPONGE_STATE_VALUES: usize = 3 + KECCAK_WIDTH_U32S;
+
+/// On each resumed block, the state the sponge resumes from, see `SPONGE_STATE_VALUES`. These are
+/// looked up by the public initial states of the proof.
+pub fn ctl_looked_initial_state<F: Field>() -> Vec<Column<F>> {
+    let cols = KECCAK_SPONGE_COL_MAP;
+    Column::singles(
+        [
+            [cols.timestamp, cols.len, cols.already_absorbed_bytes].as_slice(),
+            &cols.original_state_u32s,
+        ]
+        .concat(),
+    )
+    .collect()
+}
+
+pub(crate) fn ctl_looked_initial_state_filter<F: Field>() -> Column<F> {
+    Column::single(KECCAK_SPONGE_COL_MAP.is_resumed_block)
+}
+
+/// On each suspended block, the state the sponge is suspended in, after the block is absorbed, see
+/// `SPONGE_STATE_VALUES`. These are looked up by the public final states of the proof.
+pub fn ctl_looked_final_state<F: Field>(variant: KeccakSpongeVariant) -> Vec<Column<F>> {
+    let cols = KECCAK_SPONGE_COL_MAP;
+    [
+        Column::single(cols.timestamp),
+        Column::single(cols.len),
+        Column::linear_combination_with_constant(
+            [(cols.already_absorbed_bytes, F::ONE)],
+            F::from_canonical_usize(variant.rate_bytes),
+        ),
+    ]
+    .into_iter()
+    .chain(Column::singles(cols.updated_state_u32s))
+    .collect()
+}
+
+pub(crate) fn ctl_looked_final_state_filter<F: Field>() -> Column<F> {
+    Column::single(KECCAK_SPONGE_COL_MAP.is_suspended_block)
+}
+
+/// The `i`-th byte of the block: the timestamp of the sponge, the position of the byte in the
+/// input and its value. Looked up in the bytes table, see `keccak_bytes::ctl::ctl_looked_bytes`.
+pub fn ctl_looking_bytes<F: Field>(i: usize) -> Vec<Column<F>> {
+    let cols = KECCAK_SPONGE_COL_MAP;
+    vec![
+        Column::single(cols.timestamp),
+        Column::linear_combination_with_constant(
+            [(cols.already_absorbed_bytes, F::ONE)],
+            F::from_canonical_usize(i),
+        ),
+        Column::single(cols.block_bytes[i]),
+    ]
+}
+
+/// The `i`-th byte of the block is an input byte, rather than padding, in full input blocks and in
+/// final blocks with more than `i` input bytes.
+pub fn ctl_looking_bytes_filter<F: Field>(i: usize) -> Column<F> {
+    let cols = KECCAK_SPONGE_COL_MAP;
+    Column::sum(once(&cols.is_full_input_block).chain(&cols.is_final_input_len[i + 1..]))
 }
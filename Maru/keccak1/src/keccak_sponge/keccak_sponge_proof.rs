@@ -1,41 +1,61 @@
 use env_logger::{try_init_from_env, Env, DEFAULT_FILTER_ENV};
-use itertools::Itertools;
 
 use anyhow::Result;
 
-use plonky2::field::types::Field;
 use plonky2::hash::hash_types::RichField;
 use plonky2::plonk::config::{AlgebraicHasher, GenericConfig};
 use plonky2::timed;
 use plonky2::util::timing::TimingTree;
 use plonky2::{field::goldilocks_field::GoldilocksField, plonk::config::PoseidonGoldilocksConfig};
 use plonky2_field::extension::Extendable;
-use plonky2_field::polynomial::PolynomialValues;
 use starky::config::StarkConfig;
 use log::{info};
 use starky::proof::StarkProofWithPublicInputs;
 use starky::prover::prove;
 use starky::stark::Stark;
-use starky::util::trace_rows_to_poly_values;
 use starky::verifier::verify_stark_proof;
+use std::io::Read;
 use std::time::{ Instant };
 use crate::{
     keccak_sponge::columns::KECCAK_WIDTH_U32S,
-    keccak_sponge::keccak_sponge_stark::{KeccakSpongeOp, KeccakSpongeStark},
+    keccak_sponge::keccak_sponge_stark::KeccakSpongeStark,
 };
 
-use super::keccak_util::u8_to_u32_reverse;
-
 use plonky2::plonk::config::Hasher;
 
-// make a proof that the hash matches the message
-pub fn keccak256_sponge_proof<F, C, const D: usize>(
+/// Proves the keccak256 sponge trace of `msg` on its own. The sponge STARK alone neither checks
+/// the permutations nor exposes the digest, which are bound through the CTL with the permutation,
+/// so this is no proof of the hash: `keccak256_ctl_proof` is.
+pub fn sponge_trace_proof<F, C, const D: usize>(
     msg: &[u8],
-    hash: &[u8],
+    config: &StarkConfig,
+) -> Result<(
+    StarkProofWithPublicInputs<F, C, D>,
+    Vec<[u32; KECCAK_WIDTH_U32S]>,
+    f32,
+    f32
+)>
+where
+    F: RichField + Extendable<D>,
+    C: GenericConfig<D, F = F>,
+    C::Hasher: AlgebraicHasher<F>,
+    [(); C::Hasher::HASH_SIZE]:,
+    [(); KeccakSpongeStark::<F, D>::COLUMNS]:,
+    [(); KeccakSpongeStark::<F, D>::PUBLIC_INPUTS]:,
+{
+    sponge_trace_proof_from_reader(msg, msg.len(), config)
+}
+
+/// Like `sponge_trace_proof`, for a message of `len` bytes streamed from `msg`. The trace is
+/// generated block by block straight into its columns, so the message is never held in memory and
+/// the trace only once.
+pub fn sponge_trace_proof_from_reader<F, C, const D: usize>(
+    msg: impl Read,
+    len: usize,
+    config: &StarkConfig,
 ) -> Result<(
     StarkProofWithPublicInputs<F, C, D>,
     Vec<[u32; KECCAK_WIDTH_U32S]>,
-    Vec<PolynomialValues<F>>,
     f32,
     f32
 )>
@@ -48,53 +68,36 @@ where
     [(); KeccakSpongeStark::<F, D>::PUBLIC_INPUTS]:,
 {
     let _ = try_init_from_env(Env::default().filter_or(DEFAULT_FILTER_ENV, "debug"));
-    let expected_hash: [F; 8] = u8_to_u32_reverse(hash)
-        .iter()
-        .map(|x| F::from_canonical_u32(*x))
-        .collect_vec()
-        .try_into()
-        .expect("to field error");
     let generate_sponge_proof = Instant::now();
-    let config = StarkConfig::standard_fast_config();
     let stark = KeccakSpongeStark::<F, D>::default();
     
     let mut timing = TimingTree::new("prove", log::Level::Debug);
 
-    let mut sponge_operations: Vec<KeccakSpongeOp> = Vec::new();
-    sponge_operations.push(KeccakSpongeOp {
-        timestamp: 0,
-        input: msg.to_vec(),
-    });
-
-    let (sponge_trace_rows, sponge_states) = timed!(
+    let (sponge_poly_values, sponge_states) = timed!(
         timing,
         "generate trace",
-        stark.generate_trace_rows(sponge_operations.try_into().unwrap(), 8)
-    );
-
-    let sponge_poly_values = timed!(
-        timing,
-        "convert to PolynomialValues",
-        trace_rows_to_poly_values(sponge_trace_rows.clone())
+        stark.generate_trace_from_reader(0, len, msg, 8)?
     );
     let generate_sponge_proof_time = generate_sponge_proof.elapsed().as_secs_f32();
     let proof_generation = Instant::now();
     let sponge_proof = prove::<F, C, KeccakSpongeStark<F, D>, D>(
         stark,
-        &config,
-        sponge_poly_values.clone(),
-        Some(expected_hash),
+        config,
+        sponge_poly_values,
+        None,
         &mut timing,
     )?;
     let proof_generation_time = proof_generation.elapsed().as_secs_f32();
 
     timing.print();
 
-    Ok((sponge_proof, sponge_states, sponge_poly_values, generate_sponge_proof_time, proof_generation_time))
+    Ok((sponge_proof, sponge_states, generate_sponge_proof_time, proof_generation_time))
 }
 
-pub fn keccak256_sponge_verify<F, C, const D: usize>(
+/// Verifies a proof made by `sponge_trace_proof`
+pub fn sponge_trace_verify<F, C, const D: usize>(
     proof: StarkProofWithPublicInputs<F, C, D>,
+    config: &StarkConfig,
 ) -> Result<()>
 where
     F: RichField + Extendable<D>,
@@ -104,14 +107,13 @@ where
     [(); KeccakSpongeStark::<F, D>::COLUMNS]:,
     [(); KeccakSpongeStark::<F, D>::PUBLIC_INPUTS]:,
 {
-    let config = StarkConfig::standard_fast_config();
     let stark = KeccakSpongeStark::<F, D>::default();
 
     let _ = try_init_from_env(Env::default().filter_or(DEFAULT_FILTER_ENV, "debug"));
 
     let timing = TimingTree::new("verify", log::Level::Debug);
 
-    let res = verify_stark_proof(stark, proof, &config);
+    let res = verify_stark_proof(stark, proof, config);
 
     timing.print();
 
@@ -120,14 +122,20 @@ where
 
 #[cfg(test)]
 mod tests {
-    use crate::keccak_sponge::keccak_sponge_proof::keccak256_sponge_verify;
+    use crate::keccak_sponge::keccak_sponge_proof::{
+        sponge_trace_proof_from_reader, sponge_trace_verify,
+    };
     use crate::keccak_sponge::{
-        keccak_sponge_proof::keccak256_sponge_proof, keccak_sponge_stark::KeccakSpongeStark,
+        keccak_sponge_proof::sponge_trace_proof,
+        keccak_sponge_stark::{KeccakSpongeOp, KeccakSpongeStark},
     };
+    use crate::keccak_sponge::variant::KeccakSpongeVariant;
     use anyhow::Result;
-    use keccak_hash::keccak;
     use plonky2::plonk::config::{GenericConfig, PoseidonGoldilocksConfig};
+    use starky::config::StarkConfig;
     use starky::stark_testing::{test_stark_circuit_constraints, test_stark_low_degree};
+    use starky::util::trace_rows_to_poly_values;
+    use std::io::Read;
 
     const MSG_1_BYTES: usize = 1;
     const MSG_100_BYTES: usize = 100;
@@ -159,22 +167,102 @@ mod tests {
         test_stark_circuit_constraints::<F, C, S, D>(stark)
     }
 
+    #[test]
+    fn test_stark_circuit_sha3_256() -> Result<()> {
+        const D: usize = 2;
+        type C = PoseidonGoldilocksConfig;
+        type F = <C as GenericConfig<D>>::F;
+        type S = KeccakSpongeStark<F, D>;
+
+        let stark = S::new(KeccakSpongeVariant::SHA3_256);
+        test_stark_circuit_constraints::<F, C, S, D>(stark)
+    }
+
+    #[test]
+    fn test_trace_from_reader_matches_rows() {
+        const D: usize = 2;
+        type C = PoseidonGoldilocksConfig;
+        type F = <C as GenericConfig<D>>::F;
+        type S = KeccakSpongeStark<F, D>;
+
+        for variant in [
+            KeccakSpongeVariant::KECCAK256,
+            KeccakSpongeVariant::shake128(32),
+            KeccakSpongeVariant::shake256(300),
+        ] {
+            let stark = S::new(variant);
+            for len in [0, 1, 135, 136, 168, 1_000] {
+                let input: Vec<u8> = (0..len).map(|_| rand::random()).collect();
+                let (rows, states) = stark.generate_trace_rows(
+                    vec![KeccakSpongeOp {
+                        timestamp: 3,
+                        input: input.clone(),
+                    }],
+                    8,
+                );
+                let (trace, streamed_states) = stark
+                    .generate_trace_from_reader(3, len, input.as_slice(), 8)
+                    .unwrap();
+                assert_eq!(trace, trace_rows_to_poly_values(rows));
+                assert_eq!(streamed_states, states);
+
+                // The reader must hold exactly `len` bytes.
+                assert!(stark
+                    .generate_trace_from_reader(3, len + 1, input.as_slice(), 8)
+                    .is_err());
+                if len > 0 {
+                    assert!(stark
+                        .generate_trace_from_reader(3, len - 1, input.as_slice(), 8)
+                        .is_err());
+                }
+            }
+        }
+    }
+
     fn prove_verify(msg_len: usize) -> Result<()> {
         const D: usize = 2;
         type C = PoseidonGoldilocksConfig;
         type F = <C as GenericConfig<D>>::F;
 
         let input: Vec<u8> = (0..msg_len).map(|_| rand::random()).collect();
-        let expected = keccak(&input);
 
-        let (sponge_proof, _, _) = keccak256_sponge_proof::<F, C, D>(&input, expected.as_bytes())?;
+        let config = StarkConfig::standard_fast_config();
+        let (sponge_proof, _, _, _) = sponge_trace_proof::<F, C, D>(&input, &config)?;
 
         println!(
             "Proof size sponge (bytes): {}",
             sponge_proof.to_bytes().len()
         );
 
-        keccak256_sponge_verify(sponge_proof)
+        sponge_trace_verify(sponge_proof, &config)
+    }
+
+    /// Like `prove_verify`, but the message is streamed to the prover instead of being held in
+    /// memory.
+    fn prove_verify_streamed(msg_len: usize) -> Result<()> {
+        const D: usize = 2;
+        type C = PoseidonGoldilocksConfig;
+        type F = <C as GenericConfig<D>>::F;
+
+        let config = StarkConfig::standard_fast_config();
+        let reader = MessageReader((0..msg_len).map(|i| (i % 251) as u8));
+        let (sponge_proof, _, _, _) =
+            sponge_trace_proof_from_reader::<F, C, D>(reader, msg_len, &config)?;
+
+        sponge_trace_verify(sponge_proof, &config)
+    }
+
+    struct MessageReader<I>(I);
+
+    impl<I: Iterator<Item = u8>> Read for MessageReader<I> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let mut n = 0;
+            for (byte, value) in buf.iter_mut().zip(&mut self.0) {
+                *byte = value;
+                n += 1;
+            }
+            Ok(n)
+        }
     }
 
     #[test]
@@ -183,7 +271,7 @@ mod tests {
         prove_verify(MSG_1_BYTES)?;
         prove_verify(MSG_100_BYTES)?;
         prove_verify(MSG_1_000_BYTES)?;
-        prove_verify(MSG_500_000_BYTES)?;
-        prove_verify(MSG_1_000_000_BYTES)
+        prove_verify_streamed(MSG_500_000_BYTES)?;
+        prove_verify_streamed(MSG_1_000_000_BYTES)
     }
 }
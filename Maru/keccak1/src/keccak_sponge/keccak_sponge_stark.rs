@@ -1,4 +1,5 @@
 use std::borrow::Borrow;
+use std::io::{self, Read};
 use std::marker::PhantomData;
 
 use itertools::Itertools;
@@ -12,6 +13,7 @@ use plonky2::util::timing::TimingTree;
 
 use crate::keccak_sponge::columns::*;
 use crate::keccak_sponge::keccak_util::keccakf_u32s;
+use crate::keccak_sponge::variant::KeccakSpongeVariant;
 use starky::constraint_consumer::{ConstraintConsumer, RecursiveConstraintConsumer};
 use starky::stark::Stark;
 use starky::util::trace_rows_to_poly_values;
@@ -27,12 +29,94 @@ pub struct KeccakSpongeOp {
     pub input: Vec<u8>,
 }
 
+/// A segment of a Keccak sponge operation over a long input, which resumes from the state reached
+/// by the previous segments and, unless it is the last one, is suspended before the end of the
+/// input. Each segment can be proved on its own, see `keccak_sponge_segment_ctl_proof`.
+#[derive(Clone, Debug)]
+pub struct KeccakSpongeSegmentOp {
+    /// The timestamp at which inputs are read.
+    pub timestamp: usize,
+
+    /// The length of the whole input, in bytes.
+    pub len: usize,
+
+    /// The number of input bytes absorbed by the previous segments, a multiple of the rate.
+    pub already_absorbed_bytes: usize,
+
+    /// The sponge state after the previous segments, all 0s for the first one.
+    pub initial_state: [u32; KECCAK_WIDTH_U32S],
+
+    /// The bytes of the input absorbed by this segment: a non-zero multiple of the rate, except in
+    /// the last segment.
+    pub input: Vec<u8>,
+}
+
+impl KeccakSpongeSegmentOp {
+    /// Splits `op` into segments absorbing `segment_blocks` blocks of input each, computing the
+    /// state each of them resumes from natively.
+    pub fn split(
+        variant: KeccakSpongeVariant,
+        op: &KeccakSpongeOp,
+        segment_blocks: usize,
+    ) -> Vec<Self> {
+        assert!(segment_blocks > 0);
+        let segment_bytes = segment_blocks * variant.rate_bytes;
+        let len = op.input.len();
+        // The last segment also holds the final block, so there is a segment even without input.
+        let num_segments = ((len + segment_bytes - 1) / segment_bytes).max(1);
+        let mut state = [0u32; KECCAK_WIDTH_U32S];
+        (0..num_segments)
+            .map(|i| {
+                let already_absorbed_bytes = i * segment_bytes;
+                let input = op.input[already_absorbed_bytes..]
+                    .iter()
+                    .copied()
+                    .take(segment_bytes)
+                    .collect_vec();
+                let segment = Self {
+                    timestamp: op.timestamp,
+                    len,
+                    already_absorbed_bytes,
+                    initial_state: state,
+                    input,
+                };
+                for block in segment.input.chunks_exact(variant.rate_bytes) {
+                    for (state_i, block_i) in state.iter_mut().zip(block.chunks_exact(4)) {
+                        *state_i ^= u32::from_le_bytes(block_i.try_into().unwrap());
+                    }
+                    keccakf_u32s(&mut state);
+                }
+                segment
+            })
+            .collect()
+    }
+
+    /// Whether this segment absorbs the end of the input, and outputs the digest.
+    pub fn is_last(&self) -> bool {
+        self.already_absorbed_bytes + self.input.len() == self.len
+    }
+
+    /// The number of input bytes absorbed after this segment.
+    pub fn absorbed_bytes(&self) -> usize {
+        self.already_absorbed_bytes + self.input.len()
+    }
+}
+
+/// The sponge of `variant`, Keccak-256 by default.
 #[derive(Copy, Clone, Default)]
 pub struct KeccakSpongeStark<F, const D: usize> {
+    pub variant: KeccakSpongeVariant,
     f: PhantomData<F>,
 }
 
 impl<F: RichField + Extendable<D>, const D: usize> KeccakSpongeStark<F, D> {
+    pub fn new(variant: KeccakSpongeVariant) -> Self {
+        Self {
+            variant,
+            f: PhantomData,
+        }
+    }
+
     pub fn generate_trace(
         &self,
         operations: Vec<KeccakSpongeOp>,
@@ -77,6 +161,74 @@ impl<F: RichField + Extendable<D>, const D: usize> KeccakSpongeStark<F, D> {
         (rows, states)
     }
 
+    /// Generates the trace of a single sponge over the `len` bytes read from `input` with the given
+    /// `timestamp`, one block at a time. Each row is written straight into preallocated columns, so
+    /// the trace is only held once, as `PolynomialValues`. Also returns the state before each
+    /// permutation, like `generate_trace_rows`.
+    pub fn generate_trace_from_reader(
+        &self,
+        timestamp: usize,
+        len: usize,
+        input: impl Read,
+        min_rows: usize,
+    ) -> io::Result<(Vec<PolynomialValues<F>>, Vec<[u32; KECCAK_WIDTH_U32S]>)> {
+        let num_rows = self.num_rows(len);
+        let padded_rows = num_rows.max(min_rows).next_power_of_two();
+        // Padding rows are all 0s, see `generate_padding_row`.
+        let mut trace = vec![PolynomialValues::zero(padded_rows); NUM_KECCAK_SPONGE_COLUMNS];
+        let mut states = Vec::with_capacity(num_rows);
+        let mut i = 0;
+        self.generate_rows_from_reader(timestamp, len, input, |row| {
+            states.push(row.xored_state_u32s.map(|f| f.to_canonical_u64() as u32));
+            let row: [F; NUM_KECCAK_SPONGE_COLUMNS] = row.into();
+            for (column, value) in trace.iter_mut().zip(row) {
+                column.values[i] = value;
+            }
+            i += 1;
+        })?;
+        Ok((trace, states))
+    }
+
+    /// The number of rows of a sponge over `len` input bytes, before padding: one per full input
+    /// block, the final block, and one per further output block.
+    pub fn num_rows(&self, len: usize) -> usize {
+        len / self.variant.rate_bytes + self.variant.output_blocks()
+    }
+
+    /// Like `generate_trace_rows`, for a single segment of a sponge. Its first row is resumed from
+    /// the `initial_state` of the segment, and its last full input block is suspended unless the
+    /// segment is the last one.
+    pub fn generate_segment_trace_rows(
+        &self,
+        segment: &KeccakSpongeSegmentOp,
+        min_rows: usize,
+    ) -> (
+        Vec<[F; NUM_KECCAK_SPONGE_COLUMNS]>,
+        Vec<[u32; KECCAK_WIDTH_U32S]>,
+    ) {
+        let mut rows = vec![];
+        let mut states = vec![];
+        self.generate_segment_rows_from_reader(
+            segment.timestamp,
+            segment.len,
+            segment.already_absorbed_bytes,
+            segment.initial_state,
+            true,
+            segment.input.len(),
+            segment.input.as_slice(),
+            |row| {
+                states.push(row.xored_state_u32s.map(|f| f.to_canonical_u64() as u32));
+                rows.push(row.into());
+            },
+        )
+        .expect("The input holds exactly the bytes of the segment");
+        let padded_rows = rows.len().max(min_rows).next_power_of_two();
+        for _ in rows.len()..padded_rows {
+            rows.push(self.generate_padding_row());
+        }
+        (rows, states)
+    }
+
     pub fn generate_rows_for_op(
         &self,
         op: KeccakSpongeOp,
@@ -86,122 +238,226 @@ impl<F: RichField + Extendable<D>, const D: usize> KeccakSpongeStark<F, D> {
     ) {
         let mut rows = vec![];
         let mut states = vec![];
+        self.generate_rows_from_reader(op.timestamp, op.input.len(), op.input.as_slice(), |row| {
+            states.push(row.xored_state_u32s.map(|f| f.to_canonical_u64() as u32));
+            rows.push(row.into());
+        })
+        .expect("The input holds exactly `len` bytes");
+        (rows, states)
+    }
 
-        let mut sponge_state = [0u32; KECCAK_WIDTH_U32S];
+    /// Generates the rows of a sponge over the `len` bytes read from `input`, in order, and passes
+    /// each of them to `push_row`. Fails if `input` does not hold exactly `len` bytes.
+    fn generate_rows_from_reader(
+        &self,
+        timestamp: usize,
+        len: usize,
+        input: impl Read,
+        push_row: impl FnMut(KeccakSpongeColumnsView<F>),
+    ) -> io::Result<()> {
+        self.generate_segment_rows_from_reader(
+            timestamp,
+            len,
+            0,
+            [0; KECCAK_WIDTH_U32S],
+            false,
+            len,
+            input,
+            push_row,
+        )
+    }
 
-        let mut input_blocks = op.input.chunks_exact(KECCAK_RATE_BYTES);
-        let mut already_absorbed_bytes = 0;
-        for block in input_blocks.by_ref() {
-            let row = self.generate_full_input_row(
-                &op,
+    /// Generates the rows of the segment of a sponge over `len` bytes which absorbs the
+    /// `segment_len` bytes read from `input`, after `already_absorbed_bytes` bytes took the sponge
+    /// to `sponge_state`. The first row is marked as resumed if `is_resumed`, and the last full
+    /// input block is suspended unless the segment reaches the end of the input. Fails if `input`
+    /// does not hold exactly `segment_len` bytes.
+    fn generate_segment_rows_from_reader(
+        &self,
+        timestamp: usize,
+        len: usize,
+        mut already_absorbed_bytes: usize,
+        mut sponge_state: [u32; KECCAK_WIDTH_U32S],
+        mut is_resumed: bool,
+        segment_len: usize,
+        mut input: impl Read,
+        mut push_row: impl FnMut(KeccakSpongeColumnsView<F>),
+    ) -> io::Result<()> {
+        let rate_bytes = self.variant.rate_bytes;
+        let segment_end = already_absorbed_bytes + segment_len;
+        let is_last_segment = segment_end == len;
+        assert!(segment_end <= len);
+        assert!(is_last_segment || (segment_len > 0 && segment_len % rate_bytes == 0));
+
+        let mut block = [0u8; KECCAK_MAX_RATE_BYTES];
+        while already_absorbed_bytes + rate_bytes <= segment_end {
+            input.read_exact(&mut block[..rate_bytes])?;
+            let mut row = self.generate_full_input_row(
+                timestamp,
+                len,
                 already_absorbed_bytes,
                 sponge_state,
-                block.try_into().unwrap(),
+                &block[..rate_bytes],
             );
+            row.is_resumed_block = F::from_bool(is_resumed);
+            is_resumed = false;
+            already_absorbed_bytes += rate_bytes;
+            if already_absorbed_bytes == segment_end && !is_last_segment {
+                row.is_suspended_block = F::ONE;
+                row.is_sponge_end = F::ONE;
+            }
 
             sponge_state = row.updated_state_u32s.map(|f| f.to_canonical_u64() as u32);
-
-            states.push(row.xored_state_u32s.map(|f| f.to_canonical_u64() as u32));
-
-            rows.push(row.into());
-            already_absorbed_bytes += KECCAK_RATE_BYTES;
+            push_row(row);
         }
 
-        let row = self.generate_final_row(
-            &op,
+        let final_inputs = &mut block[..segment_end - already_absorbed_bytes];
+        input.read_exact(final_inputs)?;
+        if input.read(&mut [0])? != 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("The input is longer than {segment_len} bytes"),
+            ));
+        }
+        if !is_last_segment {
+            return Ok(());
+        }
+        let mut row = self.generate_final_row(
+            timestamp,
+            len,
             already_absorbed_bytes,
             sponge_state,
-            input_blocks.remainder(),
+            final_inputs,
         );
+        row.is_resumed_block = F::from_bool(is_resumed);
+        let output_blocks = self.variant.output_blocks();
+        row.is_sponge_end = F::from_bool(output_blocks == 1);
+
+        sponge_state = row.updated_state_u32s.map(|f| f.to_canonical_u64() as u32);
+        push_row(row);
 
-        states.push(row.xored_state_u32s.map(|f| f.to_canonical_u64() as u32));
+        for squeeze_index in 1..output_blocks {
+            let mut row = self.generate_squeeze_row(
+                timestamp,
+                len,
+                already_absorbed_bytes,
+                sponge_state,
+                squeeze_index,
+            );
+            row.is_sponge_end = F::from_bool(squeeze_index == output_blocks - 1);
 
-        rows.push(row.into());
+            sponge_state = row.updated_state_u32s.map(|f| f.to_canonical_u64() as u32);
+            push_row(row);
+        }
 
-        (rows, states)
+        Ok(())
     }
 
     fn generate_full_input_row(
         &self,
-        op: &KeccakSpongeOp,
+        timestamp: usize,
+        len: usize,
         already_absorbed_bytes: usize,
         sponge_state: [u32; KECCAK_WIDTH_U32S],
-        block: [u8; KECCAK_RATE_BYTES],
+        block: &[u8],
     ) -> KeccakSpongeColumnsView<F> {
         let mut row = KeccakSpongeColumnsView {
             is_full_input_block: F::ONE,
             ..Default::default()
         };
 
-        row.block_bytes = block.map(F::from_canonical_u8);
+        for (block_byte, input_byte) in row.block_bytes.iter_mut().zip(block) {
+            *block_byte = F::from_canonical_u8(*input_byte);
+        }
 
-        Self::generate_common_fields(&mut row, op, already_absorbed_bytes, sponge_state);
+        self.generate_common_fields(
+            &mut row,
+            timestamp,
+            len,
+            already_absorbed_bytes,
+            sponge_state,
+        );
         row
     }
 
     fn generate_final_row(
         &self,
-        op: &KeccakSpongeOp,
+        timestamp: usize,
+        len: usize,
         already_absorbed_bytes: usize,
         sponge_state: [u32; KECCAK_WIDTH_U32S],
         final_inputs: &[u8],
     ) -> KeccakSpongeColumnsView<F> {
-        assert_eq!(already_absorbed_bytes + final_inputs.len(), op.input.len());
+        assert_eq!(already_absorbed_bytes + final_inputs.len(), len);
 
         let mut row = KeccakSpongeColumnsView {
             is_final_block: F::ONE,
             ..Default::default()
         };
 
-        for (block_byte, input_byte) in row.block_bytes.iter_mut().zip(final_inputs) {
-            *block_byte = F::from_canonical_u8(*input_byte);
-        }
-
-        // pad10*1 rule
-        if final_inputs.len() == KECCAK_RATE_BYTES - 1 {
-            // Both 1s are placed in the same byte.
-            row.block_bytes[final_inputs.len()] = F::from_canonical_u8(0b10000001);
-        } else {
-            row.block_bytes[final_inputs.len()] = F::ONE;
-            row.block_bytes[KECCAK_RATE_BYTES - 1] = F::from_canonical_u8(0b10000000);
+        // pad10*1 rule, after the domain separation bits
+        let block = self.variant.pad(final_inputs);
+        for (block_byte, input_byte) in row.block_bytes.iter_mut().zip(block) {
+            *block_byte = F::from_canonical_u8(input_byte);
         }
 
         row.is_final_input_len[final_inputs.len()] = F::ONE;
 
-        Self::generate_common_fields(&mut row, op, already_absorbed_bytes, sponge_state);
+        self.generate_common_fields(
+            &mut row,
+            timestamp,
+            len,
+            already_absorbed_bytes,
+            sponge_state,
+        );
         row
     }
 
-    /// Generate fields that are common to both full-input-block rows and final-block rows.
+    fn generate_squeeze_row(
+        &self,
+        timestamp: usize,
+        len: usize,
+        already_absorbed_bytes: usize,
+        sponge_state: [u32; KECCAK_WIDTH_U32S],
+        squeeze_index: usize,
+    ) -> KeccakSpongeColumnsView<F> {
+        let mut row = KeccakSpongeColumnsView {
+            is_squeeze_block: F::ONE,
+            squeeze_index: F::from_canonical_usize(squeeze_index),
+            ..Default::default()
+        };
+
+        // Nothing is absorbed, the block stays all 0s.
+        self.generate_common_fields(
+            &mut row,
+            timestamp,
+            len,
+            already_absorbed_bytes,
+            sponge_state,
+        );
+        row
+    }
+
+    /// Generate fields that are common to full-input-block, final-block and squeeze-block rows.
     /// Also updates the sponge state with a single absorption.
     fn generate_common_fields(
+        &self,
         row: &mut KeccakSpongeColumnsView<F>,
-        op: &KeccakSpongeOp,
+        timestamp: usize,
+        len: usize,
         already_absorbed_bytes: usize,
         mut sponge_state: [u32; KECCAK_WIDTH_U32S],
     ) {
         //row.context = F::from_canonical_usize(op.base_address.context);
         //row.segment = F::from_canonical_usize(op.base_address.segment);
         //row.virt = F::from_canonical_usize(op.base_address.virt);
-        row.timestamp = F::from_canonical_usize(op.timestamp);
-        row.len = F::from_canonical_usize(op.input.len());
+        row.timestamp = F::from_canonical_usize(timestamp);
+        row.len = F::from_canonical_usize(len);
         row.already_absorbed_bytes = F::from_canonical_usize(already_absorbed_bytes);
 
-        row.original_rate_u32s = sponge_state[..KECCAK_RATE_U32S]
-            .iter()
-            .map(|x| F::from_canonical_u32(*x))
-            .collect_vec()
-            .try_into()
-            .unwrap();
-
-        row.original_capacity_u32s = sponge_state[KECCAK_RATE_U32S..]
-            .iter()
-            .map(|x| F::from_canonical_u32(*x))
-            .collect_vec()
-            .try_into()
-            .unwrap();
+        row.original_state_u32s = sponge_state.map(F::from_canonical_u32);
 
-        let block_u32s = (0..KECCAK_RATE_U32S).map(|i| {
+        let block_u32s = (0..self.variant.rate_u32s()).map(|i| {
             u32::from_le_bytes(
                 row.block_bytes[i * 4..(i + 1) * 4]
                     .iter()
@@ -224,7 +480,7 @@ impl<F: RichField + Extendable<D>, const D: usize> KeccakSpongeStark<F, D> {
     }
 
     fn generate_padding_row(&self) -> [F; NUM_KECCAK_SPONGE_COLUMNS] {
-        // The default instance has is_full_input_block = is_final_block = 0,
+        // The default instance has is_full_input_block = is_final_block = is_squeeze_block = 0,
         // indicating that it's a dummy/padding row.
         KeccakSpongeColumnsView::default().into()
     }
@@ -232,7 +488,9 @@ impl<F: RichField + Extendable<D>, const D: usize> KeccakSpongeStark<F, D> {
 
 impl<F: RichField + Extendable<D>, const D: usize> Stark<F, D> for KeccakSpongeStark<F, D> {
     const COLUMNS: usize = NUM_KECCAK_SPONGE_COLUMNS;
-    const PUBLIC_INPUTS: usize = 8;
+    // The digests are exposed through the `ctl_keccak_sponge_digests` lookup instead, so that a
+    // single trace can hold any number of hashes.
+    const PUBLIC_INPUTS: usize = 0;
 
     fn eval_packed_generic<FE, P, const D2: usize>(
         &self,
@@ -245,19 +503,37 @@ impl<F: RichField + Extendable<D>, const D: usize> Stark<F, D> for KeccakSpongeS
         let local_values: &KeccakSpongeColumnsView<P> = vars.local_values.borrow();
         let next_values: &KeccakSpongeColumnsView<P> = vars.next_values.borrow();
 
-        // Each flag (full-input block, final block or implied dummy flag) must be boolean.
+        // Each flag (full-input block, final block, squeeze block or implied dummy flag) must be
+        // boolean.
         let is_full_input_block = local_values.is_full_input_block;
         yield_constr.constraint(is_full_input_block * (is_full_input_block - P::ONES));
 
         let is_final_block = local_values.is_final_block;
         yield_constr.constraint(is_final_block * (is_final_block - P::ONES));
 
+        let is_squeeze_block = local_values.is_squeeze_block;
+        yield_constr.constraint(is_squeeze_block * (is_squeeze_block - P::ONES));
+
+        let is_resumed_block = local_values.is_resumed_block;
+        yield_constr.constraint(is_resumed_block * (is_resumed_block - P::ONES));
+
+        let is_suspended_block = local_values.is_suspended_block;
+        yield_constr.constraint(is_suspended_block * (is_suspended_block - P::ONES));
+
+        // A resumed block absorbs input, and only a full input block can be suspended.
+        yield_constr
+            .constraint(is_resumed_block * (P::ONES - is_full_input_block - is_final_block));
+        yield_constr.constraint(is_suspended_block * (P::ONES - is_full_input_block));
+
         for &is_final_len in local_values.is_final_input_len.iter() {
             yield_constr.constraint(is_final_len * (is_final_len - P::ONES));
         }
 
-        // Ensure that full-input block and final block flags are not set to 1 at the same time.
+        // Ensure that no two of the full-input block, final block and squeeze block flags are set
+        // to 1 at the same time.
         yield_constr.constraint(is_final_block * is_full_input_block);
+        yield_constr.constraint(is_squeeze_block * is_full_input_block);
+        yield_constr.constraint(is_squeeze_block * is_final_block);
 
         // Sum of is_final_input_len should equal is_final_block (which will be 0 or 1).
         let is_final_input_len_sum: P = local_values.is_final_input_len.iter().copied().sum();
@@ -266,66 +542,112 @@ impl<F: RichField + Extendable<D>, const D: usize> Stark<F, D> for KeccakSpongeS
         // If this is a full-input block, is_final_input_len should contain all 0s.
         yield_constr.constraint(is_full_input_block * is_final_input_len_sum);
 
-        // If this is the first row, the original sponge state should be 0 and already_absorbed_bytes = 0.
+        // If this is the first row and it is not resumed, the original sponge state should be 0
+        // and already_absorbed_bytes = 0.
         let already_absorbed_bytes = local_values.already_absorbed_bytes;
-        yield_constr.constraint_first_row(already_absorbed_bytes);
-        for &original_rate_elem in local_values.original_rate_u32s.iter() {
-            yield_constr.constraint_first_row(original_rate_elem);
-        }
-        for &original_capacity_elem in local_values.original_capacity_u32s.iter() {
-            yield_constr.constraint_first_row(original_capacity_elem);
+        let is_fresh_block = P::ONES - is_resumed_block;
+        yield_constr.constraint_first_row(is_fresh_block * already_absorbed_bytes);
+        for &original_state_elem in local_values.original_state_u32s.iter() {
+            yield_constr.constraint_first_row(is_fresh_block * original_state_elem);
         }
+        // A sponge can't start by squeezing.
+        yield_constr.constraint_first_row(is_squeeze_block);
 
-        // If this is a final block, the next row's original sponge state should be 0 and already_absorbed_bytes = 0.
-        yield_constr.constraint_transition(is_final_block * next_values.already_absorbed_bytes);
-        for &original_rate_elem in next_values.original_rate_u32s.iter() {
-            yield_constr.constraint_transition(is_final_block * original_rate_elem);
-        }
-        for &original_capacity_elem in next_values.original_capacity_u32s.iter() {
-            yield_constr.constraint_transition(is_final_block * original_capacity_elem);
+        // A sponge ends with a suspended block, or with its last output block, i.e. a final or
+        // squeeze block not followed by a squeeze block.
+        let is_sponge_end = local_values.is_sponge_end;
+        yield_constr.constraint_transition(
+            is_sponge_end
+                - (is_final_block + is_squeeze_block) * (P::ONES - next_values.is_squeeze_block)
+                - is_suspended_block,
+        );
+
+        // If this is the end of a sponge and the next one is not resumed, the next row's original
+        // sponge state should be 0 and already_absorbed_bytes = 0.
+        let next_is_fresh_sponge = is_sponge_end * (P::ONES - next_values.is_resumed_block);
+        yield_constr
+            .constraint_transition(next_is_fresh_sponge * next_values.already_absorbed_bytes);
+        for &original_state_elem in next_values.original_state_u32s.iter() {
+            yield_constr.constraint_transition(next_is_fresh_sponge * original_state_elem);
         }
 
-        // If this is a full-input block, the next row's address, time and len must match as well as its timestamp.
+        // A squeeze block follows the final block of the sponge or another squeeze block, and
+        // permutes its "after" state without absorbing anything.
+        let next_is_squeeze_block = next_values.is_squeeze_block;
         yield_constr.constraint_transition(
-            is_full_input_block * (local_values.context - next_values.context),
+            next_is_squeeze_block * (P::ONES - is_final_block - is_squeeze_block),
         );
+        for (&current_after, &next_before) in local_values
+            .updated_state_u32s
+            .iter()
+            .zip(next_values.original_state_u32s.iter())
+        {
+            yield_constr
+                .constraint_transition(next_is_squeeze_block * (next_before - current_after));
+        }
         yield_constr.constraint_transition(
-            is_full_input_block * (local_values.segment - next_values.segment),
+            next_is_squeeze_block * (next_values.timestamp - local_values.timestamp),
         );
         yield_constr
-            .constraint_transition(is_full_input_block * (local_values.virt - next_values.virt));
+            .constraint_transition(next_is_squeeze_block * (next_values.len - local_values.len));
         yield_constr.constraint_transition(
-            is_full_input_block * (local_values.timestamp - next_values.timestamp),
+            next_is_squeeze_block * (next_values.already_absorbed_bytes - already_absorbed_bytes),
         );
-
-        // If this is a full-input block, the next row's "before" should match our "after" state.
-        for (&current_after, &next_before) in local_values
-            .updated_state_u32s
+        yield_constr.constraint_transition(
+            next_is_squeeze_block
+                * (next_values.squeeze_index - local_values.squeeze_index - P::ONES),
+        );
+        for (&original, &xored) in local_values
+            .original_state_u32s
             .iter()
-            .zip(next_values.original_rate_u32s.iter())
+            .zip(local_values.xored_state_u32s.iter())
         {
-            yield_constr.constraint_transition(is_full_input_block * (next_before - current_after));
+            yield_constr.constraint(is_squeeze_block * (xored - original));
         }
+
+        // Only squeeze blocks squeeze past the first output block.
+        yield_constr.constraint((P::ONES - is_squeeze_block) * local_values.squeeze_index);
+
+        // The sponge goes on after a full-input block, unless it is suspended.
+        let is_continued_block = is_full_input_block - is_suspended_block;
+
+        // If this is a continued full-input block, the next row's address, time and len must match as well as its timestamp.
+        yield_constr.constraint_transition(
+            is_continued_block * (local_values.context - next_values.context),
+        );
+        yield_constr.constraint_transition(
+            is_continued_block * (local_values.segment - next_values.segment),
+        );
+        yield_constr
+            .constraint_transition(is_continued_block * (local_values.virt - next_values.virt));
+        yield_constr.constraint_transition(
+            is_continued_block * (local_values.timestamp - next_values.timestamp),
+        );
+
+        // If this is a continued full-input block, the next row's "before" should match our "after" state.
         for (&current_after, &next_before) in local_values
             .updated_state_u32s
             .iter()
-            .skip(KECCAK_RATE_U32S)
-            .zip(next_values.original_capacity_u32s.iter())
+            .zip(next_values.original_state_u32s.iter())
         {
-            yield_constr.constraint_transition(is_full_input_block * (next_before - current_after));
+            yield_constr.constraint_transition(is_continued_block * (next_before - current_after));
         }
 
-        // If this is a full-input block, the next row's already_absorbed_bytes should be ours plus 136.
+        // If this is a continued full-input block, the next row's already_absorbed_bytes should be ours plus the rate.
+        let rate_bytes = self.variant.rate_bytes;
         yield_constr.constraint_transition(
-            is_full_input_block
-                * (already_absorbed_bytes + P::from(FE::from_canonical_u64(136))
+            is_continued_block
+                * (already_absorbed_bytes + P::from(FE::from_canonical_usize(rate_bytes))
                     - next_values.already_absorbed_bytes),
         );
 
         // A dummy row is always followed by another dummy row, so the prover can't put dummy rows "in between" to avoid the above checks.
-        let is_dummy = P::ONES - is_full_input_block - is_final_block;
+        let is_dummy = P::ONES - is_full_input_block - is_final_block - is_squeeze_block;
         yield_constr.constraint_transition(
-            is_dummy * (next_values.is_full_input_block + next_values.is_final_block),
+            is_dummy
+                * (next_values.is_full_input_block
+                    + next_values.is_final_block
+                    + next_values.is_squeeze_block),
         );
 
         // If this is a final block, is_final_input_len implies `len - already_absorbed == i`.
@@ -335,12 +657,32 @@ impl<F: RichField + Extendable<D>, const D: usize> Stark<F, D> for KeccakSpongeS
             yield_constr.constraint(is_final_len * entry_match);
         }
 
-        // If this is a final block (is_final_block = 1), then this row contains a hash
-        for i in 0..Self::PUBLIC_INPUTS {
+        // A block holds at most `rate_bytes` bytes, and a squeeze block none.
+        for i in rate_bytes..KECCAK_MAX_RATE_BYTES {
+            yield_constr.constraint(local_values.is_final_input_len[i]);
+            yield_constr.constraint(local_values.block_bytes[i]);
+        }
+        for &block_byte in &local_values.block_bytes[..rate_bytes] {
+            yield_constr.constraint(is_squeeze_block * block_byte);
+        }
+
+        // In a final block, the input bytes are followed by the domain separation bits and the
+        // pad10*1 rule: the byte after the input starts with the domain separator, the last byte
+        // of the block ends with 0x80, and the bytes in between are 0.
+        let domain_separator = P::from(FE::from_canonical_u8(self.variant.domain_separator));
+        let final_bit = P::from(FE::from_canonical_u8(0b10000000));
+        let mut is_after_padding_start = P::ZEROS;
+        for i in 0..rate_bytes {
+            let is_padding_start = local_values.is_final_input_len[i];
+            let mut expected = is_padding_start * domain_separator;
+            if i == rate_bytes - 1 {
+                expected += is_final_block * final_bit;
+            }
             yield_constr.constraint(
-                local_values.is_final_block
-                    * (local_values.updated_state_u32s[i] - vars.public_inputs[i]),
+                (is_padding_start + is_after_padding_start)
+                    * (local_values.block_bytes[i] - expected),
             );
+            is_after_padding_start += is_padding_start;
         }
     }
 
@@ -355,7 +697,8 @@ impl<F: RichField + Extendable<D>, const D: usize> Stark<F, D> for KeccakSpongeS
 
         let one = builder.one_extension();
 
-        // Each flag (full-input block, final block or implied dummy flag) must be boolean.
+        // Each flag (full-input block, final block, squeeze block or implied dummy flag) must be
+        // boolean.
         let is_full_input_block = local_values.is_full_input_block;
         let constraint = builder.mul_sub_extension(
             is_full_input_block,
@@ -368,14 +711,47 @@ impl<F: RichField + Extendable<D>, const D: usize> Stark<F, D> for KeccakSpongeS
         let constraint = builder.mul_sub_extension(is_final_block, is_final_block, is_final_block);
         yield_constr.constraint(builder, constraint);
 
+        let is_squeeze_block = local_values.is_squeeze_block;
+        let constraint =
+            builder.mul_sub_extension(is_squeeze_block, is_squeeze_block, is_squeeze_block);
+        yield_constr.constraint(builder, constraint);
+
+        let is_resumed_block = local_values.is_resumed_block;
+        let constraint =
+            builder.mul_sub_extension(is_resumed_block, is_resumed_block, is_resumed_block);
+        yield_constr.constraint(builder, constraint);
+
+        let is_suspended_block = local_values.is_suspended_block;
+        let constraint =
+            builder.mul_sub_extension(is_suspended_block, is_suspended_block, is_suspended_block);
+        yield_constr.constraint(builder, constraint);
+
+        // A resumed block absorbs input, and only a full input block can be suspended.
+        let constraint = {
+            let tmp = builder.sub_extension(one, is_full_input_block);
+            let tmp = builder.sub_extension(tmp, is_final_block);
+            builder.mul_extension(is_resumed_block, tmp)
+        };
+        yield_constr.constraint(builder, constraint);
+        let constraint = {
+            let tmp = builder.sub_extension(one, is_full_input_block);
+            builder.mul_extension(is_suspended_block, tmp)
+        };
+        yield_constr.constraint(builder, constraint);
+
         for &is_final_len in local_values.is_final_input_len.iter() {
             let constraint = builder.mul_sub_extension(is_final_len, is_final_len, is_final_len);
             yield_constr.constraint(builder, constraint);
         }
 
-        // Ensure that full-input block and final block flags are not set to 1 at the same time.
+        // Ensure that no two of the full-input block, final block and squeeze block flags are set
+        // to 1 at the same time.
         let constraint = builder.mul_extension(is_final_block, is_full_input_block);
         yield_constr.constraint(builder, constraint);
+        let constraint = builder.mul_extension(is_squeeze_block, is_full_input_block);
+        yield_constr.constraint(builder, constraint);
+        let constraint = builder.mul_extension(is_squeeze_block, is_final_block);
+        yield_constr.constraint(builder, constraint);
 
         // Sum of is_final_input_len should equal is_final_block (which will be 0 or 1).
         let mut is_final_input_len_sum = builder.add_extension(
@@ -392,82 +768,143 @@ impl<F: RichField + Extendable<D>, const D: usize> Stark<F, D> for KeccakSpongeS
         let constraint = builder.mul_extension(is_full_input_block, is_final_input_len_sum);
         yield_constr.constraint(builder, constraint);
 
-        // If this is the first row, the original sponge state should be 0 and already_absorbed_bytes = 0.
+        // If this is the first row and it is not resumed, the original sponge state should be 0
+        // and already_absorbed_bytes = 0.
         let already_absorbed_bytes = local_values.already_absorbed_bytes;
-        yield_constr.constraint_first_row(builder, already_absorbed_bytes);
-        for &original_rate_elem in local_values.original_rate_u32s.iter() {
-            yield_constr.constraint_first_row(builder, original_rate_elem);
+        let is_fresh_block = builder.sub_extension(one, is_resumed_block);
+        let constraint = builder.mul_extension(is_fresh_block, already_absorbed_bytes);
+        yield_constr.constraint_first_row(builder, constraint);
+        for &original_state_elem in local_values.original_state_u32s.iter() {
+            let constraint = builder.mul_extension(is_fresh_block, original_state_elem);
+            yield_constr.constraint_first_row(builder, constraint);
         }
-        for &original_capacity_elem in local_values.original_capacity_u32s.iter() {
-            yield_constr.constraint_first_row(builder, original_capacity_elem);
+        // A sponge can't start by squeezing.
+        yield_constr.constraint_first_row(builder, is_squeeze_block);
+
+        // A sponge ends with a suspended block, or with its last output block, i.e. a final or
+        // squeeze block not followed by a squeeze block.
+        let is_sponge_end = local_values.is_sponge_end;
+        let is_output_block = builder.add_extension(is_final_block, is_squeeze_block);
+        let constraint = {
+            let tmp = builder.sub_extension(one, next_values.is_squeeze_block);
+            let is_last_output_block = builder.mul_extension(is_output_block, tmp);
+            let tmp = builder.sub_extension(is_sponge_end, is_last_output_block);
+            builder.sub_extension(tmp, is_suspended_block)
+        };
+        yield_constr.constraint_transition(builder, constraint);
+
+        // If this is the end of a sponge and the next one is not resumed, the next row's original
+        // sponge state should be 0 and already_absorbed_bytes = 0.
+        let next_is_fresh_sponge = {
+            let tmp = builder.sub_extension(one, next_values.is_resumed_block);
+            builder.mul_extension(is_sponge_end, tmp)
+        };
+        let constraint =
+            builder.mul_extension(next_is_fresh_sponge, next_values.already_absorbed_bytes);
+        yield_constr.constraint_transition(builder, constraint);
+        for &original_state_elem in next_values.original_state_u32s.iter() {
+            let constraint = builder.mul_extension(next_is_fresh_sponge, original_state_elem);
+            yield_constr.constraint_transition(builder, constraint);
         }
 
-        // If this is a final block, the next row's original sponge state should be 0 and already_absorbed_bytes = 0.
-        let constraint = builder.mul_extension(is_final_block, next_values.already_absorbed_bytes);
+        // A squeeze block follows the final block of the sponge or another squeeze block, and
+        // permutes its "after" state without absorbing anything.
+        let next_is_squeeze_block = next_values.is_squeeze_block;
+        let constraint = {
+            let tmp = builder.sub_extension(one, is_output_block);
+            builder.mul_extension(next_is_squeeze_block, tmp)
+        };
         yield_constr.constraint_transition(builder, constraint);
-        for &original_rate_elem in next_values.original_rate_u32s.iter() {
-            let constraint = builder.mul_extension(is_final_block, original_rate_elem);
+        for (&current_after, &next_before) in local_values
+            .updated_state_u32s
+            .iter()
+            .zip(next_values.original_state_u32s.iter())
+        {
+            let diff = builder.sub_extension(next_before, current_after);
+            let constraint = builder.mul_extension(next_is_squeeze_block, diff);
             yield_constr.constraint_transition(builder, constraint);
         }
-        for &original_capacity_elem in next_values.original_capacity_u32s.iter() {
-            let constraint = builder.mul_extension(is_final_block, original_capacity_elem);
+        for (next, local) in [
+            (next_values.timestamp, local_values.timestamp),
+            (next_values.len, local_values.len),
+            (next_values.already_absorbed_bytes, already_absorbed_bytes),
+        ] {
+            let diff = builder.sub_extension(next, local);
+            let constraint = builder.mul_extension(next_is_squeeze_block, diff);
             yield_constr.constraint_transition(builder, constraint);
         }
+        let constraint = {
+            let diff = builder.sub_extension(next_values.squeeze_index, local_values.squeeze_index);
+            let tmp = builder.sub_extension(diff, one);
+            builder.mul_extension(next_is_squeeze_block, tmp)
+        };
+        yield_constr.constraint_transition(builder, constraint);
+        for (&original, &xored) in local_values
+            .original_state_u32s
+            .iter()
+            .zip(local_values.xored_state_u32s.iter())
+        {
+            let diff = builder.sub_extension(xored, original);
+            let constraint = builder.mul_extension(is_squeeze_block, diff);
+            yield_constr.constraint(builder, constraint);
+        }
+
+        // Only squeeze blocks squeeze past the first output block.
+        let constraint = {
+            let tmp = builder.sub_extension(one, is_squeeze_block);
+            builder.mul_extension(tmp, local_values.squeeze_index)
+        };
+        yield_constr.constraint(builder, constraint);
+
+        // The sponge goes on after a full-input block, unless it is suspended.
+        let is_continued_block = builder.sub_extension(is_full_input_block, is_suspended_block);
 
-        // If this is a full-input block, the next row's address, time and len must match as well as its timestamp.
+        // If this is a continued full-input block, the next row's address, time and len must match as well as its timestamp.
         let context_diff = builder.sub_extension(local_values.context, next_values.context);
-        let constraint = builder.mul_extension(is_full_input_block, context_diff);
+        let constraint = builder.mul_extension(is_continued_block, context_diff);
         yield_constr.constraint_transition(builder, constraint);
 
         let segment_diff = builder.sub_extension(local_values.segment, next_values.segment);
-        let constraint = builder.mul_extension(is_full_input_block, segment_diff);
+        let constraint = builder.mul_extension(is_continued_block, segment_diff);
         yield_constr.constraint_transition(builder, constraint);
 
         let virt_diff = builder.sub_extension(local_values.virt, next_values.virt);
-        let constraint = builder.mul_extension(is_full_input_block, virt_diff);
+        let constraint = builder.mul_extension(is_continued_block, virt_diff);
         yield_constr.constraint_transition(builder, constraint);
 
         let timestamp_diff = builder.sub_extension(local_values.timestamp, next_values.timestamp);
-        let constraint = builder.mul_extension(is_full_input_block, timestamp_diff);
+        let constraint = builder.mul_extension(is_continued_block, timestamp_diff);
         yield_constr.constraint_transition(builder, constraint);
 
-        // If this is a full-input block, the next row's "before" should match our "after" state.
+        // If this is a continued full-input block, the next row's "before" should match our "after" state.
         for (&current_after, &next_before) in local_values
             .updated_state_u32s
             .iter()
-            .zip(next_values.original_rate_u32s.iter())
+            .zip(next_values.original_state_u32s.iter())
         {
             let diff = builder.sub_extension(next_before, current_after);
-            let constraint = builder.mul_extension(is_full_input_block, diff);
-            yield_constr.constraint_transition(builder, constraint);
-        }
-        for (&current_after, &next_before) in local_values
-            .updated_state_u32s
-            .iter()
-            .skip(KECCAK_RATE_U32S)
-            .zip(next_values.original_capacity_u32s.iter())
-        {
-            let diff = builder.sub_extension(next_before, current_after);
-            let constraint = builder.mul_extension(is_full_input_block, diff);
+            let constraint = builder.mul_extension(is_continued_block, diff);
             yield_constr.constraint_transition(builder, constraint);
         }
 
-        // If this is a full-input block, the next row's already_absorbed_bytes should be ours plus 136.
-        let absorbed_bytes =
-            builder.add_const_extension(already_absorbed_bytes, F::from_canonical_u64(136));
+        // If this is a continued full-input block, the next row's already_absorbed_bytes should be ours plus the rate.
+        let rate_bytes = self.variant.rate_bytes;
+        let absorbed_bytes = builder
+            .add_const_extension(already_absorbed_bytes, F::from_canonical_usize(rate_bytes));
         let absorbed_diff =
             builder.sub_extension(absorbed_bytes, next_values.already_absorbed_bytes);
-        let constraint = builder.mul_extension(is_full_input_block, absorbed_diff);
+        let constraint = builder.mul_extension(is_continued_block, absorbed_diff);
         yield_constr.constraint_transition(builder, constraint);
 
         // A dummy row is always followed by another dummy row, so the prover can't put dummy rows "in between" to avoid the above checks.
         let is_dummy = {
-            let tmp = builder.sub_extension(one, is_final_block);
+            let tmp = builder.sub_extension(one, is_output_block);
             builder.sub_extension(tmp, is_full_input_block)
         };
         let constraint = {
             let tmp =
                 builder.add_extension(next_values.is_final_block, next_values.is_full_input_block);
+            let tmp = builder.add_extension(tmp, next_values.is_squeeze_block);
             builder.mul_extension(is_dummy, tmp)
         };
         yield_constr.constraint_transition(builder, constraint);
@@ -481,11 +918,34 @@ impl<F: RichField + Extendable<D>, const D: usize> Stark<F, D> for KeccakSpongeS
             let constraint = builder.mul_extension(is_final_len, entry_match);
             yield_constr.constraint(builder, constraint);
         }
-        // If this is a final block (is_final_block = 1), then this row contains a hash
-        for i in 0..Self::PUBLIC_INPUTS {
-            let hash_match = builder.sub_extension(vars.local_values[i], vars.public_inputs[i]);
-            let constraint = builder.mul_extension(local_values.is_final_block, hash_match);
-            yield_constr.constraint_transition(builder, constraint);
+
+        // A block holds at most `rate_bytes` bytes, and a squeeze block none.
+        for i in rate_bytes..KECCAK_MAX_RATE_BYTES {
+            yield_constr.constraint(builder, local_values.is_final_input_len[i]);
+            yield_constr.constraint(builder, local_values.block_bytes[i]);
+        }
+        for &block_byte in &local_values.block_bytes[..rate_bytes] {
+            let constraint = builder.mul_extension(is_squeeze_block, block_byte);
+            yield_constr.constraint(builder, constraint);
+        }
+
+        // In a final block, the input bytes are followed by the domain separation bits and the
+        // pad10*1 rule: the byte after the input starts with the domain separator, the last byte
+        // of the block ends with 0x80, and the bytes in between are 0.
+        let domain_separator = F::from_canonical_u8(self.variant.domain_separator);
+        let final_bit = F::from_canonical_u8(0b10000000);
+        let mut is_after_padding_start = builder.zero_extension();
+        for i in 0..rate_bytes {
+            let is_padding_start = local_values.is_final_input_len[i];
+            let mut expected = builder.mul_const_extension(domain_separator, is_padding_start);
+            if i == rate_bytes - 1 {
+                expected = builder.mul_const_add_extension(final_bit, is_final_block, expected);
+            }
+            let is_padding = builder.add_extension(is_padding_start, is_after_padding_start);
+            let diff = builder.sub_extension(local_values.block_bytes[i], expected);
+            let constraint = builder.mul_extension(is_padding, diff);
+            yield_constr.constraint(builder, constraint);
+            is_after_padding_start = is_padding;
         }
     }
 
@@ -1,9 +1,11 @@
 //! The Keccak sponge STARK is used to hash a variable amount of data which is read from memory.
 //! It connects to the memory STARK to read input data, and to the Keccak-f STARK to evaluate the
-//! permutation at each absorption step.
+//! permutation at each absorption step. Its rate, padding and output length are those of a
+//! `KeccakSpongeVariant`, e.g. Keccak-256, SHA3-256 or SHAKE128.
 
 pub mod columns;
 pub mod ctl;
 pub mod keccak_sponge_proof;
 pub mod keccak_sponge_stark;
 pub mod keccak_util;
+pub mod variant;
@@ -0,0 +1,207 @@
+use crate::keccak_sponge::columns::{KECCAK_MAX_RATE_BYTES, KECCAK_WIDTH_BYTES};
+use crate::keccak_sponge::keccak_util::keccakf_u8s;
+
+/// A sponge over Keccak-f[1600]: its rate, the domain separation bits which start its padding, and
+/// the number of bytes it squeezes. All of them share the permutation STARK.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct KeccakSpongeVariant {
+    /// The number of bytes absorbed per permutation, a multiple of 8 up to
+    /// `KECCAK_MAX_RATE_BYTES`.
+    pub rate_bytes: usize,
+    /// The first padding byte: the domain separation bits followed by the first bit of pad10*1.
+    pub domain_separator: u8,
+    /// The number of output bytes, a multiple of 4. Beyond `rate_bytes`, the sponge squeezes one
+    /// more permutation per block of output.
+    pub output_bytes: usize,
+}
+
+impl KeccakSpongeVariant {
+    pub const KECCAK256: Self = Self::keccak(256);
+    pub const KECCAK512: Self = Self::keccak(512);
+    pub const SHA3_224: Self = Self::sha3(224);
+    pub const SHA3_256: Self = Self::sha3(256);
+    pub const SHA3_384: Self = Self::sha3(384);
+    pub const SHA3_512: Self = Self::sha3(512);
+
+    pub const fn new(rate_bytes: usize, domain_separator: u8, output_bytes: usize) -> Self {
+        assert!(rate_bytes > 0 && rate_bytes <= KECCAK_MAX_RATE_BYTES && rate_bytes % 8 == 0);
+        assert!(domain_separator != 0 && domain_separator < 0x80);
+        assert!(output_bytes > 0 && output_bytes % 4 == 0);
+        Self {
+            rate_bytes,
+            domain_separator,
+            output_bytes,
+        }
+    }
+
+    /// Keccak as in Ethereum, with a capacity of twice the output size.
+    pub const fn keccak(output_bits: usize) -> Self {
+        Self::new(KECCAK_WIDTH_BYTES - output_bits / 4, 0x01, output_bits / 8)
+    }
+
+    /// SHA3 as standardized in FIPS 202, with a capacity of twice the output size.
+    pub const fn sha3(output_bits: usize) -> Self {
+        Self::new(KECCAK_WIDTH_BYTES - output_bits / 4, 0x06, output_bits / 8)
+    }
+
+    /// SHAKE128, squeezing `output_bytes`.
+    pub const fn shake128(output_bytes: usize) -> Self {
+        Self::new(168, 0x1f, output_bytes)
+    }
+
+    /// SHAKE256, squeezing `output_bytes`.
+    pub const fn shake256(output_bytes: usize) -> Self {
+        Self::new(136, 0x1f, output_bytes)
+    }
+
+    pub const fn rate_u32s(&self) -> usize {
+        self.rate_bytes / 4
+    }
+
+    pub const fn output_u32s(&self) -> usize {
+        self.output_bytes / 4
+    }
+
+    /// The number of blocks the output is squeezed in: the state after absorbing the message, and
+    /// one more permutation for each further block.
+    pub const fn output_blocks(&self) -> usize {
+        (self.output_bytes + self.rate_bytes - 1) / self.rate_bytes
+    }
+
+    /// The number of limbs exposed from each output block.
+    pub const fn output_u32s_per_block(&self) -> usize {
+        if self.output_u32s() < self.rate_u32s() {
+            self.output_u32s()
+        } else {
+            self.rate_u32s()
+        }
+    }
+
+    /// The number of limbs exposed from all output blocks, which is `output_u32s` rounded up to a
+    /// whole number of blocks.
+    pub const fn squeezed_u32s(&self) -> usize {
+        self.output_blocks() * self.output_u32s_per_block()
+    }
+
+    /// Hashes `msg` natively, as the sponge STARK does.
+    pub fn hash(&self, msg: &[u8]) -> Vec<u8> {
+        let mut output = self.squeeze(msg);
+        output.truncate(self.output_bytes);
+        output
+    }
+
+    /// Absorbs `msg` and squeezes `output_u32s_per_block` limbs from each of the `output_blocks`,
+    /// i.e. the bytes exposed by the sponge STARK, of which the digest is a prefix.
+    pub fn squeeze(&self, msg: &[u8]) -> Vec<u8> {
+        self.squeeze_from([0; KECCAK_WIDTH_BYTES], msg)
+    }
+
+    /// Like `squeeze`, for the rest `msg` of a message whose first blocks took the sponge to
+    /// `state`.
+    pub fn squeeze_from(&self, mut state: [u8; KECCAK_WIDTH_BYTES], msg: &[u8]) -> Vec<u8> {
+        let mut blocks = msg.chunks_exact(self.rate_bytes);
+        for block in blocks.by_ref() {
+            self.absorb(&mut state, block);
+        }
+        self.absorb(&mut state, &self.pad(blocks.remainder()));
+
+        let block_bytes = self.output_u32s_per_block() * 4;
+        let mut output = state[..block_bytes].to_vec();
+        for _ in 1..self.output_blocks() {
+            keccakf_u8s(&mut state);
+            output.extend_from_slice(&state[..block_bytes]);
+        }
+        output
+    }
+
+    /// Pads the last, partial block of a message with the pad10*1 rule, starting with the domain
+    /// separation bits.
+    pub fn pad(&self, final_inputs: &[u8]) -> Vec<u8> {
+        assert!(final_inputs.len() < self.rate_bytes);
+        let mut block = final_inputs.to_vec();
+        block.resize(self.rate_bytes, 0);
+        block[final_inputs.len()] = self.domain_separator;
+        block[self.rate_bytes - 1] |= 0b10000000;
+        block
+    }
+
+    fn absorb(&self, state: &mut [u8; KECCAK_WIDTH_BYTES], block: &[u8]) {
+        for (state_byte, block_byte) in state.iter_mut().zip(block) {
+            *state_byte ^= block_byte;
+        }
+        keccakf_u8s(state);
+    }
+}
+
+impl Default for KeccakSpongeVariant {
+    fn default() -> Self {
+        Self::KECCAK256
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::random;
+    use tiny_keccak::{Hasher, Keccak, Sha3, Shake};
+
+    use crate::keccak_sponge::variant::KeccakSpongeVariant;
+
+    fn reference_hash(mut hasher: impl Hasher, msg: &[u8], output_bytes: usize) -> Vec<u8> {
+        let mut output = vec![0; output_bytes];
+        hasher.update(msg);
+        hasher.finalize(&mut output);
+        output
+    }
+
+    #[test]
+    fn test_variants_match_reference() {
+        for len in [0, 1, 71, 72, 135, 136, 167, 168, 1_000] {
+            let msg: Vec<u8> = (0..len).map(|_| random()).collect();
+            let cases = [
+                (
+                    KeccakSpongeVariant::KECCAK256,
+                    reference_hash(Keccak::v256(), &msg, 32),
+                ),
+                (
+                    KeccakSpongeVariant::KECCAK512,
+                    reference_hash(Keccak::v512(), &msg, 64),
+                ),
+                (
+                    KeccakSpongeVariant::SHA3_224,
+                    reference_hash(Sha3::v224(), &msg, 28),
+                ),
+                (
+                    KeccakSpongeVariant::SHA3_256,
+                    reference_hash(Sha3::v256(), &msg, 32),
+                ),
+                (
+                    KeccakSpongeVariant::SHA3_384,
+                    reference_hash(Sha3::v384(), &msg, 48),
+                ),
+                (
+                    KeccakSpongeVariant::SHA3_512,
+                    reference_hash(Sha3::v512(), &msg, 64),
+                ),
+                (
+                    KeccakSpongeVariant::shake128(100),
+                    reference_hash(Shake::v128(), &msg, 100),
+                ),
+                (
+                    KeccakSpongeVariant::shake256(136),
+                    reference_hash(Shake::v256(), &msg, 136),
+                ),
+                (
+                    KeccakSpongeVariant::shake128(400),
+                    reference_hash(Shake::v128(), &msg, 400),
+                ),
+                (
+                    KeccakSpongeVariant::shake256(300),
+                    reference_hash(Shake::v256(), &msg, 300),
+                ),
+            ];
+            for (variant, expected) in cases {
+                assert_eq!(variant.hash(&msg), expected, "{variant:?}, {len} bytes");
+            }
+        }
+    }
+}
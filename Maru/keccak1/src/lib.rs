@@ -3,11 +3,15 @@
 #![allow(clippy::type_complexity)]
 #![feature(generic_const_exprs)]
 
+pub mod config;
 pub mod cross_table_lookup;
 pub mod keccak;
+pub mod keccak_bytes;
+pub mod keccak_ctl_proof;
 pub mod keccak_ctl_stark;
 pub mod keccak_permutation;
 pub mod keccak_sponge;
+pub mod poseidon_bn128;
 pub mod recursion;
 pub mod snark_aggregation;
 pub mod stark_aggregation;
@@ -1,5 +1,7 @@
 use std::borrow::Borrow;
+use bench_memory::{PhaseMemory, TrackingAllocator};
 use hashbrown::HashMap;
+use keccak1::config::{KeccakConfig, KeccakConfigPreset};
 use keccak1::cross_table_lookup::cross_table_lookup_data;
 use keccak1::cross_table_lookup::ctl_keccak_permutation;
 use keccak1::cross_table_lookup::CrossTableLookup;
@@ -20,35 +22,44 @@ use plonky2::hash::hash_types::RichField;
 use plonky2::plonk::config::{GenericConfig};
 use starky::permutation::get_permutation_challenge_set;
 use std::{fs::File, io::{Write}};
+use jemallocator::Jemalloc;
 
-fn bench_keccak_136_000() {
+#[global_allocator]
+static GLOBAL: TrackingAllocator<Jemalloc> = TrackingAllocator::new(Jemalloc);
+
+fn bench_keccak_136_000(preset: KeccakConfigPreset) {
     std::fs::create_dir_all("bench").unwrap();
-    let file_path = format!("bench/keccak_136_000.csv");
+    let file_path = match preset {
+        KeccakConfigPreset::Fast => "bench/keccak_136_000.csv".to_string(),
+        _ => format!("bench/keccak_136_000_{preset}.csv"),
+    };
+    let config = preset.config();
     let mut fs_results = File::create(file_path).unwrap();
     writeln!(
         fs_results,
         "{}", "message_len,"
             .to_owned() +
-            "generate_sponge_proof_time," +
-            "verify_sponge_proof_time," +
-            "generate_permutation_proof_time," +
-            "verify_permutation_proof_time," +
+            "generate_sponge_trace_time," +
+            "generate_permutation_trace_time," +
+            "generate_ctl_proof_time," +
+            "verify_ctl_proof_time," +
             "aggregation_sponge_permutations_proof_time," +
             "verify_aggregation_time," +
             "sponge_proof_size," +
             "permutations_proof_size," +
             "aggregated_proof_size," +
-            "build_sponge_circuit," +
-            "build_perm_circuit," +
-            "aggregation_circuit_build_time"
+            "aggregation_circuit_build_time," +
+            &PhaseMemory::csv_header("build") + "," +
+            &PhaseMemory::csv_header("witness") + "," +
+            &PhaseMemory::csv_header("prove")
     )
         .unwrap();
 
     for i in 1..=1000 {
-        let output = keccak_evaluate(i);
+        let output = keccak_evaluate(i, &config);
         writeln!(
             fs_results,
-            "{},{},{},{},{},{},{},{},{},{},{},{},{}",
+            "{},{},{},{},{},{},{},{},{},{},{},{},{},{}",
             output.9,
             output.0,
             output.1,
@@ -61,12 +72,19 @@ fn bench_keccak_136_000() {
             output.8,
             output.10,
             output.11,
-            output.12
+            output.12,
+            output.13
         ).unwrap();
     }
 }
 
-fn keccak_evaluate(i: usize) -> (f32, f32, f32, f32, f32, f32, usize, usize, usize, usize, f32, f32, f32) {
+fn keccak_evaluate(
+    i: usize,
+    config: &KeccakConfig,
+) -> (
+    f32, f32, f32, f32, f32, f32, usize, usize, usize, usize, f32,
+    PhaseMemory, PhaseMemory, PhaseMemory,
+) {
     const D: usize = 2;
     type C = PoseidonGoldilocksConfig;
     type F = <C as GenericConfig<D>>::F;
@@ -75,42 +93,50 @@ fn keccak_evaluate(i: usize) -> (f32, f32, f32, f32, f32, f32, usize, usize, usi
 
     let input: Vec<u8> = (0..msg_len).map(|_| rand::random()).collect();
     let expected = keccak(&input);
-    let result = keccak256::<F, C, D>(&input, expected.as_bytes()).unwrap();
+    let result = keccak256::<F, C, D>(&input, expected.as_bytes(), config).unwrap();
     let (
         _,
         _,
-        generate_sponge_proof_time,
-        verify_sponge_proof_time,
-        generate_permutation_proof_time,
-        verify_permutation_proof_time,
+        _,
+        generate_sponge_trace_time,
+        generate_permutation_trace_time,
+        generate_ctl_proof_time,
+        verify_ctl_proof_time,
         aggregation_sponge_permutations_proof_time,
         verify_aggregation_time,
         sponge_proof_size,
         permutations_proof_size,
         aggregated_proof_size,
-        sponge_circuit_time,
-        circuit_perm_time,
-        aggregation_circuit_build_time
+        aggregation_circuit_build_time,
+        build_memory,
+        witness_memory,
+        prove_memory
     ) = result;
     (
-        generate_sponge_proof_time,
-        verify_sponge_proof_time,
-        generate_permutation_proof_time,
-        verify_permutation_proof_time,
+        generate_sponge_trace_time,
+        generate_permutation_trace_time,
+        generate_ctl_proof_time,
+        verify_ctl_proof_time,
         aggregation_sponge_permutations_proof_time,
         verify_aggregation_time,
         sponge_proof_size,
         permutations_proof_size,
         aggregated_proof_size,
         msg_len,
-        sponge_circuit_time,
-        circuit_perm_time,
-        aggregation_circuit_build_time
+        aggregation_circuit_build_time,
+        build_memory,
+        witness_memory,
+        prove_memory
     )
 }
 
+// cargo run --release -- [fast|small-proof|high-security]
 fn main() {
-    bench_keccak_136_000()
+    let preset = std::env::args()
+        .nth(1)
+        .map(|preset| preset.parse().unwrap())
+        .unwrap_or(KeccakConfigPreset::Fast);
+    bench_keccak_136_000(preset)
 }
 
 pub fn get_ctl_data<F, C, const D: usize>(
@@ -4,8 +4,11 @@ use anyhow::{ensure, Result};
 use itertools::Itertools;
 use plonky2::field::extension::Extendable;
 use plonky2::field::types::Field;
+use plonky2::fri::proof::FriProofTarget;
 use plonky2::hash::hash_types::RichField;
+use plonky2::iop::challenger::RecursiveChallenger;
 use plonky2::iop::ext_target::ExtensionTarget;
+use plonky2::iop::target::Target;
 use plonky2::iop::witness::PartialWitness;
 use plonky2::plonk::circuit_builder::CircuitBuilder;
 use plonky2::plonk::circuit_data::{CircuitConfig, CircuitData};
@@ -16,15 +19,29 @@ use plonky2::with_context;
 
 use starky::config::StarkConfig;
 use starky::constraint_consumer::RecursiveConstraintConsumer;
-use starky::permutation::PermutationCheckDataTarget;
+use starky::permutation::{
+    get_n_permutation_challenge_sets_target, get_permutation_challenge_set_target,
+    PermutationCheckDataTarget,
+};
 use starky::proof::{
-    StarkOpeningSetTarget, StarkProofChallengesTarget, StarkProofWithPublicInputs,
-    StarkProofWithPublicInputsTarget,
+    StarkOpeningSetTarget, StarkProofChallengesTarget, StarkProofTarget,
+    StarkProofWithPublicInputs, StarkProofWithPublicInputsTarget,
 };
 use starky::stark::Stark;
 use starky::vanishing_poly::eval_vanishing_poly_circuit;
 use starky::vars::StarkEvaluationTargets;
 
+use crate::cross_table_lookup::{
+    ctl_extra_looking_products_circuit, eval_cross_table_lookup_checks_circuit,
+    eval_cross_table_lookup_zs_last_circuit, verify_cross_table_lookups_circuit,
+    CtlCheckVarsTarget, Table,
+};
+use crate::keccak_bytes::keccak_bytes_stark::KeccakBytesStark;
+use crate::keccak_ctl_proof::{fri_instance_target, KeccakCtlProofTarget};
+use crate::keccak_ctl_stark::KeccakCtl;
+use crate::keccak_permutation::keccak_permutation_stark::KeccakPermutationStark;
+use crate::keccak_sponge::ctl::public_values_per_hash;
+use crate::keccak_sponge::keccak_sponge_stark::KeccakSpongeStark;
 use crate::stark_aggregation::{add_virtual_stark_proof_with_pis, set_stark_proof_with_pis_target};
 
 pub fn recursive_proof<
@@ -47,7 +64,7 @@ where
     let mut builder = CircuitBuilder::<F, D>::new(circuit_config);
     let mut pw = PartialWitness::new();
     let degree_bits = inner_proof.proof.recover_degree_bits(inner_config);
-    let pt = add_virtual_stark_proof_with_pis(&mut builder, stark, inner_config, degree_bits);
+    let pt = add_virtual_stark_proof_with_pis(&mut builder, stark, inner_config, degree_bits, 0);
     set_stark_proof_with_pis_target(&mut pw, &pt, &inner_proof);
 
     verify_stark_proof_circuit::<F, InnerC, S, D>(&mut builder, stark, pt, inner_config);
@@ -89,11 +106,264 @@ pub fn verify_stark_proof_circuit<
         stark,
         proof_with_pis,
         challenges,
+        &[],
+        &[],
         inner_config,
         degree_bits,
     );
 }
 
+/// Recursively verifies a `KeccakCtlProof`, including the cross-table lookups between its tables.
+/// Mirrors `verify_keccak_ctl_proof`. The circuit is specific to the length of each public message.
+pub fn verify_keccak_ctl_proof_circuit<
+    F: RichField + Extendable<D>,
+    C: GenericConfig<D, F = F>,
+    const D: usize,
+>(
+    builder: &mut CircuitBuilder<F, D>,
+    keccak_ctl: &KeccakCtl<F, D>,
+    keccak_ctl_proof: KeccakCtlProofTarget<D>,
+    inner_config: &StarkConfig,
+) where
+    C::Hasher: AlgebraicHasher<F>,
+    [(); KeccakSpongeStark::<F, D>::COLUMNS]:,
+    [(); KeccakSpongeStark::<F, D>::PUBLIC_INPUTS]:,
+    [(); KeccakPermutationStark::<F, D>::COLUMNS]:,
+    [(); KeccakPermutationStark::<F, D>::PUBLIC_INPUTS]:,
+    [(); KeccakBytesStark::<F, D>::COLUMNS]:,
+    [(); KeccakBytesStark::<F, D>::PUBLIC_INPUTS]:,
+{
+    let KeccakCtlProofTarget {
+        stark_proofs,
+        ctl_zs_last,
+        public_values,
+        initial_states,
+        final_states,
+        messages,
+    } = keccak_ctl_proof;
+
+    let mut challenger = RecursiveChallenger::<F, C::Hasher, D>::new(builder);
+    for stark_proof in &stark_proofs {
+        challenger.observe_cap(&stark_proof.proof.trace_cap);
+    }
+    challenger.observe_elements(&public_values);
+    challenger.observe_elements(&initial_states);
+    challenger.observe_elements(&final_states);
+    for message in &messages {
+        challenger.observe_elements(message);
+    }
+    let variant = keccak_ctl.variant();
+    let public_values_per_hash = public_values_per_hash(variant);
+    if cfg!(feature = "bytes-table") {
+        assert!(initial_states.is_empty() && final_states.is_empty());
+        assert_eq!(messages.len() * public_values_per_hash, public_values.len());
+    }
+    for (message, len_and_digest) in messages
+        .iter()
+        .zip(public_values.chunks_exact(public_values_per_hash))
+    {
+        let len = builder.constant(F::from_canonical_usize(message.len()));
+        builder.connect(len_and_digest[0], len);
+        for &byte in message {
+            builder.range_check(byte, 8);
+        }
+    }
+
+    let ctl_challenges = get_permutation_challenge_set_target(
+        builder,
+        &mut challenger,
+        inner_config.num_challenges,
+    );
+    let nums_permutation_zs = keccak_ctl.nums_permutation_zs(inner_config);
+    let ctl_vars_per_table = Table::all().map(|table| {
+        CtlCheckVarsTarget::from_proof(
+            table,
+            &stark_proofs[table as usize].proof,
+            &keccak_ctl.cross_table_lookups,
+            &ctl_challenges,
+            nums_permutation_zs[table as usize],
+        )
+    });
+
+    #[cfg(not(feature = "bytes-table"))]
+    let [permutation_proof, sponge_proof] = stark_proofs;
+    #[cfg(feature = "bytes-table")]
+    let [permutation_proof, sponge_proof, bytes_proof] = stark_proofs;
+    let permutation_degree_bits = permutation_proof.proof.recover_degree_bits(inner_config);
+    let permutation_challenges = with_context!(
+        builder,
+        "compute permutation challenges",
+        get_stark_challenges_target::<F, C, _, D>(
+            builder,
+            &keccak_ctl.keccak_permutation_stark,
+            &permutation_proof.proof,
+            &ctl_zs_last[Table::KeccakPermutation as usize],
+            &mut challenger,
+            inner_config,
+        )
+    );
+    let sponge_degree_bits = sponge_proof.proof.recover_degree_bits(inner_config);
+    let sponge_challenges = with_context!(
+        builder,
+        "compute sponge challenges",
+        get_stark_challenges_target::<F, C, _, D>(
+            builder,
+            &keccak_ctl.keccak_sponge_stark,
+            &sponge_proof.proof,
+            &ctl_zs_last[Table::KeccakSponge as usize],
+            &mut challenger,
+            inner_config,
+        )
+    );
+    #[cfg(feature = "bytes-table")]
+    let bytes_degree_bits = bytes_proof.proof.recover_degree_bits(inner_config);
+    #[cfg(feature = "bytes-table")]
+    let bytes_challenges = with_context!(
+        builder,
+        "compute bytes challenges",
+        get_stark_challenges_target::<F, C, _, D>(
+            builder,
+            &keccak_ctl.keccak_bytes_stark,
+            &bytes_proof.proof,
+            &ctl_zs_last[Table::KeccakBytes as usize],
+            &mut challenger,
+            inner_config,
+        )
+    );
+
+    with_context!(
+        builder,
+        "verify permutation proof",
+        verify_stark_proof_with_challenges_circuit::<F, C, _, D>(
+            builder,
+            keccak_ctl.keccak_permutation_stark,
+            permutation_proof,
+            permutation_challenges,
+            &ctl_vars_per_table[Table::KeccakPermutation as usize],
+            &ctl_zs_last[Table::KeccakPermutation as usize],
+            inner_config,
+            permutation_degree_bits,
+        )
+    );
+    with_context!(
+        builder,
+        "verify sponge proof",
+        verify_stark_proof_with_challenges_circuit::<F, C, _, D>(
+            builder,
+            keccak_ctl.keccak_sponge_stark,
+            sponge_proof,
+            sponge_challenges,
+            &ctl_vars_per_table[Table::KeccakSponge as usize],
+            &ctl_zs_last[Table::KeccakSponge as usize],
+            inner_config,
+            sponge_degree_bits,
+        )
+    );
+    #[cfg(feature = "bytes-table")]
+    with_context!(
+        builder,
+        "verify bytes proof",
+        verify_stark_proof_with_challenges_circuit::<F, C, _, D>(
+            builder,
+            keccak_ctl.keccak_bytes_stark,
+            bytes_proof,
+            bytes_challenges,
+            &ctl_vars_per_table[Table::KeccakBytes as usize],
+            &ctl_zs_last[Table::KeccakBytes as usize],
+            inner_config,
+            bytes_degree_bits,
+        )
+    );
+
+    let ctl_extra_looking_products = ctl_extra_looking_products_circuit(
+        builder,
+        variant,
+        &public_values,
+        &initial_states,
+        &final_states,
+        &messages,
+        &ctl_challenges,
+    );
+    with_context!(
+        builder,
+        "verify cross-table lookups",
+        verify_cross_table_lookups_circuit::<F, D>(
+            builder,
+            keccak_ctl.cross_table_lookups.clone(),
+            ctl_zs_last,
+            &ctl_extra_looking_products,
+            inner_config,
+        )
+    );
+}
+
+/// Computes the challenges of one table from the shared challenger, in the order used by
+/// `prove_single_table`.
+fn get_stark_challenges_target<
+    F: RichField + Extendable<D>,
+    C: GenericConfig<D, F = F>,
+    S: Stark<F, D>,
+    const D: usize,
+>(
+    builder: &mut CircuitBuilder<F, D>,
+    stark: &S,
+    proof: &StarkProofTarget<D>,
+    ctl_zs_last: &[Target],
+    challenger: &mut RecursiveChallenger<F, C::Hasher, D>,
+    inner_config: &StarkConfig,
+) -> StarkProofChallengesTarget<D>
+where
+    C::Hasher: AlgebraicHasher<F>,
+{
+    let StarkProofTarget {
+        permutation_zs_cap,
+        quotient_polys_cap,
+        openings,
+        opening_proof:
+            FriProofTarget {
+                commit_phase_merkle_caps,
+                final_poly,
+                pow_witness,
+                ..
+            },
+        ..
+    } = proof;
+    let num_challenges = inner_config.num_challenges;
+
+    let permutation_challenge_sets = stark.uses_permutation_args().then(|| {
+        get_n_permutation_challenge_sets_target(
+            builder,
+            challenger,
+            num_challenges,
+            stark.permutation_batch_size(),
+        )
+    });
+    if let Some(cap) = permutation_zs_cap {
+        challenger.observe_cap(cap);
+    }
+    challenger.observe_elements(ctl_zs_last);
+
+    let stark_alphas = challenger.get_n_challenges(builder, num_challenges);
+
+    challenger.observe_cap(quotient_polys_cap);
+    let stark_zeta = challenger.get_extension_challenge(builder);
+
+    challenger.observe_openings(&openings.to_fri_openings());
+
+    StarkProofChallengesTarget {
+        permutation_challenge_sets,
+        stark_alphas,
+        stark_zeta,
+        fri_challenges: challenger.fri_challenges(
+            builder,
+            commit_phase_merkle_caps,
+            final_poly,
+            *pow_witness,
+            &inner_config.fri_config,
+        ),
+    }
+}
+
 /// Recursively verifies an inner proof.
 fn verify_stark_proof_with_challenges_circuit<
     F: RichField + Extendable<D>,
@@ -105,6 +375,8 @@ fn verify_stark_proof_with_challenges_circuit<
     stark: S,
     proof_with_pis: StarkProofWithPublicInputsTarget<D>,
     challenges: StarkProofChallengesTarget<D>,
+    ctl_vars: &[CtlCheckVarsTarget<F, D>],
+    ctl_zs_last: &[Target],
     inner_config: &StarkConfig,
     degree_bits: usize,
 ) where
@@ -112,7 +384,7 @@ fn verify_stark_proof_with_challenges_circuit<
     [(); S::COLUMNS]:,
     [(); S::PUBLIC_INPUTS]:,
 {
-    check_permutation_options(&stark, &proof_with_pis, &challenges).unwrap();
+    check_permutation_options(&stark, &proof_with_pis, &challenges, ctl_vars.len()).unwrap();
     let one = builder.one_extension();
 
     let StarkProofWithPublicInputsTarget {
@@ -153,25 +425,40 @@ fn verify_stark_proof_with_challenges_circuit<
         l_last,
     );
 
+    let num_permutation_zs = stark.num_permutation_batches(inner_config);
     let permutation_data = stark
         .uses_permutation_args()
         .then(|| PermutationCheckDataTarget {
-            local_zs: permutation_zs.as_ref().unwrap().clone(),
-            next_zs: permutation_zs_next.as_ref().unwrap().clone(),
+            local_zs: permutation_zs.as_ref().unwrap()[..num_permutation_zs].to_vec(),
+            next_zs: permutation_zs_next.as_ref().unwrap()[..num_permutation_zs].to_vec(),
             permutation_challenge_sets: challenges.permutation_challenge_sets.unwrap(),
         });
 
     with_context!(
         builder,
         "evaluate vanishing polynomial",
-        eval_vanishing_poly_circuit::<F, C, S, D>(
-            builder,
-            &stark,
-            inner_config,
-            vars,
-            permutation_data,
-            &mut consumer,
-        )
+        {
+            eval_vanishing_poly_circuit::<F, C, S, D>(
+                builder,
+                &stark,
+                inner_config,
+                vars,
+                permutation_data,
+                &mut consumer,
+            );
+            eval_cross_table_lookup_checks_circuit::<S, F, D>(
+                builder,
+                vars,
+                ctl_vars,
+                &mut consumer,
+            );
+            eval_cross_table_lookup_zs_last_circuit::<F, D>(
+                builder,
+                ctl_vars,
+                ctl_zs_last,
+                &mut consumer,
+            );
+        }
     );
     let vanishing_polys_zeta = consumer.accumulators();
 
@@ -191,10 +478,12 @@ fn verify_stark_proof_with_challenges_circuit<
         .chain(once(proof.quotient_polys_cap))
         .collect_vec();
 
-    let fri_instance = stark.fri_instance_target(
+    let fri_instance = fri_instance_target(
         builder,
+        &stark,
         challenges.stark_zeta,
         F::primitive_root_of_unity(degree_bits),
+        ctl_vars.len(),
         inner_config,
     );
     builder.verify_fri_proof::<C>(
@@ -227,22 +516,23 @@ fn eval_l_0_and_l_last_circuit<F: RichField + Extendable<D>, const D: usize>(
 }
 
 /// Utility function to check that all permutation data wrapped in `Option`s are `Some` iff
-/// the Stark uses a permutation argument.
+/// the Stark uses a permutation argument or has CTL `Z`s, and that permutation challenges are
+/// present iff the Stark uses a permutation argument.
 fn check_permutation_options<F: RichField + Extendable<D>, S: Stark<F, D>, const D: usize>(
     stark: &S,
     proof_with_pis: &StarkProofWithPublicInputsTarget<D>,
     challenges: &StarkProofChallengesTarget<D>,
+    num_ctl_zs: usize,
 ) -> Result<()> {
+    let uses_zs = stark.uses_permutation_args() || num_ctl_zs > 0;
     let options_is_some = [
         proof_with_pis.proof.permutation_zs_cap.is_some(),
         proof_with_pis.proof.openings.permutation_zs.is_some(),
         proof_with_pis.proof.openings.permutation_zs_next.is_some(),
-        challenges.permutation_challenge_sets.is_some(),
     ];
     ensure!(
-        options_is_some
-            .into_iter()
-            .all(|b| b == stark.uses_permutation_args()),
+        options_is_some.into_iter().all(|b| b == uses_zs)
+            && challenges.permutation_challenge_sets.is_some() == stark.uses_permutation_args(),
         "Permutation data doesn't match with Stark configuration."
     );
     Ok(())
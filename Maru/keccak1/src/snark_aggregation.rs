@@ -1,15 +1,27 @@
-use anyhow::Result;
+use std::iter::once;
+
+use anyhow::{ensure, Result};
+use itertools::Itertools;
 use log::Level;
+use plonky2::field::goldilocks_field::GoldilocksField;
+use plonky2::gates::noop::NoopGate;
 use plonky2::hash::hash_types::RichField;
+use plonky2::iop::target::Target;
 use plonky2::iop::witness::{PartialWitness, WitnessWrite};
 use plonky2::plonk::circuit_builder::CircuitBuilder;
-use plonky2::plonk::circuit_data::VerifierCircuitTarget;
-use plonky2::plonk::circuit_data::{CircuitConfig, CircuitData};
-use plonky2::plonk::config::{AlgebraicHasher, GenericConfig};
-use plonky2::plonk::proof::ProofWithPublicInputs;
+use plonky2::plonk::circuit_data::{
+    CircuitConfig, CircuitData, CommonCircuitData, VerifierOnlyCircuitData,
+};
+use plonky2::plonk::config::{AlgebraicHasher, GenericConfig, GenericHashOut, Hasher};
+use plonky2::plonk::proof::{ProofWithPublicInputs, ProofWithPublicInputsTarget};
 use plonky2::util::timing::TimingTree;
 use plonky2_field::extension::Extendable;
 
+use crate::poseidon_bn128::PoseidonBN128GoldilocksConfig;
+
+/// The degree of the circuit of `aggregation_wrap`, so that its proofs all have the same size.
+pub const WRAPPER_DEGREE_BITS: usize = 13;
+
 pub fn verification<F: RichField + Extendable<D>, C: GenericConfig<D, F = F>, const D: usize>(
     (data, proof): (&CircuitData<F, C, D>, &ProofWithPublicInputs<F, C, D>),
 ) -> Result<()> {
@@ -20,61 +32,398 @@ pub fn verification<F: RichField + Extendable<D>, C: GenericConfig<D, F = F>, co
 }
 
 pub fn aggregation_two<F: RichField + Extendable<D>, C: GenericConfig<D, F = F>, const D: usize>(
+    data_proof_1: (&CircuitData<F, C, D>, &ProofWithPublicInputs<F, C, D>),
+    data_proof_2: Option<(&CircuitData<F, C, D>, &ProofWithPublicInputs<F, C, D>)>,
+) -> Result<(CircuitData<F, C, D>, ProofWithPublicInputs<F, C, D>)>
+where
+    C::Hasher: AlgebraicHasher<F>,
+{
+    aggregation_two_with_public_inputs(data_proof_1, data_proof_2, |_, _, _| {})
+}
+
+/// Like `aggregation_two`, but `public_inputs` is given the public inputs of both inner proofs, or
+/// none for a missing second proof, to constrain them and register the public inputs of the
+/// aggregated proof.
+pub fn aggregation_two_with_public_inputs<
+    F: RichField + Extendable<D>,
+    C: GenericConfig<D, F = F>,
+    const D: usize,
+>(
     (data1, proof1): (&CircuitData<F, C, D>, &ProofWithPublicInputs<F, C, D>),
     data_proof_2: Option<(&CircuitData<F, C, D>, &ProofWithPublicInputs<F, C, D>)>,
+    public_inputs: impl FnOnce(&mut CircuitBuilder<F, D>, &[Target], &[Target]),
 ) -> Result<(CircuitData<F, C, D>, ProofWithPublicInputs<F, C, D>)>
 where
     C::Hasher: AlgebraicHasher<F>,
 {
-    verification((data1, proof1))?;
     let mut builder = CircuitBuilder::<F, D>::new(CircuitConfig::standard_recursion_config());
-    let proof_with_pis_target_1 = builder.add_virtual_proof_with_pis(&data1.common);
-    let verifier_circuit_target_1 = VerifierCircuitTarget {
-        constants_sigmas_cap: builder.add_virtual_cap(data1.common.config.fri_config.cap_height),
-        circuit_digest: builder.add_virtual_hash(),
-    };
     let mut pw = PartialWitness::new();
-    pw.set_proof_with_pis_target(&proof_with_pis_target_1, proof1);
-    pw.set_cap_target(
-        &verifier_circuit_target_1.constants_sigmas_cap,
-        &data1.verifier_only.constants_sigmas_cap,
-    );
-    pw.set_hash_target(
-        verifier_circuit_target_1.circuit_digest,
-        data1.verifier_only.circuit_digest,
-    );
-    builder.verify_proof::<C>(
-        &proof_with_pis_target_1,
-        &verifier_circuit_target_1,
-        &data1.common,
-    );
-    if data_proof_2.is_some() {
-        verification((data_proof_2.unwrap().0, data_proof_2.unwrap().1))?;
-        let proof_with_pis_target_2 =
-            builder.add_virtual_proof_with_pis(&data_proof_2.unwrap().0.common);
-        let verifier_circuit_target_2 = VerifierCircuitTarget {
-            constants_sigmas_cap: builder
-                .add_virtual_cap(data_proof_2.unwrap().0.common.config.fri_config.cap_height),
-            circuit_digest: builder.add_virtual_hash(),
-        };
-        pw.set_proof_with_pis_target(&proof_with_pis_target_2, data_proof_2.unwrap().1);
-        pw.set_cap_target(
-            &verifier_circuit_target_2.constants_sigmas_cap,
-            &data_proof_2.unwrap().0.verifier_only.constants_sigmas_cap,
-        );
-        pw.set_hash_target(
-            verifier_circuit_target_2.circuit_digest,
-            data_proof_2.unwrap().0.verifier_only.circuit_digest,
-        );
-        builder.verify_proof::<C>(
-            &proof_with_pis_target_2,
-            &verifier_circuit_target_2,
-            &data_proof_2.unwrap().0.common,
-        );
+    let mut public_inputs_targets = vec![];
+    for (data, proof) in once((data1, proof1)).chain(data_proof_2) {
+        verification((data, proof))?;
+        let proof_with_pis_target =
+            add_verified_proof::<F, C, D>(&mut builder, &data.common, &data.verifier_only);
+        pw.set_proof_with_pis_target(&proof_with_pis_target, proof);
+        public_inputs_targets.push(proof_with_pis_target.public_inputs);
     }
+    public_inputs_targets.resize(2, vec![]);
+    public_inputs(
+        &mut builder,
+        &public_inputs_targets[0],
+        &public_inputs_targets[1],
+    );
     let data_new = builder.build::<C>();
     let timing = TimingTree::new("prove", Level::Debug);
     let proof_new = data_new.prove(pw)?;
     timing.print();
     Ok((data_new, proof_new))
 }
+
+/// Adds a proof of the circuit with the given common and verifier data to `builder` and verifies
+/// it. The verifier data is a constant of the circuit, so that only proofs of that circuit pass.
+fn add_verified_proof<F: RichField + Extendable<D>, C: GenericConfig<D, F = F>, const D: usize>(
+    builder: &mut CircuitBuilder<F, D>,
+    common: &CommonCircuitData<F, D>,
+    verifier_only: &VerifierOnlyCircuitData<C, D>,
+) -> ProofWithPublicInputsTarget<D>
+where
+    C::Hasher: AlgebraicHasher<F>,
+{
+    let proof_with_pis_target = builder.add_virtual_proof_with_pis(common);
+    let verifier_circuit_target = builder.constant_verifier_data(verifier_only);
+    builder.verify_proof::<C>(&proof_with_pis_target, &verifier_circuit_target, common);
+    proof_with_pis_target
+}
+
+/// How an aggregated proof exposes the public inputs of the proofs it verifies.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum PublicInputsMode {
+    /// All of them, in order.
+    #[default]
+    Concatenate,
+    /// The hash of all of them, in order, so that the number of public inputs is constant.
+    Hash,
+}
+
+impl PublicInputsMode {
+    /// The public inputs of a proof verifying proofs with the concatenated `public_inputs`.
+    pub fn apply<F: RichField, H: Hasher<F>>(&self, public_inputs: Vec<F>) -> Vec<F> {
+        match self {
+            Self::Concatenate => public_inputs,
+            Self::Hash => H::hash_no_pad(&public_inputs).to_vec(),
+        }
+    }
+
+    fn register<F: RichField + Extendable<D>, H: AlgebraicHasher<F>, const D: usize>(
+        &self,
+        builder: &mut CircuitBuilder<F, D>,
+        public_inputs: Vec<Target>,
+    ) {
+        match self {
+            Self::Concatenate => builder.register_public_inputs(&public_inputs),
+            Self::Hash => {
+                let hash = builder.hash_n_to_hash_no_pad::<H>(public_inputs);
+                builder.register_public_inputs(&hash.elements);
+            }
+        }
+    }
+}
+
+/// A node of the tree of `aggregation_tree`: a proof, and the data needed to verify it.
+struct AggregationNode<F: RichField + Extendable<D>, C: GenericConfig<D, F = F>, const D: usize> {
+    common: CommonCircuitData<F, D>,
+    verifier_only: VerifierOnlyCircuitData<C, D>,
+    proof: ProofWithPublicInputs<F, C, D>,
+}
+
+/// A circuit verifying the proofs of the `children` circuits, reused for every node of the tree
+/// with the same children circuits.
+struct AggregationCircuit<F: RichField + Extendable<D>, C: GenericConfig<D, F = F>, const D: usize>
+{
+    children: Vec<(CommonCircuitData<F, D>, VerifierOnlyCircuitData<C, D>)>,
+    data: CircuitData<F, C, D>,
+    proof_targets: Vec<ProofWithPublicInputsTarget<D>>,
+}
+
+impl<F: RichField + Extendable<D>, C: GenericConfig<D, F = F>, const D: usize>
+    AggregationCircuit<F, C, D>
+where
+    C::Hasher: AlgebraicHasher<F>,
+{
+    fn new(children: &[AggregationNode<F, C, D>], public_inputs_mode: PublicInputsMode) -> Self {
+        let mut builder = CircuitBuilder::<F, D>::new(CircuitConfig::standard_recursion_config());
+        let proof_targets = children
+            .iter()
+            .map(|child| {
+                add_verified_proof::<F, C, D>(&mut builder, &child.common, &child.verifier_only)
+            })
+            .collect_vec();
+        let public_inputs = proof_targets
+            .iter()
+            .flat_map(|target| target.public_inputs.iter().copied())
+            .collect();
+        public_inputs_mode.register::<F, C::Hasher, D>(&mut builder, public_inputs);
+        Self {
+            children: children
+                .iter()
+                .map(|child| (child.common.clone(), child.verifier_only.clone()))
+                .collect(),
+            data: builder.build::<C>(),
+            proof_targets,
+        }
+    }
+
+    fn verifies(&self, children: &[AggregationNode<F, C, D>]) -> bool {
+        self.children.len() == children.len()
+            && self
+                .children
+                .iter()
+                .zip(children)
+                .all(|((common, verifier_only), child)| {
+                    *common == child.common && *verifier_only == child.verifier_only
+                })
+    }
+
+    fn prove(&self, children: &[AggregationNode<F, C, D>]) -> Result<AggregationNode<F, C, D>> {
+        let mut pw = PartialWitness::new();
+        for (target, child) in self.proof_targets.iter().zip_eq(children) {
+            pw.set_proof_with_pis_target(target, &child.proof);
+        }
+        let timing = TimingTree::new("prove aggregation node", Level::Debug);
+        let proof = self.data.prove(pw)?;
+        timing.print();
+        Ok(AggregationNode {
+            common: self.data.common.clone(),
+            verifier_only: self.data.verifier_only.clone(),
+            proof,
+        })
+    }
+}
+
+/// Aggregates `proofs` in a tree of recursive proofs: each level verifies up to `arity` proofs of
+/// the level below per node, until a single proof is left, which is then wrapped by
+/// `aggregation_wrap`. A circuit is built once for each distinct list of verified circuits, and
+/// reused for all the nodes of any level that verify the same circuits, e.g. all the full nodes of
+/// a level over proofs of one circuit. The public inputs of each node follow `public_inputs_mode`,
+/// see `aggregation_tree_public_inputs`.
+pub fn aggregation_tree<F: RichField + Extendable<D>, C: GenericConfig<D, F = F>, const D: usize>(
+    proofs: Vec<(CircuitData<F, C, D>, ProofWithPublicInputs<F, C, D>)>,
+    arity: usize,
+    public_inputs_mode: PublicInputsMode,
+) -> Result<(CircuitData<F, C, D>, ProofWithPublicInputs<F, C, D>)>
+where
+    C::Hasher: AlgebraicHasher<F>,
+{
+    ensure!(
+        arity >= 2,
+        "The arity of an aggregation tree must be at least 2"
+    );
+    ensure!(!proofs.is_empty(), "Nothing to aggregate");
+    let mut level = proofs
+        .into_iter()
+        .map(|(data, proof)| {
+            verification((&data, &proof))?;
+            Ok(AggregationNode {
+                common: data.common,
+                verifier_only: data.verifier_only,
+                proof,
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let mut circuits: Vec<AggregationCircuit<F, C, D>> = vec![];
+    loop {
+        level = level
+            .chunks(arity)
+            .map(|children| {
+                let circuit = match circuits.iter().position(|c| c.verifies(children)) {
+                    Some(i) => &circuits[i],
+                    None => {
+                        circuits.push(AggregationCircuit::new(children, public_inputs_mode));
+                        circuits.last().unwrap()
+                    }
+                };
+                circuit.prove(children)
+            })
+            .collect::<Result<Vec<_>>>()?;
+        if level.len() == 1 {
+            break;
+        }
+    }
+
+    let root = level.pop().unwrap();
+    let root_data = circuits
+        .iter()
+        .find(|circuit| circuit.data.verifier_only == root.verifier_only)
+        .map(|circuit| &circuit.data)
+        .unwrap();
+    aggregation_wrap((root_data, &root.proof))
+}
+
+/// The public inputs of the proof of `aggregation_tree` over proofs with the given public inputs,
+/// for the verifier to compare with the public inputs of the aggregated proof.
+pub fn aggregation_tree_public_inputs<F: RichField, H: Hasher<F>>(
+    public_inputs: &[Vec<F>],
+    arity: usize,
+    public_inputs_mode: PublicInputsMode,
+) -> Vec<F> {
+    assert!(arity >= 2 && !public_inputs.is_empty());
+    let mut level = public_inputs.to_vec();
+    loop {
+        level = level
+            .chunks(arity)
+            .map(|children| public_inputs_mode.apply::<F, H>(children.concat()))
+            .collect();
+        if level.len() == 1 {
+            return level.pop().unwrap();
+        }
+    }
+}
+
+/// Verifies `proof` in a circuit padded to `WRAPPER_DEGREE_BITS`, with the same public inputs, so
+/// that the wrapped proof has the same size whatever the circuit of `proof`. Fails if `proof`
+/// cannot be verified in a circuit of that size.
+pub fn aggregation_wrap<F: RichField + Extendable<D>, C: GenericConfig<D, F = F>, const D: usize>(
+    data_proof: (&CircuitData<F, C, D>, &ProofWithPublicInputs<F, C, D>),
+) -> Result<(CircuitData<F, C, D>, ProofWithPublicInputs<F, C, D>)>
+where
+    C::Hasher: AlgebraicHasher<F>,
+{
+    aggregation_wrap_with_config::<F, C, C, D>(data_proof)
+}
+
+/// Like `aggregation_wrap`, but proves the wrapper with `PoseidonBN128GoldilocksConfig`, so that
+/// the wrapped proof can be verified in a circuit over BN254 with `plonky2-halo2`.
+pub fn aggregation_wrap_bn128<C: GenericConfig<2, F = GoldilocksField>>(
+    data_proof: (
+        &CircuitData<GoldilocksField, C, 2>,
+        &ProofWithPublicInputs<GoldilocksField, C, 2>,
+    ),
+) -> Result<(
+    CircuitData<GoldilocksField, PoseidonBN128GoldilocksConfig, 2>,
+    ProofWithPublicInputs<GoldilocksField, PoseidonBN128GoldilocksConfig, 2>,
+)>
+where
+    C::Hasher: AlgebraicHasher<GoldilocksField>,
+{
+    aggregation_wrap_with_config::<GoldilocksField, C, PoseidonBN128GoldilocksConfig, 2>(data_proof)
+}
+
+fn aggregation_wrap_with_config<
+    F: RichField + Extendable<D>,
+    C: GenericConfig<D, F = F>,
+    CW: GenericConfig<D, F = F>,
+    const D: usize,
+>(
+    (data, proof): (&CircuitData<F, C, D>, &ProofWithPublicInputs<F, C, D>),
+) -> Result<(CircuitData<F, CW, D>, ProofWithPublicInputs<F, CW, D>)>
+where
+    C::Hasher: AlgebraicHasher<F>,
+{
+    let mut builder = CircuitBuilder::<F, D>::new(CircuitConfig::standard_recursion_config());
+    let proof_with_pis_target =
+        add_verified_proof::<F, C, D>(&mut builder, &data.common, &data.verifier_only);
+    builder.register_public_inputs(&proof_with_pis_target.public_inputs);
+    // Building adds at least the gates hashing the public inputs, hence the next power of 2.
+    while builder.num_gates() < 1 << (WRAPPER_DEGREE_BITS - 1) {
+        builder.add_gate(NoopGate, vec![]);
+    }
+    let data_new = builder.build::<CW>();
+    ensure!(
+        data_new.common.degree_bits() == WRAPPER_DEGREE_BITS,
+        "The proof is too large to be wrapped in a circuit of degree {}",
+        WRAPPER_DEGREE_BITS
+    );
+    let mut pw = PartialWitness::new();
+    pw.set_proof_with_pis_target(&proof_with_pis_target, proof);
+    let timing = TimingTree::new("prove wrapper", Level::Debug);
+    let proof_new = data_new.prove(pw)?;
+    timing.print();
+    Ok((data_new, proof_new))
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use itertools::Itertools;
+    use plonky2::field::types::Field;
+    use plonky2::hash::poseidon::PoseidonHash;
+    use plonky2::iop::witness::{PartialWitness, WitnessWrite};
+    use plonky2::plonk::circuit_builder::CircuitBuilder;
+    use plonky2::plonk::circuit_data::{CircuitConfig, CircuitData};
+    use plonky2::plonk::config::{GenericConfig, Hasher, PoseidonGoldilocksConfig};
+    use plonky2::plonk::proof::ProofWithPublicInputs;
+
+    use crate::snark_aggregation::{
+        aggregation_tree, aggregation_tree_public_inputs, PublicInputsMode, WRAPPER_DEGREE_BITS,
+    };
+
+    const D: usize = 2;
+    type C = PoseidonGoldilocksConfig;
+    type F = <C as GenericConfig<D>>::F;
+
+    /// A proof that `x * x = y`, with `x` and `y` public.
+    fn square_proof(x: u64) -> Result<(CircuitData<F, C, D>, ProofWithPublicInputs<F, C, D>)> {
+        let mut builder = CircuitBuilder::<F, D>::new(CircuitConfig::standard_recursion_config());
+        let x_target = builder.add_virtual_target();
+        let y_target = builder.square(x_target);
+        builder.register_public_input(x_target);
+        builder.register_public_input(y_target);
+        let data = builder.build::<C>();
+        let mut pw = PartialWitness::new();
+        pw.set_target(x_target, F::from_canonical_u64(x));
+        let proof = data.prove(pw)?;
+        Ok((data, proof))
+    }
+
+    #[test]
+    fn test_aggregation_tree_public_inputs() {
+        let leaves = (0..5u64)
+            .map(|i| vec![F::from_canonical_u64(i)])
+            .collect_vec();
+        assert_eq!(
+            aggregation_tree_public_inputs::<F, PoseidonHash>(
+                &leaves,
+                2,
+                PublicInputsMode::Concatenate
+            ),
+            leaves.concat()
+        );
+
+        // Levels of 3, 2 and 1 nodes.
+        let hash = |inputs: Vec<F>| PoseidonHash::hash_no_pad(&inputs).elements.to_vec();
+        let level_1 = [
+            hash(leaves[..2].concat()),
+            hash(leaves[2..4].concat()),
+            hash(leaves[4].clone()),
+        ];
+        let level_2 = [hash(level_1[..2].concat()), hash(level_1[2].clone())];
+        assert_eq!(
+            aggregation_tree_public_inputs::<F, PoseidonHash>(&leaves, 2, PublicInputsMode::Hash),
+            hash(level_2.concat())
+        );
+    }
+
+    #[test]
+    #[ignore]
+    fn test_aggregation_tree() -> Result<()> {
+        for (arity, mode) in [
+            (2, PublicInputsMode::Hash),
+            (4, PublicInputsMode::Concatenate),
+        ] {
+            let proofs = (0..5).map(square_proof).collect::<Result<Vec<_>>>()?;
+            let public_inputs = proofs
+                .iter()
+                .map(|(_, proof)| proof.public_inputs.clone())
+                .collect_vec();
+            let (data, proof) = aggregation_tree(proofs, arity, mode)?;
+            data.verify(proof.clone())?;
+            assert_eq!(data.common.degree_bits(), WRAPPER_DEGREE_BITS);
+            assert_eq!(
+                proof.public_inputs,
+                aggregation_tree_public_inputs::<F, PoseidonHash>(&public_inputs, arity, mode)
+            );
+        }
+        Ok(())
+    }
+}
@@ -2,6 +2,7 @@ use core::iter::once;
 use std::time::Instant;
 
 use anyhow::Result;
+use bench_memory::{PhaseMemory, PhaseTracker};
 use itertools::Itertools;
 use log::Level;
 use plonky2::field::extension::Extendable;
@@ -9,12 +10,11 @@ use plonky2::fri::witness_util::set_fri_proof_target;
 use plonky2::hash::hash_types::RichField;
 use plonky2::iop::witness::{PartialWitness, Witness};
 use plonky2::plonk::circuit_builder::CircuitBuilder;
-use plonky2::plonk::circuit_data::{CircuitConfig, CircuitData};
-use plonky2::plonk::config::{AlgebraicHasher, GenericConfig, PoseidonGoldilocksConfig};
+use plonky2::plonk::circuit_data::CircuitData;
+use plonky2::plonk::config::{AlgebraicHasher, GenericConfig};
 use plonky2::plonk::proof::ProofWithPublicInputs;
 use plonky2::util::timing::TimingTree;
 
-use plonky2_field::goldilocks_field::GoldilocksField;
 use starky::config::StarkConfig;
 use starky::proof::{
     StarkOpeningSetTarget, StarkProof, StarkProofTarget, StarkProofWithPublicInputs,
@@ -22,60 +22,251 @@ use starky::proof::{
 };
 use starky::stark::Stark;
 
-use crate::keccak_permutation::keccak_permutation_proof::{
-    keccak256_permutation_proof, keccak256_permutation_verify,
-};
-use crate::keccak_permutation::keccak_permutation_stark::{KeccakPermutationStark, NUM_INPUTS};
-use crate::keccak_sponge::keccak_sponge_proof::{keccak256_sponge_proof, keccak256_sponge_verify};
+use crate::config::KeccakConfig;
+use crate::cross_table_lookup::{Table, NUM_TABLES};
+use crate::keccak_bytes::keccak_bytes_stark::KeccakBytesStark;
+use crate::keccak_ctl_proof::{KeccakCtlProof, KeccakCtlProofTarget};
+use crate::keccak_ctl_stark::KeccakCtl;
+use crate::keccak_permutation::keccak_permutation_stark::KeccakPermutationStark;
 use crate::keccak_sponge::keccak_sponge_stark::KeccakSpongeStark;
+use crate::keccak_sponge::variant::KeccakSpongeVariant;
+use crate::recursion::verify_keccak_ctl_proof_circuit;
 
+/// Aggregates a proof of `keccak_sponge_batch_ctl_proof` for the sponge of `variant`.
 pub fn aggregation_sponge_permutation<F, C, const D: usize>(
-    sponge_proof: StarkProofWithPublicInputs<F, C, D>,
-    permutation_proof: StarkProofWithPublicInputs<F, C, D>,
-) -> Result<(CircuitData<F, C, D>, ProofWithPublicInputs<F, C, D>, f32, f32)>
+    variant: KeccakSpongeVariant,
+    keccak_ctl_proof: &KeccakCtlProof<F, C, D>,
+    config: &KeccakConfig,
+) -> Result<(
+    CircuitData<F, C, D>,
+    ProofWithPublicInputs<F, C, D>,
+    f32,
+    f32,
+    PhaseMemory,
+    PhaseMemory,
+)>
 where
     F: RichField + Extendable<D>,
     C: GenericConfig<D, F = F>,
     C::Hasher: AlgebraicHasher<F>,
+    [(); KeccakSpongeStark::<F, D>::COLUMNS]:,
+    [(); KeccakSpongeStark::<F, D>::PUBLIC_INPUTS]:,
+    [(); KeccakPermutationStark::<F, D>::COLUMNS]:,
+    [(); KeccakPermutationStark::<F, D>::PUBLIC_INPUTS]:,
+    [(); KeccakBytesStark::<F, D>::COLUMNS]:,
+    [(); KeccakBytesStark::<F, D>::PUBLIC_INPUTS]:,
 {
+    let build_memory = PhaseTracker::start();
     let aggregation_circuit_build_init = Instant::now();
-    let config = StarkConfig::standard_fast_config();
-    let mut builder = CircuitBuilder::<F, D>::new(CircuitConfig::standard_recursion_config());
-
-    let degree_bits = sponge_proof.proof.recover_degree_bits(&config);
-    let stark_proof_with_pis_target = add_virtual_stark_proof_with_pis(
-        &mut builder,
-        KeccakSpongeStark::<F, D>::default(),
-        &config,
-        degree_bits,
-    );
-
-    let degree_bits = permutation_proof.proof.recover_degree_bits(&config);
-    let stark_proof_target = add_virtual_stark_proof(
-        &mut builder,
-        KeccakPermutationStark::<F, D>::default(),
-        &config,
-        degree_bits,
-    );
-
     let mut pw = PartialWitness::new();
-    set_stark_proof_with_pis_target(&mut pw, &stark_proof_with_pis_target, &sponge_proof);
-    // set PI (hash) from sponge stark
-    builder.register_public_inputs(&stark_proof_with_pis_target.public_inputs);
-    set_stark_proof_target(&mut pw, &stark_proof_target, &permutation_proof.proof);
-    let data = builder.build::<C>();
+    let data = aggregation_circuit(variant, keccak_ctl_proof, config, &mut pw);
     let aggregation_circuit_build_time = aggregation_circuit_build_init
         .elapsed()
         .as_secs_f32();
+    let build_memory = build_memory.finish();
     let timing = TimingTree::new("prove aggregation: sponge & permutation", Level::Debug);
+    let prove_memory = PhaseTracker::start();
     let aggregation_sponge_permutations_proof = Instant::now();
     let proof = data.prove(pw)?;
     let aggregation_sponge_permutations_proof_time = aggregation_sponge_permutations_proof
         .elapsed()
         .as_secs_f32();
+    let prove_memory = prove_memory.finish();
     timing.print();
-    assert_eq!(sponge_proof.public_inputs, proof.public_inputs);
-    Ok((data, proof, aggregation_circuit_build_time, aggregation_sponge_permutations_proof_time))
+    assert_eq!(
+        keccak_ctl_proof
+            .public_values
+            .iter()
+            .chain(&keccak_ctl_proof.initial_states)
+            .chain(&keccak_ctl_proof.final_states)
+            .chain(keccak_ctl_proof.messages.iter().flatten())
+            .copied()
+            .collect_vec(),
+        proof.public_inputs
+    );
+    Ok((
+        data,
+        proof,
+        aggregation_circuit_build_time,
+        aggregation_sponge_permutations_proof_time,
+        build_memory,
+        prove_memory,
+    ))
+}
+
+/// Builds the circuit aggregating proofs of `keccak_sponge_batch_ctl_proof` with the shape of
+/// `keccak_ctl_proof`, the degrees of its STARK proofs and its numbers of public values, and sets
+/// `keccak_ctl_proof` in `pw`. As the circuit only depends on this shape, it can be rebuilt from a
+/// saved CTL proof to verify a saved aggregated proof.
+pub fn aggregation_circuit<F, C, const D: usize>(
+    variant: KeccakSpongeVariant,
+    keccak_ctl_proof: &KeccakCtlProof<F, C, D>,
+    config: &KeccakConfig,
+    pw: &mut PartialWitness<F>,
+) -> CircuitData<F, C, D>
+where
+    F: RichField + Extendable<D>,
+    C: GenericConfig<D, F = F>,
+    C::Hasher: AlgebraicHasher<F>,
+    [(); KeccakSpongeStark::<F, D>::COLUMNS]:,
+    [(); KeccakSpongeStark::<F, D>::PUBLIC_INPUTS]:,
+    [(); KeccakPermutationStark::<F, D>::COLUMNS]:,
+    [(); KeccakPermutationStark::<F, D>::PUBLIC_INPUTS]:,
+    [(); KeccakBytesStark::<F, D>::COLUMNS]:,
+    [(); KeccakBytesStark::<F, D>::PUBLIC_INPUTS]:,
+{
+    let KeccakConfig {
+        stark_config: config,
+        aggregation_config,
+    } = config;
+    let keccak_ctl = KeccakCtl::<F, D>::new(variant);
+    let mut builder = CircuitBuilder::<F, D>::new(aggregation_config.clone());
+
+    let degree_bits = Table::all().map(|table| {
+        keccak_ctl_proof.stark_proofs[table as usize]
+            .proof
+            .recover_degree_bits(config)
+    });
+    let num_public_values = keccak_ctl_proof.public_values.len();
+    let num_initial_states = keccak_ctl_proof.initial_states.len();
+    let num_final_states = keccak_ctl_proof.final_states.len();
+    let message_lens = keccak_ctl_proof.messages.iter().map(Vec::len).collect_vec();
+    let keccak_ctl_proof_target = add_virtual_keccak_ctl_proof(
+        &mut builder,
+        &keccak_ctl,
+        config,
+        degree_bits,
+        num_public_values,
+        num_initial_states,
+        num_final_states,
+        &message_lens,
+    );
+
+    set_keccak_ctl_proof_target(pw, &keccak_ctl_proof_target, keccak_ctl_proof);
+    // set PI (length and hash of each message, the states of the resumed and suspended sponges,
+    // then the messages if public) from the CTL proof
+    builder.register_public_inputs(&keccak_ctl_proof_target.public_values);
+    builder.register_public_inputs(&keccak_ctl_proof_target.initial_states);
+    builder.register_public_inputs(&keccak_ctl_proof_target.final_states);
+    for message in &keccak_ctl_proof_target.messages {
+        builder.register_public_inputs(message);
+    }
+    verify_keccak_ctl_proof_circuit::<F, C, D>(
+        &mut builder,
+        &keccak_ctl,
+        keccak_ctl_proof_target,
+        config,
+    );
+    builder.build::<C>()
+}
+
+pub fn add_virtual_keccak_ctl_proof<F: RichField + Extendable<D>, const D: usize>(
+    builder: &mut CircuitBuilder<F, D>,
+    keccak_ctl: &KeccakCtl<F, D>,
+    config: &StarkConfig,
+    degree_bits: [usize; NUM_TABLES],
+    num_public_values: usize,
+    num_initial_states: usize,
+    num_final_states: usize,
+    message_lens: &[usize],
+) -> KeccakCtlProofTarget<D> {
+    let nums_ctl_zs = keccak_ctl.nums_ctl_zs(config);
+    let stark_proofs = [
+        add_virtual_stark_proof_with_pis(
+            builder,
+            keccak_ctl.keccak_permutation_stark,
+            config,
+            degree_bits[Table::KeccakPermutation as usize],
+            nums_ctl_zs[Table::KeccakPermutation as usize],
+        ),
+        add_virtual_stark_proof_with_pis(
+            builder,
+            keccak_ctl.keccak_sponge_stark,
+            config,
+            degree_bits[Table::KeccakSponge as usize],
+            nums_ctl_zs[Table::KeccakSponge as usize],
+        ),
+        #[cfg(feature = "bytes-table")]
+        add_virtual_stark_proof_with_pis(
+            builder,
+            keccak_ctl.keccak_bytes_stark,
+            config,
+            degree_bits[Table::KeccakBytes as usize],
+            nums_ctl_zs[Table::KeccakBytes as usize],
+        ),
+    ];
+    let ctl_zs_last = nums_ctl_zs.map(|num_ctl_zs| builder.add_virtual_targets(num_ctl_zs));
+    let public_values = builder.add_virtual_targets(num_public_values);
+    let initial_states = builder.add_virtual_targets(num_initial_states);
+    let final_states = builder.add_virtual_targets(num_final_states);
+    let messages = message_lens
+        .iter()
+        .map(|&len| builder.add_virtual_targets(len))
+        .collect();
+    KeccakCtlProofTarget {
+        stark_proofs,
+        ctl_zs_last,
+        public_values,
+        initial_states,
+        final_states,
+        messages,
+    }
+}
+
+pub fn set_keccak_ctl_proof_target<F, C: GenericConfig<D, F = F>, W, const D: usize>(
+    witness: &mut W,
+    keccak_ctl_proof_target: &KeccakCtlProofTarget<D>,
+    keccak_ctl_proof: &KeccakCtlProof<F, C, D>,
+) where
+    F: RichField + Extendable<D>,
+    C::Hasher: AlgebraicHasher<F>,
+    W: Witness<F>,
+{
+    for (proof_target, proof) in keccak_ctl_proof_target
+        .stark_proofs
+        .iter()
+        .zip(&keccak_ctl_proof.stark_proofs)
+    {
+        set_stark_proof_with_pis_target(witness, proof_target, proof);
+    }
+    for (zs_last_targets, zs_last) in keccak_ctl_proof_target
+        .ctl_zs_last
+        .iter()
+        .zip(&keccak_ctl_proof.ctl_zs_last)
+    {
+        for (&z_last_t, &z_last) in zs_last_targets.iter().zip_eq(zs_last) {
+            witness.set_target(z_last_t, z_last);
+        }
+    }
+    for (&pv_t, &pv) in keccak_ctl_proof_target
+        .public_values
+        .iter()
+        .zip_eq(&keccak_ctl_proof.public_values)
+    {
+        witness.set_target(pv_t, pv);
+    }
+    for (&state_t, &state) in keccak_ctl_proof_target
+        .initial_states
+        .iter()
+        .chain(&keccak_ctl_proof_target.final_states)
+        .zip_eq(
+            keccak_ctl_proof
+                .initial_states
+                .iter()
+                .chain(&keccak_ctl_proof.final_states),
+        )
+    {
+        witness.set_target(state_t, state);
+    }
+    for (message_t, message) in keccak_ctl_proof_target
+        .messages
+        .iter()
+        .zip_eq(&keccak_ctl_proof.messages)
+    {
+        for (&byte_t, &byte) in message_t.iter().zip_eq(message) {
+            witness.set_target(byte_t, byte);
+        }
+    }
 }
 
 pub fn add_virtual_stark_proof_with_pis<
@@ -87,8 +278,9 @@ pub fn add_virtual_stark_proof_with_pis<
     stark: S,
     config: &StarkConfig,
     degree_bits: usize,
+    num_ctl_zs: usize,
 ) -> StarkProofWithPublicInputsTarget<D> {
-    let proof = add_virtual_stark_proof::<F, S, D>(builder, stark, config, degree_bits);
+    let proof = add_virtual_stark_proof::<F, S, D>(builder, stark, config, degree_bits, num_ctl_zs);
     let public_inputs = builder.add_virtual_targets(S::PUBLIC_INPUTS);
     StarkProofWithPublicInputsTarget {
         proof,
@@ -101,28 +293,25 @@ pub fn add_virtual_stark_proof<F: RichField + Extendable<D>, S: Stark<F, D>, con
     stark: S,
     config: &StarkConfig,
     degree_bits: usize,
+    num_ctl_zs: usize,
 ) -> StarkProofTarget<D> {
     let fri_params = config.fri_params(degree_bits);
     let cap_height = fri_params.config.cap_height;
 
+    // CTL `Z`s, if any, are committed after the permutation `Z`s in the same oracle.
+    let num_zs = stark.num_permutation_batches(config) + num_ctl_zs;
     let num_leaves_per_oracle = once(S::COLUMNS)
-        .chain(
-            stark
-                .uses_permutation_args()
-                .then(|| stark.num_permutation_batches(config)),
-        )
+        .chain((num_zs > 0).then_some(num_zs))
         .chain(once(stark.quotient_degree_factor() * config.num_challenges))
         .collect_vec();
 
-    let permutation_zs_cap = stark
-        .uses_permutation_args()
-        .then(|| builder.add_virtual_cap(cap_height));
+    let permutation_zs_cap = (num_zs > 0).then(|| builder.add_virtual_cap(cap_height));
 
     StarkProofTarget {
         trace_cap: builder.add_virtual_cap(cap_height),
         permutation_zs_cap,
         quotient_polys_cap: builder.add_virtual_cap(cap_height),
-        openings: add_stark_opening_set_target::<F, S, D>(builder, stark, config),
+        openings: add_stark_opening_set_target::<F, S, D>(builder, stark, config, num_ctl_zs),
         opening_proof: builder.add_virtual_fri_proof(&num_leaves_per_oracle, &fri_params),
     }
 }
@@ -131,17 +320,15 @@ fn add_stark_opening_set_target<F: RichField + Extendable<D>, S: Stark<F, D>, co
     builder: &mut CircuitBuilder<F, D>,
     stark: S,
     config: &StarkConfig,
+    num_ctl_zs: usize,
 ) -> StarkOpeningSetTarget<D> {
     let num_challenges = config.num_challenges;
+    let num_zs = stark.num_permutation_batches(config) + num_ctl_zs;
     StarkOpeningSetTarget {
         local_values: builder.add_virtual_extension_targets(S::COLUMNS),
         next_values: builder.add_virtual_extension_targets(S::COLUMNS),
-        permutation_zs: stark
-            .uses_permutation_args()
-            .then(|| builder.add_virtual_extension_targets(stark.num_permutation_batches(config))),
-        permutation_zs_next: stark
-            .uses_permutation_args()
-            .then(|| builder.add_virtual_extension_targets(stark.num_permutation_batches(config))),
+        permutation_zs: (num_zs > 0).then(|| builder.add_virtual_extension_targets(num_zs)),
+        permutation_zs_next: (num_zs > 0).then(|| builder.add_virtual_extension_targets(num_zs)),
         quotient_polys: builder
             .add_virtual_extension_targets(stark.quotient_degree_factor() * num_challenges),
     }
@@ -204,7 +391,8 @@ pub fn u32_to_u64(vecu32: &[u32]) -> Vec<u64> {
     assert_eq!(vecu32.len() % 2, 0);
     let mut vecu64: Vec<u64> = Vec::new();
     for i in (0..vecu32.len()).step_by(2) {
-        let a: u64 = ((vecu32[i] as u64) << 32) | (vecu32[i + 1] as u64);
+        // Lanes are stored as `lo, hi` limbs, matching `keccakf_u32s`.
+        let a: u64 = ((vecu32[i + 1] as u64) << 32) | (vecu32[i] as u64);
         vecu64.push(a);
     }
     vecu64
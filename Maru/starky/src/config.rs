@@ -1,6 +1,7 @@
 use plonky2::fri::reduction_strategies::FriReductionStrategy;
 use plonky2::fri::{FriConfig, FriParams};
 
+#[derive(Clone, Debug)]
 pub struct StarkConfig {
     pub security_bits: usize,
 
@@ -15,15 +16,34 @@ impl StarkConfig {
     /// A typical configuration with a rate of 2, resulting in fast but large proofs.
     /// Targets ~100 bit conjectured security.
     pub fn standard_fast_config() -> Self {
+        Self::with_rate(100, 1, 16)
+    }
+
+    /// A configuration with a rate of 16: proving is slower than with `standard_fast_config`, but
+    /// 4x fewer FRI queries are needed, resulting in much smaller proofs.
+    /// Targets ~100 bit conjectured security.
+    pub fn standard_small_proof_config() -> Self {
+        Self::with_rate(100, 4, 16)
+    }
+
+    /// `standard_fast_config` with ~128 bit conjectured security.
+    pub fn standard_high_security_config() -> Self {
+        Self::with_rate(128, 1, 20)
+    }
+
+    /// A configuration targeting `security_bits` of conjectured security, with a blowup factor of
+    /// `2^rate_bits` and `proof_of_work_bits` of grinding. Each FRI query contributes `rate_bits`
+    /// bits of conjectured security, so the number of query rounds is derived from the other three.
+    pub fn with_rate(security_bits: usize, rate_bits: usize, proof_of_work_bits: u32) -> Self {
         Self {
-            security_bits: 100,
+            security_bits,
             num_challenges: 2,
             fri_config: FriConfig {
-                rate_bits: 1,
+                rate_bits,
                 cap_height: 4,
-                proof_of_work_bits: 16,
+                proof_of_work_bits,
                 reduction_strategy: FriReductionStrategy::ConstantArityBits(4, 5),
-                num_query_rounds: 84,
+                num_query_rounds: num_query_rounds(security_bits, rate_bits, proof_of_work_bits),
             },
         }
     }
@@ -32,3 +52,10 @@ impl StarkConfig {
         self.fri_config.fri_params(degree_bits, false)
     }
 }
+
+/// The number of FRI query rounds needed to reach `security_bits` of conjectured security.
+pub fn num_query_rounds(security_bits: usize, rate_bits: usize, proof_of_work_bits: u32) -> usize {
+    assert!(rate_bits > 0, "FRI needs a rate of at least 2");
+    let query_security_bits = security_bits.saturating_sub(proof_of_work_bits as usize);
+    (query_security_bits + rate_bits - 1) / rate_bits
+}
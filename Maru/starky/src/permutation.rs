@@ -12,7 +12,9 @@ use plonky2::iop::ext_target::ExtensionTarget;
 use plonky2::iop::target::Target;
 use plonky2::plonk::circuit_builder::CircuitBuilder;
 use plonky2::plonk::config::{AlgebraicHasher, GenericConfig, Hasher};
-use plonky2::plonk::plonk_common::{reduce_with_powers, reduce_with_powers_ext_circuit};
+use plonky2::plonk::plonk_common::{
+    reduce_with_powers, reduce_with_powers_circuit, reduce_with_powers_ext_circuit,
+};
 use plonky2::util::reducing::{ReducingFactor, ReducingFactorTarget};
 use plonky2_maybe_rayon::*;
 
@@ -75,6 +77,15 @@ impl PermutationChallenge<Target> {
         let gamma = builder.convert_to_ext(self.gamma);
         builder.add_extension(reduced, gamma)
     }
+
+    pub fn combine_base_circuit<F: RichField + Extendable<D>, const D: usize>(
+        &self,
+        builder: &mut CircuitBuilder<F, D>,
+        terms: &[Target],
+    ) -> Target {
+        let reduced = reduce_with_powers_circuit(builder, terms, self.beta);
+        builder.add(reduced, self.gamma)
+    }
 }
 
 /// Like `PermutationChallenge`, but with `num_challenges` copies to boost soundness.
@@ -189,7 +200,7 @@ pub fn get_permutation_challenge_set<F: RichField, H: Hasher<F>>(
     PermutationChallengeSet { challenges }
 }
 
-pub(crate) fn get_n_permutation_challenge_sets<F: RichField, H: Hasher<F>>(
+pub fn get_n_permutation_challenge_sets<F: RichField, H: Hasher<F>>(
     challenger: &mut Challenger<F, H>,
     num_challenges: usize,
     num_sets: usize,
@@ -212,7 +223,7 @@ fn get_permutation_challenge_target<
     PermutationChallenge { beta, gamma }
 }
 
-fn get_permutation_challenge_set_target<
+pub fn get_permutation_challenge_set_target<
     F: RichField + Extendable<D>,
     H: AlgebraicHasher<F>,
     const D: usize,
@@ -227,7 +238,7 @@ fn get_permutation_challenge_set_target<
     PermutationChallengeSet { challenges }
 }
 
-pub(crate) fn get_n_permutation_challenge_sets_target<
+pub fn get_n_permutation_challenge_sets_target<
     F: RichField + Extendable<D>,
     H: AlgebraicHasher<F>,
     const D: usize,
@@ -276,9 +287,9 @@ where
     FE: FieldExtension<D2, BaseField = F>,
     P: PackedField<Scalar = FE>,
 {
-    pub(crate) local_zs: Vec<P>,
-    pub(crate) next_zs: Vec<P>,
-    pub(crate) permutation_challenge_sets: Vec<PermutationChallengeSet<F>>,
+    pub local_zs: Vec<P>,
+    pub next_zs: Vec<P>,
+    pub permutation_challenge_sets: Vec<PermutationChallengeSet<F>>,
 }
 
 pub(crate) fn eval_permutation_checks<F, FE, P, C, S, const D: usize, const D2: usize>(
@@ -18,7 +18,7 @@ use serde::{Deserialize, Serialize};
 use crate::config::StarkConfig;
 use crate::permutation::PermutationChallengeSet;
 
-use crate::serialization::Write;
+use crate::serialization::{Read, StarkProofShape, Write};
 
 #[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq)]
 #[serde(bound = "")]
@@ -88,6 +88,17 @@ impl<F: RichField + Extendable<D>, C: GenericConfig<D, F = F>, const D: usize>
             .expect("Writing to a byte-vector cannot fail.");
         buffer
     }
+
+    /// Inverse of `to_bytes`, for a proof of the given `shape` generated with `config`.
+    pub fn from_bytes(
+        mut bytes: &[u8],
+        shape: &StarkProofShape,
+        config: &StarkConfig,
+    ) -> anyhow::Result<Self> {
+        bytes
+            .read_proof_with_public_inputs(shape, config)
+            .map_err(|_| anyhow::anyhow!("Invalid proof encoding"))
+    }
 }
 
 pub struct StarkProofWithPublicInputsTarget<const D: usize> {
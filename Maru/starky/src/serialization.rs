@@ -1,15 +1,23 @@
 #[cfg(feature = "std")]
 use crate::hash::merkle_proofs::MerkleProof;
+use crate::config::StarkConfig;
 use crate::proof::{StarkOpeningSet, StarkProof, StarkProofWithPublicInputs};
+use crate::stark::Stark;
 use core::convert::Infallible;
 use core::fmt::{Debug, Display, Formatter};
+use core::iter::once;
+use core::mem::size_of;
+use std::path::Path;
 
+use anyhow::{anyhow, ensure, Result};
 use plonky2::fri::proof::{FriInitialTreeProof, FriProof, FriQueryRound, FriQueryStep};
+use plonky2::fri::FriParams;
 use plonky2::hash::hash_types::RichField;
 use plonky2::hash::merkle_proofs::MerkleProof;
 use plonky2::hash::merkle_tree::MerkleCap;
 use plonky2::plonk::config::{GenericConfig, GenericHashOut, Hasher};
 use plonky2_field::extension::{Extendable, FieldExtension};
+use plonky2_field::polynomial::PolynomialCoeffs;
 use plonky2_field::types::PrimeField64;
 
 /// A no_std compatible variant of `std::io::Error`
@@ -261,3 +269,429 @@ impl Write for Vec<u8> {
         Ok(())
     }
 }
+
+/// The dimensions of a [`StarkProof`], which are not part of its serialization. Together with the
+/// [`StarkConfig`] they play the role of plonky2's `CommonCircuitData` when reading a proof.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct StarkProofShape {
+    /// Log2 of the trace length.
+    pub degree_bits: usize,
+    /// Number of trace columns.
+    pub num_columns: usize,
+    /// Number of permutation `Z` polynomials, including the cross-table lookup `Z`s of proofs
+    /// made with a shared challenger. Zero if the proof has no permutation commitment.
+    pub num_zs: usize,
+    /// Number of quotient polynomial chunks.
+    pub num_quotient_polys: usize,
+}
+
+impl StarkProofShape {
+    /// The shape of a proof of `stark` over a trace of `2^degree_bits` rows.
+    pub fn new<F, S, const D: usize>(stark: &S, config: &StarkConfig, degree_bits: usize) -> Self
+    where
+        F: RichField + Extendable<D>,
+        S: Stark<F, D>,
+    {
+        Self {
+            degree_bits,
+            num_columns: S::COLUMNS,
+            num_zs: if stark.uses_permutation_args() {
+                stark.num_permutation_batches(config)
+            } else {
+                0
+            },
+            num_quotient_polys: stark.num_quotient_polys(config),
+        }
+    }
+
+    /// The shape of an existing `proof`.
+    pub fn from_proof<F, C, const D: usize>(proof: &StarkProof<F, C, D>, config: &StarkConfig) -> Self
+    where
+        F: RichField + Extendable<D>,
+        C: GenericConfig<D, F = F>,
+    {
+        Self {
+            degree_bits: proof.recover_degree_bits(config),
+            num_columns: proof.openings.local_values.len(),
+            num_zs: proof.openings.permutation_zs.as_ref().map_or(0, Vec::len),
+            num_quotient_polys: proof.openings.quotient_polys.len(),
+        }
+    }
+
+    pub fn fri_params(&self, config: &StarkConfig) -> FriParams {
+        config.fri_params(self.degree_bits)
+    }
+}
+
+/// Reading
+pub trait Read {
+    /// Error Type
+    type Error;
+
+    /// Reads exactly the length of `bytes` from `self` and writes it to `bytes`.
+    fn read_exact(&mut self, bytes: &mut [u8]) -> IoResult<()>;
+
+    /// Returns the number of bytes remaining in `self`.
+    fn remaining(&self) -> usize;
+
+    /// Reads a byte from `self`.
+    #[inline]
+    fn read_u8(&mut self) -> IoResult<u8> {
+        let mut buf = [0; size_of::<u8>()];
+        self.read_exact(&mut buf)?;
+        Ok(buf[0])
+    }
+
+    /// Reads a word from `self`.
+    #[inline]
+    fn read_u32(&mut self) -> IoResult<u32> {
+        let mut buf = [0; size_of::<u32>()];
+        self.read_exact(&mut buf)?;
+        Ok(u32::from_le_bytes(buf))
+    }
+
+    /// Reads an element from the field `F` from `self`. Non-canonical encodings are rejected.
+    #[inline]
+    fn read_field<F>(&mut self) -> IoResult<F>
+    where
+        F: PrimeField64,
+    {
+        let mut buf = [0; size_of::<u64>()];
+        self.read_exact(&mut buf)?;
+        let x = u64::from_le_bytes(buf);
+        if x >= F::ORDER {
+            return Err(IoError);
+        }
+        Ok(F::from_canonical_u64(x))
+    }
+
+    /// Reads a vector of `length` elements from the field `F` from `self`.
+    #[inline]
+    fn read_field_vec<F>(&mut self, length: usize) -> IoResult<Vec<F>>
+    where
+        F: PrimeField64,
+    {
+        (0..length).map(|_| self.read_field()).collect()
+    }
+
+    /// Reads an element from the field extension of `F` from `self`.
+    #[inline]
+    fn read_field_ext<F, const D: usize>(&mut self) -> IoResult<F::Extension>
+    where
+        F: RichField + Extendable<D>,
+    {
+        let mut arr = [F::ZERO; D];
+        for a in arr.iter_mut() {
+            *a = self.read_field()?;
+        }
+        Ok(F::Extension::from_basefield_array(arr))
+    }
+
+    /// Reads a vector of `length` elements from the field extension of `F` from `self`.
+    #[inline]
+    fn read_field_ext_vec<F, const D: usize>(&mut self, length: usize) -> IoResult<Vec<F::Extension>>
+    where
+        F: RichField + Extendable<D>,
+    {
+        (0..length).map(|_| self.read_field_ext::<F, D>()).collect()
+    }
+
+    /// Reads a hash from `self`.
+    #[inline]
+    fn read_hash<F, H>(&mut self) -> IoResult<H::Hash>
+    where
+        F: RichField,
+        H: Hasher<F>,
+    {
+        let mut buf = vec![0; H::HASH_SIZE];
+        self.read_exact(&mut buf)?;
+        Ok(H::Hash::from_bytes(&buf))
+    }
+
+    /// Reads a value of type [`MerkleCap`] with the given `cap_height` from `self`.
+    #[inline]
+    fn read_merkle_cap<F, H>(&mut self, cap_height: usize) -> IoResult<MerkleCap<F, H>>
+    where
+        F: RichField,
+        H: Hasher<F>,
+    {
+        Ok(MerkleCap(
+            (0..1 << cap_height)
+                .map(|_| self.read_hash::<F, H>())
+                .collect::<IoResult<_>>()?,
+        ))
+    }
+
+    /// Reads a value of type [`StarkOpeningSet`] with the given `shape` from `self`.
+    #[inline]
+    fn read_opening_set<F, const D: usize>(
+        &mut self,
+        shape: &StarkProofShape,
+    ) -> IoResult<StarkOpeningSet<F, D>>
+    where
+        F: RichField + Extendable<D>,
+    {
+        let local_values = self.read_field_ext_vec::<F, D>(shape.num_columns)?;
+        let next_values = self.read_field_ext_vec::<F, D>(shape.num_columns)?;
+        let (permutation_zs, permutation_zs_next) = if shape.num_zs > 0 {
+            (
+                Some(self.read_field_ext_vec::<F, D>(shape.num_zs)?),
+                Some(self.read_field_ext_vec::<F, D>(shape.num_zs)?),
+            )
+        } else {
+            (None, None)
+        };
+        let quotient_polys = self.read_field_ext_vec::<F, D>(shape.num_quotient_polys)?;
+        Ok(StarkOpeningSet {
+            local_values,
+            next_values,
+            permutation_zs,
+            permutation_zs_next,
+            quotient_polys,
+        })
+    }
+
+    /// Reads a value of type [`MerkleProof`] from `self`.
+    #[inline]
+    fn read_merkle_proof<F, H>(&mut self) -> IoResult<MerkleProof<F, H>>
+    where
+        F: RichField,
+        H: Hasher<F>,
+    {
+        let length = self.read_u8()?;
+        Ok(MerkleProof {
+            siblings: (0..length)
+                .map(|_| self.read_hash::<F, H>())
+                .collect::<IoResult<_>>()?,
+        })
+    }
+
+    /// Reads a value of type [`FriInitialTreeProof`] with the given `shape` from `self`.
+    #[inline]
+    fn read_fri_initial_proof<F, C, const D: usize>(
+        &mut self,
+        shape: &StarkProofShape,
+    ) -> IoResult<FriInitialTreeProof<F, C::Hasher>>
+    where
+        F: RichField + Extendable<D>,
+        C: GenericConfig<D, F = F>,
+    {
+        // The trace, permutation `Z`s (if any) and quotient oracles, in commitment order.
+        let num_polys = once(shape.num_columns)
+            .chain((shape.num_zs > 0).then_some(shape.num_zs))
+            .chain(once(shape.num_quotient_polys));
+        let evals_proofs = num_polys
+            .map(|num_polys| Ok((self.read_field_vec(num_polys)?, self.read_merkle_proof()?)))
+            .collect::<IoResult<_>>()?;
+        Ok(FriInitialTreeProof { evals_proofs })
+    }
+
+    /// Reads a value of type [`FriQueryStep`] with the given `arity` from `self`.
+    #[inline]
+    fn read_fri_query_step<F, C, const D: usize>(
+        &mut self,
+        arity: usize,
+    ) -> IoResult<FriQueryStep<F, C::Hasher, D>>
+    where
+        F: RichField + Extendable<D>,
+        C: GenericConfig<D, F = F>,
+    {
+        let evals = self.read_field_ext_vec::<F, D>(arity)?;
+        let merkle_proof = self.read_merkle_proof()?;
+        Ok(FriQueryStep {
+            evals,
+            merkle_proof,
+        })
+    }
+
+    /// Reads a vector of [`FriQueryRound`]s with the given `shape` and `config` from `self`.
+    #[inline]
+    fn read_fri_query_rounds<F, C, const D: usize>(
+        &mut self,
+        shape: &StarkProofShape,
+        config: &StarkConfig,
+    ) -> IoResult<Vec<FriQueryRound<F, C::Hasher, D>>>
+    where
+        F: RichField + Extendable<D>,
+        C: GenericConfig<D, F = F>,
+    {
+        let fri_params = shape.fri_params(config);
+        (0..config.fri_config.num_query_rounds)
+            .map(|_| {
+                let initial_trees_proof = self.read_fri_initial_proof::<F, C, D>(shape)?;
+                let steps = fri_params
+                    .reduction_arity_bits
+                    .iter()
+                    .map(|&arity_bits| self.read_fri_query_step::<F, C, D>(1 << arity_bits))
+                    .collect::<IoResult<_>>()?;
+                Ok(FriQueryRound {
+                    initial_trees_proof,
+                    steps,
+                })
+            })
+            .collect()
+    }
+
+    /// Reads a value of type [`FriProof`] with the given `shape` and `config` from `self`.
+    #[inline]
+    fn read_fri_proof<F, C, const D: usize>(
+        &mut self,
+        shape: &StarkProofShape,
+        config: &StarkConfig,
+    ) -> IoResult<FriProof<F, C::Hasher, D>>
+    where
+        F: RichField + Extendable<D>,
+        C: GenericConfig<D, F = F>,
+    {
+        let fri_params = shape.fri_params(config);
+        let commit_phase_merkle_caps = (0..fri_params.reduction_arity_bits.len())
+            .map(|_| self.read_merkle_cap(config.fri_config.cap_height))
+            .collect::<IoResult<_>>()?;
+        let query_round_proofs = self.read_fri_query_rounds::<F, C, D>(shape, config)?;
+        let final_poly =
+            PolynomialCoeffs::new(self.read_field_ext_vec::<F, D>(fri_params.final_poly_len())?);
+        let pow_witness = self.read_field()?;
+        Ok(FriProof {
+            commit_phase_merkle_caps,
+            query_round_proofs,
+            final_poly,
+            pow_witness,
+        })
+    }
+
+    /// Reads a value of type [`StarkProof`] with the given `shape` and `config` from `self`.
+    #[inline]
+    fn read_proof<F, C, const D: usize>(
+        &mut self,
+        shape: &StarkProofShape,
+        config: &StarkConfig,
+    ) -> IoResult<StarkProof<F, C, D>>
+    where
+        F: RichField + Extendable<D>,
+        C: GenericConfig<D, F = F>,
+    {
+        let cap_height = config.fri_config.cap_height;
+        let trace_cap = self.read_merkle_cap(cap_height)?;
+        let permutation_zs_cap = if shape.num_zs > 0 {
+            Some(self.read_merkle_cap(cap_height)?)
+        } else {
+            None
+        };
+        let quotient_polys_cap = self.read_merkle_cap(cap_height)?;
+        let openings = self.read_opening_set::<F, D>(shape)?;
+        let opening_proof = self.read_fri_proof::<F, C, D>(shape, config)?;
+        Ok(StarkProof {
+            trace_cap,
+            permutation_zs_cap,
+            quotient_polys_cap,
+            openings,
+            opening_proof,
+        })
+    }
+
+    /// Reads a value of type [`StarkProofWithPublicInputs`] with the given `shape` and `config`
+    /// from `self`. The public inputs are the rest of the input.
+    #[inline]
+    fn read_proof_with_public_inputs<F, C, const D: usize>(
+        &mut self,
+        shape: &StarkProofShape,
+        config: &StarkConfig,
+    ) -> IoResult<StarkProofWithPublicInputs<F, C, D>>
+    where
+        F: RichField + Extendable<D>,
+        C: GenericConfig<D, F = F>,
+    {
+        let proof = self.read_proof(shape, config)?;
+        if self.remaining() % size_of::<u64>() != 0 {
+            return Err(IoError);
+        }
+        let public_inputs = self.read_field_vec(self.remaining() / size_of::<u64>())?;
+        Ok(StarkProofWithPublicInputs {
+            proof,
+            public_inputs,
+        })
+    }
+}
+
+impl Read for &[u8] {
+    type Error = IoError;
+
+    #[inline]
+    fn read_exact(&mut self, bytes: &mut [u8]) -> IoResult<()> {
+        if self.len() < bytes.len() {
+            return Err(IoError);
+        }
+        let (head, tail) = self.split_at(bytes.len());
+        bytes.copy_from_slice(head);
+        *self = tail;
+        Ok(())
+    }
+
+    #[inline]
+    fn remaining(&self) -> usize {
+        self.len()
+    }
+}
+
+/// Writes `proof_with_pis` to the file at `path`, preceded by its [`StarkProofShape`], so that
+/// [`load_proof`] only needs the [`StarkConfig`] it was generated with.
+pub fn save_proof<F, C, const D: usize>(
+    path: impl AsRef<Path>,
+    proof_with_pis: &StarkProofWithPublicInputs<F, C, D>,
+    config: &StarkConfig,
+) -> Result<()>
+where
+    F: RichField + Extendable<D>,
+    C: GenericConfig<D, F = F>,
+{
+    let shape = StarkProofShape::from_proof(&proof_with_pis.proof, config);
+    let mut bytes = Vec::new();
+    for dim in [
+        shape.degree_bits,
+        shape.num_columns,
+        shape.num_zs,
+        shape.num_quotient_polys,
+    ] {
+        bytes
+            .write_u32(dim.try_into()?)
+            .expect("Writing to a byte-vector cannot fail.");
+    }
+    bytes
+        .write_proof_with_public_inputs(proof_with_pis)
+        .expect("Writing to a byte-vector cannot fail.");
+    std::fs::write(path.as_ref(), bytes)
+        .map_err(|e| anyhow!("Cannot write proof to {}: {e}", path.as_ref().display()))
+}
+
+/// Reads a proof written by [`save_proof`] from the file at `path`. The proof still has to be
+/// checked with `verify_stark_proof`, which also checks that its shape matches the STARK.
+pub fn load_proof<F, C, const D: usize>(
+    path: impl AsRef<Path>,
+    config: &StarkConfig,
+) -> Result<StarkProofWithPublicInputs<F, C, D>>
+where
+    F: RichField + Extendable<D>,
+    C: GenericConfig<D, F = F>,
+{
+    let bytes = std::fs::read(path.as_ref())
+        .map_err(|e| anyhow!("Cannot read proof from {}: {e}", path.as_ref().display()))?;
+    let mut reader = bytes.as_slice();
+    let mut read_dim = || -> Result<usize> {
+        let dim = reader
+            .read_u32()
+            .map_err(|_| anyhow!("Truncated proof header"))?;
+        Ok(dim as usize)
+    };
+    let shape = StarkProofShape {
+        degree_bits: read_dim()?,
+        num_columns: read_dim()?,
+        num_zs: read_dim()?,
+        num_quotient_polys: read_dim()?,
+    };
+    ensure!(
+        shape.degree_bits + config.fri_config.rate_bits <= F::TWO_ADICITY,
+        "Invalid degree bits {} in proof header",
+        shape.degree_bits
+    );
+    StarkProofWithPublicInputs::from_bytes(reader, &shape, config)
+}
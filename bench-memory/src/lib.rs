@@ -0,0 +1,149 @@
+//! Per-phase memory measurements for the keccak benchmarks.
+//!
+//! Install [`TrackingAllocator`] as the global allocator of the benchmark binary,
+//! then wrap each phase (circuit build, witness generation, proving) between
+//! [`PhaseTracker::start`] and [`PhaseTracker::finish`].
+
+use std::alloc::{GlobalAlloc, Layout};
+use std::fmt;
+use std::fs;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+
+/// Global allocator wrapper that counts the total number of bytes allocated.
+///
+/// ```ignore
+/// #[global_allocator]
+/// static GLOBAL: TrackingAllocator<Jemalloc> = TrackingAllocator::new(Jemalloc);
+/// ```
+pub struct TrackingAllocator<A> {
+    inner: A,
+}
+
+impl<A> TrackingAllocator<A> {
+    pub const fn new(inner: A) -> Self {
+        Self { inner }
+    }
+}
+
+unsafe impl<A: GlobalAlloc> GlobalAlloc for TrackingAllocator<A> {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = self.inner.alloc(layout);
+        if !ptr.is_null() {
+            ALLOCATED.fetch_add(layout.size(), Ordering::Relaxed);
+        }
+        ptr
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        let ptr = self.inner.alloc_zeroed(layout);
+        if !ptr.is_null() {
+            ALLOCATED.fetch_add(layout.size(), Ordering::Relaxed);
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        self.inner.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let new_ptr = self.inner.realloc(ptr, layout, new_size);
+        // Only growth counts as newly allocated bytes.
+        if !new_ptr.is_null() && new_size > layout.size() {
+            ALLOCATED.fetch_add(new_size - layout.size(), Ordering::Relaxed);
+        }
+        new_ptr
+    }
+}
+
+/// Total number of bytes allocated through [`TrackingAllocator`] so far.
+/// Always zero if it is not installed as the global allocator.
+pub fn total_allocated() -> usize {
+    ALLOCATED.load(Ordering::Relaxed)
+}
+
+/// Resets the peak resident set size of this process, so that the next
+/// `peak_rss` reading only covers what happened after this call.
+/// Only supported on Linux; elsewhere this is a no-op.
+pub fn reset_peak_rss() {
+    let _ = fs::write("/proc/self/clear_refs", "5");
+}
+
+/// Peak resident set size of this process in bytes, if it can be read.
+pub fn peak_rss() -> Option<usize> {
+    let status = fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|line| line.starts_with("VmHWM:"))?;
+    let kb: usize = line.split_whitespace().nth(1)?.parse().ok()?;
+    Some(kb * 1024)
+}
+
+/// Memory used by one benchmark phase, in bytes.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PhaseMemory {
+    /// Peak resident set size of the process during the phase.
+    pub peak_rss: Option<usize>,
+    /// Bytes allocated during the phase, including memory freed before it ended.
+    pub allocated: usize,
+}
+
+impl PhaseMemory {
+    /// CSV header for the two columns written by `Display`, prefixed by `phase`.
+    pub fn csv_header(phase: &str) -> String {
+        format!("{phase}_peak_rss,{phase}_allocated")
+    }
+
+    /// Combines two disjoint parts of the same phase.
+    pub fn merge(self, other: Self) -> Self {
+        Self {
+            peak_rss: self.peak_rss.max(other.peak_rss),
+            allocated: self.allocated + other.allocated,
+        }
+    }
+}
+
+impl fmt::Display for PhaseMemory {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.peak_rss {
+            Some(peak_rss) => write!(f, "{},{}", peak_rss, self.allocated),
+            None => write!(f, ",{}", self.allocated),
+        }
+    }
+}
+
+/// Measures the memory used between `start` and `finish`.
+///
+/// Peak RSS is process wide, so phases must not overlap.
+pub struct PhaseTracker {
+    allocated_at_start: usize,
+}
+
+impl PhaseTracker {
+    pub fn start() -> Self {
+        reset_peak_rss();
+        Self { allocated_at_start: total_allocated() }
+    }
+
+    pub fn finish(self) -> PhaseMemory {
+        PhaseMemory {
+            peak_rss: peak_rss(),
+            allocated: total_allocated() - self.allocated_at_start,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PhaseMemory;
+
+    #[test]
+    fn test_merge_phase_memory() {
+        let a = PhaseMemory { peak_rss: Some(10), allocated: 3 };
+        let b = PhaseMemory { peak_rss: None, allocated: 4 };
+        assert_eq!(a.merge(b), PhaseMemory { peak_rss: Some(10), allocated: 7 });
+        assert_eq!(a.to_string(), "10,3");
+        assert_eq!(b.to_string(), ",4");
+        assert_eq!(PhaseMemory::csv_header("prove"), "prove_peak_rss,prove_allocated");
+    }
+}
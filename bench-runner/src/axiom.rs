@@ -0,0 +1,283 @@
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter};
+use std::path::Path;
+use std::time::Instant;
+
+use anyhow::{anyhow, ensure, Result};
+use bench_memory::PhaseTracker;
+use halo2_base::halo2_proofs::{
+    halo2curves::bn256::{Bn256, Fr},
+    plonk::Circuit,
+    poly::{commitment::Params, kzg::commitment::ParamsKZG},
+    SerdeFormat,
+};
+use rand::rngs::OsRng;
+#[cfg(feature = "axiom-aggregation")]
+use zkevm_keccak::aggregation::{gen_snark_shplonk, KeccakAggregator};
+use zkevm_keccak::circuit::{verify, KeccakCircuit, KeccakCircuitProver};
+#[cfg(feature = "axiom-evm")]
+use zkevm_keccak::evm::{gen_evm_proof_shplonk, EvmVerifier};
+use zkevm_keccak::keccak_packed_multi::multi_keccak_phase0;
+use zkevm_keccak::planner::Calibration;
+use zkevm_keccak::KeccakConfigParams;
+
+use crate::prover::{BenchResult, KeccakProver, VerifyResult};
+
+/// Axiom's halo2-lib port of the zkEVM keccak circuit, proven with SHPLONK over BN254.
+/// The digest is a public input, as two 128-bit halves. The circuit is `KeccakCircuit`, proven and
+/// verified by `KeccakCircuitProver` with keys generated from the circuit without its witness.
+///
+/// `build_time` covers the KZG setup and key generation, `witness_time` the
+/// phase 0 witness generation, which the prover repeats during `prove_time`.
+///
+/// The saved verifier data is the KZG parameters, the verifying key preceded by the circuit
+/// shape, and the public inputs.
+///
+/// With `with_aggregation`, the proof is aggregated by a `KeccakAggregator`, like Maru's STARK
+/// proofs are: `build_time` also covers the keygen of the aggregation circuit, which lays it out
+/// with a sample proof, `prove_time` also covers the aggregation proof, and `verify_time` and
+/// `proof_size` refer to the aggregation proof, verified with its accumulator.
+///
+/// With `with_evm`, `evm_gas` is the gas used by the verifier contract of the circuit, called in
+/// an in-process EVM with a second proof whose transcript is keccak256. Neither the contract
+/// generation nor the second proof is timed.
+pub struct AxiomProver {
+    params: KeccakConfigParams,
+    #[cfg(feature = "axiom-aggregation")]
+    aggregation_k: Option<u32>,
+    #[cfg(feature = "axiom-evm")]
+    evm: bool,
+}
+
+impl AxiomProver {
+    pub fn new(k: u32, rows_per_round: usize) -> Self {
+        Self {
+            params: KeccakConfigParams { k, rows_per_round },
+            #[cfg(feature = "axiom-aggregation")]
+            aggregation_k: None,
+            #[cfg(feature = "axiom-evm")]
+            evm: false,
+        }
+    }
+
+    /// Aggregates the proofs in a circuit of `2^aggregation_k` rows.
+    #[cfg(feature = "axiom-aggregation")]
+    pub fn with_aggregation(mut self, aggregation_k: u32) -> Self {
+        self.aggregation_k = Some(aggregation_k);
+        self
+    }
+
+    /// Measures the gas used to verify the proofs on the EVM.
+    #[cfg(feature = "axiom-evm")]
+    pub fn with_evm(mut self) -> Self {
+        self.evm = true;
+        self
+    }
+}
+
+#[cfg(feature = "axiom-evm")]
+impl AxiomProver {
+    /// Generates the verifier contract of the circuit, proves `circuit` for it and returns the
+    /// gas used by the contract to verify the proof.
+    fn evm_gas(
+        prover: &KeccakCircuitProver,
+        circuit: KeccakCircuit<Fr>,
+        instance: &[Fr],
+    ) -> Result<u64> {
+        let (params, pk) = (prover.params(), prover.pk());
+        let verifier =
+            EvmVerifier::new::<KeccakCircuit<Fr>>(params, pk.get_vk(), instance.len(), None);
+        let proof = gen_evm_proof_shplonk(params, pk, circuit, vec![instance.to_vec()]);
+        verifier.verify(instance, &proof).map_err(|e| anyhow!("EVM verification failed: {}", e))
+    }
+}
+
+#[cfg(feature = "axiom-aggregation")]
+impl AxiomProver {
+    fn prove_and_aggregate(
+        &self,
+        circuit: KeccakCircuit<Fr>,
+        aggregation_k: u32,
+    ) -> Result<BenchResult> {
+        let msg_len = circuit.inputs()[0].len();
+        let build_memory = PhaseTracker::start();
+        let build_start = Instant::now();
+        let params = ParamsKZG::<Bn256>::setup(aggregation_k, OsRng);
+        let mut inner_params = params.clone();
+        inner_params.downsize(self.params.k);
+        let prover = KeccakCircuitProver::keygen(inner_params, &circuit.without_witnesses())?;
+        // The sample proof is only used to derive the keys of the aggregation circuit, which
+        // depend on the verifying key and the number of public inputs, not on their values. It
+        // is a proof of the circuit without its witness, of the same input lengths, so that it
+        // holds for the keys of the circuit.
+        let sample = circuit.without_witnesses();
+        ensure!(sample.has_same_keys(&circuit), "the sample circuit does not match the keys");
+        let sample_snark = gen_snark_shplonk(prover.params(), prover.pk(), sample, None::<&str>);
+        let aggregator =
+            KeccakAggregator::keygen(params, aggregation_k as usize - 1, vec![sample_snark])?;
+        let build_time = build_start.elapsed().as_secs_f64();
+        let build_memory = build_memory.finish();
+
+        let witness_memory = PhaseTracker::start();
+        let witness_start = Instant::now();
+        multi_keccak_phase0::<Fr>(circuit.inputs(), Some(circuit.capacity()), self.params)?;
+        let witness_time = witness_start.elapsed().as_secs_f64();
+        let witness_memory = witness_memory.finish();
+
+        let prove_memory = PhaseTracker::start();
+        let prove_start = Instant::now();
+        let snark = gen_snark_shplonk(prover.params(), prover.pk(), circuit, None::<&str>);
+        let (instance, proof) = aggregator.prove(vec![snark]);
+        let prove_time = prove_start.elapsed().as_secs_f64();
+        let prove_memory = prove_memory.finish();
+
+        let verify_start = Instant::now();
+        aggregator.verify(&instance, &proof)?;
+        let verify_time = verify_start.elapsed().as_secs_f64();
+
+        Ok(BenchResult {
+            implementation: self.name(),
+            msg_len,
+            build_time,
+            witness_time,
+            prove_time,
+            verify_time,
+            proof_size: proof.len(),
+            evm_gas: None,
+            build_memory,
+            witness_memory,
+            prove_memory,
+        })
+    }
+}
+
+impl AxiomProver {
+    /// Runs within `self.params.scope`, which configures the circuit.
+    fn prove(&self, msg: &[u8], save_dir: Option<&Path>) -> Result<BenchResult> {
+        let circuit = KeccakCircuit::<Fr>::new(self.params, vec![msg.to_vec()])?;
+        ensure!(
+            circuit.num_keccak_f() <= circuit.capacity(),
+            "message of {} bytes needs {} keccak_f, circuit with k = {} fits {}",
+            msg.len(),
+            circuit.num_keccak_f(),
+            self.params.k,
+            circuit.capacity()
+        );
+        #[cfg(feature = "axiom-aggregation")]
+        if let Some(aggregation_k) = self.aggregation_k {
+            ensure!(save_dir.is_none(), "saving aggregated Axiom proofs is not supported");
+            return self.prove_and_aggregate(circuit, aggregation_k);
+        }
+
+        let build_memory = PhaseTracker::start();
+        let build_start = Instant::now();
+        let params = ParamsKZG::<Bn256>::setup(self.params.k, OsRng);
+        let prover = KeccakCircuitProver::keygen(params, &circuit.without_witnesses())?;
+        let build_time = build_start.elapsed().as_secs_f64();
+        let build_memory = build_memory.finish();
+
+        let witness_memory = PhaseTracker::start();
+        let witness_start = Instant::now();
+        multi_keccak_phase0::<Fr>(circuit.inputs(), Some(circuit.capacity()), self.params)?;
+        let witness_time = witness_start.elapsed().as_secs_f64();
+        let witness_memory = witness_memory.finish();
+
+        // The EVM proof is made after the timed ones, from the same circuit
+        #[cfg(feature = "axiom-evm")]
+        let evm_circuit = self.evm.then(|| circuit.clone());
+
+        let prove_memory = PhaseTracker::start();
+        let prove_start = Instant::now();
+        let (instance, proof) = prover.prove(circuit)?;
+        let prove_time = prove_start.elapsed().as_secs_f64();
+        let prove_memory = prove_memory.finish();
+
+        let verify_start = Instant::now();
+        prover.verify(&instance, &proof)?;
+        let verify_time = verify_start.elapsed().as_secs_f64();
+
+        #[cfg(feature = "axiom-evm")]
+        let evm_gas =
+            evm_circuit.map(|circuit| Self::evm_gas(&prover, circuit, &instance)).transpose()?;
+        #[cfg(not(feature = "axiom-evm"))]
+        let evm_gas = None;
+
+        if let Some(dir) = save_dir {
+            fs::create_dir_all(dir)?;
+            prover.params().write(&mut BufWriter::new(File::create(dir.join("params.bin"))?))?;
+            self.params.write_vk(
+                &mut BufWriter::new(File::create(dir.join("vk.bin"))?),
+                prover.vk(),
+                SerdeFormat::RawBytes,
+            )?;
+            let instance_bytes: Vec<u8> = instance.iter().flat_map(|x| x.to_bytes()).collect();
+            fs::write(dir.join("instance.bin"), instance_bytes)?;
+            fs::write(dir.join("proof.bin"), &proof)?;
+        }
+
+        Ok(BenchResult {
+            implementation: self.name(),
+            msg_len: msg.len(),
+            build_time,
+            witness_time,
+            prove_time,
+            verify_time,
+            proof_size: proof.len(),
+            evm_gas,
+            build_memory,
+            witness_memory,
+            prove_memory,
+        })
+    }
+}
+
+impl KeccakProver for AxiomProver {
+    fn name(&self) -> &'static str {
+        "axiom"
+    }
+
+    fn prove_and_verify(&self, msg: &[u8], save_dir: Option<&Path>) -> Result<BenchResult> {
+        self.params.scope(|| self.prove(msg, save_dir))
+    }
+
+    fn verify_saved(&self, dir: &Path) -> Result<VerifyResult> {
+        let load_start = Instant::now();
+        let params =
+            ParamsKZG::<Bn256>::read(&mut BufReader::new(File::open(dir.join("params.bin"))?))?;
+        // The verifying key is read by configuring the circuit with the shape saved before it.
+        let (_, vk) = KeccakConfigParams::read_vk::<_, KeccakCircuit<Fr>>(
+            &mut BufReader::new(File::open(dir.join("vk.bin"))?),
+            SerdeFormat::RawBytes,
+        )?;
+        let instance_bytes = fs::read(dir.join("instance.bin"))?;
+        ensure!(instance_bytes.len() % 32 == 0, "invalid instance length");
+        let instance = instance_bytes
+            .chunks(32)
+            .map(|bytes| Option::from(Fr::from_bytes(bytes.try_into().unwrap())))
+            .collect::<Option<Vec<_>>>()
+            .ok_or_else(|| anyhow!("non-canonical public input"))?;
+        let proof = fs::read(dir.join("proof.bin"))?;
+        let load_time = load_start.elapsed().as_secs_f64();
+
+        let verify_start = Instant::now();
+        let valid = verify(&params, &vk, &instance, &proof).is_ok();
+        let verify_time = verify_start.elapsed().as_secs_f64();
+
+        Ok(VerifyResult { implementation: self.name(), load_time, verify_time, valid })
+    }
+}
+
+/// Measures a proof of the Axiom circuit of shape `parameters`, to calibrate
+/// `zkevm_keccak::planner::plan`. A small `k` keeps it quick.
+pub fn calibrate(parameters: KeccakConfigParams) -> Result<Calibration> {
+    let params = ParamsKZG::<Bn256>::setup(parameters.k, OsRng);
+    let circuit = KeccakCircuit::new(parameters, vec![])?;
+    let prover = KeccakCircuitProver::keygen(params, &circuit)?;
+
+    let memory = PhaseTracker::start();
+    let start = Instant::now();
+    let (_, proof) = prover.prove(circuit)?;
+    let prove_time = start.elapsed().as_secs_f64();
+    let memory = memory.finish();
+    Ok(Calibration { parameters, prove_time, proof_size: proof.len(), memory: memory.peak_rss })
+}
@@ -0,0 +1,169 @@
+//! The stages shared by the plonky2 implementations on their way to the EVM: their proof is
+//! wrapped in a recursion layer hashed with Poseidon over BN254, whose proof `plonky2-halo2`
+//! verifies in a halo2 circuit proven with KZG.
+
+use std::time::Instant;
+
+use anyhow::Result;
+use plonky2_halo2::{CommonData, Plonky2Verifier, Proof, VerifierOnlyData};
+
+use crate::prover::Bn254Result;
+
+/// Defines `to_halo2_data`, converting the data and a proof of a `$config` circuit of the plonky2
+/// crate `$plonky2` to the types of `plonky2-halo2`. The implementations pin different plonky2
+/// versions, with the same proof format, so the conversion is expanded in each of them.
+macro_rules! to_halo2_data {
+    ($plonky2:ident, $config:ty) => {
+        fn to_halo2_data(
+            data: &$plonky2::plonk::circuit_data::CircuitData<
+                $plonky2::field::goldilocks_field::GoldilocksField,
+                $config,
+                2,
+            >,
+            proof: &$plonky2::plonk::proof::ProofWithPublicInputs<
+                $plonky2::field::goldilocks_field::GoldilocksField,
+                $config,
+                2,
+            >,
+        ) -> (plonky2_halo2::CommonData, plonky2_halo2::VerifierOnlyData, plonky2_halo2::Proof) {
+            use plonky2_halo2::data::{
+                FriConfig, FriProof, FriQueryRound, FriQueryStep, OpeningSet,
+            };
+            use $plonky2::field::extension::quadratic::QuadraticExtension;
+            use $plonky2::field::extension::FieldExtension;
+            use $plonky2::field::goldilocks_field::GoldilocksField;
+            use $plonky2::field::types::PrimeField64;
+            use $plonky2::hash::hash_types::HashOut;
+            use $plonky2::hash::merkle_tree::MerkleCap;
+            use $plonky2::plonk::config::GenericConfig;
+
+            type Hasher = <$config as GenericConfig<2>>::Hasher;
+
+            fn hash(hash: &HashOut<GoldilocksField>) -> plonky2_halo2::data::HashOut {
+                hash.elements.map(|x| x.to_canonical_u64())
+            }
+            fn cap(cap: &MerkleCap<GoldilocksField, Hasher>) -> plonky2_halo2::data::MerkleCap {
+                cap.0.iter().map(hash).collect()
+            }
+            fn exts(
+                values: &[QuadraticExtension<GoldilocksField>],
+            ) -> Vec<plonky2_halo2::data::Extension> {
+                values
+                    .iter()
+                    .map(|x| x.to_basefield_array().map(|c| c.to_canonical_u64()))
+                    .collect()
+            }
+
+            let common = &data.common;
+            let fri_config = &common.config.fri_config;
+            let common_data = plonky2_halo2::CommonData {
+                degree_bits: common.degree_bits(),
+                num_wires: common.config.num_wires,
+                num_routed_wires: common.config.num_routed_wires,
+                num_constants: common.num_constants,
+                num_public_inputs: common.num_public_inputs,
+                num_challenges: common.config.num_challenges,
+                num_partial_products: common.num_partial_products,
+                quotient_degree_factor: common.quotient_degree_factor,
+                num_gate_constraints: common.num_gate_constraints,
+                hiding: common.fri_params.hiding,
+                k_is: common.k_is.iter().map(|k| k.to_canonical_u64()).collect(),
+                fri: FriConfig {
+                    rate_bits: fri_config.rate_bits,
+                    cap_height: fri_config.cap_height,
+                    proof_of_work_bits: fri_config.proof_of_work_bits,
+                    num_query_rounds: fri_config.num_query_rounds,
+                    reduction_arity_bits: common.fri_params.reduction_arity_bits.clone(),
+                },
+                gates: common.gates.iter().map(|gate| gate.0.id()).collect(),
+            };
+            let verifier_only = plonky2_halo2::VerifierOnlyData {
+                constants_sigmas_cap: cap(&data.verifier_only.constants_sigmas_cap),
+                circuit_digest: hash(&data.verifier_only.circuit_digest),
+            };
+
+            let openings = &proof.proof.openings;
+            let fri = &proof.proof.opening_proof;
+            let proof = plonky2_halo2::Proof {
+                public_inputs: proof.public_inputs.iter().map(|x| x.to_canonical_u64()).collect(),
+                wires_cap: cap(&proof.proof.wires_cap),
+                plonk_zs_partial_products_cap: cap(&proof.proof.plonk_zs_partial_products_cap),
+                quotient_polys_cap: cap(&proof.proof.quotient_polys_cap),
+                openings: OpeningSet {
+                    constants: exts(&openings.constants),
+                    plonk_sigmas: exts(&openings.plonk_sigmas),
+                    wires: exts(&openings.wires),
+                    plonk_zs: exts(&openings.plonk_zs),
+                    plonk_zs_next: exts(&openings.plonk_zs_next),
+                    partial_products: exts(&openings.partial_products),
+                    quotient_polys: exts(&openings.quotient_polys),
+                },
+                opening_proof: FriProof {
+                    commit_phase_merkle_caps: fri
+                        .commit_phase_merkle_caps
+                        .iter()
+                        .map(cap)
+                        .collect(),
+                    query_round_proofs: fri
+                        .query_round_proofs
+                        .iter()
+                        .map(|round| FriQueryRound {
+                            initial_trees_proof: round
+                                .initial_trees_proof
+                                .evals_proofs
+                                .iter()
+                                .map(|(evals, merkle_proof)| {
+                                    (
+                                        evals.iter().map(|x| x.to_canonical_u64()).collect(),
+                                        merkle_proof.siblings.iter().map(hash).collect(),
+                                    )
+                                })
+                                .collect(),
+                            steps: round
+                                .steps
+                                .iter()
+                                .map(|step| FriQueryStep {
+                                    evals: exts(&step.evals),
+                                    merkle_proof: step
+                                        .merkle_proof
+                                        .siblings
+                                        .iter()
+                                        .map(hash)
+                                        .collect(),
+                                })
+                                .collect(),
+                        })
+                        .collect(),
+                    final_poly: exts(&fri.final_poly.coeffs),
+                    pow_witness: fri.pow_witness.to_canonical_u64(),
+                },
+            };
+            (common_data, verifier_only, proof)
+        }
+    };
+}
+pub(crate) use to_halo2_data;
+
+/// Generates the keys of the halo2 verifier of the wrapped circuit with `2^k` rows, proves that
+/// `proof` is valid and verifies the halo2 proof, filling the halo2 stage of `result`.
+pub fn prove_halo2(
+    k: u32,
+    common: CommonData,
+    verifier_only: VerifierOnlyData,
+    proof: &Proof,
+    result: &mut Bn254Result,
+) -> Result<()> {
+    let keygen_start = Instant::now();
+    let verifier = Plonky2Verifier::keygen(k, k as usize - 1, common, verifier_only, proof)?;
+    result.halo2_keygen_time = keygen_start.elapsed().as_secs_f64();
+
+    let prove_start = Instant::now();
+    let (instance, halo2_proof) = verifier.prove(proof)?;
+    result.halo2_prove_time = prove_start.elapsed().as_secs_f64();
+    result.halo2_proof_size = halo2_proof.len();
+
+    let verify_start = Instant::now();
+    verifier.verify(&instance, &halo2_proof)?;
+    result.halo2_verify_time = verify_start.elapsed().as_secs_f64();
+    Ok(())
+}
@@ -0,0 +1,161 @@
+use std::fs;
+use std::path::Path;
+use std::time::Instant;
+
+use anyhow::{ensure, Result};
+use bench_memory::PhaseTracker;
+use keccak256::keccak256::{CircuitBuilderHashKeccak, WitnessHashKeccak, KECCAK256_R};
+#[cfg(feature = "bn254")]
+use keccak256::poseidon_bn128::{wrap_bn128, PoseidonBN128GoldilocksConfig};
+use keccak256::types::{HashOutputTarget, VarHashInputTarget};
+use keccak_hash::keccak;
+use plonky2_jump::iop::witness::PartialWitness;
+use plonky2_jump::plonk::circuit_builder::CircuitBuilder;
+use plonky2_jump::plonk::circuit_data::{CircuitConfig, CircuitData};
+#[cfg(feature = "bn254")]
+use plonky2_jump::plonk::config::PoseidonGoldilocksConfig;
+use plonky2_jump::plonk::config::{GenericConfig, GenericHashOut, KeccakGoldilocksConfig};
+use plonky2_jump::plonk::proof::ProofWithPublicInputs;
+
+#[cfg(feature = "bn254")]
+use crate::prover::Bn254Result;
+use crate::prover::{BenchResult, KeccakProver, VerifyResult};
+
+const D: usize = 2;
+type C = KeccakGoldilocksConfig;
+type F = <C as GenericConfig<D>>::F;
+
+/// JumpCrypto's plonky2 keccak256 gadget.
+///
+/// The circuit is built with exactly as many blocks as `msg` needs after padding, which is
+/// enforced in-circuit from the message length.
+/// `witness_time` covers setting the input and output targets; the remaining
+/// witness generation runs inside plonky2's prover and is part of `prove_time`.
+///
+/// plonky2 cannot serialize the common circuit data, so the saved verifier data is the
+/// number of blocks and the circuit digest: `verify_saved` rebuilds the circuit, as part
+/// of `load_time`, and checks that its digest matches before reading the proof.
+///
+/// Keccak Merkle trees cannot be verified in a circuit, so the proof wrapped by `prove_bn254` is
+/// one of the same circuit with `PoseidonGoldilocksConfig`.
+pub struct JumpCryptoProver;
+
+#[cfg(feature = "bn254")]
+crate::bn254::to_halo2_data!(plonky2_jump, PoseidonBN128GoldilocksConfig);
+
+fn build_circuit<C: GenericConfig<D, F = F>>(
+    block_num: usize,
+) -> (CircuitData<F, C, D>, VarHashInputTarget, HashOutputTarget) {
+    let mut builder = CircuitBuilder::<F, D>::new(CircuitConfig::standard_recursion_config());
+    let hash_target = builder.add_virtual_keccak256_var_input_target(block_num);
+    let hash_output = builder.hash_keccak256(&hash_target.hash);
+    (builder.build::<C>(), hash_target, hash_output)
+}
+
+impl KeccakProver for JumpCryptoProver {
+    fn name(&self) -> &'static str {
+        "jumpcrypto"
+    }
+
+    fn prove_and_verify(&self, msg: &[u8], save_dir: Option<&Path>) -> Result<BenchResult> {
+        let hash = keccak(msg);
+        let block_num = 1 + msg.len() * 8 / KECCAK256_R;
+
+        let build_memory = PhaseTracker::start();
+        let build_start = Instant::now();
+        let (data, hash_target, hash_output) = build_circuit::<C>(block_num);
+        let build_time = build_start.elapsed().as_secs_f64();
+        let build_memory = build_memory.finish();
+
+        let witness_memory = PhaseTracker::start();
+        let witness_start = Instant::now();
+        let mut pw = PartialWitness::new();
+        pw.set_keccak256_var_input_target(&hash_target, msg);
+        pw.set_keccak256_output_target(&hash_output, hash.as_bytes());
+        let witness_time = witness_start.elapsed().as_secs_f64();
+        let witness_memory = witness_memory.finish();
+
+        let prove_memory = PhaseTracker::start();
+        let prove_start = Instant::now();
+        let proof = data.prove(pw)?;
+        let prove_time = prove_start.elapsed().as_secs_f64();
+        let prove_memory = prove_memory.finish();
+        let proof_bytes = proof.to_bytes();
+        let proof_size = proof_bytes.len();
+        if let Some(dir) = save_dir {
+            fs::create_dir_all(dir)?;
+            fs::write(dir.join("block_num"), block_num.to_string())?;
+            let circuit_digest = data.verifier_only.circuit_digest.to_bytes();
+            fs::write(dir.join("circuit_digest"), circuit_digest)?;
+            fs::write(dir.join("proof.bin"), proof_bytes)?;
+        }
+
+        let verify_start = Instant::now();
+        data.verify(proof)?;
+        let verify_time = verify_start.elapsed().as_secs_f64();
+
+        Ok(BenchResult {
+            implementation: self.name(),
+            msg_len: msg.len(),
+            build_time,
+            witness_time,
+            prove_time,
+            verify_time,
+            proof_size,
+            evm_gas: None,
+            build_memory,
+            witness_memory,
+            prove_memory,
+        })
+    }
+
+    fn verify_saved(&self, dir: &Path) -> Result<VerifyResult> {
+        let load_start = Instant::now();
+        let block_num: usize = fs::read_to_string(dir.join("block_num"))?.trim().parse()?;
+        let (data, _, _) = build_circuit::<C>(block_num);
+        ensure!(
+            data.verifier_only.circuit_digest.to_bytes() == fs::read(dir.join("circuit_digest"))?,
+            "the saved proof is for a different circuit"
+        );
+        let proof_bytes = fs::read(dir.join("proof.bin"))?;
+        let proof = ProofWithPublicInputs::from_bytes(proof_bytes, &data.common)?;
+        let load_time = load_start.elapsed().as_secs_f64();
+
+        let verify_start = Instant::now();
+        let valid = data.verify(proof).is_ok();
+        let verify_time = verify_start.elapsed().as_secs_f64();
+
+        Ok(VerifyResult { implementation: self.name(), load_time, verify_time, valid })
+    }
+
+    #[cfg(feature = "bn254")]
+    fn prove_bn254(&self, msg: &[u8], k: u32) -> Result<Bn254Result> {
+        let hash = keccak(msg);
+        let block_num = 1 + msg.len() * 8 / KECCAK256_R;
+        let (data, hash_target, hash_output) = build_circuit::<PoseidonGoldilocksConfig>(block_num);
+        let mut pw = PartialWitness::new();
+        pw.set_keccak256_var_input_target(&hash_target, msg);
+        pw.set_keccak256_output_target(&hash_output, hash.as_bytes());
+
+        let prove_start = Instant::now();
+        let proof = data.prove(pw)?;
+        let plonky2_prove_time = prove_start.elapsed().as_secs_f64();
+
+        let wrap_start = Instant::now();
+        let (wrapper, wrapped_proof) = wrap_bn128(&data, &proof)?;
+        let wrap_time = wrap_start.elapsed().as_secs_f64();
+
+        let mut result = Bn254Result {
+            implementation: self.name(),
+            msg_len: msg.len(),
+            plonky2_prove_time,
+            plonky2_proof_size: proof.to_bytes().len(),
+            wrap_time,
+            wrap_proof_size: wrapped_proof.to_bytes().len(),
+            ..Default::default()
+        };
+        let (common, verifier_only, proof) = to_halo2_data(&wrapper, &wrapped_proof);
+        crate::bn254::prove_halo2(k, common, verifier_only, &proof, &mut result)?;
+        Ok(result)
+    }
+}
@@ -0,0 +1,15 @@
+pub mod prover;
+
+#[cfg(feature = "axiom")]
+pub mod axiom;
+#[cfg(feature = "bn254")]
+pub mod bn254;
+#[cfg(feature = "jumpcrypto")]
+pub mod jumpcrypto;
+#[cfg(feature = "maru")]
+pub mod maru;
+
+pub use prover::{BenchResult, Bn254Result, KeccakProver, VerifyResult};
+
+/// Names accepted by `--impl`, in the order they are run by default.
+pub const IMPLEMENTATIONS: [&str; 3] = ["axiom", "jumpcrypto", "maru"];
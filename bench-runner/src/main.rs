@@ -0,0 +1,316 @@
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, bail, Context, Result};
+use bench_memory::TrackingAllocator;
+use bench_runner::{BenchResult, Bn254Result, KeccakProver, VerifyResult, IMPLEMENTATIONS};
+use env_logger::{try_init_from_env, Env, DEFAULT_FILTER_ENV};
+use jemallocator::Jemalloc;
+use log::{info, warn};
+
+#[global_allocator]
+static GLOBAL: TrackingAllocator<Jemalloc> = TrackingAllocator::new(Jemalloc);
+
+/// keccak256 rate in bytes; message sizes given with `--blocks` are multiples of it.
+const RATE: usize = 136;
+
+const USAGE: &str = "\
+Usage: bench-runner [OPTIONS]
+       bench-runner verify --impl <NAME> --dir <DIR>
+       bench-runner plan [--blocks <RANGE> | --sizes <BYTES> | --budget <BYTES>] [--metric <METRIC>]
+
+Options:
+  --impl <NAMES>       comma separated implementations to run [default: axiom,jumpcrypto,maru]
+  --blocks <RANGE>     message sizes in 136 byte blocks, e.g. 4, 1..=10 or 1..=100:10 [default: 1..=10]
+  --sizes <BYTES>      comma separated message sizes in bytes, overrides --blocks
+  --out <PATH>         output csv file [default: bench/keccak_runner.csv]
+  --axiom-k <K>        log2 of the number of rows of the Axiom circuit [default: 15]
+  --axiom-rows <ROWS>  rows per round of the Axiom circuit [default: 25]
+  --axiom-aggregate <K>
+                       aggregate each Axiom proof in a circuit of 2^K rows and report the
+                       aggregation proof (needs the axiom-aggregation feature)
+  --axiom-evm          report the gas used by the EVM verifier of each Axiom proof (needs the
+                       axiom-evm feature and solc)
+  --maru-config <NAME> Maru proof config: fast, small-proof or high-security [default: fast]
+  --save <DIR>         save each proof and its verifier data to <DIR>/<impl>/<msg_len>
+  --bn254              also wrap the plonky2 proofs with Poseidon over BN254 and verify them
+                       in halo2, writing the stages to <OUT>_bn254.csv (needs the bn254 feature)
+  --bn254-k <K>        log2 of the number of rows of the halo2 verifier [default: 22]
+
+The plan command recommends the shape of the Axiom circuit, --axiom-k and --axiom-rows, to hash
+all the messages of --blocks/--sizes in one proof. Costs are predicted from one proof of the
+shape given by --axiom-k and --axiom-rows, and all the shapes that fit are printed, best first.
+  --budget <BYTES>     plan for a single message of <BYTES> bytes instead
+  --metric <METRIC>    cost to minimize: prove-time, proof-size or memory [default: prove-time]
+
+The verify command loads a proof saved with --save in <DIR> and reports its
+verification time and result, away from the prover's process.
+  -h, --help           print this message";
+
+#[cfg_attr(not(feature = "axiom"), allow(dead_code))]
+struct Args {
+    implementations: Vec<String>,
+    sizes: Vec<usize>,
+    out: String,
+    axiom_k: u32,
+    axiom_rows: usize,
+    #[cfg_attr(not(feature = "axiom-aggregation"), allow(dead_code))]
+    axiom_aggregate: Option<u32>,
+    axiom_evm: bool,
+    #[cfg_attr(not(feature = "maru"), allow(dead_code))]
+    maru_config: String,
+    save: Option<String>,
+    bn254: bool,
+    bn254_k: u32,
+    /// Set by the `verify` command.
+    verify_dir: Option<String>,
+    /// Set by the `plan` command.
+    plan: bool,
+    budget: Option<usize>,
+    metric: String,
+}
+
+/// Parses `n`, `a..b`, `a..=b`, optionally followed by `:step`.
+fn parse_blocks(range: &str) -> Result<Vec<usize>> {
+    let (range, step) = match range.split_once(':') {
+        Some((range, step)) => (range, step.parse()?),
+        None => (range, 1),
+    };
+    if step == 0 {
+        bail!("block step must be positive");
+    }
+    let blocks: Vec<usize> = if let Some((start, end)) = range.split_once("..=") {
+        (start.parse()?..=end.parse()?).step_by(step).collect()
+    } else if let Some((start, end)) = range.split_once("..") {
+        (start.parse()?..end.parse()?).step_by(step).collect()
+    } else {
+        vec![range.parse()?]
+    };
+    Ok(blocks.into_iter().map(|b| b * RATE).collect())
+}
+
+fn parse_args() -> Result<Args> {
+    let mut args = Args {
+        implementations: IMPLEMENTATIONS.iter().map(|s| s.to_string()).collect(),
+        sizes: parse_blocks("1..=10")?,
+        out: "bench/keccak_runner.csv".to_string(),
+        axiom_k: 15,
+        axiom_rows: 25,
+        axiom_aggregate: None,
+        axiom_evm: false,
+        maru_config: "fast".to_string(),
+        save: None,
+        bn254: false,
+        bn254_k: 22,
+        verify_dir: None,
+        plan: false,
+        budget: None,
+        metric: "prove-time".to_string(),
+    };
+    let mut iter = std::env::args().skip(1).peekable();
+    let verify = iter.next_if_eq("verify").is_some();
+    args.plan = !verify && iter.next_if_eq("plan").is_some();
+    while let Some(flag) = iter.next() {
+        if flag == "-h" || flag == "--help" {
+            println!("{}", USAGE);
+            std::process::exit(0);
+        }
+        if flag == "--bn254" {
+            if !cfg!(feature = "bn254") {
+                bail!("--bn254 support is not compiled in, enable the `bn254` feature");
+            }
+            args.bn254 = true;
+            continue;
+        }
+        if flag == "--axiom-evm" {
+            if !cfg!(feature = "axiom-evm") {
+                bail!("--axiom-evm support is not compiled in, enable the `axiom-evm` feature");
+            }
+            args.axiom_evm = true;
+            continue;
+        }
+        let value =
+            iter.next().ok_or_else(|| anyhow!("missing value for {}\n\n{}", flag, USAGE))?;
+        match flag.as_str() {
+            "--impl" => args.implementations = value.split(',').map(|s| s.to_string()).collect(),
+            "--blocks" => args.sizes = parse_blocks(&value)?,
+            "--sizes" => {
+                args.sizes = value.split(',').map(|s| s.parse()).collect::<Result<_, _>>()?
+            }
+            "--out" => args.out = value,
+            "--axiom-k" => args.axiom_k = value.parse()?,
+            "--axiom-rows" => args.axiom_rows = value.parse()?,
+            "--axiom-aggregate" => {
+                if !cfg!(feature = "axiom-aggregation") {
+                    bail!(
+                        "--axiom-aggregate support is not compiled in, enable the \
+                         `axiom-aggregation` feature"
+                    );
+                }
+                args.axiom_aggregate = Some(value.parse()?)
+            }
+            "--maru-config" => args.maru_config = value,
+            "--save" => args.save = Some(value),
+            "--bn254-k" => args.bn254_k = value.parse()?,
+            "--dir" if verify => args.verify_dir = Some(value),
+            "--budget" if args.plan => args.budget = Some(value.parse()?),
+            "--metric" if args.plan => args.metric = value,
+            _ => bail!("unknown option {}\n\n{}", flag, USAGE),
+        }
+    }
+    if args.axiom_evm && args.axiom_aggregate.is_some() {
+        bail!("--axiom-evm measures the Axiom proofs themselves, not with --axiom-aggregate");
+    }
+    if verify {
+        if args.verify_dir.is_none() || args.implementations.len() != 1 {
+            bail!("verify needs --dir and a single --impl\n\n{}", USAGE);
+        }
+    }
+    Ok(args)
+}
+
+#[cfg_attr(not(any(feature = "axiom", feature = "maru")), allow(unused_variables))]
+fn make_prover(name: &str, args: &Args) -> Result<Box<dyn KeccakProver>> {
+    let prover: Box<dyn KeccakProver> = match name {
+        #[cfg(feature = "axiom")]
+        "axiom" => {
+            let prover = bench_runner::axiom::AxiomProver::new(args.axiom_k, args.axiom_rows);
+            #[cfg(feature = "axiom-aggregation")]
+            let prover = match args.axiom_aggregate {
+                Some(aggregation_k) => prover.with_aggregation(aggregation_k),
+                None => prover,
+            };
+            #[cfg(feature = "axiom-evm")]
+            let prover = if args.axiom_evm { prover.with_evm() } else { prover };
+            Box::new(prover)
+        }
+        #[cfg(feature = "jumpcrypto")]
+        "jumpcrypto" => Box::new(bench_runner::jumpcrypto::JumpCryptoProver),
+        #[cfg(feature = "maru")]
+        "maru" => Box::new(bench_runner::maru::MaruProver::new(args.maru_config.parse()?)),
+        _ if IMPLEMENTATIONS.contains(&name) => {
+            bail!("{} support is not compiled in, enable the `{}` feature", name, name)
+        }
+        _ => bail!("unknown implementation {}, expected one of {:?}", name, IMPLEMENTATIONS),
+    };
+    Ok(prover)
+}
+
+fn verify(prover: &dyn KeccakProver, dir: &str) -> Result<()> {
+    info!("{}: verifying the proof saved in {}", prover.name(), dir);
+    let result = prover.verify_saved(Path::new(dir))?;
+    println!("{}", VerifyResult::csv_header());
+    println!("{}", result.to_csv_row());
+    if !result.valid {
+        bail!("{}: the proof in {} is invalid", prover.name(), dir);
+    }
+    Ok(())
+}
+
+#[cfg(feature = "axiom")]
+fn plan(args: &Args) -> Result<()> {
+    use bench_runner::axiom::calibrate;
+    use zkevm_keccak::planner::{self, ShapePlan, Workload};
+    use zkevm_keccak::KeccakConfigParams;
+
+    let workload = match args.budget {
+        Some(bytes) => Workload::Bytes(bytes),
+        None => Workload::Messages(args.sizes.clone()),
+    };
+    let metric: planner::Metric = args.metric.parse().map_err(|e: String| anyhow!(e))?;
+    let parameters = KeccakConfigParams { k: args.axiom_k, rows_per_round: args.axiom_rows };
+    info!(
+        "calibrating with a proof of the Axiom circuit with k = {} and {} rows per round",
+        parameters.k, parameters.rows_per_round
+    );
+    let calibration = calibrate(parameters)?;
+    if calibration.memory.is_none() && metric == planner::Metric::Memory {
+        bail!("the peak memory cannot be read on this platform");
+    }
+    let (ks, rows_per_round) = planner::default_shapes();
+    let plans = planner::plan(&workload, metric, &calibration, ks, rows_per_round);
+    let best = plans.first().ok_or_else(|| {
+        anyhow!("no shape of the Axiom circuit fits {} keccak_f's", workload.num_keccak_f())
+    })?;
+    println!("{}", ShapePlan::csv_header());
+    for plan in &plans {
+        println!("{}", plan.to_csv_row());
+    }
+    info!(
+        "recommended for {}: --axiom-k {} --axiom-rows {}, using {:.1}% of its {} keccak_f's",
+        metric,
+        best.parameters.k,
+        best.parameters.rows_per_round,
+        best.utilization * 100.0,
+        best.capacity
+    );
+    Ok(())
+}
+
+// cargo run --release -- --blocks 1..=10 --save bench/proofs
+// cargo run --release -- verify --impl maru --dir bench/proofs/maru/136
+// cargo run --release -- plan --sizes 136,1000,5000 --metric proof-size
+fn main() -> Result<()> {
+    let _ = try_init_from_env(Env::default().filter_or(DEFAULT_FILTER_ENV, "info"));
+    let args = parse_args()?;
+    if args.plan {
+        #[cfg(feature = "axiom")]
+        return plan(&args);
+        #[cfg(not(feature = "axiom"))]
+        bail!("plan support is not compiled in, enable the `axiom` feature");
+    }
+    if let Some(dir) = &args.verify_dir {
+        let prover = make_prover(&args.implementations[0], &args)?;
+        return verify(prover.as_ref(), dir);
+    }
+    let provers = args
+        .implementations
+        .iter()
+        .map(|name| make_prover(name, &args))
+        .collect::<Result<Vec<_>>>()?;
+
+    if let Some(dir) = Path::new(&args.out).parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let mut fs_results =
+        File::create(&args.out).with_context(|| format!("cannot create {}", args.out))?;
+    writeln!(fs_results, "{}", BenchResult::csv_header())?;
+    let mut fs_bn254 = if args.bn254 {
+        let out = format!("{}_bn254.csv", args.out.trim_end_matches(".csv"));
+        let mut file = File::create(&out).with_context(|| format!("cannot create {}", out))?;
+        writeln!(file, "{}", Bn254Result::csv_header())?;
+        Some(file)
+    } else {
+        None
+    };
+
+    for &msg_len in &args.sizes {
+        let msg: Vec<u8> = (0..msg_len).map(|_| rand::random()).collect();
+        for prover in &provers {
+            info!("{}: proving keccak256 of {} bytes", prover.name(), msg_len);
+            let save_dir = args
+                .save
+                .as_ref()
+                .map(|dir| PathBuf::from(dir).join(prover.name()).join(msg_len.to_string()));
+            match prover.prove_and_verify(&msg, save_dir.as_deref()) {
+                Ok(result) => {
+                    writeln!(fs_results, "{}", result.to_csv_row())?;
+                    fs_results.flush()?;
+                }
+                Err(e) => warn!("{}: {} bytes failed: {:#}", prover.name(), msg_len, e),
+            }
+            if let Some(fs_bn254) = &mut fs_bn254 {
+                info!("{}: wrapping the proof of {} bytes for BN254", prover.name(), msg_len);
+                match prover.prove_bn254(&msg, args.bn254_k) {
+                    Ok(result) => {
+                        writeln!(fs_bn254, "{}", result.to_csv_row())?;
+                        fs_bn254.flush()?;
+                    }
+                    Err(e) => warn!("{}: {} bytes BN254 failed: {:#}", prover.name(), msg_len, e),
+                }
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,168 @@
+use std::fs;
+use std::path::Path;
+use std::time::Instant;
+
+use anyhow::{ensure, Result};
+use keccak1::config::{KeccakConfig, KeccakConfigPreset};
+use keccak1::keccak::keccak256;
+use keccak1::keccak_ctl_proof::{load_keccak_ctl_proof, save_keccak_ctl_proof};
+use keccak1::keccak_sponge::variant::KeccakSpongeVariant;
+#[cfg(feature = "bn254")]
+use keccak1::poseidon_bn128::PoseidonBN128GoldilocksConfig;
+#[cfg(feature = "bn254")]
+use keccak1::snark_aggregation::aggregation_wrap_bn128;
+use keccak1::stark_aggregation::aggregation_circuit;
+use keccak_hash::keccak;
+use plonky2_maru::iop::witness::PartialWitness;
+use plonky2_maru::plonk::config::{GenericConfig, GenericHashOut, PoseidonGoldilocksConfig};
+use plonky2_maru::plonk::proof::ProofWithPublicInputs;
+
+#[cfg(feature = "bn254")]
+use crate::prover::Bn254Result;
+use crate::prover::{BenchResult, KeccakProver, VerifyResult};
+
+const D: usize = 2;
+type C = PoseidonGoldilocksConfig;
+type F = <C as GenericConfig<D>>::F;
+
+/// Maru's sponge and permutation STARKs, joined by a cross-table lookup and
+/// aggregated into a single plonky2 proof.
+///
+/// `witness_time` is the sponge and permutation trace generation, `prove_time`
+/// the CTL proof plus the aggregation proof, `build_time` the aggregation
+/// circuit build, and `verify_time`/`proof_size` refer to the aggregated proof.
+///
+/// The saved proof is the aggregated proof, next to the CTL proof it aggregates: the
+/// aggregation circuit only depends on the shape of the CTL proof, so `verify_saved`
+/// rebuilds it from the saved CTL proof and the config preset, then verifies the
+/// aggregated proof with it like `prove_and_verify` does.
+///
+/// `prove_bn254` wraps the aggregated proof.
+pub struct MaruProver {
+    preset: KeccakConfigPreset,
+    config: KeccakConfig,
+}
+
+#[cfg(feature = "bn254")]
+crate::bn254::to_halo2_data!(plonky2_maru, PoseidonBN128GoldilocksConfig);
+
+impl MaruProver {
+    pub fn new(preset: KeccakConfigPreset) -> Self {
+        Self { preset, config: preset.config() }
+    }
+}
+
+impl KeccakProver for MaruProver {
+    fn name(&self) -> &'static str {
+        "maru"
+    }
+
+    fn prove_and_verify(&self, msg: &[u8], save_dir: Option<&Path>) -> Result<BenchResult> {
+        let hash = keccak(msg);
+        let (
+            data,
+            proof,
+            ctl_proof,
+            sponge_trace_time,
+            permutation_trace_time,
+            proof_ctl_time,
+            _verify_ctl_proof_time,
+            aggregation_proof_time,
+            verify_aggregation_time,
+            _sponge_proof_size,
+            _permutations_proof_size,
+            aggregated_proof_size,
+            aggregation_circuit_build_time,
+            build_memory,
+            witness_memory,
+            prove_memory,
+        ) = keccak256::<F, C, D>(msg, hash.as_bytes(), &self.config)?;
+
+        if let Some(dir) = save_dir {
+            save_keccak_ctl_proof(dir, &ctl_proof, &self.config.stark_config)?;
+            fs::write(dir.join("config"), self.preset.name())?;
+            fs::write(dir.join("circuit_digest"), data.verifier_only.circuit_digest.to_bytes())?;
+            fs::write(dir.join("proof.bin"), proof.to_bytes())?;
+        }
+
+        Ok(BenchResult {
+            implementation: self.name(),
+            msg_len: msg.len(),
+            build_time: aggregation_circuit_build_time as f64,
+            witness_time: (sponge_trace_time + permutation_trace_time) as f64,
+            prove_time: (proof_ctl_time + aggregation_proof_time) as f64,
+            verify_time: verify_aggregation_time as f64,
+            proof_size: aggregated_proof_size,
+            evm_gas: None,
+            build_memory,
+            witness_memory,
+            prove_memory,
+        })
+    }
+
+    fn verify_saved(&self, dir: &Path) -> Result<VerifyResult> {
+        let load_start = Instant::now();
+        let preset: KeccakConfigPreset = fs::read_to_string(dir.join("config"))?.parse()?;
+        let config = preset.config();
+        let ctl_proof = load_keccak_ctl_proof::<F, C, D>(dir, &config.stark_config)?;
+        let data = aggregation_circuit(
+            KeccakSpongeVariant::KECCAK256,
+            &ctl_proof,
+            &config,
+            &mut PartialWitness::new(),
+        );
+        ensure!(
+            data.verifier_only.circuit_digest.to_bytes() == fs::read(dir.join("circuit_digest"))?,
+            "the saved proof is for a different circuit"
+        );
+        let proof =
+            ProofWithPublicInputs::from_bytes(fs::read(dir.join("proof.bin"))?, &data.common)?;
+        let load_time = load_start.elapsed().as_secs_f64();
+
+        let verify_start = Instant::now();
+        let valid = data.verify(proof).is_ok();
+        let verify_time = verify_start.elapsed().as_secs_f64();
+
+        Ok(VerifyResult { implementation: self.name(), load_time, verify_time, valid })
+    }
+
+    #[cfg(feature = "bn254")]
+    fn prove_bn254(&self, msg: &[u8], k: u32) -> Result<Bn254Result> {
+        let hash = keccak(msg);
+        let (
+            data,
+            proof,
+            _ctl_proof,
+            _sponge_trace_time,
+            _permutation_trace_time,
+            proof_ctl_time,
+            _verify_ctl_proof_time,
+            aggregation_proof_time,
+            _verify_aggregation_time,
+            _sponge_proof_size,
+            _permutations_proof_size,
+            aggregated_proof_size,
+            _aggregation_circuit_build_time,
+            _build_memory,
+            _witness_memory,
+            _prove_memory,
+        ) = keccak256::<F, C, D>(msg, hash.as_bytes(), &self.config)?;
+
+        let wrap_start = Instant::now();
+        let (wrapper, wrapped_proof) = aggregation_wrap_bn128((&data, &proof))?;
+        let wrap_time = wrap_start.elapsed().as_secs_f64();
+
+        let mut result = Bn254Result {
+            implementation: self.name(),
+            msg_len: msg.len(),
+            plonky2_prove_time: (proof_ctl_time + aggregation_proof_time) as f64,
+            plonky2_proof_size: aggregated_proof_size,
+            wrap_time,
+            wrap_proof_size: wrapped_proof.to_bytes().len(),
+            ..Default::default()
+        };
+        let (common, verifier_only, proof) = to_halo2_data(&wrapper, &wrapped_proof);
+        crate::bn254::prove_halo2(k, common, verifier_only, &proof, &mut result)?;
+        Ok(result)
+    }
+}
@@ -0,0 +1,146 @@
+use std::path::Path;
+
+use anyhow::{bail, Result};
+use bench_memory::PhaseMemory;
+
+/// Normalized measurements of proving and verifying a single keccak256 hash.
+///
+/// Times are in seconds, sizes in bytes. Each implementation splits its pipeline
+/// differently, so the adapters document which of their steps fall into each phase.
+#[derive(Clone, Debug, Default)]
+pub struct BenchResult {
+    pub implementation: &'static str,
+    pub msg_len: usize,
+    pub build_time: f64,
+    pub witness_time: f64,
+    pub prove_time: f64,
+    pub verify_time: f64,
+    pub proof_size: usize,
+    /// Gas used by the call of an EVM verifier contract, for the implementations that measure it.
+    pub evm_gas: Option<u64>,
+    pub build_memory: PhaseMemory,
+    pub witness_memory: PhaseMemory,
+    pub prove_memory: PhaseMemory,
+}
+
+impl BenchResult {
+    pub fn csv_header() -> String {
+        format!(
+            "implementation,msg_len,build_time,witness_time,prove_time,verify_time,proof_size,\
+             evm_gas,peak_memory,{},{},{}",
+            PhaseMemory::csv_header("build"),
+            PhaseMemory::csv_header("witness"),
+            PhaseMemory::csv_header("prove"),
+        )
+    }
+
+    /// Highest peak resident set size over all measured phases.
+    pub fn peak_memory(&self) -> Option<usize> {
+        [self.build_memory, self.witness_memory, self.prove_memory]
+            .iter()
+            .filter_map(|phase| phase.peak_rss)
+            .max()
+    }
+
+    pub fn to_csv_row(&self) -> String {
+        format!(
+            "{},{},{},{},{},{},{},{},{},{},{},{}",
+            self.implementation,
+            self.msg_len,
+            self.build_time,
+            self.witness_time,
+            self.prove_time,
+            self.verify_time,
+            self.proof_size,
+            self.evm_gas.map(|gas| gas.to_string()).unwrap_or_default(),
+            self.peak_memory().map(|m| m.to_string()).unwrap_or_default(),
+            self.build_memory,
+            self.witness_memory,
+            self.prove_memory,
+        )
+    }
+}
+
+/// Measurements of verifying a saved proof in a separate process, in seconds.
+#[derive(Clone, Debug)]
+pub struct VerifyResult {
+    pub implementation: &'static str,
+    /// Reading the verifier data and the proof, plus any setup the verifier needs before it
+    /// can check the proof.
+    pub load_time: f64,
+    pub verify_time: f64,
+    pub valid: bool,
+}
+
+impl VerifyResult {
+    pub fn csv_header() -> &'static str {
+        "implementation,load_time,verify_time,valid"
+    }
+
+    pub fn to_csv_row(&self) -> String {
+        format!("{},{},{},{}", self.implementation, self.load_time, self.verify_time, self.valid)
+    }
+}
+
+/// Times, in seconds, and sizes, in bytes, of each stage from a keccak256 proof to a halo2 KZG
+/// proof over BN254, which an EVM can verify, for the implementations with plonky2 proofs.
+#[derive(Clone, Debug, Default)]
+pub struct Bn254Result {
+    pub implementation: &'static str,
+    pub msg_len: usize,
+    /// The keccak256 proof, with a config that can be verified recursively.
+    pub plonky2_prove_time: f64,
+    pub plonky2_proof_size: usize,
+    /// Building and proving the recursion layer hashed with Poseidon over BN254.
+    pub wrap_time: f64,
+    pub wrap_proof_size: usize,
+    /// The halo2 circuit verifying the wrapped proof, proven with SHPLONK.
+    pub halo2_keygen_time: f64,
+    pub halo2_prove_time: f64,
+    pub halo2_verify_time: f64,
+    pub halo2_proof_size: usize,
+}
+
+impl Bn254Result {
+    pub fn csv_header() -> &'static str {
+        "implementation,msg_len,plonky2_prove_time,plonky2_proof_size,wrap_time,wrap_proof_size,\
+         halo2_keygen_time,halo2_prove_time,halo2_verify_time,halo2_proof_size"
+    }
+
+    pub fn to_csv_row(&self) -> String {
+        format!(
+            "{},{},{},{},{},{},{},{},{},{}",
+            self.implementation,
+            self.msg_len,
+            self.plonky2_prove_time,
+            self.plonky2_proof_size,
+            self.wrap_time,
+            self.wrap_proof_size,
+            self.halo2_keygen_time,
+            self.halo2_prove_time,
+            self.halo2_verify_time,
+            self.halo2_proof_size,
+        )
+    }
+}
+
+/// A keccak256 circuit implementation that can be benchmarked by the runner.
+pub trait KeccakProver {
+    /// Short identifier used in the `implementation` column and on the command line.
+    fn name(&self) -> &'static str;
+
+    /// Builds a circuit for `msg`, proves that it hashes to keccak256(msg), verifies
+    /// the proof and reports the timings and memory usage of each phase. With `save_dir`,
+    /// also writes the verifier data and the proof there for `verify_saved`.
+    fn prove_and_verify(&self, msg: &[u8], save_dir: Option<&Path>) -> Result<BenchResult>;
+
+    /// Loads the verifier data and the proof written by `prove_and_verify` from `dir` and
+    /// verifies the proof, without any state shared with the prover.
+    fn verify_saved(&self, dir: &Path) -> Result<VerifyResult>;
+
+    /// Proves `msg` as `prove_and_verify` does, wraps the proof in a recursion layer hashed with
+    /// Poseidon over BN254 and proves that layer in a halo2 circuit of `2^k` rows.
+    fn prove_bn254(&self, _msg: &[u8], _k: u32) -> Result<Bn254Result> {
+        bail!("{} has no plonky2 proof to wrap", self.name())
+    }
+}
@@ -0,0 +1,1778 @@
+// Generated with the Grain LFSR of the Poseidon reference implementation for the BN254
+// scalar field, t = 4, R_F = 8, R_P = 56 and alpha = 5, in Montgomery form.
+
+use crate::Fr;
+
+pub(crate) const ROUND_CONSTANTS: [[Fr; 4]; 64] = [
+    [
+        Fr([
+            0x40e29857eccba526,
+            0x78b5d11f628bb63c,
+            0x90a91f8124d71c1d,
+            0x22b90b99257c701f,
+        ]),
+        Fr([
+            0xdda103bcd5e88168,
+            0xcadec275563908df,
+            0xcb42faa49bda666a,
+            0x12c1e60e14878465,
+        ]),
+        Fr([
+            0xe2ee3f59de1800c2,
+            0xc7979d60539090ba,
+            0xb17490108efd09c7,
+            0x157ae4cd6c889238,
+        ]),
+        Fr([
+            0x8d47060cfdf35e56,
+            0x24d3c6c56012e0bb,
+            0x23e529e2d9c7f211,
+            0x0ca313c0e7feeb7c,
+        ]),
+    ],
+    [
+        Fr([
+            0x969ae5874ec24b7c,
+            0x178d4318d4b3aa33,
+            0x6c88dcc609ed64f8,
+            0x2f010ac693dc51cf,
+        ]),
+        Fr([
+            0x57fb28a4e65e43e8,
+            0x8879374e40bb2e20,
+            0x2edbd9642db3a00b,
+            0x03519d1e42f98e5e,
+        ]),
+        Fr([
+            0x6cd497820ea1bdea,
+            0x7547fcc6a94b2b4d,
+            0xd170eabdd0b9a60b,
+            0x2d4d4875bba571b3,
+        ]),
+        Fr([
+            0x6ef12da5bec195b2,
+            0x1949053b40632693,
+            0x52a07adc23aaa26c,
+            0x2462f2ed9e08bb84,
+        ]),
+    ],
+    [
+        Fr([
+            0x86b215d7eed56c26,
+            0x8e8a775ab44e8536,
+            0x4b28315db24328ac,
+            0x2284e6859b91a22b,
+        ]),
+        Fr([
+            0x24435d7bbcecf683,
+            0x30558031784b101a,
+            0x8152ba72043ca93a,
+            0x2ef0fea092a1bf2f,
+        ]),
+        Fr([
+            0x31f7ebe8ea71df23,
+            0x5a40c44d8b396ad0,
+            0x12103e68c9964844,
+            0x2fbf0a0711796f09,
+        ]),
+        Fr([
+            0xd171b165b9a474d2,
+            0xde9cc2290d05e663,
+            0xaaa87c63209d7a7a,
+            0x138033c1c13594ca,
+        ]),
+    ],
+    [
+        Fr([
+            0x7bfd9fcbd4ed1036,
+            0x5d413eaa832eba40,
+            0xb509d471e9b81cf4,
+            0x1287cef711f48909,
+        ]),
+        Fr([
+            0xaa8460cf946b6f1f,
+            0xd383ed881df9301d,
+            0x13dab19464f23a61,
+            0x03edc8f24d94f496,
+        ]),
+        Fr([
+            0x8fb0a2baec4a0545,
+            0x62950a986fa7e269,
+            0x8b2e63ef08420ccb,
+            0x24357c1c0ff8ad46,
+        ]),
+        Fr([
+            0xebc3984647d708de,
+            0xfa8876a528054f45,
+            0x529aa21b353025ae,
+            0x11e5fb7b548a5daa,
+        ]),
+    ],
+    [
+        Fr([
+            0x1c447b0131825829,
+            0x6eea85c396cfd279,
+            0x906fa66e44792b70,
+            0x0f417b2b1192fc58,
+        ]),
+        Fr([
+            0x893dc44ff4ab8c3d,
+            0xdc5162ae7480b4d7,
+            0xd2d2263cef2ce577,
+            0x2bff5bd4cf84da5b,
+        ]),
+        Fr([
+            0xb77ac980d5b4b49b,
+            0x333a038690498325,
+            0x7d075bd47648701e,
+            0x14e2b4d3be83b2ac,
+        ]),
+        Fr([
+            0x529b26c9e1e7fee7,
+            0xe3b9ce82471f6c16,
+            0x7b784e18fc148e9a,
+            0x0af58ae3a7d121ad,
+        ]),
+    ],
+    [
+        Fr([
+            0xf8d1c651163db662,
+            0x1e3bceb597676b5a,
+            0x2e280d1a469bd269,
+            0x1cc9d2eaf4da683b,
+        ]),
+        Fr([
+            0x284f1e6a5e8c3243,
+            0xdf6e2c5fa443c438,
+            0xa141ac6c13990c9c,
+            0x2406d65ed9963ebf,
+        ]),
+        Fr([
+            0x833da6aed5e8b643,
+            0xf6e1ac68d94bbf99,
+            0x92e1ab49bac7591f,
+            0x1f14ec7cf779855e,
+        ]),
+        Fr([
+            0x1f360ba6b2dbe89c,
+            0x39a6bfcefa7aa570,
+            0x7f9114a8abd3c437,
+            0x241559c0a38ff005,
+        ]),
+    ],
+    [
+        Fr([
+            0x82f0f015e32b0fa5,
+            0x15b14571a9827bdf,
+            0xb77fbead604b82fb,
+            0x084368258f1bd0dd,
+        ]),
+        Fr([
+            0x6e0151e98341eaab,
+            0x347e8c0f39d2ae2b,
+            0x2f6a4f01fd8e7354,
+            0x1224728eae4780c7,
+        ]),
+        Fr([
+            0xa64a59e07277da93,
+            0xec9ffe497e750319,
+            0xf8de0d292fd81230,
+            0x200ba0070e4cf7e7,
+        ]),
+        Fr([
+            0x3eee8ca147f74c1e,
+            0xb459e1c13d394847,
+            0xad3a506f72dfc6e9,
+            0x0024a1a4a98cdf30,
+        ]),
+    ],
+    [
+        Fr([
+            0x4677744f22ba290c,
+            0x9665c3069828d067,
+            0x47a5ab4a08482095,
+            0x050856d34a7b53a1,
+        ]),
+        Fr([
+            0xe9ac990036447fd5,
+            0x67e1d967ff728f98,
+            0x520db222d8f9b85b,
+            0x300d753949da28d0,
+        ]),
+        Fr([
+            0xe0a4d3db3031c219,
+            0xbdfc328b9599d375,
+            0x11f23cd7fe507de4,
+            0x1bf0b2f3b0ae16c8,
+        ]),
+        Fr([
+            0x18018546eeba2623,
+            0x1de71123707f1b9c,
+            0x7f6c0e2ad000f8e9,
+            0x28f46b322d82057f,
+        ]),
+    ],
+    [
+        Fr([
+            0x3ac593da4e5db6eb,
+            0x3ff4bba0193a3b2d,
+            0xe0ad26ec69346ef2,
+            0x24849ad2c138914e,
+        ]),
+        Fr([
+            0xa68ceacc9a188251,
+            0x905a4a607ab50758,
+            0xdeba6d539f998afa,
+            0x1fff49f7df96654f,
+        ]),
+        Fr([
+            0xc067c30e7cc3a431,
+            0x34d758f9cd522f83,
+            0x3435beccd217edeb,
+            0x2a89e5139fbdf039,
+        ]),
+        Fr([
+            0xd5a6847c60a8f7cc,
+            0xd5aff503c7ba7883,
+            0x8b2bfd2ad28ddd20,
+            0x101835888a279fc0,
+        ]),
+    ],
+    [
+        Fr([
+            0x50fa7503e3eb3735,
+            0xd8495c79cd5e9211,
+            0x7b7e2a5040c3544b,
+            0x197e6d90a94415a4,
+        ]),
+        Fr([
+            0x6ea1d22532ef2471,
+            0x4e96177082af0ac7,
+            0xed2f1dfb1189f2bb,
+            0x27fbb36a7bd9b353,
+        ]),
+        Fr([
+            0xa7ee25387ddb3c34,
+            0xf30145444d0fe612,
+            0x9c0473e043c5762b,
+            0x2bfa2b7461fc9c12,
+        ]),
+        Fr([
+            0xe74c0280a8d7a2d8,
+            0x15b2fc08d1baac9f,
+            0x7ef491cb5fda8359,
+            0x2622426d179ee045,
+        ]),
+    ],
+    [
+        Fr([
+            0xfeea8798b783f986,
+            0x21f8b55837df791c,
+            0x4715c04841f1f926,
+            0x100ebb18ec5c3f93,
+        ]),
+        Fr([
+            0x122aa722f6947206,
+            0x522b121946ac0fee,
+            0x45b9250f69dac1c0,
+            0x25293cd27572f636,
+        ]),
+        Fr([
+            0x936f54ff136b08ab,
+            0x700dd002430020cc,
+            0xd06242f794739877,
+            0x2268c9c162417cab,
+        ]),
+        Fr([
+            0x1c9b445dd0a2241a,
+            0xc11cb47b21df29cd,
+            0xf7a492c3594f0f09,
+            0x034b38a7146edc66,
+        ]),
+    ],
+    [
+        Fr([
+            0x1dfe4ac0183b8133,
+            0x1ef0319b13fb779a,
+            0x11dbf892ab5f926e,
+            0x17a856c53659a7ee,
+        ]),
+        Fr([
+            0x059e222bcd5307a6,
+            0xccfaf000d2effb7c,
+            0x1fd59fef9a8cf932,
+            0x0c4ce24e93909eae,
+        ]),
+        Fr([
+            0x42d0cafee2301d0e,
+            0x79696ca22bdcb93f,
+            0x5bacb0d63592018b,
+            0x29294687e7715542,
+        ]),
+        Fr([
+            0x42a5414804c7099b,
+            0x6fe76c6243e5c38a,
+            0x254493c8af4e6367,
+            0x1e3fcd8156bc983d,
+        ]),
+    ],
+    [
+        Fr([
+            0xa7ff7255f2412486,
+            0x7501ca14401795c0,
+            0x2d0e3a0db6e4b1ff,
+            0x0096d162a3cc320f,
+        ]),
+        Fr([
+            0x789c20e48b1125f9,
+            0x528ce70d5612a45c,
+            0x8d645f61a75e2b38,
+            0x26549dc15b1fa715,
+        ]),
+        Fr([
+            0x775b5e0fba5758e9,
+            0xd39a069c41c98c95,
+            0x68a784316ed9fd47,
+            0x2dd54f4070bcfbe6,
+        ]),
+        Fr([
+            0x9d57dffcd1ba98ba,
+            0x2554bc6bf0d32b4f,
+            0xfaa1a29bff5eaacc,
+            0x1bf848fcc8ea6c31,
+        ]),
+    ],
+    [
+        Fr([
+            0x3264c88684b1bcb6,
+            0xb5e9ff4335f99477,
+            0x81aca7593bdc6348,
+            0x1a6338f428a0b816,
+        ]),
+        Fr([
+            0x24779cc69cba1dea,
+            0x308776f8218f1be6,
+            0x3a9e52d12b3f619e,
+            0x06d8d604ce3e2de3,
+        ]),
+        Fr([
+            0xc7b00cf37e64dc49,
+            0x06d1c1802949bc23,
+            0xfd608a1fa1b25168,
+            0x22ebae49ba188f7a,
+        ]),
+        Fr([
+            0xca1469d4458d8a3d,
+            0x7623fb53afb084b6,
+            0xa0abc7e8d556d8f0,
+            0x2a8b5c3b5f3dfadf,
+        ]),
+    ],
+    [
+        Fr([
+            0x33f5eadd9c1595c3,
+            0x54d6810e1d084193,
+            0xa13eb5c950c2db99,
+            0x1ac1693fb6209dfc,
+        ]),
+        Fr([
+            0x57b804a8493ffadb,
+            0xf4ecda7bda07b05e,
+            0x365ad26d1dde9556,
+            0x26840fe4f165a935,
+        ]),
+        Fr([
+            0xcb958721444341c1,
+            0xd202fc009bbb1137,
+            0xe10a117befbfbaef,
+            0x2fefe67ccff0718b,
+        ]),
+        Fr([
+            0x57166a5f56a5da25,
+            0x9651ac4d559f2de5,
+            0xe0a3ce57440d7536,
+            0x1d7d8da39c2e8df9,
+        ]),
+    ],
+    [
+        Fr([
+            0x955694cf7e751759,
+            0x1f19c299b2bf9669,
+            0x891a562d7d697b72,
+            0x25b1eb3a2c62c131,
+        ]),
+        Fr([
+            0x27d15e03d244680e,
+            0x52a139938503b16b,
+            0xa60293987e1780d7,
+            0x27bdf8da7f79736c,
+        ]),
+        Fr([
+            0x116f555634550fcd,
+            0x3df9127a2fff7266,
+            0xf3cd0bf9d381aa33,
+            0x16c19d6f034f31b9,
+        ]),
+        Fr([
+            0x66d80999699047ae,
+            0xd3be6bda729fbceb,
+            0xcfabbd2afaf882be,
+            0x05b5d05f43fd6e3f,
+        ]),
+    ],
+    [
+        Fr([
+            0x2c74114e481ddafa,
+            0x807434126e9f472b,
+            0xab570928cd20418a,
+            0x283d4a89a200a5af,
+        ]),
+        Fr([
+            0xd411d7242d41f6b9,
+            0x2633b38dce58b2a3,
+            0xb88e71e3b9f250f6,
+            0x1fd33b9c3927530c,
+        ]),
+        Fr([
+            0x97b472e62acef0f6,
+            0x14790dc3fcd93bbc,
+            0x5400b0481e349424,
+            0x2ceaf69d01ac2e60,
+        ]),
+        Fr([
+            0x668c4cd514449780,
+            0x43c5c8d06641b850,
+            0x4f75ba88b2b749e2,
+            0x267a187cac4732f2,
+        ]),
+    ],
+    [
+        Fr([
+            0xb085fdc4f9a8fb0b,
+            0x71be2e0129e44e36,
+            0xe6b0eeb850b499a4,
+            0x02b354c2103cddba,
+        ]),
+        Fr([
+            0x0597e2d67467dcbf,
+            0xfb80c331cfc45fb9,
+            0xdac61e4a03d6a081,
+            0x1a11b729bcdb094a,
+        ]),
+        Fr([
+            0xf3fee4e36f25bfbd,
+            0x2e1ebc2bb09c65b3,
+            0xe2e0496cea732590,
+            0x0880a9b301012352,
+        ]),
+        Fr([
+            0x71df5a89af984d00,
+            0x7edb46887d56dd30,
+            0xec05215acaf42515,
+            0x18ff6fdc4fcf3d35,
+        ]),
+    ],
+    [
+        Fr([
+            0xd452149f1a7623ba,
+            0x13d73472905865d9,
+            0x68860941a82dff72,
+            0x00f33f4676757a07,
+        ]),
+        Fr([
+            0x0e60a44a6d294f41,
+            0x03b13324ddc4baa0,
+            0x4c7ce58cc39ab341,
+            0x139be6cb2abe19c0,
+        ]),
+        Fr([
+            0x9ee46be37fa5c783,
+            0x4b80d6e92fa0605d,
+            0xe18d39eaa0b58710,
+            0x019935cf03fe6d3b,
+        ]),
+        Fr([
+            0xa11eecbd6f646bef,
+            0xdf805be13d0c2ba1,
+            0xb1843fb18f8d6723,
+            0x0ecac0e06c90fbd4,
+        ]),
+    ],
+    [
+        Fr([
+            0x20ca3789db7f08aa,
+            0x8d4280081bb2f0df,
+            0x37f8a0a772d173e5,
+            0x2704fea2f81ae93b,
+        ]),
+        Fr([
+            0x0b57a210d5ab61aa,
+            0xb5bcedca8189a1ea,
+            0x1cdbbf1ab293e0ac,
+            0x100b4344f9ed48ae,
+        ]),
+        Fr([
+            0x907e5f4a9c057fc4,
+            0x575a1e1729d2396b,
+            0x2f81eb61b02a0d47,
+            0x201c9d0d354d25f3,
+        ]),
+        Fr([
+            0x6e0d1e92b19775e8,
+            0x1b0ff212c85487ae,
+            0x77fa05e5ed730bb5,
+            0x0da570e83ab6b8d0,
+        ]),
+    ],
+    [
+        Fr([
+            0xe33228d2d96b8f30,
+            0xb89a3bb8fb9be93a,
+            0x35e9eca0bee565b1,
+            0x273df6b0ae156020,
+        ]),
+        Fr([
+            0xa436a2acdb5ac551,
+            0x8514ac3b41c606e4,
+            0xc28036d7cdf04c60,
+            0x1ea071117eb66e66,
+        ]),
+        Fr([
+            0x6126235d108821f4,
+            0x4a7765cfee75f731,
+            0x743ed0ff07674e14,
+            0x05925bb80a3cc48c,
+        ]),
+        Fr([
+            0x7a92010e5b2e74a1,
+            0xfee649f03b0684db,
+            0x7aa7ccf81a3caab2,
+            0x23720c2cfdfbc79d,
+        ]),
+    ],
+    [
+        Fr([
+            0x460a85496e304e37,
+            0xdd8f527562040055,
+            0x40a70979fd5a7579,
+            0x1f03fbf1087a3e1c,
+        ]),
+        Fr([
+            0x0b43e0bf81c186ed,
+            0x47ce7c0961079afb,
+            0xb9f3357e2e55217d,
+            0x17e4fd8d6775a696,
+        ]),
+        Fr([
+            0xb74d133c633b1599,
+            0x4bf76b0fba2d94e2,
+            0xfec7cce4c5cd67f8,
+            0x183bceaeea7f1aaa,
+        ]),
+        Fr([
+            0x62a251f58bfc0bd8,
+            0xa47572f6ebc5885b,
+            0x37c60ff8efcf2b6d,
+            0x1fd8d931dc1b5f33,
+        ]),
+    ],
+    [
+        Fr([
+            0x75b3e54a5cdaaffe,
+            0x7ec80dc1562dc345,
+            0x6a48129759a833ac,
+            0x2fc9974a82a07fb8,
+        ]),
+        Fr([
+            0xeba8b0eb2795f017,
+            0xdba5a4f2a2641fee,
+            0x5792dd59f90eb5c8,
+            0x252e817931c59366,
+        ]),
+        Fr([
+            0x6a94198f6802fc00,
+            0x4af4f6a80279ac2f,
+            0x32916f9784f358fb,
+            0x07d6b6afbe4419aa,
+        ]),
+        Fr([
+            0x5970f06a69059757,
+            0x7f3029c3839f1ca4,
+            0x07aef1800e191c11,
+            0x0e5b98f37b97d967,
+        ]),
+    ],
+    [
+        Fr([
+            0xc43dff0434b7997e,
+            0x949a692bb405c21f,
+            0x710c0ad545eef327,
+            0x2e519c190d8ab005,
+        ]),
+        Fr([
+            0x743edc296a356c08,
+            0xbcb91cac24d38adc,
+            0xd9056eb212ec5c9e,
+            0x1010f747fd0c8463,
+        ]),
+        Fr([
+            0x66efcd4ce7fe3608,
+            0x9c833d7d7e46175a,
+            0x92febc92e7776bd5,
+            0x1787e89c1708200e,
+        ]),
+        Fr([
+            0x36c3d3ee449ad781,
+            0xe2515055c4b45997,
+            0x9042908f4fde7f86,
+            0x020f985d6e9d8d45,
+        ]),
+    ],
+    [
+        Fr([
+            0x1dac1abd3b5a9001,
+            0x51dd7fe0d39a3ca3,
+            0x6dfa5641c34e9b8e,
+            0x2a761370bb92e183,
+        ]),
+        Fr([
+            0x8466c220c3ee6bbc,
+            0x236705ae2b8fcb3e,
+            0x1d108ea940aabccd,
+            0x1982ea7529643450,
+        ]),
+        Fr([
+            0x7fb2b43005d6931c,
+            0x7a4dc146052eba7e,
+            0xfc7bb5a058187dbe,
+            0x26f223404d134585,
+        ]),
+        Fr([
+            0x4ab70f4d178b4763,
+            0x8efe6ff9b4a51f59,
+            0xfe541d4324ef4f5c,
+            0x1d7e9c034dd10b4e,
+        ]),
+    ],
+    [
+        Fr([
+            0x5eff84b09e0720dc,
+            0x70eb23f4336b0dd0,
+            0xb7b4c6dc394e9b94,
+            0x06313b7f836cd6f3,
+        ]),
+        Fr([
+            0xe1680367db603c6f,
+            0xec1ce37c703f167b,
+            0x77ed16f9edfb76ba,
+            0x0db1223727e76a84,
+        ]),
+        Fr([
+            0x4184dcb0878e2f8e,
+            0xcaf76e5617e33395,
+            0x87973035120f160a,
+            0x1088ea4553b89702,
+        ]),
+        Fr([
+            0x1c1283981a986fbb,
+            0xa13559c59b084bd1,
+            0xcc3c843e804902b9,
+            0x05e81d80059329d8,
+        ]),
+    ],
+    [
+        Fr([
+            0x6df846ad0897371d,
+            0xa0796b766d196ddb,
+            0x5521d7577d5e3451,
+            0x0dfbed052e7b1647,
+        ]),
+        Fr([
+            0x9bab92f979900231,
+            0xcc80cfebe4155c90,
+            0x0e14275034b23244,
+            0x16f0e04a48a71270,
+        ]),
+        Fr([
+            0x2032deeaa7f58ce3,
+            0x3398acd7f11bb5b7,
+            0x40182bee98c7124b,
+            0x2908ce21f17b9dda,
+        ]),
+        Fr([
+            0x26b529936e4e2024,
+            0xa6c0ba64325b75a8,
+            0x1a2d97ddf301b13c,
+            0x0eb8d9eb541d3f8c,
+        ]),
+    ],
+    [
+        Fr([
+            0xa10fa19afc14b912,
+            0xf76a7f8a6f7855a2,
+            0x905092133a7ac212,
+            0x0d224b168ea449f3,
+        ]),
+        Fr([
+            0x117464a50cdedc98,
+            0x3e406a52a4268c95,
+            0x0126ca9694762708,
+            0x17d093b8a3617092,
+        ]),
+        Fr([
+            0x801b01c017642829,
+            0xa0b3023f43cb1f10,
+            0xd2aed0975dcff391,
+            0x263dbe17b136f6ae,
+        ]),
+        Fr([
+            0xd9a4dc784bf504b9,
+            0x6b05ed147aeafcae,
+            0x935f49627e64a8c5,
+            0x05bbcffeb0b92465,
+        ]),
+    ],
+    [
+        Fr([
+            0x1256268ad7fdb21b,
+            0x06aacdf761242e78,
+            0xe7db68bc2ae776ef,
+            0x1ba84a7a415606d8,
+        ]),
+        Fr([
+            0xfd43d0d9d099820b,
+            0x06077f2baed24a23,
+            0x11d9802b12e6a913,
+            0x0be45209652872c8,
+        ]),
+        Fr([
+            0x034a9dc5fe163ac8,
+            0x2d97e79a0a31c6d7,
+            0xffb80347a58ef610,
+            0x02160c48234cb223,
+        ]),
+        Fr([
+            0xfe607de9d92ba63b,
+            0x3bed90225e7f8bd9,
+            0x064d316348f68eda,
+            0x025fc12ce1fff9ed,
+        ]),
+    ],
+    [
+        Fr([
+            0x1df0dcc9b51e7999,
+            0xa564d60294f84a23,
+            0x60b6e2ce83d15fcc,
+            0x19f1177d7deae419,
+        ]),
+        Fr([
+            0x7ecd8ffb36470bea,
+            0x2fb06c445557e893,
+            0x4cb8dfe0ee46a90d,
+            0x1868500123758f74,
+        ]),
+        Fr([
+            0x7d8e5ab384f73409,
+            0xb593b7b0b050cad9,
+            0xb1924234400dc16c,
+            0x283087f13259a45b,
+        ]),
+        Fr([
+            0x2b66c69e9a0f2bce,
+            0x7653879192b49538,
+            0xdd007e2669a05982,
+            0x18e2c37c3751293a,
+        ]),
+    ],
+    [
+        Fr([
+            0x147ed539b5d85283,
+            0x88b8a79b91fce245,
+            0x58e9a33d8e8b4dc8,
+            0x0d9a113b51de2c8a,
+        ]),
+        Fr([
+            0xa086069e4d8a9fa1,
+            0x70af385c48320ac7,
+            0x8b55b8fef6099ccd,
+            0x2f51e57ef8952c9a,
+        ]),
+        Fr([
+            0x831443febe187254,
+            0xd8924e571f9155b4,
+            0x69c8fd977fc6e36c,
+            0x1de83d37b03ad0f9,
+        ]),
+        Fr([
+            0xdd4aabf7d59175b4,
+            0xe73df9ec4cf87ad3,
+            0x32e31d50df40e181,
+            0x1d5d9ac1044306ab,
+        ]),
+    ],
+    [
+        Fr([
+            0xc335124cee1b0a5d,
+            0x3ff32a69b4482890,
+            0x5f29b5cf9b2492c9,
+            0x06af7afac9a91933,
+        ]),
+        Fr([
+            0x9bc610fb60af9764,
+            0x5ce2f2df666ca827,
+            0x31428fdde9eb1622,
+            0x24e70a88f666c829,
+        ]),
+        Fr([
+            0x3c70d30489532c2f,
+            0xdf4dab327b1eeff9,
+            0x6fc476f8bdd6f6b9,
+            0x10dabb4fbda6281b,
+        ]),
+        Fr([
+            0x44d6dd7a51c3a458,
+            0x9e0bed358e72c257,
+            0x5c7dd63672f8626f,
+            0x105d42ef5ce39fab,
+        ]),
+    ],
+    [
+        Fr([
+            0xf382274f1863892a,
+            0xb5e2d928d1e734b2,
+            0xe7f87063e7714186,
+            0x2e6c5ee40605d941,
+        ]),
+        Fr([
+            0x92530fd8cbca66e9,
+            0x1b3dce1349ddc807,
+            0x39619225fa60bebe,
+            0x00bb70efa5f4f11a,
+        ]),
+        Fr([
+            0xa2526e6b73a9aba5,
+            0xfdb94d44b70a897d,
+            0xa2ad7a254b305224,
+            0x16b5ad8323ab4b5b,
+        ]),
+        Fr([
+            0x4a8a6f6f626ef31c,
+            0xe7b7b26351f6d494,
+            0x388f0acd4ff778f1,
+            0x2b4ec7658e75beb4,
+        ]),
+    ],
+    [
+        Fr([
+            0xf21495f95224c9bc,
+            0x6ee58caf4ffade1e,
+            0xd69a41b9de6394e4,
+            0x1ba85426f3beb860,
+        ]),
+        Fr([
+            0x9a468233f3361de7,
+            0x0e68d30254540a8f,
+            0xb65c03e02881c652,
+            0x09845abf4c947294,
+        ]),
+        Fr([
+            0xb8c95930f8b1667c,
+            0xec0a3b3d34894bc5,
+            0x6f89407634760ab5,
+            0x1fd6752701d61fa0,
+        ]),
+        Fr([
+            0x60c0d62da39724a8,
+            0x51660377a910681a,
+            0xa62c8a24dc74501b,
+            0x26858b8c95ff42fb,
+        ]),
+    ],
+    [
+        Fr([
+            0x146b20091a89f67f,
+            0xde82f27aeedb31eb,
+            0x9d7e97860dfd065b,
+            0x163fab8d2f843c1d,
+        ]),
+        Fr([
+            0x667d67c7962e3613,
+            0xe3e056caed1a2e8a,
+            0x2668622ab832a037,
+            0x163413785dae738f,
+        ]),
+        Fr([
+            0xbd2f11c7611b8323,
+            0xd560a347ad41ae3a,
+            0xeeb481f7ca7b46f0,
+            0x093037838049b5ab,
+        ]),
+        Fr([
+            0xd6679340ec154896,
+            0xc46be00a1c338c96,
+            0xc70f07c89ba92315,
+            0x17a464bf902d0a7f,
+        ]),
+    ],
+    [
+        Fr([
+            0x8e76b4adf7810eb5,
+            0x8351475eec8ef140,
+            0x339230e70c331053,
+            0x160c7e1895d64ca1,
+        ]),
+        Fr([
+            0xb4d86bad828fb23c,
+            0x75b6e8147b1e4872,
+            0xd5e02e89d97e3c63,
+            0x16632423973b38c7,
+        ]),
+        Fr([
+            0xe81a6d943d383005,
+            0xb517a6ab182a4151,
+            0x50ad4cced88806ff,
+            0x2526f4db0ec6553f,
+        ]),
+        Fr([
+            0x979978adee93c1f6,
+            0x3250b54516534693,
+            0x0e25170062bc549c,
+            0x01dd58d4d255ed50,
+        ]),
+    ],
+    [
+        Fr([
+            0x3e2a93c501570f0f,
+            0x3a2c2f39c6192691,
+            0x4bd50b1cc96208b6,
+            0x09ca9c6a1a661b65,
+        ]),
+        Fr([
+            0x0fd90ff4a02f8476,
+            0xe8dc15488aa15682,
+            0xce574ea4c3888596,
+            0x17365f9d339d403f,
+        ]),
+        Fr([
+            0xe1288b51d12050ad,
+            0x651365d3efb1512c,
+            0xbe6014fc6df33a53,
+            0x19507fe7c01c0bcf,
+        ]),
+        Fr([
+            0x3dd4b6f3971de823,
+            0xb484480b0ce78321,
+            0x373c8c22768cca36,
+            0x17525880874646dc,
+        ]),
+    ],
+    [
+        Fr([
+            0x68dd6821a0f54491,
+            0x652d7fd195e356df,
+            0xf455e22a567196b5,
+            0x067807ca5211c77f,
+        ]),
+        Fr([
+            0xec6697515316260d,
+            0x0e2c431cc0946c37,
+            0x068f85458efa7954,
+            0x1cc36cc242f72a89,
+        ]),
+        Fr([
+            0x61a7290860189ed1,
+            0x9ba99004d5ce1f03,
+            0x5104e651295eb3f7,
+            0x20b9ad7bd168d4e0,
+        ]),
+        Fr([
+            0x2ab32d7082d964f5,
+            0xe51899b22b0a6055,
+            0x46827a7d3485005b,
+            0x199e3e2a5ed2a04e,
+        ]),
+    ],
+    [
+        Fr([
+            0xe6263c938ca24f78,
+            0xd9e7996e78cccd8c,
+            0xaacc2f02d0b2a3c9,
+            0x1b78c3b0e080a092,
+        ]),
+        Fr([
+            0x5d66a561a72d371a,
+            0xf60042c903a08f6d,
+            0xd5bb7dd4fd7fdf87,
+            0x0b96896c752172a6,
+        ]),
+        Fr([
+            0xbf06388854608359,
+            0xde2af4866ae71888,
+            0x27abd2c795b125ee,
+            0x14b8105179e71c9b,
+        ]),
+        Fr([
+            0x84d23eddfddb3cef,
+            0xbde22f49c46046b6,
+            0x35e3758a134822a0,
+            0x2f529b7764a414f0,
+        ]),
+    ],
+    [
+        Fr([
+            0x56ff193760840b76,
+            0xf24934593a527dfe,
+            0xfb544530fc4a024a,
+            0x25736c030637c884,
+        ]),
+        Fr([
+            0x8c4ad64ca93ade0e,
+            0xc5f7ac6242ef21c2,
+            0xbef611c87d3ecad4,
+            0x253d3c65cd58044a,
+        ]),
+        Fr([
+            0x6144cd25837f0e4c,
+            0x9a1c9cb221d87a2a,
+            0x3f5979c89a610541,
+            0x11196d97894f9837,
+        ]),
+        Fr([
+            0xd14456aa185a9650,
+            0x0145b8eb3d85d370,
+            0x2efcfdb4f6e72543,
+            0x0294249ef94e1db8,
+        ]),
+    ],
+    [
+        Fr([
+            0x671827bf8917c0f9,
+            0x5b86025369e6b69d,
+            0x19d3639730deb86a,
+            0x1934196b67f34e4c,
+        ]),
+        Fr([
+            0xa962783bcdd97138,
+            0xc528d85220dc358a,
+            0x2e1585818ef2aa72,
+            0x2d078c7771e4dd41,
+        ]),
+        Fr([
+            0x96d0b2add8b35089,
+            0x7887575eb5cfd36a,
+            0x9d8173d33cb98f58,
+            0x28011bf2ab562e65,
+        ]),
+        Fr([
+            0xf1e5c4e98612bcb8,
+            0xb9554806fbb61763,
+            0x097e746598f8b3ba,
+            0x1d31df8587ea2428,
+        ]),
+    ],
+    [
+        Fr([
+            0xdf545ca231cf854d,
+            0x281896bc314064e0,
+            0x9477d091490f3e78,
+            0x00e73b85a66b8d24,
+        ]),
+        Fr([
+            0xb4d7c16cf401b088,
+            0x83bfceec36e1ac56,
+            0x3bffe8faeec959c2,
+            0x1b97eb9cfcfdcb87,
+        ]),
+        Fr([
+            0x4c8e32d0177c5a0e,
+            0x04844a3a5b5551dc,
+            0x2f5e23a629c3d9ca,
+            0x21faaaed0c4f27ce,
+        ]),
+        Fr([
+            0x0f87127a80a4268f,
+            0x3f822dbfe6a8e09b,
+            0x2922afe764d3fd8a,
+            0x300438bf20c2064f,
+        ]),
+    ],
+    [
+        Fr([
+            0x271ed8b378bdccb1,
+            0xcb0e116361cee0f7,
+            0x5c7b19803a122740,
+            0x0dee59bea749473b,
+        ]),
+        Fr([
+            0x056ac2448e0c717d,
+            0x676f5949b2d20f95,
+            0x9d2ea637dab53c33,
+            0x206dbc64092a66db,
+        ]),
+        Fr([
+            0x2caf7db6d65e424c,
+            0x31a422f542d69836,
+            0xed0f5a79bdc18e52,
+            0x07698445d45687a0,
+        ]),
+        Fr([
+            0xbb5847440340f148,
+            0x51ae65088a72b07f,
+            0x532d3b259ed44ecb,
+            0x22c9e2be1e5b6c55,
+        ]),
+    ],
+    [
+        Fr([
+            0x8b11bd1f7fc3f9d8,
+            0x857df3396475a639,
+            0x614af4806a50f861,
+            0x0c62cde32aef1dd4,
+        ]),
+        Fr([
+            0x50faebac3d8ec827,
+            0x0728f280fcc21a93,
+            0xdfb078b375877d8d,
+            0x1aa850fcdafec702,
+        ]),
+        Fr([
+            0xa08c6f37a78a29af,
+            0xcb79f81c2ff2877c,
+            0xfef3cbd169836ba3,
+            0x1e67ae3bb879b64d,
+        ]),
+        Fr([
+            0x24d8a59d45fa4168,
+            0x70e1f10214f0473b,
+            0xbb8fb1aeae5bbff7,
+            0x0e04752ec3d334b3,
+        ]),
+    ],
+    [
+        Fr([
+            0xdbfd58d72c50186d,
+            0xd5c3514ca425a6db,
+            0xcad0fadf2d85bb61,
+            0x1813b5e7bb6af0e9,
+        ]),
+        Fr([
+            0x10a59479ce4c5045,
+            0xc8e8485c5f43470e,
+            0x637d5a9cb79f888a,
+            0x25cc7baf1dd457cb,
+        ]),
+        Fr([
+            0xa9aeb1d22e42a348,
+            0x4104562fa8d46ddd,
+            0x5405b9d3be418785,
+            0x02497f892db2eee4,
+        ]),
+        Fr([
+            0x2371c525915188a0,
+            0xd0ebabdcb09f27f6,
+            0x98bdc587a4509f32,
+            0x297e85ff07f576d9,
+        ]),
+    ],
+    [
+        Fr([
+            0x2f481080398f4ff9,
+            0xd9663e11821d8f32,
+            0x12e4fef28b6ca141,
+            0x066ac523b87243a5,
+        ]),
+        Fr([
+            0x48e624147a4ca810,
+            0x627a47f9a871a1d7,
+            0xebc31b190a00a4cc,
+            0x19c7bbcc6a4d3a87,
+        ]),
+        Fr([
+            0x6e71424a2ad865f0,
+            0xf16f4d7e7fc84654,
+            0xa95bb0b3d19d9ca8,
+            0x03fc4aa5db1e7daf,
+        ]),
+        Fr([
+            0x064e3ea36344a728,
+            0xb37a8d3ee1ea3581,
+            0xca746178eeec669f,
+            0x27a30712391b3e5d,
+        ]),
+    ],
+    [
+        Fr([
+            0xdd3ed4716a1c3fd0,
+            0x79edcd0fd6495aaa,
+            0x5200966d3eacb648,
+            0x07f1ce6304822a54,
+        ]),
+        Fr([
+            0xc809b41cdace1623,
+            0x7a33a604d367bfe3,
+            0x27797cd40951477a,
+            0x0b075dc4f543cbd1,
+        ]),
+        Fr([
+            0x262c515a6cecd4d8,
+            0x6130235443ad5d7f,
+            0x9aa8f881e1999eff,
+            0x1a42229d5c044403,
+        ]),
+        Fr([
+            0xd475e0dc22f987e0,
+            0xfa54dc5b2fdcb996,
+            0x5ae38b138d578ffa,
+            0x2940c682acf88df5,
+        ]),
+    ],
+    [
+        Fr([
+            0x118562b24a46cdfb,
+            0x2f75c498d6b8823b,
+            0x843d2e0d74fe6032,
+            0x27986838de90e9a2,
+        ]),
+        Fr([
+            0x947e1fff0f48a70b,
+            0x52688f33c2ff7dfd,
+            0xd47d9a0ca998f361,
+            0x2c292d440e0ae012,
+        ]),
+        Fr([
+            0x06e476f5923f2726,
+            0xe6595a037d9cc616,
+            0x076915d7cb8f06fe,
+            0x1e679943e405e672,
+        ]),
+        Fr([
+            0x24229b88e9f72cb4,
+            0xd9873103e3bc8100,
+            0xe8a734cc6c50d550,
+            0x16caaeddb4165727,
+        ]),
+    ],
+    [
+        Fr([
+            0x4f1ca735f72d519e,
+            0x70e37623856c45fb,
+            0x472763041429adbc,
+            0x25ea6dc7946272ef,
+        ]),
+        Fr([
+            0x8146a418c3a184d2,
+            0x88cdf8dbb2d11d5f,
+            0x4f9971bb5558fcb4,
+            0x1adf59e78604ba46,
+        ]),
+        Fr([
+            0xa13ec3e625af38ba,
+            0xe4f205e60c8ec7b2,
+            0x6eac32334fb7a468,
+            0x27b2b0aab4f6514a,
+        ]),
+        Fr([
+            0x9c5f28e753433c26,
+            0x113aac989d3047ae,
+            0xb736a85ebf28d166,
+            0x0d74e61b07d3ad7e,
+        ]),
+    ],
+    [
+        Fr([
+            0xf1d1269cfc2e7c5a,
+            0xb0e9d9ab49677167,
+            0xd0a084d47ea1dc50,
+            0x06853a10d0d9c0d0,
+        ]),
+        Fr([
+            0x23b52007844dbb88,
+            0x95e3bffedc5c5dd5,
+            0x6ef8cf7dee53eb28,
+            0x20b445fccd4abf08,
+        ]),
+        Fr([
+            0x7c6bc903d2070c89,
+            0xd962817946105e6b,
+            0xfd50354547caa058,
+            0x2db4d424372c0d81,
+        ]),
+        Fr([
+            0x65cd36f7d03ca08b,
+            0xb3dafd94e0db95df,
+            0xb06c88a7f154b16b,
+            0x1d38376208cdf0f6,
+        ]),
+    ],
+    [
+        Fr([
+            0x1d5771152bea8cd5,
+            0x055a008a0fad7acb,
+            0x716692892364d1ad,
+            0x031b484482b3898c,
+        ]),
+        Fr([
+            0xaa06d9de7526e008,
+            0x8613ed97893eeb9e,
+            0x2c5abd526ede31bd,
+            0x225e893c3fdbb992,
+        ]),
+        Fr([
+            0x56b5042afe4f2662,
+            0xca2c6637fb24d7f0,
+            0xb85752e5f7c2f2b7,
+            0x2b177d825c1ac151,
+        ]),
+        Fr([
+            0xf9545f2f50cad376,
+            0x7c775ebdac9f6801,
+            0x4420fa082207d9f4,
+            0x1ced167c26db8cd1,
+        ]),
+    ],
+    [
+        Fr([
+            0x7192a56206a2f520,
+            0xbfd4804b6f0835e5,
+            0x340d1a4123050bef,
+            0x1fbda6eb83faf80b,
+        ]),
+        Fr([
+            0xa6a697bd73602d88,
+            0x1b46f18d21d3818d,
+            0xc4992ecf34eac00c,
+            0x0368cbedd4edd2a1,
+        ]),
+        Fr([
+            0x562c3049163cb5b3,
+            0x212be8682e88c4ba,
+            0x80de222519d49f28,
+            0x26f3c1852983a311,
+        ]),
+        Fr([
+            0xad3e474fa50ecef6,
+            0xf574c8f9ad01ff72,
+            0x309a3fac8f081188,
+            0x0f501edfd6afc296,
+        ]),
+    ],
+    [
+        Fr([
+            0x06f5dd9674825210,
+            0x32f5eacb3348aa70,
+            0xdad574de82785351,
+            0x162044e689925d91,
+        ]),
+        Fr([
+            0xdfb894b0f8af8e01,
+            0x4a055857c08c34fb,
+            0x4962d001dffec0f4,
+            0x09fd178acb519c84,
+        ]),
+        Fr([
+            0x5785198dd2c07bfc,
+            0x2857e45dc96f9735,
+            0x483a11dbe85cd7f8,
+            0x04b11d3670adc4ce,
+        ]),
+        Fr([
+            0x71bb7258772e3f06,
+            0x65c0369e85c66e98,
+            0x666b9b89e0d2aeb9,
+            0x212695ef8d051305,
+        ]),
+    ],
+    [
+        Fr([
+            0x8a5849b671510c9c,
+            0xedb281f07227786b,
+            0x23908c4bb3e6ae92,
+            0x0886de4a9efe13df,
+        ]),
+        Fr([
+            0x5f72f14df6c576f1,
+            0x084eaf309979e0d1,
+            0x028422a851767e60,
+            0x2f8a764139c3b57f,
+        ]),
+        Fr([
+            0xab53676186d4f463,
+            0x70069e50c2274281,
+            0x02bee7c120a9a6a6,
+            0x2704dd8d6700b893,
+        ]),
+        Fr([
+            0xe78d93c4f4455718,
+            0xe4e080bbe606c47a,
+            0xc79b27129a052ac9,
+            0x2363d5a69ab69f05,
+        ]),
+    ],
+    [
+        Fr([
+            0xb30ed22502385858,
+            0xd17b7ff2e9572b3b,
+            0x18f51dcbe27232b6,
+            0x009d5a8be5c21709,
+        ]),
+        Fr([
+            0x852aff9a40c1d0e1,
+            0xf9424d6262de511b,
+            0xcef6cf901c4340a2,
+            0x0a27bd434d1e8ee3,
+        ]),
+        Fr([
+            0x4a7de9a21243f6c7,
+            0x92583d6684220a28,
+            0x3eff7a7f335d8c56,
+            0x2d3c178cb59087fd,
+        ]),
+        Fr([
+            0xec38f3f10804ff1b,
+            0x27c2b2def899d460,
+            0x462cf1ca34a1f056,
+            0x12bacdf20485d681,
+        ]),
+    ],
+    [
+        Fr([
+            0x0fbf53da39b68907,
+            0xbb19b1a015ac2b28,
+            0x0dbba45ab80287f5,
+            0x089a3067c21545ac,
+        ]),
+        Fr([
+            0x2e5f73a6a827cd7d,
+            0xb16e9064002ec97a,
+            0x41818dcb4f0c0dfc,
+            0x241c10a0a5e62305,
+        ]),
+        Fr([
+            0x54276ad3606c689e,
+            0xeb22529803cb4714,
+            0x6de6ab8df1c07d9c,
+            0x095e9babf1cf37aa,
+        ]),
+        Fr([
+            0x8b716aa364147ea2,
+            0x045f07a022e97de1,
+            0xe9308a9514ed9a49,
+            0x2d5eae853f9bdd40,
+        ]),
+    ],
+    [
+        Fr([
+            0x16f5c213efc97f18,
+            0x8365a12aa2887d11,
+            0xe65dfeef9ba9d743,
+            0x140a73d75e29db16,
+        ]),
+        Fr([
+            0xc247ac4364828aed,
+            0x64cdd086d0facb57,
+            0xb8ee706558d1be74,
+            0x0872eeff92dd9267,
+        ]),
+        Fr([
+            0x5f0fe25f78229b78,
+            0xd49ec2c3be8ea7cd,
+            0xf26d6f41e8b78bae,
+            0x13d232f3a2f1a390,
+        ]),
+        Fr([
+            0x6030f24e3cff157a,
+            0x3e0a538fe0bba63f,
+            0xfa54c6c88b738623,
+            0x016bf25300f2f69c,
+        ]),
+    ],
+    [
+        Fr([
+            0x14d85e8df9ccf27d,
+            0xeee670723c3a8188,
+            0x711fc54af7ae23af,
+            0x23b09c9376ac072c,
+        ]),
+        Fr([
+            0x4484ac24c6fe8fb5,
+            0xaef8dd48af2202ee,
+            0xcfd24e2e1e259f70,
+            0x1e70df590d8b27d4,
+        ]),
+        Fr([
+            0x4d9b739ec82f7a2d,
+            0x8c86c40aa6e8d6c7,
+            0x7066937404caad4f,
+            0x28305a578b1549cf,
+        ]),
+        Fr([
+            0x5c10457c8e634bf1,
+            0x4d3056587102511c,
+            0xc58960ddcc7317df,
+            0x0ab592a418d120a6,
+        ]),
+    ],
+    [
+        Fr([
+            0x3a4fa2ee5cd4e901,
+            0x1fad0396381b4b45,
+            0xa1a216c4bf898888,
+            0x1888054e81e2ddac,
+        ]),
+        Fr([
+            0x01c084b6f0df2c28,
+            0xc90848506ce269fe,
+            0xc284f97a839801a6,
+            0x23f05ed0f47ba81e,
+        ]),
+        Fr([
+            0x274ba4ee4b493f4b,
+            0x206288ade6d5e2ba,
+            0x6847e58f1c3734f2,
+            0x16925c4877c3ee38,
+        ]),
+        Fr([
+            0x17b70ed31e8cd819,
+            0xa35af94b37ac28c8,
+            0x209fbb2f209320cb,
+            0x11b9a36bd80295e9,
+        ]),
+    ],
+    [
+        Fr([
+            0xe132a14eed063f3d,
+            0x6c0371e40b6b8f71,
+            0x997a9833d4d77a76,
+            0x2c4d3110e49200ef,
+        ]),
+        Fr([
+            0xaf77dbf0f4e882fa,
+            0x581337d438f6706d,
+            0x1f350b68eda6847f,
+            0x23426a5cba59e064,
+        ]),
+        Fr([
+            0xd905ddc68b7bb8f0,
+            0x6194d3dc94d8322b,
+            0xbedf52823466286c,
+            0x2b4567b9fa227ac2,
+        ]),
+        Fr([
+            0x2aaca58769e6583c,
+            0xe7be1cabfcd27c27,
+            0xc884e167e69e91d2,
+            0x1b547d1ce6392136,
+        ]),
+    ],
+    [
+        Fr([
+            0x7b219467382ab782,
+            0xd70c8b2df24334cb,
+            0x9cf1b13e02b67972,
+            0x2e7084f35b17dd17,
+        ]),
+        Fr([
+            0x40ab0d720c69df1d,
+            0xd9a490647d1bbb56,
+            0x109cda61a1768bf7,
+            0x07be33b2f7c444aa,
+        ]),
+        Fr([
+            0xfaa27f11d951fd73,
+            0xa0ebdfb5be4b2be9,
+            0xf48aac128824d06e,
+            0x163a2f397fc2c580,
+        ]),
+        Fr([
+            0xb84022403b0665ca,
+            0x8dc8d0b451da4b05,
+            0xa38fa09b3c7eef28,
+            0x22cf98676b9e54fc,
+        ]),
+    ],
+    [
+        Fr([
+            0x50054222fdad8a3f,
+            0xd872d046f35f42f5,
+            0xe2ff644afe5d2494,
+            0x003ee1bd7437dadd,
+        ]),
+        Fr([
+            0xe5b1ed4648bba081,
+            0x9c7bab3ae8419a4c,
+            0x36b5495c7f471856,
+            0x06702f4862e41535,
+        ]),
+        Fr([
+            0xab892dcfedbee2f4,
+            0x8d3580b9734f7605,
+            0xbe7ab77561fb37d8,
+            0x18bc5f9a2eec6ee3,
+        ]),
+        Fr([
+            0x3e209b14c3b0d092,
+            0xb26774da542c328e,
+            0xcac95d3019df2c5c,
+            0x1385099c0f7fec62,
+        ]),
+    ],
+    [
+        Fr([
+            0x2bc039db6718ce5c,
+            0xfdd2f0788d85722d,
+            0xa5a456e83e2efa06,
+            0x184d1b4f8e3759d3,
+        ]),
+        Fr([
+            0xd4e139a00c563bea,
+            0xfee50bad68ec34af,
+            0xbf7b9f4531f958e3,
+            0x124cdaa7f933dd89,
+        ]),
+        Fr([
+            0xc723d7f39cc6ecc2,
+            0xa851bf747afea447,
+            0x14db504403a9ba7c,
+            0x127614761019d477,
+        ]),
+        Fr([
+            0xc0d30d47bbf2488d,
+            0xd8f1027c2dda8335,
+            0xa853dc518c57dc60,
+            0x284405373f563d59,
+        ]),
+    ],
+    [
+        Fr([
+            0x459b62eb3088fd48,
+            0x21ae32a424bd8eb6,
+            0x5db2e821b1c760f4,
+            0x084173a63034523b,
+        ]),
+        Fr([
+            0x5808e7afe0927ba5,
+            0x9a117acb61c04115,
+            0x79975fc2d5834662,
+            0x2f434e85b9b0935c,
+        ]),
+        Fr([
+            0x53cdc44c9841b4cc,
+            0x7458febc9f9ab7e1,
+            0x7a0071be148390d6,
+            0x13fe55a1079cc141,
+        ]),
+        Fr([
+            0xc446f1f9dc1bca8c,
+            0x059a569291cc9a75,
+            0x813cddf359f77af0,
+            0x0ed5034097ff57ee,
+        ]),
+    ],
+];
+
+pub(crate) const MDS: [[Fr; 4]; 4] = [
+    [
+        Fr([
+            0xc746d3a1ea1cc193,
+            0x6909fa60cbf91537,
+            0x5b41f9011d64f292,
+            0x163fd60c1936197d,
+        ]),
+        Fr([
+            0x0181d5313bbdbfdb,
+            0xe18378c86214909e,
+            0xcc39e35f710ea822,
+            0x214f5455afa43479,
+        ]),
+        Fr([
+            0x582d922f6dc48b16,
+            0x38c8cb41c45cff82,
+            0x7df9249ce4429252,
+            0x007ab9de1893f215,
+        ]),
+        Fr([
+            0x9cff2c0f6412a4c4,
+            0x30fb90c591f50925,
+            0x6e6ab78411b6fcb9,
+            0x1b77cf0ecb224202,
+        ]),
+    ],
+    [
+        Fr([
+            0xc8c85e8ccec69c00,
+            0xbe0283254c087a25,
+            0xe7e873d447f84ea7,
+            0x006de5b38e5fd49e,
+        ]),
+        Fr([
+            0x437c9035a82c8a7e,
+            0x2cc53efa3fc81999,
+            0x16a3050a16ec5fef,
+            0x1405c2ce761b1ffe,
+        ]),
+        Fr([
+            0x8100aebd55865e63,
+            0x4efac234996ba0f3,
+            0xd7620cc74699967a,
+            0x1910150019158192,
+        ]),
+        Fr([
+            0x6339508f8d23fda0,
+            0xff4e29746759d717,
+            0xc9d745285d69fd3c,
+            0x1c752d58c2ef9d67,
+        ]),
+    ],
+    [
+        Fr([
+            0xbb83fa3e0a031edf,
+            0xa247540e9ab2abde,
+            0x10ce1ad9c5a34aa0,
+            0x0e0d7c4459fde200,
+        ]),
+        Fr([
+            0x742a45e4beba79e2,
+            0x262159b82a5d52b2,
+            0x67c2c32c9a7262fb,
+            0x1a016e5249486d11,
+        ]),
+        Fr([
+            0x032ee49d3f46f8e3,
+            0x9581df46402300b5,
+            0xb073993fd3900255,
+            0x2ff42c980d018110,
+        ]),
+        Fr([
+            0xef89cf7680de46a2,
+            0xaf9fb3890c2afe43,
+            0xc6241b343d910469,
+            0x2906a4070cbc228f,
+        ]),
+    ],
+    [
+        Fr([
+            0x37d7866bffe26d19,
+            0x6ced6607b03ae067,
+            0x68fce24891c473c7,
+            0x0bb889c2fd6eac56,
+        ]),
+        Fr([
+            0x6135f5062b5b2e93,
+            0x6f938788c83dcb7f,
+            0x4fc8df2d97896992,
+            0x077d3ed0c2de7b50,
+        ]),
+        Fr([
+            0xaafcf1bd4770a975,
+            0xfd91630f7b3a8546,
+            0x789e7c4c49ebb73c,
+            0x0018a0fab8043422,
+        ]),
+        Fr([
+            0xd2e8592dd30a25b5,
+            0x4f626b2e7f68559e,
+            0xa8ca8234e9a2e036,
+            0x03b26515166e9d76,
+        ]),
+    ],
+];
@@ -0,0 +1,198 @@
+// limb arithmetic indexes several arrays in lockstep
+#![allow(clippy::needless_range_loop)]
+
+use std::ops::{Add, AddAssign, Mul, Sub};
+
+/// Modulus of the BN254 scalar field, little-endian limbs.
+pub const MODULUS: [u64; 4] = [
+    0x43e1f593f0000001,
+    0x2833e84879b97091,
+    0xb85045b68181585d,
+    0x30644e72e131a029,
+];
+
+/// -MODULUS^-1 mod 2^64
+const INV: u64 = 0xc2e1f593efffffff;
+
+/// 2^512 mod MODULUS
+const R2: [u64; 4] = [
+    0x1bb8e645ae216da7,
+    0x53fe3ab1e35c59e3,
+    0x8c49833d53bb8085,
+    0x0216d0b17f4e44a5,
+];
+
+/// Element of the BN254 scalar field, in Montgomery form.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Fr(pub(crate) [u64; 4]);
+
+#[inline(always)]
+fn mac(a: u64, b: u64, c: u64, carry: u64) -> (u64, u64) {
+    let t = a as u128 + b as u128 * c as u128 + carry as u128;
+    (t as u64, (t >> 64) as u64)
+}
+
+#[inline(always)]
+fn adc(a: u64, b: u64, carry: u64) -> (u64, u64) {
+    let t = a as u128 + b as u128 + carry as u128;
+    (t as u64, (t >> 64) as u64)
+}
+
+#[inline(always)]
+fn sbb(a: u64, b: u64, borrow: u64) -> (u64, u64) {
+    let t = (a as u128).wrapping_sub(b as u128 + borrow as u128);
+    (t as u64, (t >> 127) as u64)
+}
+
+fn geq_modulus(a: &[u64; 4]) -> bool {
+    for i in (0..4).rev() {
+        if a[i] != MODULUS[i] {
+            return a[i] > MODULUS[i];
+        }
+    }
+    true
+}
+
+fn sub_modulus(a: &[u64; 4]) -> [u64; 4] {
+    let mut r = [0u64; 4];
+    let mut borrow = 0;
+    for i in 0..4 {
+        (r[i], borrow) = sbb(a[i], MODULUS[i], borrow);
+    }
+    r
+}
+
+impl Fr {
+    pub const ZERO: Self = Self([0; 4]);
+
+    /// `limbs` is the canonical little-endian representation, it must be below the modulus.
+    pub fn from_canonical(limbs: [u64; 4]) -> Self {
+        assert!(
+            !geq_modulus(&limbs),
+            "{limbs:?} is not a canonical BN254 scalar"
+        );
+        Self(limbs) * Self(R2)
+    }
+
+    pub fn from_u64(value: u64) -> Self {
+        Self::from_canonical([value, 0, 0, 0])
+    }
+
+    /// Canonical little-endian representation.
+    pub fn to_canonical(&self) -> [u64; 4] {
+        (*self * Self([1, 0, 0, 0])).0
+    }
+
+    pub fn square(&self) -> Self {
+        *self * *self
+    }
+
+    /// The S-box of the permutation.
+    pub fn pow5(&self) -> Self {
+        self.square().square() * *self
+    }
+}
+
+impl Add for Fr {
+    type Output = Self;
+
+    fn add(self, rhs: Self) -> Self {
+        let mut r = [0u64; 4];
+        let mut carry = 0;
+        for i in 0..4 {
+            (r[i], carry) = adc(self.0[i], rhs.0[i], carry);
+        }
+        // both are below the modulus, which is below 2^254, so there is no carry out
+        if geq_modulus(&r) {
+            r = sub_modulus(&r);
+        }
+        Self(r)
+    }
+}
+
+impl AddAssign for Fr {
+    fn add_assign(&mut self, rhs: Self) {
+        *self = *self + rhs;
+    }
+}
+
+impl Sub for Fr {
+    type Output = Self;
+
+    fn sub(self, rhs: Self) -> Self {
+        let mut r = [0u64; 4];
+        let mut borrow = 0;
+        for i in 0..4 {
+            (r[i], borrow) = sbb(self.0[i], rhs.0[i], borrow);
+        }
+        if borrow != 0 {
+            let mut carry = 0;
+            for i in 0..4 {
+                (r[i], carry) = adc(r[i], MODULUS[i], carry);
+            }
+        }
+        Self(r)
+    }
+}
+
+impl Mul for Fr {
+    type Output = Self;
+
+    // Montgomery multiplication, coarsely integrated operand scanning
+    fn mul(self, rhs: Self) -> Self {
+        let (a, b) = (self.0, rhs.0);
+        let mut t = [0u64; 6];
+        for i in 0..4 {
+            let mut carry = 0;
+            for j in 0..4 {
+                (t[j], carry) = mac(t[j], a[j], b[i], carry);
+            }
+            (t[4], t[5]) = adc(t[4], carry, 0);
+
+            let m = t[0].wrapping_mul(INV);
+            let (_, mut carry) = mac(t[0], m, MODULUS[0], 0);
+            for j in 1..4 {
+                (t[j - 1], carry) = mac(t[j], m, MODULUS[j], carry);
+            }
+            let (s, c) = adc(t[4], carry, 0);
+            t[3] = s;
+            t[4] = t[5] + c;
+            t[5] = 0;
+        }
+
+        let r = [t[0], t[1], t[2], t[3]];
+        if t[4] != 0 || geq_modulus(&r) {
+            Self(sub_modulus(&r))
+        } else {
+            Self(r)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fr_arithmetic() {
+        let minus_one = Fr::ZERO - Fr::from_u64(1);
+        let mut expected = MODULUS;
+        expected[0] -= 1;
+        assert_eq!(minus_one.to_canonical(), expected);
+        assert_eq!((minus_one + Fr::from_u64(3)).to_canonical(), [2, 0, 0, 0]);
+        assert_eq!((minus_one * minus_one).to_canonical(), [1, 0, 0, 0]);
+
+        let a = Fr::from_canonical([u64::MAX, u64::MAX, 0, 0]);
+        // (2^128 - 1)^2 = 2^256 - 2^129 + 1, reduced mod the modulus
+        assert_eq!(
+            (a * a).to_canonical(),
+            [
+                0xac96341c4ffffffc,
+                0x36fc76959f60cd29,
+                0x666ea36f7879462c,
+                0x0e0a77c19a07df2f
+            ]
+        );
+        assert_eq!(Fr::from_u64(7).pow5().to_canonical(), [16807, 0, 0, 0]);
+    }
+}
@@ -0,0 +1,267 @@
+//! Poseidon permutation over the BN254 scalar field, applied to a plonky2 sponge state.
+//!
+//! plonky2 hashes with a permutation of 12 Goldilocks elements. [`permute_goldilocks`]
+//! packs them three at a time into the 4 elements of a BN254 Poseidon state, so that a
+//! plonky2 proof hashed with it is cheap to verify in a circuit over BN254, e.g. in halo2.
+//!
+//! The instance is the reference one for width 4: 8 full rounds, 56 partial rounds and
+//! the `x^5` S-box, with the round constants and MDS matrix of the reference Grain LFSR.
+
+mod constants;
+mod fr;
+
+pub use fr::{Fr, MODULUS};
+
+use crate::constants::{MDS, ROUND_CONSTANTS};
+
+pub const WIDTH: usize = 4;
+pub const FULL_ROUNDS: usize = 8;
+pub const PARTIAL_ROUNDS: usize = 56;
+
+/// Width of the plonky2 sponge state.
+pub const GOLDILOCKS_WIDTH: usize = 12;
+/// Goldilocks elements packed into each BN254 element.
+pub const GOLDILOCKS_PER_ELEMENT: usize = GOLDILOCKS_WIDTH / WIDTH;
+pub const GOLDILOCKS_ORDER: u64 = 0xFFFF_FFFF_0000_0001;
+
+pub fn round_constants() -> &'static [[Fr; WIDTH]; FULL_ROUNDS + PARTIAL_ROUNDS] {
+    &ROUND_CONSTANTS
+}
+
+pub fn mds() -> &'static [[Fr; WIDTH]; WIDTH] {
+    &MDS
+}
+
+/// Whether `round` applies the S-box to the whole state.
+pub fn is_full_round(round: usize) -> bool {
+    !(FULL_ROUNDS / 2..FULL_ROUNDS / 2 + PARTIAL_ROUNDS).contains(&round)
+}
+
+pub fn permute(state: &mut [Fr; WIDTH]) {
+    for (round, constants) in ROUND_CONSTANTS.iter().enumerate() {
+        for (s, &c) in state.iter_mut().zip(constants) {
+            *s += c;
+        }
+
+        if is_full_round(round) {
+            for s in state.iter_mut() {
+                *s = s.pow5();
+            }
+        } else {
+            state[0] = state[0].pow5();
+        }
+
+        let mut mixed = [Fr::ZERO; WIDTH];
+        for (m, row) in mixed.iter_mut().zip(&MDS) {
+            for (&coeff, &s) in row.iter().zip(state.iter()) {
+                *m += coeff * s;
+            }
+        }
+        *state = mixed;
+    }
+}
+
+/// Permutes a plonky2 sponge state of canonical Goldilocks elements.
+///
+/// Each BN254 element holds 3 of them as a 192-bit integer. After the permutation, the
+/// low 192 bits of each element are split back into 3 limbs of 64 bits, reduced modulo the
+/// Goldilocks order.
+pub fn permute_goldilocks(input: [u64; GOLDILOCKS_WIDTH]) -> [u64; GOLDILOCKS_WIDTH] {
+    let mut state = [Fr::ZERO; WIDTH];
+    for (s, lanes) in state.iter_mut().zip(input.chunks(GOLDILOCKS_PER_ELEMENT)) {
+        *s = Fr::from_canonical([lanes[0], lanes[1], lanes[2], 0]);
+    }
+
+    permute(&mut state);
+
+    let mut output = [0u64; GOLDILOCKS_WIDTH];
+    for (lanes, s) in output.chunks_mut(GOLDILOCKS_PER_ELEMENT).zip(&state) {
+        let limbs = s.to_canonical();
+        for (lane, limb) in lanes.iter_mut().zip(limbs) {
+            *lane = limb % GOLDILOCKS_ORDER;
+        }
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Grain LFSR of the reference parameter generation script
+    struct Grain {
+        bits: Vec<u8>,
+    }
+
+    impl Grain {
+        fn new() -> Self {
+            let mut bits = Vec::new();
+            let parameters = [
+                (1, 2),
+                (0, 4),
+                (254, 12),
+                (WIDTH, 12),
+                (FULL_ROUNDS, 10),
+                (PARTIAL_ROUNDS, 10),
+            ];
+            for (value, len) in parameters {
+                bits.extend((0..len).rev().map(|i| ((value >> i) & 1) as u8));
+            }
+            bits.extend([1; 30]);
+
+            let mut grain = Self { bits };
+            for _ in 0..160 {
+                grain.step();
+            }
+            grain
+        }
+
+        fn step(&mut self) -> u8 {
+            let b = &self.bits;
+            let bit = b[62] ^ b[51] ^ b[38] ^ b[23] ^ b[13] ^ b[0];
+            self.bits.remove(0);
+            self.bits.push(bit);
+            bit
+        }
+
+        fn next_bit(&mut self) -> u8 {
+            loop {
+                if self.step() == 1 {
+                    return self.step();
+                }
+                self.step();
+            }
+        }
+
+        // 254 bits, most significant first
+        fn next_limbs(&mut self) -> [u64; 4] {
+            let mut limbs = [0u64; 4];
+            for i in (0..254).rev() {
+                limbs[i / 64] |= (self.next_bit() as u64) << (i % 64);
+            }
+            limbs
+        }
+    }
+
+    fn is_canonical(limbs: &[u64; 4]) -> bool {
+        limbs.iter().rev().cmp(MODULUS.iter().rev()).is_lt()
+    }
+
+    fn inverse(x: Fr) -> Fr {
+        // x^(p - 2)
+        let mut exponent = MODULUS;
+        exponent[0] -= 2;
+        let mut result = Fr::from_u64(1);
+        for i in (0..256).rev() {
+            result = result.square();
+            if (exponent[i / 64] >> (i % 64)) & 1 == 1 {
+                result = result * x;
+            }
+        }
+        result
+    }
+
+    #[test]
+    fn test_constants_match_grain() {
+        let mut grain = Grain::new();
+        for constants in ROUND_CONSTANTS.iter() {
+            for c in constants {
+                let mut limbs = grain.next_limbs();
+                while !is_canonical(&limbs) {
+                    limbs = grain.next_limbs();
+                }
+                assert_eq!(c.to_canonical(), limbs);
+            }
+        }
+
+        // Cauchy matrix 1 / (x_i + y_j), the reference reduces the samples
+        let samples: Vec<Fr> = (0..2 * WIDTH)
+            .map(|_| {
+                let mut limbs = grain.next_limbs();
+                while !is_canonical(&limbs) {
+                    limbs = sub(limbs, MODULUS);
+                }
+                Fr::from_canonical(limbs)
+            })
+            .collect();
+        let (xs, ys) = samples.split_at(WIDTH);
+        for (row, x) in MDS.iter().zip(xs) {
+            for (m, y) in row.iter().zip(ys) {
+                assert_eq!(*m, inverse(*x + *y));
+            }
+        }
+    }
+
+    fn sub(a: [u64; 4], b: [u64; 4]) -> [u64; 4] {
+        let mut r = [0u64; 4];
+        let mut borrow = false;
+        for i in 0..4 {
+            let (d, b1) = a[i].overflowing_sub(b[i]);
+            let (d, b2) = d.overflowing_sub(borrow as u64);
+            r[i] = d;
+            borrow = b1 || b2;
+        }
+        r
+    }
+
+    #[test]
+    fn test_permute() {
+        let mut state = [0, 1, 2, 3].map(Fr::from_u64);
+        permute(&mut state);
+        assert_eq!(
+            state.map(|s| s.to_canonical()),
+            [
+                [
+                    0xf725df34ab36d732,
+                    0xf3230e269dc5b968,
+                    0xff03d5e58dab6302,
+                    0x0e7732d89e6939c0
+                ],
+                [
+                    0x9a435b0dde022a0d,
+                    0xe47e748e493e542f,
+                    0xe6c17ee6ccdddce4,
+                    0x07b0b86b41ec7fdf
+                ],
+                [
+                    0x49f2df609fec4209,
+                    0x8b0a6efab0e12ade,
+                    0x1898d47ace20eab1,
+                    0x04362e50fcc8be42
+                ],
+                [
+                    0x24bddf3b9f2bf2d7,
+                    0xfa0e458e45a14075,
+                    0x54eae5ed74e7fa44,
+                    0x1a779bd9781d3a83
+                ],
+            ]
+        );
+    }
+
+    #[test]
+    fn test_permute_goldilocks() {
+        let input: [u64; GOLDILOCKS_WIDTH] = core::array::from_fn(|i| i as u64);
+        assert_eq!(
+            permute_goldilocks(input),
+            [
+                0xa63e62030c9dc32d,
+                0x5d74afd3d2e8c2be,
+                0xd9ae3c29ed790a47,
+                0x9e6c577edbf008de,
+                0x2067d37adf810e00,
+                0x88a6197da8abfd05,
+                0x8fbaf9a98d963b62,
+                0x42dfb0f3718f66f4,
+                0xa231bc17e0f189b2,
+                0xb36caa20a8ae0c6d,
+                0x419c347236a2a568,
+                0x53bdea1971e02d77,
+            ]
+        );
+
+        let output = permute_goldilocks([GOLDILOCKS_ORDER - 1; GOLDILOCKS_WIDTH]);
+        assert_eq!(output[0], 0x71854a78b54fd592);
+        assert_eq!(output[11], 0xbd7e8bd3527312e8);
+    }
+}